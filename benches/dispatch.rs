@@ -0,0 +1,60 @@
+use service_io::engine::Engine;
+use service_io::message::Message;
+use service_io::services::Echo;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tokio::sync::mpsc;
+
+const MESSAGES: usize = 1000;
+
+async fn dispatch(services: usize) {
+    let (input_sender, input_receiver) = mpsc::channel(32);
+    let (output_sender, mut output_receiver) = mpsc::channel(32);
+
+    let mut engine = Engine::default()
+        .input(input_receiver)
+        .output(output_sender);
+
+    for index in 0..services {
+        engine = engine.add_service(format!("s-{}", index), Echo);
+    }
+
+    let task = tokio::spawn(engine.run());
+
+    tokio::spawn(async move {
+        for index in 0..MESSAGES {
+            let message = Message::default()
+                .user("user")
+                .service_name(format!("s-{}", index % services))
+                .args(["arg0", "arg1"])
+                .body("body");
+
+            input_sender.send(message).await.unwrap();
+        }
+    });
+
+    for _ in 0..MESSAGES {
+        output_receiver.recv().await.unwrap();
+    }
+
+    // Dropping the engine closes the services
+    task.abort();
+}
+
+fn engine_dispatch(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("engine_dispatch");
+    group.throughput(Throughput::Elements(MESSAGES as u64));
+    for services in [1, 10, 100] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(services),
+            &services,
+            |b, &services| b.to_async(&runtime).iter(|| dispatch(services)),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, engine_dispatch);
+criterion_main!(benches);
@@ -1,4 +1,4 @@
-use service_io::connectors::{ImapClient, SmtpClient};
+use service_io::connectors::Email;
 use service_io::engine::Engine;
 use service_io::message::util;
 use service_io::services::{Alarm, Echo, Process, PublicIp};
@@ -44,24 +44,15 @@ async fn main() {
     configure_logger(cli.verbose.log_level_filter()).unwrap();
 
     Engine::default()
-        .input(
-            ImapClient::default()
-                .domain(cli.imap_domain)
-                .email(cli.email.clone())
-                .password(cli.password.clone())
-                .polling_time(Duration::from_secs(cli.polling_time)),
-        )
-        .output(
-            SmtpClient::default()
-                .domain(cli.smtp_domain)
-                .email(cli.email)
-                .password(cli.password)
+        .connector(
+            Email::new(cli.imap_domain, cli.smtp_domain, cli.email, cli.password)
+                .polling_time(Duration::from_secs(cli.polling_time))
                 .sender_name(cli.sender_name),
         )
         .map_input(util::service_name_first_char_to_lowercase)
         .add_service("s-echo", Echo)
         .add_service("s-alarm", Alarm)
-        .add_service("s-public-ip", PublicIp)
+        .add_service("s-public-ip", PublicIp::default())
         .add_service("s-process", Process)
         .run()
         .await;
@@ -75,7 +66,7 @@ fn configure_logger(level_filter: log::LevelFilter) -> Result<(), log::SetLogger
         .format(move |out, message, record| {
             out.finish(format_args!(
                 "[{}] [{}] {}",
-                chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
                 record.level(),
                 message
             ))
@@ -41,7 +41,7 @@ async fn main() {
         .output(DebugStdout)
         .map_input(util::service_name_first_char_to_lowercase)
         .add_service("s-echo", Echo)
-        .add_service("s-public-ip", PublicIp)
+        .add_service("s-public-ip", PublicIp::default())
         .add_service("s-alarm", Alarm)
         .add_service("s-process", Process)
         .run()
@@ -39,7 +39,7 @@ async fn main() {
         )
         .add_service("s-echo", Echo)
         .add_service("s-alarm", Alarm)
-        .add_service("s-public-ip", PublicIp)
+        .add_service("s-public-ip", PublicIp::default())
         .add_service("s-process", Process)
         .run()
         .await;
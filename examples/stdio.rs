@@ -8,7 +8,7 @@ async fn main() {
         .input(UserStdin("stdin-user"))
         .output(DebugStdout)
         .add_service("s-echo", Echo)
-        .add_service("s-public-ip", PublicIp)
+        .add_service("s-public-ip", PublicIp::default())
         .add_service("s-alarm", Alarm)
         .add_service("s-process", Process)
         .run()
@@ -0,0 +1,182 @@
+//! In-memory cache with a time to live for each entry,
+//! so the services can reuse slow responses (i.e. of a remote API) for a while.
+//!
+//! The engine shares a single [`Cache`] among its services,
+//! available for the [`SimpleService`]s with [`Context::cache()`].
+//! Unlike the [`state`], the cache is not persisted.
+//! The values are strings, usually the JSON serialization of a response.
+//!
+//! # Example
+//! ```rust
+//! use service_io::cache::Cache;
+//!
+//! use std::time::Duration;
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let cache = Cache::default();
+//!     let ttl = Duration::from_secs(60);
+//!
+//!     let fetch = || async { Ok::<_, ()>("sunny".to_string()) };
+//!     let weather = cache.get_or_try_insert_with("weather/madrid", ttl, fetch).await;
+//!     assert_eq!(weather.unwrap(), "sunny");
+//!
+//!     // Served from the cache for the next 60 seconds.
+//!     assert_eq!(cache.get("weather/madrid").as_deref(), Some("sunny"));
+//! }
+//! ```
+//!
+//! [`SimpleService`]: crate::interface::SimpleService
+//! [`Context::cache()`]: crate::interface::Context::cache()
+//! [`state`]: crate::state
+
+use tokio::time::Instant;
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+const DEFAULT_CAPACITY: usize = 10_000;
+
+/// Values by key, each one expiring after its time to live.
+/// Once the capacity is reached, the expired entries are removed,
+/// and if there are none, the one closest to expire.
+pub struct Cache {
+    entries: Mutex<HashMap<String, (String, Instant)>>,
+    capacity: AtomicUsize,
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Cache::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl Cache {
+    /// Cache of up to `capacity` entries. By default, 10000.
+    pub fn new(capacity: usize) -> Cache {
+        Cache {
+            entries: Mutex::default(),
+            capacity: AtomicUsize::new(capacity),
+        }
+    }
+
+    pub(crate) fn set_capacity(&self, capacity: usize) {
+        self.capacity.store(capacity, Ordering::Relaxed);
+    }
+
+    /// Value of the `key`, if any and not expired.
+    pub fn get(&self, key: &str) -> Option<String> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some((value, expiration)) if *expiration > Instant::now() => Some(value.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Stores the `value` with the `key` during `ttl`, replacing the previous one.
+    pub fn insert(&self, key: impl Into<String>, value: impl Into<String>, ttl: Duration) {
+        let key = key.into();
+        let now = Instant::now();
+        let capacity = self.capacity.load(Ordering::Relaxed);
+        let mut entries = self.entries.lock().unwrap();
+        if !entries.contains_key(&key) && entries.len() >= capacity {
+            entries.retain(|_, (_, expiration)| *expiration > now);
+            if entries.len() >= capacity {
+                let closest = entries
+                    .iter()
+                    .min_by_key(|(_, (_, expiration))| *expiration)
+                    .map(|(key, _)| key.clone());
+                if let Some(closest) = closest {
+                    entries.remove(&closest);
+                }
+            }
+        }
+        if capacity > 0 {
+            entries.insert(key, (value.into(), now + ttl));
+        }
+    }
+
+    /// Removes the value of the `key`. Removing a non-existent key is not an error.
+    pub fn remove(&self, key: &str) {
+        self.entries.lock().unwrap().remove(key);
+    }
+
+    /// Value of the `key`, or the one returned by `init`, that is stored during `ttl`.
+    /// The errors of `init` are not stored, so the next call tries again.
+    pub async fn get_or_try_insert_with<F, Fut, E>(
+        &self,
+        key: &str,
+        ttl: Duration,
+        init: F,
+    ) -> Result<String, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<String, E>>,
+    {
+        if let Some(value) = self.get(key) {
+            return Ok(value);
+        }
+        let value = init().await?;
+        self.insert(key, value.clone(), ttl);
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LONG: Duration = Duration::from_secs(60);
+    const SHORT: Duration = Duration::from_millis(50);
+
+    #[tokio::test]
+    async fn expiration() {
+        let cache = Cache::default();
+        cache.insert("a", "1", SHORT);
+        cache.insert("b", "2", LONG);
+        assert_eq!(cache.get("a").as_deref(), Some("1"));
+
+        tokio::time::sleep(SHORT * 2).await;
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.get("b").as_deref(), Some("2"));
+
+        let init = || async { Err::<String, _>("failed") };
+        let value = cache.get_or_try_insert_with("a", LONG, init);
+        assert_eq!(value.await, Err("failed"));
+        let init = || async { Ok::<_, ()>("3".to_string()) };
+        let value = cache.get_or_try_insert_with("a", LONG, init);
+        assert_eq!(value.await.unwrap(), "3");
+        let init = || async { Ok::<_, ()>("4".to_string()) };
+        let value = cache.get_or_try_insert_with("a", LONG, init);
+        assert_eq!(value.await.unwrap(), "3");
+
+        cache.remove("a");
+        assert_eq!(cache.get("a"), None);
+    }
+
+    #[tokio::test]
+    async fn capacity() {
+        let cache = Cache::new(2);
+        cache.insert("a", "1", LONG * 2);
+        cache.insert("b", "2", LONG);
+        cache.insert("a", "3", LONG * 2);
+
+        // Full: the entry closest to expire is removed.
+        cache.insert("c", "4", SHORT);
+        assert_eq!(cache.get("a").as_deref(), Some("3"));
+        assert_eq!(cache.get("b"), None);
+
+        // Full: the expired entries are removed first.
+        tokio::time::sleep(SHORT * 2).await;
+        cache.insert("d", "5", LONG);
+        assert_eq!(cache.get("a").as_deref(), Some("3"));
+        assert_eq!(cache.get("d").as_deref(), Some("5"));
+    }
+}
@@ -1,50 +1,589 @@
 //! Channels used to connect inputs with services and services with outputs.
 //! It basically wraps a [`tokio::sync::mpsc`] for easy management inside input/output/services
 //! implementations.
+//!
+//! The channels carry [`Message`] by default, but any type can be used with [`channel()`]
+//! to move user-defined data between the internal components of a custom pipeline.
 
+use crate::engine::{Ack, DeliveryReport, EngineHandle};
 use crate::message::Message;
 
 use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Creates a new channel with a `capacity` of messages.
+///
+/// Useful for services that need to receive messages from their own tasks,
+/// i.e. combined with [`Receiver::merge()`].
+/// The [`Receiver::cancellation_token()`] of the new receiver is never cancelled.
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    let (sender, receiver) = mpsc::channel(capacity);
+    (
+        Sender(sender),
+        Receiver(receiver, CancellationToken::new(), None),
+    )
+}
+
+/// End of a channel that was closed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelEnd {
+    /// All the senders were dropped, so no more messages can be received.
+    Sender,
+
+    /// The receiver was dropped, so the messages can no longer be sent.
+    Receiver,
+}
 
 /// Error indicating that the channel was closed.
+///
+/// It implements [`std::error::Error`], so it can be propagated with `?`
+/// into other error types (as [`crate::Error`] or `Box<dyn Error>`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClosedChannel {
+    end: ChannelEnd,
+}
+
+impl ClosedChannel {
+    pub fn new(end: ChannelEnd) -> Self {
+        Self { end }
+    }
+
+    /// End of the channel that was closed.
+    pub fn end(&self) -> ChannelEnd {
+        self.end
+    }
+}
+
+impl fmt::Display for ClosedChannel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.end {
+            ChannelEnd::Sender => write!(f, "channel closed: no senders left"),
+            ChannelEnd::Receiver => write!(f, "channel closed: the receiver was dropped"),
+        }
+    }
+}
+
+impl std::error::Error for ClosedChannel {}
+
+/// Error returned by [`Sender::try_send()`]. It gives back the message that was not sent.
 #[derive(Debug)]
-pub struct ClosedChannel;
+pub enum TrySendError<T = Message> {
+    /// The channel has no space for more messages.
+    Full(Box<T>),
+
+    /// The channel was closed.
+    Closed(Box<T>),
+}
 
 /// Sender side of the channel.
 /// It basically wraps a [`tokio::sync::mpsc::Sender`] for easy management inside input/output/services
 /// implementations.
-#[derive(Clone)]
-pub struct Sender(pub(crate) mpsc::Sender<Message>);
+pub struct Sender<T = Message>(pub(crate) mpsc::Sender<T>);
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Sender(self.0.clone())
+    }
+}
 
-impl Sender {
+impl<T> Sender<T> {
     /// Send asynchronously a message.
     ///
     /// This method is a wrapper over [`tokio::sync::mpsc::Sender::send()`] with an specific
     /// mapped error.
-    pub async fn send(&self, message: Message) -> Result<(), ClosedChannel> {
-        self.0.send(message).await.map_err(|_| ClosedChannel)
+    pub async fn send(&self, message: T) -> Result<(), ClosedChannel> {
+        self.0.send(message).await.map_err(|_| closed_receiver())
+    }
+
+    /// Send asynchronously several messages, reserving the space for them in batches
+    /// instead of waiting for each message.
+    pub async fn send_all(
+        &self,
+        messages: impl IntoIterator<Item = T>,
+    ) -> Result<(), ClosedChannel> {
+        let mut messages = messages.into_iter().peekable();
+        while messages.peek().is_some() {
+            let batch: Vec<T> = messages.by_ref().take(self.0.max_capacity()).collect();
+            let permits = self
+                .0
+                .reserve_many(batch.len())
+                .await
+                .map_err(|_| closed_receiver())?;
+
+            for (permit, message) in permits.zip(batch) {
+                permit.send(message);
+            }
+        }
+        Ok(())
     }
 
     /// Send a message.
     ///
     /// This method is a wrapper over [`tokio::sync::mpsc::Sender::blocking_send()`] with an
     /// specific mapped error.
-    pub fn blocking_send(&self, message: Message) -> Result<(), ClosedChannel> {
-        self.0.blocking_send(message).map_err(|_| ClosedChannel)
+    pub fn blocking_send(&self, message: T) -> Result<(), ClosedChannel> {
+        self.0.blocking_send(message).map_err(|_| closed_receiver())
+    }
+
+    /// Send a message only if there is space in the channel. It never waits.
+    ///
+    /// Useful for connectors that prefer to discard or report the messages on overflow
+    /// instead of waiting for the services.
+    /// This method is a wrapper over [`tokio::sync::mpsc::Sender::try_send()`].
+    pub fn try_send(&self, message: T) -> Result<(), TrySendError<T>> {
+        self.0.try_send(message).map_err(|err| match err {
+            mpsc::error::TrySendError::Full(message) => TrySendError::Full(Box::new(message)),
+            mpsc::error::TrySendError::Closed(message) => TrySendError::Closed(Box::new(message)),
+        })
+    }
+
+    /// Number of messages that can be sent without waiting.
+    pub fn capacity(&self) -> usize {
+        self.0.capacity()
+    }
+
+    /// Maximum number of messages the channel can hold.
+    pub fn max_capacity(&self) -> usize {
+        self.0.max_capacity()
+    }
+
+    /// Returns `true` if sending a message would wait.
+    pub fn is_full(&self) -> bool {
+        self.0.capacity() == 0
+    }
+
+    /// Wait asynchronously until there is space in the channel to send a message.
+    ///
+    /// Useful for input connectors to avoid reading new data while the services are busy.
+    /// This method is a wrapper over [`tokio::sync::mpsc::Sender::reserve()`] with an specific
+    /// mapped error.
+    pub async fn permit(&self) -> Result<Permit<'_, T>, ClosedChannel> {
+        self.0
+            .reserve()
+            .await
+            .map(Permit)
+            .map_err(|_| closed_receiver())
+    }
+
+    /// Wait until there is space in the channel to send a message.
+    ///
+    /// Similar to [`Sender::permit()`] but for blocking contexts as
+    /// [`tokio::task::spawn_blocking()`]. It panics if it is called from an asynchronous context.
+    pub fn blocking_permit(&self) -> Result<Permit<'_, T>, ClosedChannel> {
+        tokio::runtime::Handle::current().block_on(self.permit())
+    }
+}
+
+/// Space reserved in the channel to send a message.
+/// Created by [`Sender::permit()`].
+/// It basically wraps a [`tokio::sync::mpsc::Permit`].
+pub struct Permit<'a, T = Message>(mpsc::Permit<'a, T>);
+
+impl<T> Permit<'_, T> {
+    /// Send a message using the reserved space. It never waits.
+    pub fn send(self, message: T) {
+        self.0.send(message)
+    }
+}
+
+impl Sender<Message> {
+    /// Send asynchronously a message from an input connector, getting an [`Ack`]
+    /// to know when the engine is done with it. See [`Engine::ack_mode()`].
+    ///
+    /// # Example
+    /// ```rust
+    /// use service_io::interface::InputConnector;
+    /// use service_io::channel::Sender;
+    /// use service_io::message::Message;
+    /// use service_io::Error;
+    ///
+    /// use async_trait::async_trait;
+    ///
+    /// struct MyInput;
+    ///
+    /// #[async_trait]
+    /// impl InputConnector for MyInput {
+    ///     async fn run(self: Box<Self>, sender: Sender) -> Result<(), Error> {
+    ///          loop {
+    ///              // Read the message from its origin without removing it
+    ///              let message = Message::default();
+    ///              let ack = sender.send_acked(message).await?;
+    ///              if ack.wait().await {
+    ///                  // Safe to remove the message from its origin
+    ///              }
+    ///          }
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// [`Engine::ack_mode()`]: crate::engine::Engine::ack_mode()
+    pub async fn send_acked(&self, message: Message) -> Result<Ack, ClosedChannel> {
+        let permit = self.permit().await?;
+        Ok(permit.send_acked(message))
+    }
+}
+
+impl Permit<'_, Message> {
+    /// Similar to [`Sender::send_acked()`] but using the reserved space. It never waits.
+    pub fn send_acked(self, mut message: Message) -> Ack {
+        let ack = match EngineHandle::current() {
+            Some(engine) => engine.acks().register(&mut message),
+            None => Ack::resolved(),
+        };
+        self.send(message);
+        ack
     }
 }
 
 /// Receiver side of the channel.
 /// It basically wraps a [`tokio::sync::mpsc::Receiver`] for easy management inside input/output/services
 /// implementations.
-pub struct Receiver(pub(crate) mpsc::Receiver<Message>);
+///
+/// It also carries the [`CancellationToken`] of the engine shutdown.
+pub struct Receiver<T = Message>(
+    pub(crate) mpsc::Receiver<T>,
+    pub(crate) CancellationToken,
+    pub(crate) Option<RecvHook<T>>,
+);
+
+/// Called for each message taken out of the channel, either received or discarded
+/// when the receiver is dropped. Used by the engine to track the queued messages.
+pub(crate) type RecvHook<T> = Arc<dyn Fn(&T) + Send + Sync>;
 
-impl Receiver {
+impl<T> Receiver<T> {
     /// Receive asynchronously a message.
     ///
     /// This method is a wrapper over [`tokio::sync::mpsc::Receiver::recv()`] with an specific
     /// mapped error.
-    pub async fn recv(&mut self) -> Result<Message, ClosedChannel> {
-        self.0.recv().await.ok_or(ClosedChannel)
+    pub async fn recv(&mut self) -> Result<T, ClosedChannel> {
+        let message = self.0.recv().await.ok_or(closed_senders())?;
+        if let Some(hook) = &self.2 {
+            hook(&message);
+        }
+        Ok(message)
+    }
+
+    /// Receive asynchronously up to `limit` messages at once.
+    /// It waits until at least one message is available.
+    ///
+    /// This method is a wrapper over [`tokio::sync::mpsc::Receiver::recv_many()`] with an specific
+    /// mapped error.
+    pub async fn recv_many(&mut self, limit: usize) -> Result<Vec<T>, ClosedChannel> {
+        let mut messages = Vec::with_capacity(limit);
+        match self.0.recv_many(&mut messages, limit).await {
+            0 if limit > 0 => Err(closed_senders()),
+            _ => {
+                if let Some(hook) = &self.2 {
+                    messages.iter().for_each(|message| hook(message));
+                }
+                Ok(messages)
+            }
+        }
+    }
+
+    /// Receive asynchronously a message waiting at most `duration`.
+    /// Returns `None` if no message was received in time.
+    pub async fn recv_timeout(&mut self, duration: Duration) -> Result<Option<T>, ClosedChannel> {
+        match tokio::time::timeout(duration, self.recv()).await {
+            Ok(message) => message.map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Combine this receiver with `other` into a receiver of the messages of both.
+    /// The combined receiver is closed once both are closed,
+    /// and keeps the [`Receiver::cancellation_token()`] of this receiver.
+    ///
+    /// It spawns a task, so it must be called inside a tokio runtime.
+    ///
+    /// # Example
+    /// ```rust
+    /// use service_io::interface::{Service};
+    /// use service_io::channel::{self, ClosedChannel, Receiver, Sender};
+    /// use service_io::message::Message;
+    ///
+    /// use async_trait::async_trait;
+    ///
+    /// use std::time::Duration;
+    ///
+    /// struct Delayed;
+    ///
+    /// #[async_trait]
+    /// impl Service for Delayed {
+    ///     async fn run(self: Box<Self>, input: Receiver, output: Sender) -> Result<(), ClosedChannel> {
+    ///          let (timer_sender, timer_receiver) = channel::channel(32);
+    ///          let mut input = input.merge(timer_receiver);
+    ///          loop {
+    ///              let message = input.recv().await?;
+    ///              match message.args.first().map(|arg| arg.as_str()) {
+    ///                  // Message from the timer
+    ///                  Some("expired") => output.send(message).await?,
+    ///                  // Message from the user
+    ///                  _ => {
+    ///                      let timer_sender = timer_sender.clone();
+    ///                      tokio::spawn(async move {
+    ///                          tokio::time::sleep(Duration::from_secs(60)).await;
+    ///                          let response = Message::response(&message).args(["expired"]);
+    ///                          timer_sender.send(response).await.ok();
+    ///                      });
+    ///                  }
+    ///              }
+    ///          }
+    ///     }
+    /// }
+    /// ```
+    pub fn merge(self, other: Receiver<T>) -> Receiver<T>
+    where
+        T: Send + 'static,
+    {
+        let token = self.1.clone();
+        let (mut first, mut second) = (self, other);
+        let (sender, receiver) = mpsc::channel(first.0.max_capacity().max(1));
+
+        tokio::spawn(async move {
+            let (mut first_open, mut second_open) = (true, true);
+            while first_open || second_open {
+                let message = tokio::select! {
+                    message = first.recv(), if first_open => match message {
+                        Ok(message) => message,
+                        Err(_) => {
+                            first_open = false;
+                            continue;
+                        }
+                    },
+                    message = second.recv(), if second_open => match message {
+                        Ok(message) => message,
+                        Err(_) => {
+                            second_open = false;
+                            continue;
+                        }
+                    },
+                };
+
+                if sender.send(message).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        Receiver(receiver, token, None)
+    }
+
+    /// Token cancelled when the engine shuts down.
+    ///
+    /// Use it to abort cleanly the long-running work spawned by your service,
+    /// that otherwise would be left dangling once the engine finishes.
+    ///
+    /// # Example
+    /// ```rust
+    /// use service_io::interface::{Service};
+    /// use service_io::channel::{ClosedChannel, Receiver, Sender};
+    ///
+    /// use async_trait::async_trait;
+    ///
+    /// use std::time::Duration;
+    ///
+    /// struct MyService;
+    ///
+    /// #[async_trait]
+    /// impl Service for MyService {
+    ///     async fn run(self: Box<Self>, mut input: Receiver, output: Sender) -> Result<(), ClosedChannel> {
+    ///          loop {
+    ///              let message = input.recv().await?;
+    ///              let token = input.cancellation_token();
+    ///              let output = output.clone();
+    ///              tokio::spawn(async move {
+    ///                  tokio::select! {
+    ///                      _ = tokio::time::sleep(Duration::from_secs(60)) => {
+    ///                          output.send(message).await.ok();
+    ///                      }
+    ///                      _ = token.cancelled() => (), // The engine was shut down
+    ///                  }
+    ///              });
+    ///          }
+    ///     }
+    /// }
+    /// ```
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.1.clone()
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        if let Some(hook) = &self.2 {
+            self.0.close();
+            while let Ok(message) = self.0.try_recv() {
+                hook(&message);
+            }
+        }
+    }
+}
+
+impl Receiver<Message> {
+    /// Report the result of delivering a message received by an output connector.
+    ///
+    /// Reporting is optional. The engine collects the reports as
+    /// [`Event::MessageDelivered`] and [`Event::DeliveryError`] events,
+    /// so the failed messages can be retried, stored as dead letters or counted.
+    ///
+    /// # Example
+    /// ```rust
+    /// use service_io::interface::OutputConnector;
+    /// use service_io::channel::Receiver;
+    /// use service_io::engine::DeliveryReport;
+    /// use service_io::Error;
+    ///
+    /// use async_trait::async_trait;
+    ///
+    /// struct MyOutput;
+    ///
+    /// #[async_trait]
+    /// impl OutputConnector for MyOutput {
+    ///     async fn run(self: Box<Self>, mut receiver: Receiver) -> Result<(), Error> {
+    ///         loop {
+    ///             let message = receiver.recv().await?;
+    ///             match std::fs::write(&message.user, &message.body) {
+    ///                 Ok(()) => receiver.report(DeliveryReport::delivered(&message)),
+    ///                 Err(err) => receiver.report(DeliveryReport::failed(message, err)),
+    ///             }
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// [`Event::MessageDelivered`]: crate::engine::Event::MessageDelivered
+    /// [`Event::DeliveryError`]: crate::engine::Event::DeliveryError
+    pub fn report(&self, report: DeliveryReport) {
+        if let Some(engine) = EngineHandle::current() {
+            engine.report(report);
+        }
+    }
+}
+
+fn closed_receiver() -> ClosedChannel {
+    ClosedChannel::new(ChannelEnd::Receiver)
+}
+
+fn closed_senders() -> ClosedChannel {
+    ClosedChannel::new(ChannelEnd::Sender)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn closed_ends() {
+        let (sender, mut receiver) = channel::<Message>(1);
+        drop(sender);
+        let err = receiver.recv().await.unwrap_err();
+        assert_eq!(err.end(), ChannelEnd::Sender);
+
+        let (sender, receiver) = channel(1);
+        drop(receiver);
+        let result: Result<(), Box<dyn std::error::Error>> = async {
+            sender.send(Message::default()).await?;
+            Ok(())
+        }
+        .await;
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "channel closed: the receiver was dropped"
+        );
+    }
+
+    #[tokio::test]
+    async fn merge() {
+        let (sender_0, receiver_0) = channel(1);
+        let (sender_1, receiver_1) = channel(1);
+        let mut receiver = receiver_0.merge(receiver_1);
+
+        sender_0
+            .send(Message::default().user("user_0"))
+            .await
+            .unwrap();
+        assert_eq!(receiver.recv().await.unwrap().user, "user_0");
+
+        sender_1
+            .send(Message::default().user("user_1"))
+            .await
+            .unwrap();
+        assert_eq!(receiver.recv().await.unwrap().user, "user_1");
+
+        drop(sender_0);
+        sender_1
+            .send(Message::default().user("user_1"))
+            .await
+            .unwrap();
+        assert_eq!(receiver.recv().await.unwrap().user, "user_1");
+
+        drop(sender_1);
+        assert!(receiver.recv().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn try_send() {
+        let (sender, mut receiver) = channel(1);
+        assert_eq!(sender.capacity(), 1);
+        assert!(!sender.is_full());
+
+        sender.try_send(Message::default()).unwrap();
+        assert!(sender.is_full());
+        assert!(matches!(
+            sender.try_send(Message::default()),
+            Err(TrySendError::Full(_))
+        ));
+
+        receiver.recv().await.unwrap();
+        assert_eq!(sender.capacity(), sender.max_capacity());
+
+        drop(receiver);
+        assert!(matches!(
+            sender.try_send(Message::default()),
+            Err(TrySendError::Closed(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn batches() {
+        let (sender, mut receiver) = channel(4);
+        let messages: Vec<Message> = (0..10)
+            .map(|i| Message::default().user(format!("user_{}", i)))
+            .collect();
+
+        let expected = messages.clone();
+        let task = tokio::spawn(async move { sender.send_all(messages).await });
+
+        let mut received = Vec::new();
+        while received.len() < 10 {
+            let batch = receiver.recv_many(3).await.unwrap();
+            assert!(!batch.is_empty() && batch.len() <= 3);
+            received.extend(batch);
+        }
+        assert_eq!(received, expected);
+
+        task.await.unwrap().unwrap();
+        assert!(receiver.recv_many(3).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn recv_timeout() {
+        let (sender, mut receiver) = channel(1);
+        let timeout = Duration::from_millis(10);
+        assert_eq!(receiver.recv_timeout(timeout).await.unwrap(), None);
+
+        sender.send(Message::default()).await.unwrap();
+        assert_eq!(
+            receiver.recv_timeout(timeout).await.unwrap(),
+            Some(Message::default())
+        );
+
+        drop(sender);
+        assert!(receiver.recv_timeout(timeout).await.is_err());
     }
 }
@@ -0,0 +1,372 @@
+//! Shared queues to run several engine instances as a cluster.
+//!
+//! In clustering mode (see [`Engine::cluster()`]), the messages received by the input connector
+//! of any instance are pushed into a [`SharedQueue`] instead of being dispatched directly.
+//! Every instance pops messages from that queue and dispatches them to its own services,
+//! so the services can be scaled horizontally while each instance keeps its own connectors.
+//!
+//! A message pushed into the queue is popped by only one instance.
+//! Once the instance is done with it (see [`SharedQueue::done()`]), it is removed from the queue,
+//! or given back to the queue if it could not be processed.
+//! All the instances should register the same services.
+//! Use [`StickyQueue`] if the messages of a user must always be processed by the same instance.
+//!
+//! [`Engine::cluster()`]: crate::engine::Engine::cluster
+
+#[cfg(feature = "redis")]
+use crate::message::wire;
+use crate::message::Message;
+
+use async_trait::async_trait;
+use tokio::sync::{mpsc, Mutex};
+
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+#[cfg(feature = "redis")]
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A queue shared by all the engine instances of a cluster.
+#[async_trait]
+pub trait SharedQueue: Send + Sync {
+    /// Push a message to be processed by any instance of the cluster.
+    async fn push(&self, message: Message) -> io::Result<()>;
+
+    /// Wait for the next message. Each message must be returned only to one consumer.
+    async fn pop(&self) -> io::Result<Message>;
+
+    /// The instance is done with a message returned by [`SharedQueue::pop()`]:
+    /// it was `processed` according to the [`AckMode`], or it could not be processed
+    /// (i.e. its service is down or the engine stopped) and should be popped again.
+    /// By default, nothing is done.
+    ///
+    /// [`AckMode`]: crate::engine::AckMode
+    async fn done(&self, message: &Message, processed: bool) -> io::Result<()> {
+        let _ = (message, processed);
+        Ok(())
+    }
+}
+
+/// In-memory [`SharedQueue`] that can be shared by several engines running in the same process.
+#[derive(Clone)]
+pub struct MemoryQueue {
+    sender: mpsc::UnboundedSender<Message>,
+    receiver: Arc<Mutex<mpsc::UnboundedReceiver<Message>>>,
+}
+
+impl Default for MemoryQueue {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        Self {
+            sender,
+            receiver: Arc::new(Mutex::new(receiver)),
+        }
+    }
+}
+
+#[async_trait]
+impl SharedQueue for MemoryQueue {
+    async fn push(&self, message: Message) -> io::Result<()> {
+        // The queue owns the receiver, so the channel is never closed.
+        self.sender.send(message).ok();
+        Ok(())
+    }
+
+    async fn pop(&self) -> io::Result<Message> {
+        let message = self.receiver.lock().await.recv().await;
+        Ok(message.expect("The queue owns the sender"))
+    }
+}
+
+/// [`SharedQueue`] backed by a Redis list.
+///
+/// The messages are pushed with `LPUSH` and popped with `BLMOVE` into a processing list
+/// of the consumer, so each message is consumed by only one instance.
+/// The message is removed from the processing list once the instance is done with it.
+/// The messages left in the processing list by a crash are moved back to the queue
+/// when the consumer starts again, so each instance must use its own
+/// [`RedisQueue::consumer()`] name.
+#[cfg(feature = "redis")]
+pub struct RedisQueue {
+    client: redis::Client,
+    key: String,
+    consumer: String,
+    push_connection: Mutex<Option<redis::aio::MultiplexedConnection>>,
+    pop_connection: Mutex<Option<redis::aio::MultiplexedConnection>>,
+    // Popped messages along with their data in the processing list.
+    in_flight: std::sync::Mutex<Vec<(Message, String)>>,
+    recovered: AtomicBool,
+}
+
+#[cfg(feature = "redis")]
+impl RedisQueue {
+    /// Creates a queue stored in the list `key` of the Redis server at `url`
+    /// (e.g. `redis://127.0.0.1/`).
+    pub fn new(url: &str, key: impl Into<String>) -> io::Result<Self> {
+        Ok(Self {
+            client: redis::Client::open(url).map_err(io::Error::other)?,
+            key: key.into(),
+            consumer: String::from("default"),
+            push_connection: Mutex::default(),
+            pop_connection: Mutex::default(),
+            in_flight: std::sync::Mutex::default(),
+            recovered: AtomicBool::new(false),
+        })
+    }
+
+    /// Name of this instance among the consumers of the queue. By default `"default"`.
+    /// Its messages being processed are kept in the list `<key>:processing:<consumer>`.
+    pub fn consumer(mut self, name: impl Into<String>) -> Self {
+        self.consumer = name.into();
+        self
+    }
+
+    fn processing_key(&self) -> String {
+        format!("{}:processing:{}", self.key, self.consumer)
+    }
+
+    async fn connection(
+        &self,
+        slot: &Mutex<Option<redis::aio::MultiplexedConnection>>,
+    ) -> io::Result<redis::aio::MultiplexedConnection> {
+        let mut slot = slot.lock().await;
+        if slot.is_none() {
+            let connection = self
+                .client
+                .get_multiplexed_async_connection()
+                .await
+                .map_err(io::Error::other)?;
+            *slot = Some(connection);
+        }
+        Ok(slot.clone().unwrap())
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl SharedQueue for RedisQueue {
+    async fn push(&self, message: Message) -> io::Result<()> {
+        use redis::AsyncCommands;
+
+        let data = wire::to_json(&message);
+        let mut connection = self.connection(&self.push_connection).await?;
+        let result: redis::RedisResult<()> = connection.lpush(&self.key, data).await;
+        if result.is_err() {
+            *self.push_connection.lock().await = None;
+        }
+        result.map_err(io::Error::other)
+    }
+
+    async fn pop(&self) -> io::Result<Message> {
+        use redis::{AsyncCommands, Direction};
+
+        let mut connection = self.connection(&self.pop_connection).await?;
+        let processing = self.processing_key();
+        let result: redis::RedisResult<String> = async {
+            if !self.recovered.load(Ordering::Relaxed) {
+                // Give back the messages left by a previous run of this consumer.
+                let mut recovered = 0;
+                loop {
+                    let moved: Option<String> = connection
+                        .lmove(&processing, &self.key, Direction::Left, Direction::Right)
+                        .await?;
+                    if moved.is_none() {
+                        break;
+                    }
+                    recovered += 1;
+                }
+                if recovered > 0 {
+                    log::warn!("{} unfinished messages returned to the queue", recovered);
+                }
+                self.recovered.store(true, Ordering::Relaxed);
+            }
+            connection
+                .blmove(
+                    &self.key,
+                    &processing,
+                    Direction::Right,
+                    Direction::Left,
+                    0.0,
+                )
+                .await
+        }
+        .await;
+
+        match result {
+            Ok(data) => match wire::from_json(&data) {
+                Ok(message) => {
+                    let entry = (message.clone(), data);
+                    self.in_flight.lock().unwrap().push(entry);
+                    Ok(message)
+                }
+                Err(err) => {
+                    // Never processable, so it is not kept in the processing list.
+                    connection
+                        .lrem::<_, _, ()>(&processing, 1, &data)
+                        .await
+                        .ok();
+                    Err(err.into())
+                }
+            },
+            Err(err) => {
+                *self.pop_connection.lock().await = None;
+                Err(io::Error::other(err))
+            }
+        }
+    }
+
+    async fn done(&self, message: &Message, processed: bool) -> io::Result<()> {
+        use redis::AsyncCommands;
+
+        let data = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.iter().position(|(popped, _)| popped == message) {
+                Some(index) => in_flight.swap_remove(index).1,
+                None => return Ok(()),
+            }
+        };
+
+        let mut connection = self.connection(&self.push_connection).await?;
+        let processing = self.processing_key();
+        let result: redis::RedisResult<()> = async {
+            if !processed {
+                connection.rpush::<_, _, ()>(&self.key, &data).await?;
+            }
+            connection.lrem(&processing, 1, &data).await
+        }
+        .await;
+        if result.is_err() {
+            *self.push_connection.lock().await = None;
+        }
+        result.map_err(io::Error::other)
+    }
+}
+
+/// [`SharedQueue`] that sends all the messages of a user to the same instance of the cluster,
+/// so stateful services always see the messages of a user in the same instance.
+///
+/// Each instance of the cluster owns a queue (e.g. a Redis list per instance) and all of them
+/// are registered as members in every instance.
+/// The instance for a message is chosen by consistent hashing of [`Message::user`],
+/// so adding or removing an instance only moves the users of a small part of the ring.
+///
+/// # Example
+/// ```rust
+/// use service_io::cluster::{MemoryQueue, StickyQueue};
+///
+/// let (queue_a, queue_b) = (MemoryQueue::default(), MemoryQueue::default());
+///
+/// // Cluster configuration for the instance "a".
+/// let queue = StickyQueue::new("a")
+///     .member("a", queue_a.clone())
+///     .member("b", queue_b.clone());
+/// ```
+pub struct StickyQueue {
+    local: String,
+    members: HashMap<String, Arc<dyn SharedQueue>>,
+    ring: BTreeMap<u64, String>,
+}
+
+impl StickyQueue {
+    const VIRTUAL_NODES: usize = 64;
+
+    /// Creates the queue for the instance identified by `local`.
+    /// The local instance must also be registered with [`StickyQueue::member()`].
+    pub fn new(local: impl Into<String>) -> Self {
+        Self {
+            local: local.into(),
+            members: HashMap::default(),
+            ring: BTreeMap::default(),
+        }
+    }
+
+    /// Register the `queue` of the instance `id`.
+    pub fn member(mut self, id: impl Into<String>, queue: impl SharedQueue + 'static) -> Self {
+        let id = id.into();
+        for node in 0..Self::VIRTUAL_NODES {
+            self.ring
+                .insert(hash(&format!("{}#{}", id, node)), id.clone());
+        }
+        self.members.insert(id, Arc::new(queue));
+        self
+    }
+
+    /// The instance that processes the messages of `user`.
+    pub fn instance_for(&self, user: &str) -> Option<&str> {
+        self.ring
+            .range(hash(user)..)
+            .next()
+            .or_else(|| self.ring.iter().next())
+            .map(|(_, id)| id.as_str())
+    }
+
+    fn queue(&self, id: &str) -> io::Result<&Arc<dyn SharedQueue>> {
+        self.members.get(id).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No member '{}' in the cluster", id),
+            )
+        })
+    }
+}
+
+#[async_trait]
+impl SharedQueue for StickyQueue {
+    async fn push(&self, message: Message) -> io::Result<()> {
+        let id = self.instance_for(&message.user).unwrap_or(&self.local);
+        self.queue(id)?.push(message).await
+    }
+
+    async fn pop(&self) -> io::Result<Message> {
+        self.queue(&self.local)?.pop().await
+    }
+
+    async fn done(&self, message: &Message, processed: bool) -> io::Result<()> {
+        self.queue(&self.local)?.done(message, processed).await
+    }
+}
+
+/// FNV-1a hash, stable among instances running different builds.
+pub(crate) fn hash(value: &str) -> u64 {
+    value.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(user: &str) -> Message {
+        Message::default().user(user).service_name("s-test")
+    }
+
+    #[tokio::test]
+    async fn sticky_routing() {
+        let (queue_a, queue_b) = (MemoryQueue::default(), MemoryQueue::default());
+        let instance_a = StickyQueue::new("a")
+            .member("a", queue_a.clone())
+            .member("b", queue_b.clone());
+        let instance_b = StickyQueue::new("b")
+            .member("a", queue_a)
+            .member("b", queue_b);
+
+        let users: Vec<String> = (0..20).map(|i| format!("user_{}", i)).collect();
+        for user in &users {
+            instance_a.push(message(user)).await.unwrap();
+            instance_b.push(message(user)).await.unwrap();
+        }
+
+        for (instance, id) in [(&instance_a, "a"), (&instance_b, "b")] {
+            let expected = users
+                .iter()
+                .filter(|user| instance.instance_for(user) == Some(id))
+                .count();
+            assert!(expected > 0);
+            for _ in 0..expected * 2 {
+                let message = instance.pop().await.unwrap();
+                assert_eq!(instance_a.instance_for(&message.user), Some(id));
+            }
+        }
+    }
+}
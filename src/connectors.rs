@@ -1,15 +1,132 @@
 //! Default connectors comming with `service-io`.
+//!
+//! All of them perform their blocking work out of the async runtime,
+//! so they can be used with both, `multi_thread` and `current_thread` tokio runtimes.
 
+mod broadcast;
 mod mpsc;
+mod watch;
+
+mod config;
+pub use config::{ConfigError, FieldError};
+
+// Its internals are only used by the connectors behind features.
+#[cfg_attr(not(feature = "email"), allow(dead_code))]
+pub(crate) mod proxy;
+pub use proxy::Proxy;
+
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+mod tls;
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+pub use tls::TlsBackend;
+
+mod generator;
+pub use generator::GeneratorInput;
+
+mod stream;
+pub use stream::{SinkOutput, StreamInput};
 
 mod stdin;
-pub use stdin::UserStdin;
+pub use stdin::{JsonStdin, UserStdin};
 
 mod stdout;
 pub use stdout::DebugStdout;
 
-mod imap;
-pub use self::imap::ImapClient;
+#[cfg(feature = "email")]
+pub(crate) mod imap;
+#[cfg(feature = "email")]
+pub use self::imap::{DefaultMailParser, ImapClient, MailParser, MailRouting, ParsedMail};
+
+#[cfg(feature = "email")]
+pub(crate) mod smtp;
+#[cfg(feature = "email")]
+pub use smtp::{
+    AttachmentDisposition, DefaultMailRenderer, MailBody, MailRenderer, SmtpClient,
+    MAIL_HEADER_PREFIX, MAIL_PRIORITY_KEY,
+};
+
+#[cfg(feature = "email")]
+mod email;
+#[cfg(feature = "email")]
+pub use email::Email;
+
+#[cfg(feature = "oauth2")]
+// Its internals are only used by the Gmail and Graph connectors.
+#[cfg_attr(not(feature = "email"), allow(dead_code))]
+mod oauth;
+#[cfg(feature = "oauth2")]
+pub use oauth::OAuth2;
+
+#[cfg(all(feature = "email", feature = "oauth2"))]
+mod gmail;
+#[cfg(all(feature = "email", feature = "oauth2"))]
+pub use gmail::{Gmail, GmailInput, GmailOutput};
+
+#[cfg(all(feature = "email", feature = "oauth2"))]
+mod graph;
+#[cfg(all(feature = "email", feature = "oauth2"))]
+pub use graph::{Graph, GraphInput, GraphOutput};
+
+#[cfg(feature = "http")]
+mod text;
+
+#[cfg(feature = "http")]
+mod sms;
+#[cfg(feature = "http")]
+pub use sms::{SmsInput, SmsOutput};
+
+#[cfg(feature = "http")]
+mod chat_webhook;
+#[cfg(feature = "http")]
+pub use chat_webhook::{ChatWebhookInput, ChatWebhookOutput};
+
+#[cfg(feature = "http")]
+mod github;
+#[cfg(feature = "http")]
+pub use github::{Github, GithubInput, GithubOutput, GITHUB_ISSUE_KEY};
+
+#[cfg(feature = "http")]
+mod push;
+#[cfg(feature = "http")]
+pub use push::{NtfyOutput, PushoverOutput};
+
+#[cfg(all(feature = "email", feature = "http"))]
+mod notifier;
+#[cfg(all(feature = "email", feature = "http"))]
+pub use notifier::Notifier;
+
+#[cfg_attr(not(feature = "http"), allow(dead_code))]
+pub(crate) mod aws;
+
+#[cfg(all(feature = "email", feature = "http"))]
+mod ses;
+#[cfg(all(feature = "email", feature = "http"))]
+pub use ses::SesOutput;
+
+#[cfg(feature = "bridge")]
+mod bridge;
+#[cfg(feature = "bridge")]
+pub use bridge::{BridgeInput, BridgeOutput};
+
+mod upload;
+pub use upload::UploadOutput;
+
+mod archive;
+pub use archive::{ArchiveFormat, ArchiveOutput};
+
+mod concurrent;
+pub use concurrent::ConcurrentOutput;
+
+mod fallback;
+pub use fallback::FallbackOutput;
+
+mod progress;
+pub use progress::{ProgressOutput, ProgressPolicy};
+
+mod failover;
+pub use failover::FailoverInput;
 
-mod smtp;
-pub use smtp::SmtpClient;
+#[cfg(feature = "markdown")]
+mod markdown;
+#[cfg(feature = "markdown")]
+pub use markdown::{MarkdownFormat, MarkdownOutput};
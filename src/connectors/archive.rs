@@ -0,0 +1,311 @@
+use crate::channel::{self, Receiver, Sender};
+use crate::error::Error;
+use crate::interface::OutputConnector;
+use crate::message::Message;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use serde_json::json;
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+const ATTACHMENTS_DIR: &str = "attachments";
+const CSV_HEADER: &str = "time,user,service_name,args,body,attachments\n";
+
+/// Format of the rows written by [`ArchiveOutput`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    /// A JSON object per line, with the fields of the message.
+    Jsonl,
+    /// A row per message with the columns: `time`, `user`, `service_name`, `args`
+    /// (separated by spaces), `body` and `attachments` (separated by `;`).
+    Csv,
+}
+
+impl ArchiveFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Jsonl => "jsonl",
+            ArchiveFormat::Csv => "csv",
+        }
+    }
+}
+
+/// Output middleware that appends each message to an archive in a directory before passing
+/// it to the wrapped output, keeping a record of everything the engine sent.
+///
+/// The messages are written in `messages-<N>.<jsonl|csv>` files, starting a new file once
+/// the current one reaches [`ArchiveOutput::max_file_size()`].
+/// The attachments are saved in the `attachments` subdirectory, and the rows reference them
+/// by their path relative to the archive directory.
+/// Failures writing the archive are logged, but the messages are delivered anyway.
+///
+/// # Example
+/// ```rust no_run
+/// use service_io::connectors::{ArchiveFormat, ArchiveOutput, ImapClient, SmtpClient};
+/// use service_io::engine::Engine;
+/// use service_io::services::Echo;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     Engine::default()
+///         .input(ImapClient::default() /* ... */)
+///         .output(
+///             ArchiveOutput::new(SmtpClient::default() /* ... */, "/var/lib/service-io/sent")
+///                 .format(ArchiveFormat::Csv)
+///                 .max_file_size(50_000_000),
+///         )
+///         .add_service("s-echo", Echo)
+///         .run()
+///         .await;
+/// }
+/// ```
+pub struct ArchiveOutput<O> {
+    output: O,
+    archive: Archive,
+}
+
+impl<O: OutputConnector + Send + 'static> ArchiveOutput<O> {
+    /// Wraps `output`, archiving its messages in the directory `dir`,
+    /// created if it does not exist.
+    pub fn new(output: O, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            output,
+            archive: Archive {
+                dir: dir.into(),
+                format: ArchiveFormat::Jsonl,
+                max_file_size: 10_000_000,
+                current: None,
+                sequence: 0,
+            },
+        }
+    }
+
+    /// Format of the archive files. By default, [`ArchiveFormat::Jsonl`].
+    pub fn format(mut self, format: ArchiveFormat) -> Self {
+        self.archive.format = format;
+        self
+    }
+
+    /// Size in bytes from which a new file is started. By default, 10 MB.
+    pub fn max_file_size(mut self, bytes: u64) -> Self {
+        self.archive.max_file_size = bytes;
+        self
+    }
+}
+
+#[async_trait]
+impl<O: OutputConnector + Send + 'static> OutputConnector for ArchiveOutput<O> {
+    async fn connect(&mut self) -> Result<(), Error> {
+        self.output.connect().await
+    }
+
+    fn describe(&self) -> String {
+        format!("ArchiveOutput({})", self.output.describe())
+    }
+
+    async fn run(self: Box<Self>, mut receiver: Receiver) -> Result<(), Error> {
+        let ArchiveOutput {
+            output,
+            mut archive,
+        } = *self;
+        let (sender, output_receiver) = channel::channel(1);
+
+        tokio::select! {
+            result = Box::new(output).run(output_receiver) => result,
+            result = forward(&mut archive, &mut receiver, &sender) => result,
+        }
+    }
+}
+
+async fn forward(
+    archive: &mut Archive,
+    receiver: &mut Receiver,
+    sender: &Sender,
+) -> Result<(), Error> {
+    loop {
+        let message = receiver.recv().await?;
+        if let Err(err) = archive.write(&message).await {
+            log::error!("Message for '{}' not archived: {}", message.user, err);
+        }
+        sender.send(message).await?;
+    }
+}
+
+/// File being written, with its index and size.
+struct Current {
+    file: File,
+    index: u32,
+    size: u64,
+}
+
+struct Archive {
+    dir: PathBuf,
+    format: ArchiveFormat,
+    max_file_size: u64,
+    current: Option<Current>,
+    /// Distinguishes the attachments saved in the same millisecond.
+    sequence: u64,
+}
+
+impl Archive {
+    async fn write(&mut self, message: &Message) -> io::Result<()> {
+        let attachments = self.save_attachments(message).await?;
+        let row = self.row(message, &attachments);
+
+        let mut current = match self.current.take() {
+            Some(current)
+                if current.size == 0 || current.size + row.len() as u64 <= self.max_file_size =>
+            {
+                current
+            }
+            Some(current) => self.open(current.index + 1).await?,
+            None => self.open(self.last_index().await?).await?,
+        };
+        if current.size == 0 && self.format == ArchiveFormat::Csv {
+            current.file.write_all(CSV_HEADER.as_bytes()).await?;
+            current.size += CSV_HEADER.len() as u64;
+        }
+        current.file.write_all(row.as_bytes()).await?;
+        current.file.flush().await?;
+        current.size += row.len() as u64;
+        self.current = Some(current);
+        Ok(())
+    }
+
+    /// Index of the last file of a previous run, to continue appending to it.
+    async fn last_index(&self) -> io::Result<u32> {
+        fs::create_dir_all(&self.dir).await?;
+        let mut entries = fs::read_dir(&self.dir).await?;
+        let mut last = 1;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name();
+            let index = name
+                .to_str()
+                .and_then(|name| name.strip_prefix("messages-"))
+                .and_then(|name| name.strip_suffix(&format!(".{}", self.format.extension())))
+                .and_then(|index| index.parse().ok());
+            last = last.max(index.unwrap_or(0));
+        }
+        Ok(last)
+    }
+
+    async fn open(&self, index: u32) -> io::Result<Current> {
+        let name = format!("messages-{:06}.{}", index, self.format.extension());
+        let path = self.dir.join(name);
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await?;
+        let size = file.metadata().await?.len();
+        log::info!("Archiving messages in {}", path.display());
+        Ok(Current { file, index, size })
+    }
+
+    /// Saves the attachments, returning their paths relative to the archive directory.
+    async fn save_attachments(&mut self, message: &Message) -> io::Result<Vec<String>> {
+        let mut paths = Vec::new();
+        for (name, data) in &message.attached_data {
+            self.sequence += 1;
+            let name = name.replace(['/', '\\'], "_");
+            let relative = Path::new(ATTACHMENTS_DIR).join(format!(
+                "{}-{}-{}",
+                Utc::now().timestamp_millis(),
+                self.sequence,
+                name
+            ));
+            fs::create_dir_all(self.dir.join(ATTACHMENTS_DIR)).await?;
+            fs::write(self.dir.join(&relative), data).await?;
+            paths.push(relative.to_string_lossy().into_owned());
+        }
+        paths.sort();
+        Ok(paths)
+    }
+
+    fn row(&self, message: &Message, attachments: &[String]) -> String {
+        let time = Utc::now().to_rfc3339();
+        match self.format {
+            ArchiveFormat::Jsonl => {
+                let row = json!({
+                    "time": time,
+                    "user": message.user,
+                    "service_name": message.service_name,
+                    "args": message.args,
+                    "body": message.body,
+                    "metadata": message.metadata,
+                    "attachments": attachments,
+                });
+                format!("{}\n", row)
+            }
+            ArchiveFormat::Csv => {
+                let columns = [
+                    time,
+                    message.user.clone(),
+                    message.service_name.clone(),
+                    message.args.join(" "),
+                    message.body.clone(),
+                    attachments.join(";"),
+                ];
+                let columns = columns.iter().map(|column| csv_field(column));
+                format!("{}\n", columns.collect::<Vec<_>>().join(","))
+            }
+        }
+    }
+}
+
+/// Quotes the field if it contains separators, quotes or line breaks.
+fn csv_field(field: &str) -> String {
+    match field.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn rotation_and_attachments() {
+        let dir = std::env::temp_dir().join(format!("service-io-archive-{}", std::process::id()));
+        fs::remove_dir_all(&dir).await.ok();
+        let (output_sender, mut output) = mpsc::channel(32);
+        let archive = ArchiveOutput::new(output_sender, &dir)
+            .format(ArchiveFormat::Csv)
+            .max_file_size(150);
+
+        let (sender, receiver) = channel::channel(32);
+        tokio::spawn(Box::new(archive).run(receiver));
+
+        let message = Message::default()
+            .user("user")
+            .body("hello, \"world\"")
+            .attach([("dir/a.txt", b"1234".to_vec())]);
+        for _ in 0..2 {
+            sender.send(message.clone()).await.unwrap();
+            assert_eq!(output.recv().await.unwrap(), message);
+        }
+
+        let first = fs::read_to_string(dir.join("messages-000001.csv"))
+            .await
+            .unwrap();
+        let second = fs::read_to_string(dir.join("messages-000002.csv"))
+            .await
+            .unwrap();
+        let row = first.lines().nth(1).unwrap();
+        assert!(first.starts_with(CSV_HEADER));
+        assert!(second.starts_with(CSV_HEADER));
+        assert!(row.contains(r#",user,,,"hello, ""world""",attachments/"#));
+
+        let attachment = row.rsplit(',').next().unwrap();
+        assert!(attachment.ends_with("-dir_a.txt"));
+        assert_eq!(fs::read(dir.join(attachment)).await.unwrap(), b"1234");
+        fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
@@ -0,0 +1,545 @@
+use super::tls::TlsBackend;
+use crate::channel::{Receiver, Sender};
+use crate::error::Error;
+use crate::interface::{InputConnector, OutputConnector};
+use crate::message::wire::{self, Compression};
+
+use async_trait::async_trait;
+use base64::engine::{general_purpose::STANDARD, Engine as _};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader,
+};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time;
+
+use std::io;
+use std::time::Duration;
+
+const RECONNECTION_TIME: Duration = Duration::from_secs(5);
+
+/// Maximum time for the remote bridge to prove it knows the key.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Maximum size of the lines of the handshake.
+const HANDSHAKE_LINE_SIZE: usize = 128;
+
+const DEFAULT_MAX_FRAME_SIZE: usize = 64 * 1024 * 1024;
+
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+/// Established and authenticated link.
+type Connection = BufReader<Box<dyn Stream>>;
+
+#[derive(Clone)]
+enum Link {
+    Connect(String),
+    Listen(String),
+}
+
+#[derive(Clone)]
+enum Tls {
+    None,
+    Client(String),
+    Server { certificate: Vec<u8>, key: Vec<u8> },
+}
+
+#[derive(Clone)]
+struct Bridge {
+    link: Link,
+    key: Vec<u8>,
+    tls: Tls,
+    tls_backend: TlsBackend,
+    compression: Option<Compression>,
+    max_frame_size: usize,
+    max_message_size: usize,
+}
+
+impl Bridge {
+    fn new(link: Link, key: String) -> Bridge {
+        Bridge {
+            link,
+            key: key.into_bytes(),
+            tls: Tls::None,
+            tls_backend: TlsBackend::default(),
+            compression: None,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            max_message_size: wire::MAX_DECOMPRESSED_SIZE,
+        }
+    }
+
+    async fn open(&self, listener: &mut Option<TcpListener>) -> io::Result<Connection> {
+        let tcp = self.open_tcp(listener).await?;
+
+        // Once connected, a peer that stalls the TLS negotiation or the handshake
+        // can not keep the link busy.
+        let opening = async {
+            let mut connection = BufReader::new(self.secure(tcp).await?);
+            self.handshake(&mut connection).await?;
+            Ok(connection)
+        };
+        match time::timeout(HANDSHAKE_TIMEOUT, opening).await {
+            Ok(result) => result,
+            Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, "handshake timeout")),
+        }
+    }
+
+    async fn open_tcp(&self, listener: &mut Option<TcpListener>) -> io::Result<TcpStream> {
+        match &self.link {
+            Link::Connect(addr) => TcpStream::connect(addr).await,
+            Link::Listen(addr) => {
+                if listener.is_none() {
+                    *listener = Some(TcpListener::bind(addr).await?);
+                }
+                Ok(listener.as_ref().unwrap().accept().await?.0)
+            }
+        }
+    }
+
+    async fn secure(&self, tcp: TcpStream) -> io::Result<Box<dyn Stream>> {
+        match &self.tls {
+            Tls::None => Ok(Box::new(tcp)),
+            Tls::Client(domain) => tls_connect(self.tls_backend, domain, tcp).await,
+            Tls::Server { certificate, key } => {
+                tls_accept(self.tls_backend, certificate, key, tcp).await
+            }
+        }
+    }
+
+    /// Open the link, retrying until it is established.
+    /// A listening bridge attends the next connection at once,
+    /// so a rejected peer does not delay the legitimate one.
+    async fn establish(&self, listener: &mut Option<TcpListener>) -> Connection {
+        loop {
+            match self.open(listener).await {
+                Ok(connection) => {
+                    log::info!("Bridge link established");
+                    break connection;
+                }
+                Err(err) => {
+                    log::error!("Bridge link: {}", err);
+                    if matches!(self.link, Link::Connect(_)) || listener.is_none() {
+                        time::sleep(RECONNECTION_TIME).await;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Both ends prove they know the key answering a random challenge of the other end,
+    /// so the key is never sent through the link.
+    async fn handshake(&self, connection: &mut Connection) -> io::Result<()> {
+        let (role, remote_role) = match self.link {
+            Link::Connect(_) => (b"connect", b"listen\0"),
+            Link::Listen(_) => (b"listen\0", b"connect"),
+        };
+
+        let challenge = rand::random::<[u8; 32]>();
+        write_line(connection, &STANDARD.encode(challenge)).await?;
+        let remote_challenge = read_handshake_line(connection).await?;
+
+        let proof = self.proof(role, &remote_challenge).finalize().into_bytes();
+        write_line(connection, &STANDARD.encode(proof)).await?;
+        let remote_proof = read_handshake_line(connection).await?;
+
+        self.proof(remote_role, &challenge)
+            .verify_slice(&remote_proof)
+            .map_err(|_| io::Error::new(io::ErrorKind::PermissionDenied, "wrong bridge key"))
+    }
+
+    fn proof(&self, role: &[u8], challenge: &[u8]) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).unwrap();
+        mac.update(role);
+        mac.update(challenge);
+        mac
+    }
+}
+
+async fn tls_connect(
+    backend: TlsBackend,
+    domain: &str,
+    tcp: TcpStream,
+) -> io::Result<Box<dyn Stream>> {
+    match backend {
+        #[cfg(feature = "native-tls")]
+        TlsBackend::NativeTls => {
+            let connector = native_tls::TlsConnector::new().map_err(io::Error::other)?;
+            let connector = tokio_native_tls::TlsConnector::from(connector);
+            let stream = connector
+                .connect(domain, tcp)
+                .await
+                .map_err(io::Error::other)?;
+            Ok(Box::new(stream))
+        }
+        #[cfg(feature = "rustls")]
+        TlsBackend::Rustls => {
+            let name = rustls::pki_types::ServerName::try_from(domain.to_string())
+                .map_err(io::Error::other)?;
+            let connector = tokio_rustls::TlsConnector::from(super::tls::rustls_config());
+            Ok(Box::new(connector.connect(name, tcp).await?))
+        }
+    }
+}
+
+async fn tls_accept(
+    backend: TlsBackend,
+    certificate: &[u8],
+    key: &[u8],
+    tcp: TcpStream,
+) -> io::Result<Box<dyn Stream>> {
+    match backend {
+        #[cfg(feature = "native-tls")]
+        TlsBackend::NativeTls => {
+            let identity =
+                native_tls::Identity::from_pkcs8(certificate, key).map_err(io::Error::other)?;
+            let acceptor = native_tls::TlsAcceptor::new(identity).map_err(io::Error::other)?;
+            let acceptor = tokio_native_tls::TlsAcceptor::from(acceptor);
+            let stream = acceptor.accept(tcp).await.map_err(io::Error::other)?;
+            Ok(Box::new(stream))
+        }
+        #[cfg(feature = "rustls")]
+        TlsBackend::Rustls => {
+            use rustls::pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer};
+
+            let certificates = CertificateDer::pem_slice_iter(certificate)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(io::Error::other)?;
+            let key = PrivateKeyDer::from_pem_slice(key).map_err(io::Error::other)?;
+            let provider = rustls::crypto::ring::default_provider();
+            let config = rustls::ServerConfig::builder_with_provider(provider.into())
+                .with_safe_default_protocol_versions()
+                .map_err(io::Error::other)?
+                .with_no_client_auth()
+                .with_single_cert(certificates, key)
+                .map_err(io::Error::other)?;
+            let acceptor = tokio_rustls::TlsAcceptor::from(std::sync::Arc::new(config));
+            Ok(Box::new(acceptor.accept(tcp).await?))
+        }
+    }
+}
+
+async fn write_line(connection: &mut Connection, line: &str) -> io::Result<()> {
+    connection.write_all(line.as_bytes()).await?;
+    connection.write_all(b"\n").await?;
+    connection.flush().await
+}
+
+async fn read_handshake_line(connection: &mut Connection) -> io::Result<Vec<u8>> {
+    let line = read_line(connection, HANDSHAKE_LINE_SIZE).await?;
+    let line = line.ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+    STANDARD
+        .decode(line)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid handshake"))
+}
+
+/// Reads a line of at most `max_size` bytes, without the line break.
+/// Returns `None` at the end of the stream.
+async fn read_line(
+    reader: &mut (impl AsyncBufRead + Unpin),
+    max_size: usize,
+) -> io::Result<Option<String>> {
+    let mut line = Vec::new();
+    let limit = max_size as u64 + 1;
+    (&mut *reader)
+        .take(limit)
+        .read_until(b'\n', &mut line)
+        .await?;
+    match line.pop() {
+        None => Ok(None),
+        Some(b'\n') => String::from_utf8(line)
+            .map(Some)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+        Some(_) if line.len() >= max_size => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("line larger than {} bytes", max_size),
+        )),
+        Some(_) => Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+    }
+}
+
+macro_rules! bridge_builder {
+    ($name:ident) => {
+        impl $name {
+            /// The link is established connecting to the remote bridge listening in `addr`.
+            /// The connection is retried if it fails.
+            ///
+            /// Both bridges must use the same `key`, a long random secret.
+            pub fn connect(addr: impl Into<String>, key: impl Into<String>) -> Self {
+                $name(Bridge::new(Link::Connect(addr.into()), key.into()))
+            }
+
+            /// The link is established when the remote bridge connects to `addr`.
+            /// Only one connection is attended at the same time.
+            /// The connections that do not prove to know the same `key` are rejected.
+            pub fn listen(addr: impl Into<String>, key: impl Into<String>) -> Self {
+                $name(Bridge::new(Link::Listen(addr.into()), key.into()))
+            }
+
+            /// Use TLS as client validating the certificate of the remote `domain`.
+            /// Use it along with [`Self::connect()`].
+            pub fn tls_client(mut self, domain: impl Into<String>) -> Self {
+                self.0.tls = Tls::Client(domain.into());
+                self
+            }
+
+            /// Use TLS as server with a `certificate` chain and its PKCS #8 private `key`,
+            /// both in PEM format.
+            /// Use it along with [`Self::listen()`].
+            pub fn tls_server(
+                mut self,
+                certificate: impl Into<Vec<u8>>,
+                key: impl Into<Vec<u8>>,
+            ) -> Self {
+                self.0.tls = Tls::Server {
+                    certificate: certificate.into(),
+                    key: key.into(),
+                };
+                self
+            }
+
+            /// TLS implementation used along with [`Self::tls_client()`] or
+            /// [`Self::tls_server()`]. By default, [`TlsBackend::default()`].
+            pub fn tls_backend(mut self, backend: TlsBackend) -> Self {
+                self.0.tls_backend = backend;
+                self
+            }
+        }
+    };
+}
+
+/// Input connector that receives the messages sent by a remote [`BridgeOutput`]
+/// through a TCP link (optionally over TLS).
+///
+/// It allows to link two engines, for example, to forward some services from an
+/// internet-facing engine to an engine running inside a home network that can not be
+/// reached from the internet:
+///
+/// ```rust no_run
+/// use service_io::connectors::{BridgeInput, BridgeOutput, ImapClient, SmtpClient};
+/// use service_io::engine::Engine;
+/// use service_io::services::{Echo, Process};
+///
+/// async fn internet_engine() {
+///     Engine::default()
+///         .input(ImapClient::default() /* ... */)
+///         // Requests are forwarded to the home engine once connected,
+///         // i.e. through an SSH tunnel to this port.
+///         .output(BridgeOutput::listen("127.0.0.1:4000", "<long random key>"))
+///         .add_service("s-process", Echo)
+///         .run()
+///         .await;
+/// }
+///
+/// async fn home_engine() {
+///     Engine::default()
+///         .input(BridgeInput::connect("127.0.0.1:4000", "<long random key>"))
+///         // The home engine replies directly to the users.
+///         .output(SmtpClient::default() /* ... */)
+///         .add_service("s-process", Process)
+///         .run()
+///         .await;
+/// }
+/// ```
+///
+/// The messages are sent as JSON lines with the [`wire`] format of [`Message`],
+/// so engines running different versions of `service-io` can be linked.
+///
+/// Before sending any message, both ends prove they know the same key,
+/// so a peer without it can neither inject messages nor receive them.
+/// Prefer listening in a local address, or use TLS, since the messages are not encrypted
+/// otherwise.
+///
+/// [`Message`]: crate::message::Message
+#[derive(Clone)]
+pub struct BridgeInput(Bridge);
+
+bridge_builder!(BridgeInput);
+
+impl BridgeInput {
+    /// Maximum size of a received message, as sent through the link.
+    /// The link is closed if exceeded. By default, 64MB.
+    pub fn max_frame_size(mut self, bytes: usize) -> Self {
+        self.0.max_frame_size = bytes;
+        self
+    }
+
+    /// Maximum size of a received compressed message once decompressed.
+    /// The message is discarded if exceeded. By default, 64MB.
+    pub fn max_message_size(mut self, bytes: usize) -> Self {
+        self.0.max_message_size = bytes;
+        self
+    }
+}
+
+#[async_trait]
+impl InputConnector for BridgeInput {
+    async fn run(self: Box<Self>, sender: Sender) -> Result<(), Error> {
+        let mut listener = None;
+        loop {
+            let mut connection = self.0.establish(&mut listener).await;
+            loop {
+                match read_line(&mut connection, self.0.max_frame_size).await {
+                    Ok(Some(line)) => match wire::from_json_limited(&line, self.0.max_message_size)
+                    {
+                        Ok(message) => sender.send(message).await?,
+                        Err(err) => log::error!("Bridge received an invalid message: {}", err),
+                    },
+                    Ok(None) => {
+                        log::warn!("Bridge link closed");
+                        break;
+                    }
+                    Err(err) => {
+                        log::error!("Bridge link: {}", err);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Output connector that sends the messages to a remote [`BridgeInput`]
+/// through a TCP link (optionally over TLS).
+///
+/// The link is established when the connector starts.
+/// If the link is down, the messages wait until it is established again.
+///
+/// See [`BridgeInput`] for more information.
+#[derive(Clone)]
+pub struct BridgeOutput(Bridge);
+
+bridge_builder!(BridgeOutput);
+
+impl BridgeOutput {
+    /// Compress the big messages, i.e. with attachments, to save bandwidth.
+    /// The remote [`BridgeInput`] decompresses them automatically.
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.0.compression = Some(compression);
+        self
+    }
+}
+
+#[async_trait]
+impl OutputConnector for BridgeOutput {
+    async fn run(self: Box<Self>, mut receiver: Receiver) -> Result<(), Error> {
+        let mut listener = None;
+        let mut stream = Some(self.0.establish(&mut listener).await);
+        loop {
+            let message = receiver.recv().await?;
+            let mut line = match self.0.compression {
+                Some(compression) => wire::to_json_compressed(&message, compression),
+                None => wire::to_json(&message),
+            };
+            line.push('\n');
+
+            loop {
+                let link = match &mut stream {
+                    Some(link) => link,
+                    None => stream.insert(self.0.establish(&mut listener).await),
+                };
+
+                // Flushed, so the message does not wait in the buffer of the TLS stream.
+                let written = async {
+                    link.write_all(line.as_bytes()).await?;
+                    link.flush().await
+                };
+                match written.await {
+                    Ok(()) => break,
+                    Err(err) => {
+                        log::error!("Bridge link: {}", err);
+                        stream = None;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel;
+    use crate::message::{wire, Message};
+
+    const KEY: &str = "1234567890abcdef";
+
+    async fn free_addr() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn round_trip() {
+        let addr = free_addr().await;
+        let (output_sender, output_receiver) = channel::channel(4);
+        let output = BridgeOutput::listen(&addr, KEY).compression(Compression::zstd(0));
+        tokio::spawn(Box::new(output).run(output_receiver));
+        time::sleep(Duration::from_millis(100)).await;
+
+        let (input_sender, mut input_receiver) = channel::channel(4);
+        let input = BridgeInput::connect(&addr, KEY);
+        tokio::spawn(Box::new(input).run(input_sender));
+
+        let message = Message::default()
+            .user("user")
+            .service_name("s-echo")
+            .attach([("file", b"1234".to_vec())]);
+        output_sender.send(message.clone()).await.unwrap();
+        let received = time::timeout(Duration::from_secs(5), input_receiver.recv()).await;
+        assert_eq!(received.unwrap().unwrap(), message);
+    }
+
+    #[tokio::test]
+    async fn wrong_key() {
+        let addr = free_addr().await;
+        let (output_sender, output_receiver) = channel::channel(4);
+        tokio::spawn(Box::new(BridgeOutput::listen(&addr, KEY)).run(output_receiver));
+        time::sleep(Duration::from_millis(100)).await;
+
+        let (wrong_sender, mut wrong_receiver) = channel::channel(4);
+        let wrong = BridgeInput::connect(&addr, "other key");
+        tokio::spawn(Box::new(wrong).run(wrong_sender));
+        time::sleep(Duration::from_millis(200)).await;
+
+        let (input_sender, mut input_receiver) = channel::channel(4);
+        tokio::spawn(Box::new(BridgeInput::connect(&addr, KEY)).run(input_sender));
+
+        let message = Message::default().user("user").body("secret");
+        output_sender.send(message.clone()).await.unwrap();
+        let received = time::timeout(Duration::from_secs(5), input_receiver.recv()).await;
+        assert_eq!(received.unwrap().unwrap(), message);
+        assert!(wrong_receiver.0.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn unauthenticated_peer() {
+        let addr = free_addr().await;
+        let (input_sender, mut input_receiver) = channel::channel(4);
+        tokio::spawn(Box::new(BridgeInput::listen(&addr, KEY)).run(input_sender));
+        time::sleep(Duration::from_millis(100)).await;
+
+        // A peer writing messages without the handshake.
+        let mut peer = TcpStream::connect(&addr).await.unwrap();
+        let message = Message::default().user("admin").service_name("s-process");
+        let line = format!("{}\n{}\n", wire::to_json(&message), wire::to_json(&message));
+        peer.write_all(line.as_bytes()).await.unwrap();
+
+        let received = time::timeout(Duration::from_millis(300), input_receiver.recv()).await;
+        assert!(received.is_err());
+    }
+
+    #[tokio::test]
+    async fn frame_size() {
+        let mut reader = &b"123\n12345\n"[..];
+        assert_eq!(read_line(&mut reader, 4).await.unwrap().unwrap(), "123");
+        let err = read_line(&mut reader, 4).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut reader = &b"12"[..];
+        let err = read_line(&mut reader, 4).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(read_line(&mut reader, 4).await.unwrap(), None);
+    }
+}
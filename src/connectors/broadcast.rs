@@ -0,0 +1,34 @@
+use crate::channel::{Receiver, Sender};
+use crate::error::Error;
+use crate::interface::{InputConnector, OutputConnector};
+use crate::message::Message;
+
+use async_trait::async_trait;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+#[async_trait]
+impl InputConnector for broadcast::Receiver<Message> {
+    async fn run(mut self: Box<Self>, sender: Sender) -> Result<(), Error> {
+        loop {
+            match self.recv().await {
+                Ok(message) => sender.send(message).await?,
+                Err(RecvError::Lagged(skipped)) => {
+                    log::warn!("Broadcast input lagged: {} messages skipped", skipped)
+                }
+                Err(RecvError::Closed) => break Ok(()),
+            };
+        }
+    }
+}
+
+/// The messages are broadcasted to all the current subscribers.
+/// If there is no subscriber, the message is discarded.
+#[async_trait]
+impl OutputConnector for broadcast::Sender<Message> {
+    async fn run(self: Box<Self>, mut receiver: Receiver) -> Result<(), Error> {
+        loop {
+            let message = receiver.recv().await?;
+            self.send(message).ok();
+        }
+    }
+}
@@ -0,0 +1,375 @@
+use super::proxy::{http_client, Proxy};
+use super::text::{message_to_text, text_to_message};
+use crate::channel::{ChannelEnd, ClosedChannel, Receiver, Sender};
+use crate::engine::{ConnectorKind, DeliveryReport, EngineHandle, Event};
+use crate::error::Error;
+use crate::interface::{InputConnector, OutputConnector};
+use crate::message::Message;
+use crate::services::otp;
+
+use async_trait::async_trait;
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::routing::post;
+use axum::Router;
+use serde::Deserialize;
+use serde_json::json;
+
+use std::sync::Arc;
+
+/// Fields shared by the Mattermost and Rocket.Chat outgoing webhooks.
+#[derive(Deserialize)]
+struct OutgoingWebhook {
+    #[serde(default)]
+    token: String,
+    user_name: String,
+    text: String,
+}
+
+struct WebhookState {
+    sender: Sender,
+    token: Option<String>,
+    trigger_word: Option<String>,
+    engine: Option<EngineHandle>,
+}
+
+/// Input connector that receives the messages of a Mattermost or Rocket.Chat outgoing webhook.
+///
+/// It runs an HTTP server listening in the given address,
+/// that must be configured as callback URL of the outgoing webhook.
+/// Both, JSON and form encoded requests are accepted.
+///
+/// The name of the chat user is the user.
+/// The first line of the text (after the trigger word, if any) is interpreted as the service name
+/// followed by the spaced-separated arguments, and the following lines are the body.
+///
+/// Without [`ChatWebhookInput::token()`] anyone reaching the server could impersonate any user.
+#[derive(Clone)]
+pub struct ChatWebhookInput {
+    addr: String,
+    path: String,
+    token: Option<String>,
+    trigger_word: Option<String>,
+}
+
+impl ChatWebhookInput {
+    /// Listen the webhook requests in `addr` (e.g. `0.0.0.0:8080`).
+    pub fn new(addr: impl Into<String>) -> Self {
+        Self {
+            addr: addr.into(),
+            path: "/chat".into(),
+            token: None,
+            trigger_word: None,
+        }
+    }
+
+    /// Path of the webhook. By default `/chat`.
+    pub fn path(mut self, value: impl Into<String>) -> Self {
+        self.path = value.into();
+        self
+    }
+
+    /// Only accept requests with the token generated by the chat for the outgoing webhook.
+    pub fn token(mut self, value: impl Into<String>) -> Self {
+        self.token = Some(value.into());
+        self
+    }
+
+    /// Trigger word configured in the outgoing webhook, removed from the beginning of the text.
+    pub fn trigger_word(mut self, value: impl Into<String>) -> Self {
+        self.trigger_word = Some(value.into());
+        self
+    }
+}
+
+async fn webhook(
+    State(state): State<Arc<WebhookState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<(), StatusCode> {
+    let is_json = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.starts_with("application/json"))
+        .unwrap_or(false);
+
+    let request = match is_json {
+        true => serde_json::from_slice::<OutgoingWebhook>(&body).ok(),
+        false => serde_urlencoded::from_bytes::<OutgoingWebhook>(&body).ok(),
+    }
+    .ok_or(StatusCode::BAD_REQUEST)?;
+
+    if let Some(token) = &state.token {
+        if !otp::constant_time_eq(token, &request.token) {
+            log::warn!("Discarded chat webhook request with an invalid token");
+            if let Some(engine) = &state.engine {
+                engine.emit(Event::AuthFailed {
+                    connector: ConnectorKind::Input,
+                    error: "Invalid chat webhook token".into(),
+                });
+            }
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+
+    let text = match &state.trigger_word {
+        Some(word) => {
+            let text = request.text.trim_start();
+            text.strip_prefix(word.as_str()).unwrap_or(text)
+        }
+        None => request.text.as_str(),
+    };
+
+    if let Some(message) = text_to_message(request.user_name, text.trim_start()) {
+        state
+            .sender
+            .send(message)
+            .await
+            .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?;
+    }
+
+    Ok(())
+}
+
+#[async_trait]
+impl InputConnector for ChatWebhookInput {
+    async fn run(self: Box<Self>, sender: Sender) -> Result<(), Error> {
+        let state = Arc::new(WebhookState {
+            sender,
+            token: self.token,
+            trigger_word: self.trigger_word,
+            engine: EngineHandle::current(),
+        });
+
+        let app = Router::new()
+            .route(&self.path, post(webhook))
+            .with_state(state.clone());
+
+        let listener = tokio::net::TcpListener::bind(&self.addr)
+            .await
+            .map_err(|err| Error::connector("Chat webhook", err))?;
+        log::info!("Listening chat webhook at {}{}", self.addr, self.path);
+
+        tokio::select! {
+            result = axum::serve(listener, app) => {
+                result.map_err(|err| Error::connector("Chat webhook", err))
+            }
+            _ = state.sender.0.closed() => Err(ClosedChannel::new(ChannelEnd::Receiver).into()),
+        }
+    }
+}
+
+/// Output connector that posts the messages through a Mattermost or Rocket.Chat
+/// incoming webhook.
+///
+/// By default, the messages are sent as direct messages to the user.
+/// The text contains the service name and the arguments in the first line,
+/// and the body in the following lines. The attached data is not sent.
+#[derive(Clone)]
+pub struct ChatWebhookOutput {
+    url: String,
+    channel: Option<String>,
+    proxy: Option<Proxy>,
+}
+
+impl ChatWebhookOutput {
+    /// Post the messages to the incoming webhook `url`.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            channel: None,
+            proxy: None,
+        }
+    }
+
+    /// Reach the API through a proxy.
+    /// By default, the proxy of the environment is used, if any. See [`Proxy::from_env()`].
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Post the messages in a `channel` mentioning the user, instead of as direct messages.
+    pub fn channel(mut self, value: impl Into<String>) -> Self {
+        self.channel = Some(value.into());
+        self
+    }
+}
+
+#[async_trait]
+impl OutputConnector for ChatWebhookOutput {
+    async fn run(self: Box<Self>, mut receiver: Receiver) -> Result<(), Error> {
+        let http = http_client(self.proxy.as_ref())
+            .build()
+            .map_err(|err| Error::connector("Chat webhook", err))?;
+
+        loop {
+            let message = receiver.recv().await?;
+            let payload = payload(self.channel.as_deref(), &message);
+
+            let result = http
+                .post(&self.url)
+                .json(&payload)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+
+            match result {
+                Ok(_) => receiver.report(DeliveryReport::delivered(&message)),
+                Err(err) => {
+                    log::error!("Sending error: {}", err);
+                    receiver.report(DeliveryReport::failed(message, err));
+                }
+            }
+        }
+    }
+}
+
+/// Body of the incoming webhook request.
+fn payload(channel: Option<&str>, message: &Message) -> serde_json::Value {
+    let text = message_to_text(message);
+    match channel {
+        Some(channel) => json!({
+            "channel": channel,
+            "text": format!("@{} {}", message.user, text),
+        }),
+        None => json!({ "channel": format!("@{}", message.user), "text": text }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel;
+
+    fn state(
+        token: Option<&str>,
+        trigger_word: Option<&str>,
+        engine: Option<EngineHandle>,
+    ) -> (Arc<WebhookState>, Receiver) {
+        let (sender, receiver) = channel::channel(4);
+        let state = WebhookState {
+            sender,
+            token: token.map(|token| token.into()),
+            trigger_word: trigger_word.map(|word| word.into()),
+            engine,
+        };
+        (Arc::new(state), receiver)
+    }
+
+    fn headers(content_type: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, content_type.parse().unwrap());
+        headers
+    }
+
+    #[tokio::test]
+    async fn json_request() {
+        let (state, mut receiver) = state(Some("secret"), None, None);
+        let body = json!({
+            "token": "secret",
+            "user_name": "user",
+            "text": "s-echo a b\nhello",
+        });
+
+        let result = webhook(
+            State(state),
+            headers("application/json"),
+            Bytes::from(body.to_string()),
+        )
+        .await;
+        assert_eq!(result, Ok(()));
+
+        let message = receiver.recv().await.unwrap();
+        assert_eq!(message.user, "user");
+        assert_eq!(message.service_name, "s-echo");
+        assert_eq!(message.args, ["a", "b"]);
+        assert_eq!(message.body, "hello");
+    }
+
+    #[tokio::test]
+    async fn form_request() {
+        let (state, mut receiver) = state(None, Some("bot"), None);
+        let body = "user_name=user&text=bot+s-echo+a";
+
+        let result = webhook(
+            State(state),
+            headers("application/x-www-form-urlencoded"),
+            Bytes::from(body),
+        )
+        .await;
+        assert_eq!(result, Ok(()));
+
+        let message = receiver.recv().await.unwrap();
+        assert_eq!(message.user, "user");
+        assert_eq!(message.service_name, "s-echo");
+        assert_eq!(message.args, ["a"]);
+    }
+
+    #[tokio::test]
+    async fn trigger_word_once() {
+        let (state, mut receiver) = state(None, Some("!svc"), None);
+        let body = json!({ "user_name": "user", "text": "!svc!svc a" });
+
+        let result = webhook(
+            State(state),
+            headers("application/json"),
+            Bytes::from(body.to_string()),
+        )
+        .await;
+        assert_eq!(result, Ok(()));
+
+        let message = receiver.recv().await.unwrap();
+        assert_eq!(message.service_name, "!svc");
+        assert_eq!(message.args, ["a"]);
+    }
+
+    #[tokio::test]
+    async fn invalid_request() {
+        let (state, _receiver) = state(None, None, None);
+        let result = webhook(State(state), headers("application/json"), Bytes::from("{")).await;
+        assert_eq!(result, Err(StatusCode::BAD_REQUEST));
+    }
+
+    #[tokio::test]
+    async fn invalid_token() {
+        let engine = EngineHandle::default();
+        let mut events = engine.events();
+        let (state, _receiver) = state(Some("secret"), None, Some(engine));
+        let body = json!({ "token": "other", "user_name": "user", "text": "s-echo" });
+
+        let result = webhook(
+            State(state),
+            headers("application/json"),
+            Bytes::from(body.to_string()),
+        )
+        .await;
+        assert_eq!(result, Err(StatusCode::FORBIDDEN));
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            Event::AuthFailed {
+                connector: ConnectorKind::Input,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn output_payload() {
+        let message = Message::default()
+            .user("user")
+            .service_name("s-echo")
+            .args(["a"])
+            .body("hello");
+
+        assert_eq!(
+            payload(None, &message),
+            json!({ "channel": "@user", "text": "s-echo a\nhello" })
+        );
+        assert_eq!(
+            payload(Some("town-square"), &message),
+            json!({ "channel": "town-square", "text": "@user s-echo a\nhello" })
+        );
+    }
+}
@@ -0,0 +1,201 @@
+use crate::channel::{Receiver, Sender};
+use crate::error::Error;
+use crate::interface::OutputConnector;
+
+use async_trait::async_trait;
+use tokio::sync::mpsc;
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// Output middleware that delivers several messages at the same time,
+/// running `concurrency` copies of the wrapped output.
+/// A slow delivery (i.e. a big email through SMTP) no longer blocks the rest of replies.
+///
+/// By default, the deliveries to each user are serialized: a user is always served
+/// by the same copy, so the messages to a user are delivered in the order the services
+/// sent them. See [`ConcurrentOutput::ordered()`].
+///
+/// # Example
+/// ```rust no_run
+/// use service_io::connectors::{ConcurrentOutput, ImapClient, SmtpClient};
+/// use service_io::engine::Engine;
+/// use service_io::services::Process;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     Engine::default()
+///         .input(ImapClient::default() /* ... */)
+///         .output(ConcurrentOutput::new(SmtpClient::default() /* ... */, 4))
+///         .add_service("s-process", Process)
+///         .run()
+///         .await;
+/// }
+/// ```
+pub struct ConcurrentOutput<O> {
+    output: O,
+    concurrency: usize,
+    ordered: bool,
+}
+
+impl<O: OutputConnector + Clone + Send + 'static> ConcurrentOutput<O> {
+    /// Wraps `output` to deliver up to `concurrency` messages at the same time.
+    pub fn new(output: O, concurrency: usize) -> Self {
+        Self {
+            output,
+            concurrency: concurrency.max(1),
+            ordered: true,
+        }
+    }
+
+    /// Keep the order of the messages to the same user. Enabled by default.
+    ///
+    /// If disabled, each message is delivered by any free copy of the output.
+    /// The load is better balanced, but a message can overtake a previous one to the same user
+    /// (i.e. a short reply sent before a long report that is still being delivered).
+    pub fn ordered(mut self, enabled: bool) -> Self {
+        self.ordered = enabled;
+        self
+    }
+}
+
+#[async_trait]
+impl<O: OutputConnector + Clone + Send + 'static> OutputConnector for ConcurrentOutput<O> {
+    async fn connect(&mut self) -> Result<(), Error> {
+        self.output.connect().await
+    }
+
+    fn describe(&self) -> String {
+        format!("ConcurrentOutput({})", self.output.describe())
+    }
+
+    async fn run(self: Box<Self>, mut receiver: Receiver) -> Result<(), Error> {
+        let (senders, workers): (Vec<_>, Vec<_>) = (0..self.concurrency)
+            .map(|_| {
+                let (sender, worker_receiver) = mpsc::channel(1);
+                let worker_receiver =
+                    Receiver(worker_receiver, receiver.cancellation_token(), None);
+                let worker = Box::new(self.output.clone()).run(worker_receiver);
+                (Sender(sender), worker)
+            })
+            .unzip();
+
+        tokio::select! {
+            (result, _, _) = futures::future::select_all(workers) => result,
+            result = forward(&mut receiver, &senders, self.ordered) => result,
+        }
+    }
+}
+
+async fn forward(receiver: &mut Receiver, senders: &[Sender], ordered: bool) -> Result<(), Error> {
+    let mut next = 0;
+    loop {
+        let message = receiver.recv().await?;
+        if ordered {
+            let sender = &senders[worker(&message.user, senders.len())];
+            sender.send(message).await?;
+            continue;
+        }
+
+        // The first free copy, starting from the next one to rotate the load.
+        let free = (0..senders.len())
+            .map(|offset| (next + offset) % senders.len())
+            .find_map(|index| {
+                senders[index]
+                    .0
+                    .try_reserve()
+                    .ok()
+                    .map(|permit| (index, permit))
+            });
+
+        match free {
+            Some((index, permit)) => {
+                permit.send(message);
+                next = index + 1;
+            }
+            None => {
+                senders[next % senders.len()].send(message).await?;
+                next += 1;
+            }
+        }
+    }
+}
+
+/// Copy of the output serving the user.
+fn worker(user: &str, workers: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    user.hash(&mut hasher);
+    (hasher.finish() % workers as u64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::{ChannelEnd, ClosedChannel};
+    use crate::message::Message;
+
+    use std::time::Duration;
+
+    /// Output that takes as many milliseconds to deliver a message as its body says.
+    #[derive(Clone)]
+    struct SlowOutput(mpsc::Sender<Message>);
+
+    #[async_trait]
+    impl OutputConnector for SlowOutput {
+        async fn run(self: Box<Self>, mut receiver: Receiver) -> Result<(), Error> {
+            loop {
+                let message = receiver.recv().await?;
+                let millis = message.body.parse().unwrap();
+                tokio::time::sleep(Duration::from_millis(millis)).await;
+                self.0
+                    .send(message)
+                    .await
+                    .map_err(|_| ClosedChannel::new(ChannelEnd::Receiver))?;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn concurrent_delivery() {
+        let users = (0..).map(|index| format!("user_{}", index));
+        let slow_user = "user_slow".to_string();
+        let fast_user = users
+            .take(100)
+            .find(|user| worker(user, 2) != worker(&slow_user, 2))
+            .unwrap();
+
+        let (delivered_sender, mut delivered) = mpsc::channel(32);
+        let (sender, receiver) = crate::channel::channel(32);
+        tokio::spawn(
+            Box::new(ConcurrentOutput::new(SlowOutput(delivered_sender), 2)).run(receiver),
+        );
+
+        for (user, body) in [(&slow_user, "200"), (&slow_user, "0"), (&fast_user, "0")] {
+            let message = Message::default().user(user.as_str()).body(body);
+            sender.send(message).await.unwrap();
+        }
+
+        let order = [&fast_user, &slow_user, &slow_user];
+        for (user, body) in order.into_iter().zip(["0", "200", "0"]) {
+            let message = delivered.recv().await.unwrap();
+            assert_eq!((&message.user, message.body.as_str()), (user, body));
+        }
+    }
+
+    #[tokio::test]
+    async fn unordered_delivery() {
+        let (delivered_sender, mut delivered) = mpsc::channel(32);
+        let (sender, receiver) = crate::channel::channel(32);
+        let output = ConcurrentOutput::new(SlowOutput(delivered_sender), 2).ordered(false);
+        tokio::spawn(Box::new(output).run(receiver));
+
+        for body in ["200", "0"] {
+            let message = Message::default().user("user").body(body);
+            sender.send(message).await.unwrap();
+        }
+
+        // The second message overtakes the first one.
+        assert_eq!(delivered.recv().await.unwrap().body, "0");
+        assert_eq!(delivered.recv().await.unwrap().body, "200");
+    }
+}
@@ -0,0 +1,85 @@
+use std::fmt;
+
+/// Problem found in a field of a connector configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldError {
+    /// The field was not set or is empty.
+    Missing(&'static str),
+
+    /// The field has an invalid value.
+    Malformed { field: &'static str, reason: String },
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FieldError::Missing(field) => write!(f, "missing {}", field),
+            FieldError::Malformed { field, reason } => write!(f, "malformed {}: {}", field, reason),
+        }
+    }
+}
+
+/// Error validating the configuration of a connector, listing all the problems found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigError {
+    pub connector: &'static str,
+    pub errors: Vec<FieldError>,
+}
+
+impl ConfigError {
+    pub(crate) fn check(connector: &'static str, errors: Vec<FieldError>) -> Result<(), Self> {
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(ConfigError { connector, errors }),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let errors = self
+            .errors
+            .iter()
+            .map(|error| error.to_string())
+            .collect::<Vec<_>>();
+
+        write!(
+            f,
+            "Invalid {} configuration: {}",
+            self.connector,
+            errors.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Adds a [`FieldError::Missing`] if the `value` is empty.
+#[cfg(feature = "email")]
+pub(super) fn required(errors: &mut Vec<FieldError>, field: &'static str, value: &str) {
+    if value.trim().is_empty() {
+        errors.push(FieldError::Missing(field));
+    }
+}
+
+/// Parses the `email` of the `connector`, failing with its [`FieldError`] if it is not valid.
+#[cfg(feature = "email")]
+pub(super) fn email_address(
+    connector: &'static str,
+    email: &str,
+) -> Result<lettre::Address, ConfigError> {
+    let error = match email.trim().is_empty() {
+        true => FieldError::Missing("email"),
+        false => match email.parse() {
+            Ok(address) => return Ok(address),
+            Err(err) => FieldError::Malformed {
+                field: "email",
+                reason: format!("{}", err),
+            },
+        },
+    };
+    Err(ConfigError {
+        connector,
+        errors: vec![error],
+    })
+}
@@ -0,0 +1,204 @@
+use super::{ConfigError, ImapClient, Proxy, SmtpClient, TlsBackend};
+use crate::interface::DuplexConnector;
+use crate::util::IntoOption;
+
+use std::time::Duration;
+
+const DEFAULT_POLLING_TIME: Duration = Duration::from_secs(3);
+
+/// Configures both, an [`ImapClient`] and a [`SmtpClient`], for the same email account
+/// with sensible defaults.
+///
+/// Use it with [`Engine::connector()`].
+///
+/// # Example
+/// ```rust no_run
+/// use service_io::connectors::Email;
+/// use service_io::engine::Engine;
+/// use service_io::services::Echo;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     Engine::default()
+///         .connector(Email::gmail("service@gmail.com", "app-password"))
+///         .add_service("s-echo", Echo)
+///         .run()
+///         .await;
+/// }
+/// ```
+///
+/// [`Engine::connector()`]: crate::engine::Engine::connector
+#[derive(Clone)]
+pub struct Email {
+    imap_domain: String,
+    smtp_domain: String,
+    email: String,
+    password: String,
+    polling_time: Duration,
+    sender_name: Option<String>,
+    tls: TlsBackend,
+    proxy: Option<Proxy>,
+    timeout: Option<Duration>,
+}
+
+impl Email {
+    /// Email account with custom servers.
+    pub fn new(
+        imap_domain: impl Into<String>,
+        smtp_domain: impl Into<String>,
+        email: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        Self {
+            imap_domain: imap_domain.into(),
+            smtp_domain: smtp_domain.into(),
+            email: email.into(),
+            password: password.into(),
+            polling_time: DEFAULT_POLLING_TIME,
+            sender_name: None,
+            tls: TlsBackend::default(),
+            proxy: None,
+            timeout: None,
+        }
+    }
+
+    /// Gmail account. The `password` should be an app password.
+    pub fn gmail(email: impl Into<String>, password: impl Into<String>) -> Self {
+        Self::new("imap.gmail.com", "smtp.gmail.com", email, password)
+    }
+
+    /// Outlook or Office 365 account.
+    pub fn outlook(email: impl Into<String>, password: impl Into<String>) -> Self {
+        Self::new(
+            "outlook.office365.com",
+            "smtp.office365.com",
+            email,
+            password,
+        )
+    }
+
+    /// Waiting time to make requests to the IMAP server. By default, 3 seconds.
+    pub fn polling_time(mut self, duration: Duration) -> Self {
+        self.polling_time = duration;
+        self
+    }
+
+    /// Name alias for the email
+    pub fn sender_name(mut self, value: impl IntoOption<String>) -> Self {
+        self.sender_name = value.into_some();
+        self
+    }
+
+    /// TLS implementation used by both clients. By default, [`TlsBackend::default()`].
+    pub fn tls_backend(mut self, backend: TlsBackend) -> Self {
+        self.tls = backend;
+        self
+    }
+
+    /// Proxy used by both clients.
+    /// By default, the proxy of the environment is used, if any. See [`Proxy::from_env()`].
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Maximum time waiting for the servers. See [`ImapClient::timeout()`] and
+    /// [`SmtpClient::timeout()`].
+    pub fn timeout(mut self, duration: Duration) -> Self {
+        self.timeout = Some(duration);
+        self
+    }
+}
+
+impl Email {
+    /// Check the configuration of both clients. See [`ImapClient::validate()`] and
+    /// [`SmtpClient::validate()`].
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let (imap, smtp) = self.clone().split();
+        imap.validate()?;
+        smtp.validate()
+    }
+}
+
+impl DuplexConnector for Email {
+    type Input = ImapClient;
+    type Output = SmtpClient;
+
+    fn split(self) -> (ImapClient, SmtpClient) {
+        let mut imap = ImapClient::default()
+            .domain(self.imap_domain)
+            .email(self.email.clone())
+            .password(self.password.clone())
+            .polling_time(self.polling_time)
+            .tls_backend(self.tls);
+
+        let mut smtp = SmtpClient::default()
+            .domain(self.smtp_domain)
+            .email(self.email)
+            .password(self.password)
+            .sender_name(self.sender_name)
+            .tls_backend(self.tls);
+
+        if let Some(timeout) = self.timeout {
+            imap = imap.timeout(timeout);
+            smtp = smtp.timeout(timeout);
+        }
+
+        if let Some(proxy) = self.proxy {
+            imap = imap.proxy(proxy.clone());
+            smtp = smtp.proxy(proxy);
+        }
+
+        (imap, smtp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connectors::FieldError;
+
+    #[test]
+    fn validate() {
+        assert_eq!(Email::gmail("service@gmail.com", "1234").validate(), Ok(()));
+        assert_eq!(
+            Email::outlook("service@outlook.com", "1234").validate(),
+            Ok(())
+        );
+
+        let error = Email::new("", "smtp.domain.com", "service@domain.com", "")
+            .validate()
+            .unwrap_err();
+        assert_eq!(error.connector, "ImapClient");
+        assert_eq!(
+            error.errors,
+            [
+                FieldError::Missing("domain"),
+                FieldError::Missing("password")
+            ]
+        );
+
+        let error = Email::new("imap.domain.com", "smtp.domain.com", "no-email", "1234")
+            .validate()
+            .unwrap_err();
+        assert_eq!(error.connector, "SmtpClient");
+        assert!(matches!(
+            error.errors[0],
+            FieldError::Malformed { field: "email", .. }
+        ));
+    }
+
+    #[test]
+    fn split() {
+        let email = Email::new("imap.domain.com", "", "service@domain.com", "1234")
+            .sender_name("Service")
+            .timeout(Duration::from_secs(5));
+
+        let (imap, smtp) = email.split();
+        assert_eq!(imap.validate(), Ok(()));
+
+        let error = smtp.validate().unwrap_err();
+        assert_eq!(error.connector, "SmtpClient");
+        assert_eq!(error.errors, [FieldError::Missing("domain")]);
+    }
+}
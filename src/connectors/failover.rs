@@ -0,0 +1,284 @@
+use crate::channel::Sender;
+use crate::engine::{ConnectorKind, EngineHandle, Event};
+use crate::error::Error;
+use crate::interface::InputConnector;
+
+use async_trait::async_trait;
+use tokio::sync::watch;
+use tokio::task::{AbortHandle, JoinSet};
+use tokio::time::{self, Instant};
+
+use std::sync::Arc;
+use std::time::Duration;
+
+type Factory = Arc<dyn Fn() -> Box<dyn InputConnector + Send> + Send + Sync>;
+
+/// Input middleware that reads from a prioritized list of inputs: a lower-priority one
+/// (e.g. a webhook) takes over while the ones before it (e.g. the IMAP account) are unreachable.
+///
+/// An input is unreachable since its `connect()` or `run()` fails, until it runs again for
+/// [`FailoverInput::retry_interval()`]. The inputs are retried every
+/// [`FailoverInput::retry_interval()`], also while another one took over.
+/// Once an input is unreachable for [`FailoverInput::takeover_after()`], the next one starts,
+/// and it stops as soon as a previous one is reachable again.
+/// Each switch emits an [`Event::ConnectorFailover`].
+///
+/// The inputs are cloned for each retry.
+///
+/// # Example
+/// ```rust no_run
+/// use service_io::connectors::{ChatWebhookInput, FailoverInput, ImapClient, SmtpClient};
+/// use service_io::engine::Engine;
+/// use service_io::services::Echo;
+///
+/// use std::time::Duration;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     Engine::default()
+///         .input(
+///             FailoverInput::new(ImapClient::default() /* ... */)
+///                 .fallback(ChatWebhookInput::new("0.0.0.0:8080") /* ... */)
+///                 .takeover_after(Duration::from_secs(10 * 60)),
+///         )
+///         .output(SmtpClient::default() /* ... */)
+///         .add_service("s-echo", Echo)
+///         .run()
+///         .await;
+/// }
+/// ```
+pub struct FailoverInput {
+    inputs: Vec<Factory>,
+    takeover_after: Duration,
+    retry_interval: Duration,
+}
+
+impl FailoverInput {
+    /// Reads from the `primary` input while it is reachable.
+    pub fn new(primary: impl InputConnector + Clone + Send + Sync + 'static) -> Self {
+        Self {
+            inputs: Vec::new(),
+            takeover_after: Duration::from_secs(5 * 60),
+            retry_interval: Duration::from_secs(30),
+        }
+        .fallback(primary)
+    }
+
+    /// Adds an input with less priority than the previous ones.
+    pub fn fallback(mut self, input: impl InputConnector + Clone + Send + Sync + 'static) -> Self {
+        self.inputs.push(Arc::new(move || Box::new(input.clone())));
+        self
+    }
+
+    /// Time an input must be unreachable before the next one takes over. By default, 5 minutes.
+    pub fn takeover_after(mut self, duration: Duration) -> Self {
+        self.takeover_after = duration;
+        self
+    }
+
+    /// Time to wait before retrying an unreachable input, and running time to consider it
+    /// reachable again. By default, 30 seconds.
+    pub fn retry_interval(mut self, interval: Duration) -> Self {
+        self.retry_interval = interval;
+        self
+    }
+}
+
+#[async_trait]
+impl InputConnector for FailoverInput {
+    async fn run(self: Box<Self>, sender: Sender) -> Result<(), Error> {
+        let engine = EngineHandle::current();
+
+        // Since when each input is unreachable.
+        let (status, mut changes) = watch::channel(vec![None; self.inputs.len()]);
+        let status = Arc::new(status);
+
+        let mut slots = JoinSet::new();
+        let mut running: Vec<Option<AbortHandle>> = vec![None; self.inputs.len()];
+        let mut active = 0;
+        loop {
+            let now = Instant::now();
+            let unreachable = changes.borrow_and_update().clone();
+            let selected = unreachable
+                .iter()
+                .position(|since| since.is_none_or(|since| now - since < self.takeover_after))
+                .unwrap_or(self.inputs.len() - 1);
+
+            if selected != active {
+                log::warn!("Input switched from {} to {}", active, selected);
+                if let Some(engine) = &engine {
+                    engine.emit(Event::ConnectorFailover {
+                        connector: ConnectorKind::Input,
+                        active: selected,
+                    });
+                }
+                active = selected;
+            }
+
+            for (index, slot) in running.iter_mut().enumerate() {
+                match (index <= active, &slot) {
+                    (true, None) => {
+                        let task = Slot {
+                            index,
+                            input: self.inputs[index].clone(),
+                            sender: sender.clone(),
+                            status: status.clone(),
+                            retry_interval: self.retry_interval,
+                        }
+                        .run();
+                        *slot = Some(match &engine {
+                            Some(engine) => slots.spawn(engine.clone().scope(task)),
+                            None => slots.spawn(task),
+                        });
+                    }
+                    (false, Some(handle)) => {
+                        handle.abort();
+                        *slot = None;
+                        status.send_modify(|status| status[index] = None);
+                    }
+                    _ => (),
+                }
+            }
+
+            // Next time an unreachable input could be replaced.
+            let takeover = unreachable[..=active]
+                .iter()
+                .flatten()
+                .map(|since| *since + self.takeover_after)
+                .filter(|deadline| *deadline > now)
+                .min();
+
+            tokio::select! {
+                _ = changes.changed() => (),
+                _ = time::sleep_until(takeover.unwrap_or(now)), if takeover.is_some() => (),
+                Some(result) = slots.join_next() => match result {
+                    Ok(result) => return result,
+                    Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+                    Err(_) => (), // Aborted
+                },
+            }
+        }
+    }
+}
+
+/// Runs an input of the list, retrying it while it fails.
+struct Slot {
+    index: usize,
+    input: Factory,
+    sender: Sender,
+    status: Arc<watch::Sender<Vec<Option<Instant>>>>,
+    retry_interval: Duration,
+}
+
+impl Slot {
+    /// Only finishes if the engine closed the channel.
+    async fn run(self) -> Result<(), Error> {
+        loop {
+            let mut input = (self.input)();
+            let result = match input.connect().await {
+                Ok(()) => {
+                    let run = input.run(self.sender.clone());
+                    tokio::pin!(run);
+                    tokio::select! {
+                        result = &mut run => result,
+                        _ = time::sleep(self.retry_interval) => {
+                            self.set_reachable(true);
+                            run.await
+                        }
+                    }
+                }
+                Err(err) => Err(err),
+            };
+
+            match result {
+                Err(Error::Channel(err)) => return Err(Error::Channel(err)),
+                Err(err) => log::error!("Input {} unreachable: {}", self.index, err),
+                Ok(()) => log::warn!("Input {} finished", self.index),
+            }
+            self.set_reachable(false);
+            time::sleep(self.retry_interval).await;
+        }
+    }
+
+    fn set_reachable(&self, reachable: bool) {
+        self.status.send_if_modified(|status| {
+            let since = &mut status[self.index];
+            match (reachable, since.is_some()) {
+                (true, true) => *since = None,
+                (false, false) => *since = Some(Instant::now()),
+                _ => return false,
+            }
+            true
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel;
+    use crate::message::Message;
+
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// Input that sends a message every few milliseconds while it is reachable.
+    #[derive(Clone)]
+    struct FlakyInput {
+        reachable: Arc<AtomicBool>,
+        name: &'static str,
+    }
+
+    #[async_trait]
+    impl InputConnector for FlakyInput {
+        async fn run(self: Box<Self>, sender: Sender) -> Result<(), Error> {
+            while self.reachable.load(Ordering::Relaxed) {
+                sender.send(Message::default().body(self.name)).await?;
+                time::sleep(Duration::from_millis(10)).await;
+            }
+            Err(Error::connector("Flaky", "unreachable"))
+        }
+    }
+
+    #[tokio::test]
+    async fn failover() {
+        let engine = EngineHandle::default();
+        let mut events = engine.events();
+        let reachable = Arc::new(AtomicBool::new(false));
+        let primary = FlakyInput {
+            reachable: reachable.clone(),
+            name: "primary",
+        };
+        let fallback = FlakyInput {
+            reachable: Arc::new(AtomicBool::new(true)),
+            name: "fallback",
+        };
+        let input = FailoverInput::new(primary)
+            .fallback(fallback)
+            .takeover_after(Duration::from_millis(50))
+            .retry_interval(Duration::from_millis(20));
+
+        let (sender, mut receiver) = channel::channel(1);
+        tokio::spawn(engine.clone().scope(Box::new(input).run(sender)));
+
+        assert_eq!(receiver.recv().await.unwrap().body, "fallback");
+        let failover = Event::ConnectorFailover {
+            connector: ConnectorKind::Input,
+            active: 1,
+        };
+        assert_eq!(events.recv().await.unwrap(), failover);
+
+        reachable.store(true, Ordering::Relaxed);
+        while receiver.recv().await.unwrap().body != "primary" {}
+        let recovered = Event::ConnectorFailover {
+            connector: ConnectorKind::Input,
+            active: 0,
+        };
+        assert_eq!(events.recv().await.unwrap(), recovered);
+
+        // The fallback stopped.
+        receiver.recv().await.unwrap();
+        for _ in 0..5 {
+            assert_eq!(receiver.recv().await.unwrap().body, "primary");
+        }
+    }
+}
@@ -0,0 +1,339 @@
+use crate::channel::{self, Receiver, Sender};
+use crate::engine::{ConnectorKind, EngineHandle, Event, Events};
+use crate::error::Error;
+use crate::interface::OutputConnector;
+use crate::message::Message;
+
+use async_trait::async_trait;
+use futures::future;
+use tokio::time::Instant;
+
+use std::time::Duration;
+
+/// Output middleware that delivers through a `fallback` output (e.g. a second SMTP provider
+/// or a push notification) while the `primary` one is failing.
+///
+/// After [`FallbackOutput::max_failures()`] delivery failures in a row, the messages are sent
+/// through the `fallback`, starting with the last failed one.
+/// Every [`FallbackOutput::probe_interval()`], one message is sent through the `primary`
+/// again: if it is delivered, the `primary` is used again, otherwise it is sent through the
+/// `fallback`. If the `primary` finishes, the `fallback` is used from then on.
+///
+/// The failures are known by the [`DeliveryReport`]s of the outputs, so the `primary`
+/// must report them. Each switch emits an [`Event::ConnectorFailover`].
+///
+/// # Example
+/// ```rust no_run
+/// use service_io::connectors::{FallbackOutput, ImapClient, SmtpClient};
+/// use service_io::engine::Engine;
+/// use service_io::services::Echo;
+///
+/// use std::time::Duration;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let primary = SmtpClient::default().domain("smtp.domain.com") /* ... */;
+///     let fallback = SmtpClient::default().domain("smtp.backup.com") /* ... */;
+///     Engine::default()
+///         .input(ImapClient::default() /* ... */)
+///         .output(
+///             FallbackOutput::new(primary, fallback)
+///                 .max_failures(5)
+///                 .probe_interval(Duration::from_secs(60)),
+///         )
+///         .add_service("s-echo", Echo)
+///         .run()
+///         .await;
+/// }
+/// ```
+///
+/// [`DeliveryReport`]: crate::engine::DeliveryReport
+pub struct FallbackOutput<P, F> {
+    primary: P,
+    fallback: F,
+    max_failures: u32,
+    probe_interval: Duration,
+}
+
+impl<P, F> FallbackOutput<P, F>
+where
+    P: OutputConnector + Send + 'static,
+    F: OutputConnector + Send + 'static,
+{
+    /// Wraps the `primary` output, that is replaced by `fallback` while it fails.
+    pub fn new(primary: P, fallback: F) -> Self {
+        Self {
+            primary,
+            fallback,
+            max_failures: 3,
+            probe_interval: Duration::from_secs(5 * 60),
+        }
+    }
+
+    /// Delivery failures in a row before switching to the fallback. By default, 3.
+    pub fn max_failures(mut self, failures: u32) -> Self {
+        self.max_failures = failures.max(1);
+        self
+    }
+
+    /// Time using the fallback before trying the primary again. By default, 5 minutes.
+    pub fn probe_interval(mut self, interval: Duration) -> Self {
+        self.probe_interval = interval;
+        self
+    }
+}
+
+#[async_trait]
+impl<P, F> OutputConnector for FallbackOutput<P, F>
+where
+    P: OutputConnector + Send + 'static,
+    F: OutputConnector + Send + 'static,
+{
+    async fn connect(&mut self) -> Result<(), Error> {
+        if let Err(err) = self.primary.connect().await {
+            log::error!("Primary output could not connect: {}", err);
+        }
+        self.fallback.connect().await
+    }
+
+    fn describe(&self) -> String {
+        let (primary, fallback) = (self.primary.describe(), self.fallback.describe());
+        format!("FallbackOutput({}, {})", primary, fallback)
+    }
+
+    async fn run(self: Box<Self>, mut receiver: Receiver) -> Result<(), Error> {
+        let FallbackOutput {
+            primary,
+            fallback,
+            max_failures,
+            probe_interval,
+        } = *self;
+
+        let (primary_sender, primary_receiver) = channel::channel(1);
+        let (fallback_sender, fallback_receiver) = channel::channel(1);
+        let primary = async move {
+            if let Err(err) = Box::new(primary).run(primary_receiver).await {
+                log::error!("Primary output down: {}", err);
+            }
+            // Its sender is closed, so the fallback is used from now.
+            future::pending().await
+        };
+
+        let mut switch = Switch {
+            state: State::Primary { failures: 0 },
+            max_failures,
+            probe_interval,
+            primary: primary_sender,
+            fallback: fallback_sender,
+            engine: EngineHandle::current(),
+        };
+
+        tokio::select! {
+            result = primary => result,
+            result = Box::new(fallback).run(fallback_receiver) => result,
+            result = switch.forward(&mut receiver) => result,
+        }
+    }
+}
+
+enum State {
+    Primary {
+        failures: u32,
+    },
+    Fallback {
+        since: Instant,
+        /// User and service name of the message sent through the primary to probe it.
+        probe: Option<(String, String)>,
+    },
+}
+
+/// Decides the output of each message.
+struct Switch {
+    state: State,
+    max_failures: u32,
+    probe_interval: Duration,
+    primary: Sender,
+    fallback: Sender,
+    engine: Option<EngineHandle>,
+}
+
+impl Switch {
+    async fn forward(&mut self, receiver: &mut Receiver) -> Result<(), Error> {
+        let mut events = self.engine.as_ref().map(EngineHandle::events);
+        loop {
+            tokio::select! {
+                message = receiver.recv() => self.send(message?).await?,
+                event = next_event(&mut events), if events.is_some() => match event {
+                    Some(event) => self.track(event).await?,
+                    None => events = None,
+                },
+            }
+        }
+    }
+
+    async fn send(&mut self, message: Message) -> Result<(), Error> {
+        let probe = match &mut self.state {
+            State::Primary { .. } => true,
+            State::Fallback { since, probe } => {
+                let probing = probe.is_none() && since.elapsed() >= self.probe_interval;
+                if probing {
+                    log::info!("Probing the primary output");
+                    *probe = Some((message.user.clone(), message.service_name.clone()));
+                }
+                probing
+            }
+        };
+
+        if probe {
+            match self.primary.send(message.clone()).await {
+                Ok(()) => return Ok(()),
+                Err(_) => {
+                    // The primary finished: never probed again.
+                    self.probe_interval = Duration::MAX;
+                    self.switch(false);
+                }
+            }
+        }
+        Ok(self.fallback.send(message).await?)
+    }
+
+    /// Follows the reports of the deliveries.
+    async fn track(&mut self, event: Event) -> Result<(), Error> {
+        match (&mut self.state, event) {
+            (State::Primary { failures }, Event::MessageDelivered { .. }) => *failures = 0,
+            (State::Primary { failures }, Event::DeliveryError { message, .. }) => {
+                *failures += 1;
+                if *failures >= self.max_failures {
+                    self.switch(false);
+                    self.fallback.send(*message).await?;
+                }
+            }
+            (State::Fallback { probe, .. }, Event::MessageDelivered { user, service_name })
+                if is_probe(probe, &user, &service_name) =>
+            {
+                self.switch(true)
+            }
+            (State::Fallback { since, probe }, Event::DeliveryError { message, .. })
+                if is_probe(probe, &message.user, &message.service_name) =>
+            {
+                log::warn!("The primary output is still failing");
+                (*since, *probe) = (Instant::now(), None);
+                self.fallback.send(*message).await?;
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn switch(&mut self, to_primary: bool) {
+        self.state = match to_primary {
+            true => {
+                log::info!("Delivering through the primary output");
+                State::Primary { failures: 0 }
+            }
+            false => {
+                log::warn!("Delivering through the fallback output");
+                State::Fallback {
+                    since: Instant::now(),
+                    probe: None,
+                }
+            }
+        };
+        if let Some(engine) = &self.engine {
+            engine.emit(Event::ConnectorFailover {
+                connector: ConnectorKind::Output,
+                active: usize::from(!to_primary),
+            });
+        }
+    }
+}
+
+fn is_probe(probe: &Option<(String, String)>, user: &str, service_name: &str) -> bool {
+    probe.as_ref().is_some_and(|(probe_user, probe_service)| {
+        probe_user == user && probe_service == service_name
+    })
+}
+
+/// Next event, or `None` if the engine finished.
+async fn next_event(events: &mut Option<Events>) -> Option<Event> {
+    events.as_mut()?.recv().await.ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::{ChannelEnd, ClosedChannel};
+    use crate::engine::DeliveryReport;
+
+    use tokio::sync::mpsc;
+
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    /// Output that fails to deliver the messages while `failing` is set.
+    struct FlakyOutput {
+        failing: Arc<AtomicBool>,
+        sender: mpsc::Sender<Message>,
+    }
+
+    #[async_trait]
+    impl OutputConnector for FlakyOutput {
+        async fn run(self: Box<Self>, mut receiver: Receiver) -> Result<(), Error> {
+            loop {
+                let message = receiver.recv().await?;
+                if self.failing.load(Ordering::Relaxed) {
+                    receiver.report(DeliveryReport::failed(message, "unreachable"));
+                    continue;
+                }
+                receiver.report(DeliveryReport::delivered(&message));
+                self.sender
+                    .send(message)
+                    .await
+                    .map_err(|_| ClosedChannel::new(ChannelEnd::Receiver))?;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn failover() {
+        let engine = EngineHandle::default();
+        let mut events = engine.events();
+        let failing = Arc::new(AtomicBool::new(true));
+        let (primary_sender, mut primary) = mpsc::channel(32);
+        let (fallback_sender, mut fallback) = mpsc::channel(32);
+        let flaky = FlakyOutput {
+            failing: failing.clone(),
+            sender: primary_sender,
+        };
+        let output = FallbackOutput::new(flaky, fallback_sender)
+            .max_failures(2)
+            .probe_interval(Duration::from_millis(100));
+
+        let (sender, receiver) = channel::channel(32);
+        tokio::spawn(engine.clone().scope(Box::new(output).run(receiver)));
+        let message = |body: &str| Message::default().user("user").body(body);
+
+        sender.send(message("1")).await.unwrap();
+        while !matches!(events.recv().await.unwrap(), Event::DeliveryFailed { .. }) {}
+
+        // The second failure switches to the fallback, that delivers the failed message.
+        sender.send(message("2")).await.unwrap();
+        assert_eq!(fallback.recv().await.unwrap().body, "2");
+        sender.send(message("3")).await.unwrap();
+        assert_eq!(fallback.recv().await.unwrap().body, "3");
+
+        failing.store(false, Ordering::Relaxed);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        sender.send(message("4")).await.unwrap();
+        assert_eq!(primary.recv().await.unwrap().body, "4");
+
+        let switched_back = Event::ConnectorFailover {
+            connector: ConnectorKind::Output,
+            active: 0,
+        };
+        while events.recv().await.unwrap() != switched_back {}
+        sender.send(message("5")).await.unwrap();
+        assert_eq!(primary.recv().await.unwrap().body, "5");
+        assert!(fallback.try_recv().is_err());
+    }
+}
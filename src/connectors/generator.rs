@@ -0,0 +1,109 @@
+use crate::channel::Sender;
+use crate::error::Error;
+use crate::interface::InputConnector;
+use crate::message::Message;
+
+use async_trait::async_trait;
+use tokio::time::{self, MissedTickBehavior};
+
+use std::time::Duration;
+
+/// Input connector that produces messages from a closure at a fixed rate.
+/// The closure receives the number of messages generated before.
+///
+/// Useful for load tests, demos, or as a heartbeat for services that must run periodically.
+/// If the services are busy, the generation is delayed instead of accumulating messages.
+///
+/// # Example
+/// ```rust no_run
+/// use service_io::connectors::{DebugStdout, GeneratorInput};
+/// use service_io::engine::Engine;
+/// use service_io::message::Message;
+/// use service_io::services::Echo;
+///
+/// use std::time::Duration;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     Engine::default()
+///         .input(
+///             GeneratorInput::new(|count| {
+///                 Message::default()
+///                     .user("load-test")
+///                     .service_name("s-echo")
+///                     .body(count.to_string())
+///             })
+///             .rate(100.0)
+///             .limit(10_000),
+///         )
+///         .output(DebugStdout)
+///         .add_service("s-echo", Echo)
+///         .run()
+///         .await;
+/// }
+/// ```
+pub struct GeneratorInput<F> {
+    generator: F,
+    interval: Duration,
+    delay: Duration,
+    limit: Option<u64>,
+}
+
+impl<F> GeneratorInput<F>
+where
+    F: FnMut(u64) -> Message + Send,
+{
+    /// By default, a message is generated each second without limit.
+    pub fn new(generator: F) -> Self {
+        Self {
+            generator,
+            interval: Duration::from_secs(1),
+            delay: Duration::ZERO,
+            limit: None,
+        }
+    }
+
+    /// Time between generated messages.
+    pub fn every(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Messages generated per second.
+    pub fn rate(self, per_second: f64) -> Self {
+        self.every(Duration::from_secs_f64(1.0 / per_second))
+    }
+
+    /// Time to wait before generating the first message.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Total number of messages to generate. Then, the connector finishes.
+    pub fn limit(mut self, count: u64) -> Self {
+        self.limit = Some(count);
+        self
+    }
+}
+
+#[async_trait]
+impl<F> InputConnector for GeneratorInput<F>
+where
+    F: FnMut(u64) -> Message + Send,
+{
+    async fn run(mut self: Box<Self>, sender: Sender) -> Result<(), Error> {
+        let mut interval = time::interval_at(time::Instant::now() + self.delay, self.interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        let mut count = 0;
+        while self.limit.map(|limit| count < limit).unwrap_or(true) {
+            interval.tick().await;
+            let permit = sender.permit().await?;
+            permit.send((self.generator)(count));
+            count += 1;
+        }
+
+        Ok(())
+    }
+}
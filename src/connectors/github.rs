@@ -0,0 +1,510 @@
+use super::proxy::Proxy;
+use super::text::{message_to_text, text_to_message};
+use crate::channel::{Receiver, Sender};
+use crate::engine::{ConnectorKind, DeliveryReport, EngineHandle, Event};
+use crate::error::Error;
+use crate::interface::{DuplexConnector, InputConnector, OutputConnector};
+use crate::message::Message;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::time;
+
+use std::time::Duration;
+
+const API_URL: &str = "https://api.github.com/repos";
+const DEFAULT_POLLING_TIME: Duration = Duration::from_secs(30);
+const DEFAULT_PREFIX: &str = "/service";
+
+/// Metadata key with the number of the issue the message comes from.
+/// The [`GithubOutput`] comments the replies in that issue.
+pub const GITHUB_ISSUE_KEY: &str = "github-issue";
+
+#[derive(Deserialize)]
+struct GithubUser {
+    login: String,
+}
+
+#[derive(Deserialize)]
+struct Issue {
+    number: u64,
+    title: String,
+    #[serde(default)]
+    body: Option<String>,
+    user: GithubUser,
+    pull_request: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct Comment {
+    id: u64,
+    issue_url: String,
+    body: String,
+    user: GithubUser,
+}
+
+fn http_client(token: &str, proxy: Option<&Proxy>) -> reqwest::Client {
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert(
+        reqwest::header::AUTHORIZATION,
+        format!("Bearer {}", token).parse().unwrap(),
+    );
+    headers.insert(
+        reqwest::header::ACCEPT,
+        "application/vnd.github+json".parse().unwrap(),
+    );
+
+    super::proxy::http_client(proxy)
+        .user_agent("service-io")
+        .default_headers(headers)
+        .build()
+        .unwrap()
+}
+
+fn report_auth_error(err: &reqwest::Error, connector: ConnectorKind) {
+    if err.status() == Some(reqwest::StatusCode::UNAUTHORIZED) {
+        if let Some(engine) = EngineHandle::current() {
+            engine.emit(Event::AuthFailed {
+                connector,
+                error: err.to_string(),
+            });
+        }
+    }
+}
+
+/// Configures both, a [`GithubInput`] and a [`GithubOutput`], for the same repository,
+/// turning its issues into a command interface.
+///
+/// Use it with [`Engine::connector()`].
+///
+/// # Example
+/// ```rust no_run
+/// use service_io::connectors::Github;
+/// use service_io::engine::Engine;
+/// use service_io::services::Process;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     Engine::default()
+///         // Comments as "/ops s-process uptime" run the process.
+///         .connector(Github::new("owner/repo", "token").prefix("/ops"))
+///         .add_service_for("s-process", Process, ["admin-login"])
+///         .run()
+///         .await;
+/// }
+/// ```
+///
+/// [`Engine::connector()`]: crate::engine::Engine::connector
+#[derive(Clone)]
+pub struct Github {
+    input: GithubInput,
+    output: GithubOutput,
+}
+
+impl Github {
+    /// Uses the issues of the repository `repo` (as `owner/repo`) with the access `token`.
+    pub fn new(repo: impl Into<String>, token: impl Into<String>) -> Self {
+        let (repo, token) = (repo.into(), token.into());
+        Self {
+            input: GithubInput::new(repo.clone(), token.clone()),
+            output: GithubOutput::new(repo, token),
+        }
+    }
+
+    /// See [`GithubInput::prefix()`].
+    pub fn prefix(mut self, value: impl Into<String>) -> Self {
+        self.input = self.input.prefix(value);
+        self
+    }
+
+    /// See [`GithubInput::polling_time()`].
+    pub fn polling_time(mut self, duration: Duration) -> Self {
+        self.input = self.input.polling_time(duration);
+        self
+    }
+
+    /// Reach the API through a proxy. See [`GithubInput::proxy()`].
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.input = self.input.proxy(proxy.clone());
+        self.output = self.output.proxy(proxy);
+        self
+    }
+}
+
+impl DuplexConnector for Github {
+    type Input = GithubInput;
+    type Output = GithubOutput;
+
+    fn split(self) -> (GithubInput, GithubOutput) {
+        (self.input, self.output)
+    }
+}
+
+/// Input connector that reads the new issues and issue comments of a GitHub repository
+/// starting with a prefix.
+///
+/// The login of the author is the user.
+/// For comments, the first line after the prefix is interpreted as the service name
+/// followed by the spaced-separated arguments, and the following lines are the body.
+/// For issues, the title after the prefix is the first line and the description the body.
+///
+/// The number of the issue is added to the metadata as [`GITHUB_ISSUE_KEY`].
+/// Only the issues and comments created after the connector starts are read.
+#[derive(Clone)]
+pub struct GithubInput {
+    repo: String,
+    token: String,
+    prefix: String,
+    polling_time: Duration,
+    proxy: Option<Proxy>,
+}
+
+#[derive(Default)]
+struct Cursor {
+    last_issue: u64,
+    last_comment: u64,
+}
+
+impl GithubInput {
+    /// Reads the repository `repo` (as `owner/repo`) with the access `token`.
+    pub fn new(repo: impl Into<String>, token: impl Into<String>) -> Self {
+        Self {
+            repo: repo.into(),
+            token: token.into(),
+            prefix: DEFAULT_PREFIX.into(),
+            polling_time: DEFAULT_POLLING_TIME,
+            proxy: None,
+        }
+    }
+
+    /// Reach the API through a proxy.
+    /// By default, the proxy of the environment is used, if any. See [`Proxy::from_env()`].
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Prefix that the commands must start with. By default `/service`.
+    pub fn prefix(mut self, value: impl Into<String>) -> Self {
+        self.prefix = value.into();
+        self
+    }
+
+    /// Waiting time to make requests to the GitHub API. By default, 30 seconds.
+    pub fn polling_time(mut self, duration: Duration) -> Self {
+        self.polling_time = duration;
+        self
+    }
+
+    async fn latest(&self, http: &reqwest::Client) -> reqwest::Result<(Vec<Issue>, Vec<Comment>)> {
+        let query = [
+            ("sort", "created"),
+            ("direction", "desc"),
+            ("per_page", "30"),
+        ];
+
+        let issues = http
+            .get(format!("{}/{}/issues", API_URL, self.repo))
+            .query(&query)
+            .query(&[("state", "all")])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Vec<Issue>>()
+            .await?;
+
+        let comments = http
+            .get(format!("{}/{}/issues/comments", API_URL, self.repo))
+            .query(&query)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Vec<Comment>>()
+            .await?;
+
+        Ok((issues, comments))
+    }
+
+    /// Returns the messages of the issues and comments not read yet, from oldest to newest.
+    fn read(
+        &self,
+        cursor: &mut Cursor,
+        issues: Vec<Issue>,
+        comments: Vec<Comment>,
+    ) -> Vec<Message> {
+        let mut messages = Vec::new();
+
+        for issue in issues.into_iter().rev() {
+            if issue.number <= cursor.last_issue || issue.pull_request.is_some() {
+                continue;
+            }
+            cursor.last_issue = issue.number;
+
+            if let Some(header) = issue.title.strip_prefix(&self.prefix) {
+                let text = format!("{}\n{}", header, issue.body.unwrap_or_default());
+                if let Some(message) = text_to_message(issue.user.login, text.trim_start()) {
+                    messages.push((issue.number, message));
+                }
+            }
+        }
+
+        for comment in comments.into_iter().rev() {
+            if comment.id <= cursor.last_comment {
+                continue;
+            }
+            cursor.last_comment = comment.id;
+
+            let number = comment
+                .issue_url
+                .rsplit('/')
+                .next()
+                .and_then(|n| n.parse().ok());
+            if let (Some(number), Some(text)) = (number, comment.body.strip_prefix(&self.prefix)) {
+                if let Some(message) = text_to_message(comment.user.login, text.trim_start()) {
+                    messages.push((number, message));
+                }
+            }
+        }
+
+        messages
+            .into_iter()
+            .map(|(number, message)| message.metadata([(GITHUB_ISSUE_KEY, number.to_string())]))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl InputConnector for GithubInput {
+    async fn run(self: Box<Self>, sender: Sender) -> Result<(), Error> {
+        let http = http_client(&self.token, self.proxy.as_ref());
+        let mut cursor: Option<Cursor> = None;
+
+        loop {
+            match self.latest(&http).await {
+                Ok((issues, comments)) => match &mut cursor {
+                    Some(cursor) => {
+                        for message in self.read(cursor, issues, comments) {
+                            sender.send(message).await?;
+                        }
+                    }
+                    // The first read only sets the cursor to skip the history.
+                    None => {
+                        let mut initial = Cursor::default();
+                        self.read(&mut initial, issues, comments);
+                        cursor = Some(initial);
+                    }
+                },
+                Err(err) => {
+                    log::error!("{}", err);
+                    report_auth_error(&err, ConnectorKind::Input);
+                }
+            }
+
+            time::sleep(self.polling_time).await;
+        }
+    }
+}
+
+/// Output connector that comments the messages in the issues of a GitHub repository
+/// mentioning the user.
+///
+/// The messages are commented in the issue given by the [`GITHUB_ISSUE_KEY`] metadata.
+/// If there is no issue, a new one is created.
+/// The attached data is not sent.
+#[derive(Clone)]
+pub struct GithubOutput {
+    repo: String,
+    token: String,
+    proxy: Option<Proxy>,
+}
+
+impl GithubOutput {
+    /// Comments in the repository `repo` (as `owner/repo`) with the access `token`.
+    pub fn new(repo: impl Into<String>, token: impl Into<String>) -> Self {
+        Self {
+            repo: repo.into(),
+            token: token.into(),
+            proxy: None,
+        }
+    }
+
+    /// Reach the API through a proxy.
+    /// By default, the proxy of the environment is used, if any. See [`Proxy::from_env()`].
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// URL and body of the request that comments or creates the issue.
+    fn request(&self, message: &Message) -> (String, serde_json::Value) {
+        match message.metadata.get(GITHUB_ISSUE_KEY) {
+            Some(number) => (
+                format!("{}/{}/issues/{}/comments", API_URL, self.repo, number),
+                json!({
+                    "body": format!("@{}\n```\n{}\n```", message.user, message_to_text(message)),
+                }),
+            ),
+            None => (
+                format!("{}/{}/issues", API_URL, self.repo),
+                json!({
+                    "title": format!("{} {}", message.service_name, message.args.join(" ")),
+                    "body": format!("@{}\n```\n{}\n```", message.user, message.body),
+                }),
+            ),
+        }
+    }
+
+    async fn send(&self, http: &reqwest::Client, message: &Message) -> reqwest::Result<()> {
+        let (url, body) = self.request(message);
+        http.post(url)
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl OutputConnector for GithubOutput {
+    async fn run(self: Box<Self>, mut receiver: Receiver) -> Result<(), Error> {
+        let http = http_client(&self.token, self.proxy.as_ref());
+
+        loop {
+            let message = receiver.recv().await?;
+            match self.send(&http, &message).await {
+                Ok(()) => receiver.report(DeliveryReport::delivered(&message)),
+                Err(err) => {
+                    log::error!("Sending error: {}", err);
+                    report_auth_error(&err, ConnectorKind::Output);
+                    receiver.report(DeliveryReport::failed(message, err));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn issues() -> Vec<Issue> {
+        serde_json::from_value(json!([
+            {
+                "number": 3,
+                "title": "/ops s-process uptime",
+                "body": "details",
+                "user": { "login": "admin-login" },
+            },
+            {
+                "number": 2,
+                "title": "/ops s-process ls",
+                "user": { "login": "admin-login" },
+                "pull_request": { "url": "https://api.github.com/repos/owner/repo/pulls/2" },
+            },
+            {
+                "number": 1,
+                "title": "Unrelated issue",
+                "body": null,
+                "user": { "login": "other-login" },
+            },
+        ]))
+        .unwrap()
+    }
+
+    fn comments() -> Vec<Comment> {
+        serde_json::from_value(json!([
+            {
+                "id": 11,
+                "issue_url": "https://api.github.com/repos/owner/repo/issues/1",
+                "body": "/ops s-echo a b\nhello",
+                "user": { "login": "other-login" },
+            },
+            {
+                "id": 10,
+                "issue_url": "https://api.github.com/repos/owner/repo/issues/1",
+                "body": "Just a comment",
+                "user": { "login": "other-login" },
+            },
+        ]))
+        .unwrap()
+    }
+
+    #[test]
+    fn read() {
+        let input = GithubInput::new("owner/repo", "token").prefix("/ops");
+        let mut cursor = Cursor::default();
+
+        let messages = input.read(&mut cursor, issues(), comments());
+        assert_eq!(messages.len(), 2);
+
+        assert_eq!(messages[0].user, "admin-login");
+        assert_eq!(messages[0].service_name, "s-process");
+        assert_eq!(messages[0].args, ["uptime"]);
+        assert_eq!(messages[0].body, "details");
+        assert_eq!(messages[0].metadata[GITHUB_ISSUE_KEY], "3");
+
+        assert_eq!(messages[1].user, "other-login");
+        assert_eq!(messages[1].service_name, "s-echo");
+        assert_eq!(messages[1].args, ["a", "b"]);
+        assert_eq!(messages[1].body, "hello");
+        assert_eq!(messages[1].metadata[GITHUB_ISSUE_KEY], "1");
+
+        assert_eq!((cursor.last_issue, cursor.last_comment), (3, 11));
+        assert!(input.read(&mut cursor, issues(), comments()).is_empty());
+    }
+
+    #[test]
+    fn request() {
+        let output = GithubOutput::new("owner/repo", "token");
+        let message = Message::default()
+            .user("admin-login")
+            .service_name("s-process")
+            .args(["uptime"])
+            .body("up 3 days");
+
+        let (url, body) = output.request(&message);
+        assert_eq!(url, "https://api.github.com/repos/owner/repo/issues");
+        assert_eq!(
+            body,
+            json!({ "title": "s-process uptime", "body": "@admin-login\n```\nup 3 days\n```" })
+        );
+
+        let message = message.metadata([(GITHUB_ISSUE_KEY, "3")]);
+        let (url, body) = output.request(&message);
+        assert_eq!(
+            url,
+            "https://api.github.com/repos/owner/repo/issues/3/comments"
+        );
+        assert_eq!(
+            body,
+            json!({ "body": "@admin-login\n```\ns-process uptime\nup 3 days\n```" })
+        );
+    }
+
+    #[tokio::test]
+    async fn auth_error() {
+        let engine = EngineHandle::default();
+        let mut events = engine.events();
+
+        let response = axum::http::Response::builder()
+            .status(401)
+            .body("")
+            .unwrap();
+        let err = reqwest::Response::from(response)
+            .error_for_status()
+            .unwrap_err();
+        engine
+            .scope(async move { report_auth_error(&err, ConnectorKind::Output) })
+            .await;
+
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            Event::AuthFailed {
+                connector: ConnectorKind::Output,
+                ..
+            }
+        ));
+    }
+}
@@ -0,0 +1,350 @@
+use super::imap::email_to_message;
+use super::proxy::{http_client, Proxy};
+use super::smtp::message_to_email;
+use super::OAuth2;
+use crate::channel::{Receiver, Sender};
+use crate::engine::{ConnectorKind, DeliveryReport};
+use crate::error::Error;
+use crate::interface::{DuplexConnector, InputConnector, OutputConnector};
+use crate::message::Message;
+
+use async_trait::async_trait;
+use base64::engine::{
+    general_purpose::{URL_SAFE, URL_SAFE_NO_PAD},
+    Engine as _,
+};
+use lettre::message::Mailbox;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::time;
+
+use std::time::Duration;
+
+const API_URL: &str = "https://gmail.googleapis.com/gmail/v1/users/me/messages";
+const DEFAULT_POLLING_TIME: Duration = Duration::from_secs(3);
+
+#[derive(Deserialize)]
+struct MessageList {
+    #[serde(default)]
+    messages: Vec<MessageRef>,
+}
+
+#[derive(Deserialize)]
+struct MessageRef {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct RawMessage {
+    raw: String,
+}
+
+/// Configures both, a [`GmailInput`] and a [`GmailOutput`], sharing the same credentials.
+///
+/// The Gmail REST API is used instead of IMAP/SMTP, so it works with the Google security
+/// policies that disable the basic authentication.
+/// The credentials require the `https://www.googleapis.com/auth/gmail.modify` scope.
+///
+/// Use it with [`Engine::connector()`].
+///
+/// # Example
+/// ```rust no_run
+/// use service_io::connectors::{Gmail, OAuth2};
+/// use service_io::engine::Engine;
+/// use service_io::services::Echo;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     Engine::default()
+///         .connector(Gmail::new(
+///             "service@gmail.com",
+///             OAuth2::google("client-id", "client-secret", "refresh-token"),
+///         ))
+///         .add_service("s-echo", Echo)
+///         .run()
+///         .await;
+/// }
+/// ```
+///
+/// [`Engine::connector()`]: crate::engine::Engine::connector
+#[derive(Clone)]
+pub struct Gmail {
+    input: GmailInput,
+    output: GmailOutput,
+}
+
+impl Gmail {
+    pub fn new(email: impl Into<String>, auth: OAuth2) -> Self {
+        Self {
+            input: GmailInput::new(auth.clone()),
+            output: GmailOutput::new(email, auth),
+        }
+    }
+
+    /// Waiting time to make requests to the Gmail API. By default, 3 seconds.
+    pub fn polling_time(mut self, duration: Duration) -> Self {
+        self.input = self.input.polling_time(duration);
+        self
+    }
+
+    /// Reach the API through a proxy. See [`GmailInput::proxy()`].
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.input = self.input.proxy(proxy.clone());
+        self.output = self.output.proxy(proxy);
+        self
+    }
+}
+
+impl DuplexConnector for Gmail {
+    type Input = GmailInput;
+    type Output = GmailOutput;
+
+    fn split(self) -> (GmailInput, GmailOutput) {
+        (self.input, self.output)
+    }
+}
+
+/// Input connector that reads the unread emails of the Gmail inbox through the Gmail REST API.
+/// The emails are transformed to messages in the same way as [`ImapClient`] does.
+///
+/// Instead of removing the emails, they are marked as read and archived.
+///
+/// [`ImapClient`]: super::ImapClient
+#[derive(Clone)]
+pub struct GmailInput {
+    auth: OAuth2,
+    polling_time: Duration,
+    proxy: Option<Proxy>,
+}
+
+impl GmailInput {
+    pub fn new(auth: OAuth2) -> Self {
+        Self {
+            auth,
+            polling_time: DEFAULT_POLLING_TIME,
+            proxy: None,
+        }
+    }
+
+    /// Reach the API through a proxy.
+    /// By default, the proxy of the environment is used, if any. See [`Proxy::from_env()`].
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Waiting time to make requests to the Gmail API. By default, 3 seconds.
+    pub fn polling_time(mut self, duration: Duration) -> Self {
+        self.polling_time = duration;
+        self
+    }
+
+    async fn read_inbox(&self, http: &reqwest::Client) -> reqwest::Result<Option<Message>> {
+        let token = self.auth.access_token(http).await?;
+
+        let list = http
+            .get(API_URL)
+            .bearer_auth(&token)
+            .query(&[
+                ("labelIds", "INBOX"),
+                ("q", "is:unread"),
+                ("maxResults", "1"),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<MessageList>()
+            .await?;
+
+        let id = match list.messages.into_iter().next() {
+            Some(message) => message.id,
+            None => return Ok(None),
+        };
+
+        let email = http
+            .get(format!("{}/{}", API_URL, id))
+            .bearer_auth(&token)
+            .query(&[("format", "raw")])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<RawMessage>()
+            .await?;
+
+        http.post(format!("{}/{}/modify", API_URL, id))
+            .bearer_auth(&token)
+            .json(&json!({ "removeLabelIds": ["UNREAD", "INBOX"] }))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(raw_to_message(&email.raw))
+    }
+}
+
+/// Decodes the `raw` field of a Gmail message into a message.
+fn raw_to_message(raw: &str) -> Option<Message> {
+    let body = URL_SAFE_NO_PAD
+        .decode(raw.trim_end_matches('='))
+        .map_err(|err| log::error!("{}", err))
+        .ok()?;
+
+    log::trace!(
+        "Raw email:\n{}",
+        std::str::from_utf8(&body).unwrap_or("No utf8")
+    );
+
+    mailparse::parse_mail(&body)
+        .map(|parsed| email_to_message(&parsed))
+        .map_err(|err| log::error!("{}", err))
+        .ok()
+}
+
+/// Body of the Gmail send request.
+fn send_request(email: &lettre::Message) -> serde_json::Value {
+    json!({ "raw": URL_SAFE.encode(email.formatted()) })
+}
+
+#[async_trait]
+impl InputConnector for GmailInput {
+    async fn run(self: Box<Self>, sender: Sender) -> Result<(), Error> {
+        let http = http_client(self.proxy.as_ref())
+            .build()
+            .map_err(|err| Error::connector("Gmail", err))?;
+        loop {
+            time::sleep(self.polling_time).await;
+
+            let permit = sender.permit().await?;
+            match self.read_inbox(&http).await {
+                Ok(Some(message)) => permit.send(message),
+                Ok(None) => (),
+                Err(err) => {
+                    log::error!("{}", err);
+                    self.auth.check_rejected(&err, ConnectorKind::Input).await;
+                }
+            }
+        }
+    }
+}
+
+/// Output connector that sends the messages as emails through the Gmail REST API.
+/// The emails are built in the same way as [`SmtpClient`] does.
+///
+/// [`SmtpClient`]: super::SmtpClient
+#[derive(Clone)]
+pub struct GmailOutput {
+    email: String,
+    auth: OAuth2,
+    proxy: Option<Proxy>,
+}
+
+impl GmailOutput {
+    pub fn new(email: impl Into<String>, auth: OAuth2) -> Self {
+        Self {
+            email: email.into(),
+            auth,
+            proxy: None,
+        }
+    }
+
+    /// Reach the API through a proxy.
+    /// By default, the proxy of the environment is used, if any. See [`Proxy::from_env()`].
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    async fn send(&self, http: &reqwest::Client, email: lettre::Message) -> reqwest::Result<()> {
+        let token = self.auth.access_token(http).await?;
+        http.post(format!("{}/send", API_URL))
+            .bearer_auth(&token)
+            .json(&send_request(&email))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl OutputConnector for GmailOutput {
+    async fn run(self: Box<Self>, mut receiver: Receiver) -> Result<(), Error> {
+        let address = super::config::email_address("GmailOutput", &self.email)?;
+        let from = Mailbox::new(None, address);
+        let http = http_client(self.proxy.as_ref())
+            .build()
+            .map_err(|err| Error::connector("Gmail", err))?;
+
+        loop {
+            let message = receiver.recv().await?;
+            if let Some(email) = message_to_email(message.clone(), from.clone()) {
+                match self.send(&http, email).await {
+                    Ok(()) => receiver.report(DeliveryReport::delivered(&message)),
+                    Err(err) => {
+                        log::error!("Sending error: {}", err);
+                        self.auth.check_rejected(&err, ConnectorKind::Output).await;
+                        receiver.report(DeliveryReport::failed(message, err));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message_list() {
+        let list: MessageList = serde_json::from_str(r#"{"resultSizeEstimate": 0}"#).unwrap();
+        assert!(list.messages.is_empty());
+
+        let list: MessageList =
+            serde_json::from_str(r#"{"messages": [{"id": "a1", "threadId": "t1"}]}"#).unwrap();
+        assert_eq!(list.messages[0].id, "a1");
+    }
+
+    #[test]
+    fn round_trip() {
+        let message = Message::default()
+            .user("user@domain.com")
+            .service_name("s-echo")
+            .args(["arg0", "arg1"])
+            .body("hello")
+            .attach([("file.bin", b"1234".to_vec())]);
+
+        let from = Mailbox::new(None, "service@gmail.com".parse().unwrap());
+        let email = message_to_email(message, from).unwrap();
+        let request = send_request(&email);
+        let raw = request["raw"].as_str().unwrap();
+
+        let received = raw_to_message(raw).unwrap();
+        assert_eq!(received.user, "service@gmail.com");
+        assert_eq!(received.service_name, "s-echo");
+        assert_eq!(received.args, ["arg0", "arg1"]);
+        assert_eq!(received.body.trim_end(), "hello");
+        assert_eq!(&received.attached_data["file.bin"][..], b"1234");
+    }
+
+    #[test]
+    fn invalid_raw() {
+        assert_eq!(raw_to_message("not base64!"), None);
+    }
+
+    #[test]
+    fn invalid_recipient() {
+        let message = Message::default().user("no-email").service_name("s-echo");
+        let from = Mailbox::new(None, "service@gmail.com".parse().unwrap());
+        assert!(message_to_email(message, from).is_none());
+    }
+
+    #[tokio::test]
+    async fn malformed_email() {
+        let auth = OAuth2::google("client-id", "client-secret", "refresh-token");
+        let output = GmailOutput::new("no-email", auth);
+        let (_sender, receiver) = crate::channel::channel(4);
+        let result = Box::new(output).run(receiver).await;
+        assert!(matches!(result, Err(Error::Config(_))));
+    }
+}
@@ -0,0 +1,365 @@
+use super::imap::email_to_message;
+use super::proxy::{http_client, Proxy};
+use super::smtp::message_to_email;
+use super::OAuth2;
+use crate::channel::{Receiver, Sender};
+use crate::engine::{ConnectorKind, DeliveryReport};
+use crate::error::Error;
+use crate::interface::{DuplexConnector, InputConnector, OutputConnector};
+use crate::message::Message;
+
+use async_trait::async_trait;
+use base64::engine::{general_purpose::STANDARD, Engine as _};
+use lettre::message::Mailbox;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::time;
+
+use std::time::Duration;
+
+const API_URL: &str = "https://graph.microsoft.com/v1.0/users";
+const DEFAULT_POLLING_TIME: Duration = Duration::from_secs(3);
+
+#[derive(Deserialize)]
+struct MessageList {
+    #[serde(default)]
+    value: Vec<MessageRef>,
+}
+
+#[derive(Deserialize)]
+struct MessageRef {
+    id: String,
+}
+
+/// Configures both, a [`GraphInput`] and a [`GraphOutput`], for the same mailbox
+/// sharing the same credentials.
+///
+/// The Microsoft Graph REST API is used instead of IMAP/SMTP, so it works with the
+/// Outlook/Office 365 tenants that disable the basic authentication.
+/// The credentials require the `Mail.ReadWrite` and `Mail.Send` permissions,
+/// either as application permissions ([`OAuth2::microsoft_app()`])
+/// or as delegated permissions ([`OAuth2::microsoft()`]).
+///
+/// Use it with [`Engine::connector()`].
+///
+/// # Example
+/// ```rust no_run
+/// use service_io::connectors::{Graph, OAuth2};
+/// use service_io::engine::Engine;
+/// use service_io::services::Echo;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     Engine::default()
+///         .connector(Graph::new(
+///             "service@company.com",
+///             OAuth2::microsoft_app("tenant-id", "client-id", "client-secret"),
+///         ))
+///         .add_service("s-echo", Echo)
+///         .run()
+///         .await;
+/// }
+/// ```
+///
+/// [`Engine::connector()`]: crate::engine::Engine::connector
+#[derive(Clone)]
+pub struct Graph {
+    input: GraphInput,
+    output: GraphOutput,
+}
+
+impl Graph {
+    pub fn new(email: impl Into<String>, auth: OAuth2) -> Self {
+        let email = email.into();
+        Self {
+            input: GraphInput::new(email.clone(), auth.clone()),
+            output: GraphOutput::new(email, auth),
+        }
+    }
+
+    /// Waiting time to make requests to Microsoft Graph. By default, 3 seconds.
+    pub fn polling_time(mut self, duration: Duration) -> Self {
+        self.input = self.input.polling_time(duration);
+        self
+    }
+
+    /// Reach the API through a proxy. See [`GraphInput::proxy()`].
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.input = self.input.proxy(proxy.clone());
+        self.output = self.output.proxy(proxy);
+        self
+    }
+}
+
+impl DuplexConnector for Graph {
+    type Input = GraphInput;
+    type Output = GraphOutput;
+
+    fn split(self) -> (GraphInput, GraphOutput) {
+        (self.input, self.output)
+    }
+}
+
+/// Input connector that reads the unread emails of a mailbox through Microsoft Graph.
+/// The emails are transformed to messages in the same way as [`ImapClient`] does.
+///
+/// Instead of removing the emails, they are marked as read and moved to the archive folder.
+///
+/// [`ImapClient`]: super::ImapClient
+#[derive(Clone)]
+pub struct GraphInput {
+    email: String,
+    auth: OAuth2,
+    polling_time: Duration,
+    proxy: Option<Proxy>,
+}
+
+impl GraphInput {
+    pub fn new(email: impl Into<String>, auth: OAuth2) -> Self {
+        Self {
+            email: email.into(),
+            auth,
+            polling_time: DEFAULT_POLLING_TIME,
+            proxy: None,
+        }
+    }
+
+    /// Reach the API through a proxy.
+    /// By default, the proxy of the environment is used, if any. See [`Proxy::from_env()`].
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Waiting time to make requests to Microsoft Graph. By default, 3 seconds.
+    pub fn polling_time(mut self, duration: Duration) -> Self {
+        self.polling_time = duration;
+        self
+    }
+
+    async fn read_inbox(&self, http: &reqwest::Client) -> reqwest::Result<Option<Message>> {
+        let token = self.auth.access_token(http).await?;
+        let messages_url = format!("{}/{}/messages", API_URL, self.email);
+
+        let list = http
+            .get(format!(
+                "{}/{}/mailFolders/inbox/messages",
+                API_URL, self.email
+            ))
+            .bearer_auth(&token)
+            .query(&[
+                ("$filter", "isRead eq false"),
+                ("$top", "1"),
+                ("$select", "id"),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<MessageList>()
+            .await?;
+
+        let id = match list.value.into_iter().next() {
+            Some(message) => message.id,
+            None => return Ok(None),
+        };
+
+        let body = http
+            .get(format!("{}/{}/$value", messages_url, id))
+            .bearer_auth(&token)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+
+        http.patch(format!("{}/{}", messages_url, id))
+            .bearer_auth(&token)
+            .json(&json!({ "isRead": true }))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        http.post(format!("{}/{}/move", messages_url, id))
+            .bearer_auth(&token)
+            .json(&json!({ "destinationId": "archive" }))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(mime_to_message(&body))
+    }
+}
+
+/// Parses the MIME content of a Graph message into a message.
+fn mime_to_message(body: &[u8]) -> Option<Message> {
+    log::trace!(
+        "Raw email:\n{}",
+        std::str::from_utf8(body).unwrap_or("No utf8")
+    );
+
+    mailparse::parse_mail(body)
+        .map(|parsed| email_to_message(&parsed))
+        .map_err(|err| log::error!("{}", err))
+        .ok()
+}
+
+/// Body of the Graph `sendMail` request: the MIME content encoded as base64.
+fn send_body(email: &lettre::Message) -> String {
+    STANDARD.encode(email.formatted())
+}
+
+#[async_trait]
+impl InputConnector for GraphInput {
+    async fn run(self: Box<Self>, sender: Sender) -> Result<(), Error> {
+        let http = http_client(self.proxy.as_ref())
+            .build()
+            .map_err(|err| Error::connector("Graph", err))?;
+        loop {
+            time::sleep(self.polling_time).await;
+
+            let permit = sender.permit().await?;
+            match self.read_inbox(&http).await {
+                Ok(Some(message)) => permit.send(message),
+                Ok(None) => (),
+                Err(err) => {
+                    log::error!("{}", err);
+                    self.auth.check_rejected(&err, ConnectorKind::Input).await;
+                }
+            }
+        }
+    }
+}
+
+/// Output connector that sends the messages as emails from a mailbox through Microsoft Graph.
+/// The emails are built in the same way as [`SmtpClient`] does.
+///
+/// [`SmtpClient`]: super::SmtpClient
+#[derive(Clone)]
+pub struct GraphOutput {
+    email: String,
+    auth: OAuth2,
+    proxy: Option<Proxy>,
+}
+
+impl GraphOutput {
+    pub fn new(email: impl Into<String>, auth: OAuth2) -> Self {
+        Self {
+            email: email.into(),
+            auth,
+            proxy: None,
+        }
+    }
+
+    /// Reach the API through a proxy.
+    /// By default, the proxy of the environment is used, if any. See [`Proxy::from_env()`].
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    async fn send(&self, http: &reqwest::Client, email: lettre::Message) -> reqwest::Result<()> {
+        let token = self.auth.access_token(http).await?;
+        http.post(format!("{}/{}/sendMail", API_URL, self.email))
+            .bearer_auth(&token)
+            .header(reqwest::header::CONTENT_TYPE, "text/plain")
+            .body(send_body(&email))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl OutputConnector for GraphOutput {
+    async fn run(self: Box<Self>, mut receiver: Receiver) -> Result<(), Error> {
+        let address = super::config::email_address("GraphOutput", &self.email)?;
+        let from = Mailbox::new(None, address);
+        let http = http_client(self.proxy.as_ref())
+            .build()
+            .map_err(|err| Error::connector("Graph", err))?;
+
+        loop {
+            let message = receiver.recv().await?;
+            if let Some(email) = message_to_email(message.clone(), from.clone()) {
+                match self.send(&http, email).await {
+                    Ok(()) => receiver.report(DeliveryReport::delivered(&message)),
+                    Err(err) => {
+                        log::error!("Sending error: {}", err);
+                        self.auth.check_rejected(&err, ConnectorKind::Output).await;
+                        receiver.report(DeliveryReport::failed(message, err));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{EngineHandle, Event};
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn message_list() {
+        let list: MessageList = serde_json::from_str(r#"{"@odata.context": "x"}"#).unwrap();
+        assert!(list.value.is_empty());
+
+        let list: MessageList = serde_json::from_str(r#"{"value": [{"id": "a1"}]}"#).unwrap();
+        assert_eq!(list.value[0].id, "a1");
+    }
+
+    #[test]
+    fn round_trip() {
+        let message = Message::default()
+            .user("user@domain.com")
+            .service_name("s-echo")
+            .args(["arg0"])
+            .body("hello")
+            .attach([("file.bin", b"1234".to_vec())]);
+
+        let from = Mailbox::new(None, "service@company.com".parse().unwrap());
+        let email = message_to_email(message, from).unwrap();
+        let body = STANDARD.decode(send_body(&email)).unwrap();
+
+        let received = mime_to_message(&body).unwrap();
+        assert_eq!(received.user, "service@company.com");
+        assert_eq!(received.service_name, "s-echo");
+        assert_eq!(received.args, ["arg0"]);
+        assert_eq!(received.body.trim_end(), "hello");
+        assert_eq!(&received.attached_data["file.bin"][..], b"1234");
+    }
+
+    #[tokio::test]
+    async fn rejected_credentials() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).await.unwrap();
+            let response = "HTTP/1.1 401 Unauthorized\r\ncontent-length: 0\r\n\r\n";
+            stream.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let auth = OAuth2::client_credentials(format!("http://{}/token", addr), "id", "secret");
+        let http = reqwest::Client::builder().no_proxy().build().unwrap();
+        let err = auth.access_token(&http).await.unwrap_err();
+        assert_eq!(err.status(), Some(reqwest::StatusCode::UNAUTHORIZED));
+
+        let engine = EngineHandle::default();
+        let mut events = engine.events();
+        let check = auth.check_rejected(&err, ConnectorKind::Input);
+        engine.scope(check).await;
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            Event::AuthFailed {
+                connector: ConnectorKind::Input,
+                ..
+            }
+        ));
+    }
+}
@@ -1,29 +1,229 @@
-use crate::channel::{ClosedChannel, Sender};
+use super::config::{self, ConfigError};
+use super::proxy::Proxy;
+use super::tls::TlsBackend;
+use crate::channel::Sender;
+use crate::engine::{ConnectorKind, EngineHandle, Event};
 use crate::interface::InputConnector;
 use crate::message::Message;
 
 use async_trait::async_trait;
 use imap::{error::Error, Session};
-use mailparse::{DispositionType, MailHeaderMap, ParsedMail};
-use native_tls::{TlsConnector, TlsStream};
+use mailparse::{DispositionType, MailHeaderMap};
+use tokio::{task, time};
+
+pub use mailparse::ParsedMail;
 
 use std::collections::HashMap;
-use std::io::{Read, Write};
-use std::net::TcpStream;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Duration;
 
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
 /// Input connector that acts as an IMAP client
 /// The service fetchs and removes the email from the server, and transforms it to messages.
 /// The first word of the subjet is interpreted as the service name.
 /// The following spaced-separated words are the arguments.
 ///
 /// This connector makes attempts to the ICMP server each [`ImapClient::polling_time`] seconds.
-#[derive(Default, Clone)]
+/// No emails are fetched while the services are busy and can not accept more messages.
+///
+/// An email is only removed once the engine acknowledges its message
+/// (see [`Engine::ack_mode()`](crate::engine::Engine::ack_mode())),
+/// so it is read again if the engine could not process it.
+///
+/// In [peek mode](ImapClient::peek()), or if the engine runs in
+/// [dry-run mode](crate::engine::Engine::dry_run()),
+/// the emails are only read, without being marked as seen or removed from the server.
+///
+/// The emails are tracked by UID, and the last processed UID is kept as a cursor,
+/// so emails are neither reprocessed nor skipped when other clients modify the mailbox.
+/// Use [`ImapClient::cursor_file()`] to keep the cursor across restarts.
+///
+/// The emails are transformed by [`DefaultMailParser`],
+/// use [`ImapClient::parser()`] to customize it.
+/// See [`MailRouting`] to route the emails by the recipient address instead of by the subject.
+///
+/// By default, only the `INBOX` folder is watched.
+/// Use [`ImapClient::folder()`] to watch other folders, i.e. pre-sorted by provider-side filters.
+///
+/// The IMAP communication is performed in blocking threads,
+/// so this connector can be used in a `current_thread` runtime.
+#[derive(Clone)]
 pub struct ImapClient {
     imap_domain: String,
     email: String,
     password: String,
     polling_time: Duration,
+    port: Option<u16>,
+    insecure: bool,
+    tls: TlsBackend,
+    proxy: Option<Proxy>,
+    connect_timeout: Duration,
+    timeout: Duration,
+    peek: bool,
+    cursor_file: Option<PathBuf>,
+    folders: Vec<Folder>,
+    routing: MailRouting,
+    parser: Arc<dyn MailParser>,
+}
+
+impl Default for ImapClient {
+    fn default() -> Self {
+        Self {
+            imap_domain: String::default(),
+            email: String::default(),
+            password: String::default(),
+            polling_time: Duration::default(),
+            port: None,
+            insecure: false,
+            tls: TlsBackend::default(),
+            proxy: None,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            timeout: DEFAULT_TIMEOUT,
+            peek: false,
+            cursor_file: None,
+            folders: Vec::new(),
+            routing: MailRouting::default(),
+            parser: Arc::new(DefaultMailParser),
+        }
+    }
+}
+
+/// Transforms a received email into a message.
+///
+/// It is implemented for closures, so the default transformation can be extended:
+/// ```rust
+/// use service_io::connectors::{DefaultMailParser, ImapClient, MailParser, ParsedMail};
+///
+/// // Lines with the form 'key=value' in the body are added as arguments
+/// let client = ImapClient::default().parser(|email: &ParsedMail| {
+///     let mut message = DefaultMailParser.parse(email);
+///     let pairs = message.body.lines().filter(|line| line.contains('='));
+///     message.args.extend(pairs.map(|line| line.trim().to_owned()).collect::<Vec<_>>());
+///     message
+/// });
+/// ```
+pub trait MailParser: Send + Sync {
+    fn parse(&self, email: &ParsedMail) -> Message;
+}
+
+impl<F> MailParser for F
+where
+    F: Fn(&ParsedMail) -> Message + Send + Sync,
+{
+    fn parse(&self, email: &ParsedMail) -> Message {
+        self(email)
+    }
+}
+
+/// Default [`MailParser`].
+/// The sender is the user, the first word of the subject is the service name,
+/// the following words are the arguments, the plain text part is the body
+/// and the attachments are the attached data.
+#[derive(Default, Clone, Copy, Debug)]
+pub struct DefaultMailParser;
+
+impl MailParser for DefaultMailParser {
+    fn parse(&self, email: &ParsedMail) -> Message {
+        email_to_message(email)
+    }
+}
+
+/// Configuration to transform the emails into messages.
+#[derive(Clone)]
+struct Parsing {
+    routing: MailRouting,
+    parser: Arc<dyn MailParser>,
+}
+
+/// How the service name of an email is found.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MailRouting {
+    /// The first word of the subject is the service name.
+    #[default]
+    Subject,
+
+    /// The service name is the extension of the recipient address (plus addressing),
+    /// i.e. an email sent to `me+alarm@gmail.com` is routed to the service `"alarm"`.
+    /// All the words of the subject are arguments.
+    /// If the recipient address has no extension, the subject is used.
+    AddressExtension,
+}
+
+/// Watched mailbox folder.
+#[derive(Clone)]
+struct Folder {
+    name: String,
+    prefix: String,
+}
+
+/// Connection to the IMAP server, with or without TLS.
+pub(super) enum ImapStream {
+    #[cfg(feature = "native-tls")]
+    NativeTls(native_tls::TlsStream<TcpStream>),
+    #[cfg(feature = "rustls")]
+    Rustls(Box<rustls::StreamOwned<rustls::ClientConnection, TcpStream>>),
+    Plain(TcpStream),
+}
+
+impl ImapStream {
+    fn tls(backend: TlsBackend, domain: &str, tcp: TcpStream) -> io::Result<ImapStream> {
+        match backend {
+            #[cfg(feature = "native-tls")]
+            TlsBackend::NativeTls => {
+                let tls = native_tls::TlsConnector::new().map_err(io::Error::other)?;
+                let stream = tls.connect(domain, tcp).map_err(io::Error::other)?;
+                Ok(ImapStream::NativeTls(stream))
+            }
+            #[cfg(feature = "rustls")]
+            TlsBackend::Rustls => {
+                let name = rustls::pki_types::ServerName::try_from(domain.to_string())
+                    .map_err(io::Error::other)?;
+                let connection = rustls::ClientConnection::new(super::tls::rustls_config(), name)
+                    .map_err(io::Error::other)?;
+                let stream = rustls::StreamOwned::new(connection, tcp);
+                Ok(ImapStream::Rustls(Box::new(stream)))
+            }
+        }
+    }
+}
+
+impl Read for ImapStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            #[cfg(feature = "native-tls")]
+            ImapStream::NativeTls(stream) => stream.read(buf),
+            #[cfg(feature = "rustls")]
+            ImapStream::Rustls(stream) => stream.read(buf),
+            ImapStream::Plain(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for ImapStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            #[cfg(feature = "native-tls")]
+            ImapStream::NativeTls(stream) => stream.write(buf),
+            #[cfg(feature = "rustls")]
+            ImapStream::Rustls(stream) => stream.write(buf),
+            ImapStream::Plain(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            #[cfg(feature = "native-tls")]
+            ImapStream::NativeTls(stream) => stream.flush(),
+            #[cfg(feature = "rustls")]
+            ImapStream::Rustls(stream) => stream.flush(),
+            ImapStream::Plain(stream) => stream.flush(),
+        }
+    }
 }
 
 impl ImapClient {
@@ -47,77 +247,487 @@ impl ImapClient {
         self
     }
 
-    fn connect(&self) -> Result<Session<TlsStream<TcpStream>>, Error> {
-        let tls = TlsConnector::builder().build().unwrap();
-        let client = imap::connect(
-            (self.imap_domain.as_str(), 993),
-            self.imap_domain.as_str(),
-            &tls,
-        )?;
+    /// Port of the IMAP server. By default, 993.
+    pub fn port(mut self, value: u16) -> Self {
+        self.port = Some(value);
+        self
+    }
 
-        client.login(&self.email, &self.password).map_err(|e| e.0)
+    /// Connect without TLS. Only intended for local servers and testing.
+    pub fn insecure(mut self) -> Self {
+        self.insecure = true;
+        self
+    }
+
+    /// TLS implementation used to connect. By default, [`TlsBackend::default()`].
+    pub fn tls_backend(mut self, backend: TlsBackend) -> Self {
+        self.tls = backend;
+        self
+    }
+
+    /// Connect through a proxy.
+    /// By default, the proxy of the environment is used, if any. See [`Proxy::from_env()`].
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Maximum time to establish the connection with the server. By default, 30 seconds.
+    pub fn connect_timeout(mut self, duration: Duration) -> Self {
+        self.connect_timeout = duration;
+        self
+    }
+
+    /// Maximum time waiting for the server in each read or write. By default, 60 seconds.
+    /// Once exceeded, the connection is considered lost and the client reconnects.
+    pub fn timeout(mut self, duration: Duration) -> Self {
+        self.timeout = duration;
+        self
+    }
+
+    /// Read the emails without consuming them: they are neither marked as seen nor removed.
+    /// Useful to observe a real mailbox.
+    pub fn peek(mut self, enabled: bool) -> Self {
+        self.peek = enabled;
+        self
+    }
+
+    /// File where the UID of the last processed email is saved,
+    /// to resume from it after a restart.
+    /// It is created if it does not exist.
+    /// In read-only mode ([`ImapClient::peek()`] or a dry run of the engine) the file is read
+    /// but never written, so a later real run does not skip the emails observed.
+    pub fn cursor_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.cursor_file = Some(path.into());
+        self
+    }
+
+    /// Watch the folder `name`.
+    /// The service names of its emails are prefixed with `prefix`,
+    /// i.e. with the prefix `"bot-"`, the subject `"echo hi"` is routed to the service `"bot-echo"`.
+    ///
+    /// Once a folder is added, `INBOX` is only watched if it is also added.
+    pub fn folder(mut self, name: impl Into<String>, prefix: impl Into<String>) -> Self {
+        self.folders.push(Folder {
+            name: name.into(),
+            prefix: prefix.into(),
+        });
+        self
+    }
+
+    /// Strategy to find the service name of the emails. By default, [`MailRouting::Subject`].
+    pub fn routing(mut self, routing: MailRouting) -> Self {
+        self.routing = routing;
+        self
+    }
+
+    /// Customize how the emails are transformed into messages.
+    /// By default, [`DefaultMailParser`].
+    /// The [`MailRouting`] is applied over the parsed message.
+    pub fn parser(mut self, parser: impl MailParser + 'static) -> Self {
+        self.parser = Arc::new(parser);
+        self
+    }
+
+    fn watched_folders(&self) -> Vec<Folder> {
+        match self.folders.is_empty() {
+            true => vec![Folder {
+                name: "INBOX".into(),
+                prefix: String::new(),
+            }],
+            false => self.folders.clone(),
+        }
+    }
+
+    fn imap_port(&self) -> u16 {
+        self.port.unwrap_or(993)
+    }
+
+    /// Connect to the first reachable address of the server.
+    fn tcp_connect(&self) -> io::Result<TcpStream> {
+        let mut last_err = io::Error::from(io::ErrorKind::AddrNotAvailable);
+        for addr in (self.imap_domain.as_str(), self.imap_port()).to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, self.connect_timeout) {
+                Ok(stream) => return Ok(stream),
+                Err(err) => last_err = err,
+            }
+        }
+        Err(last_err)
+    }
+
+    /// Check that the configuration is complete.
+    /// [`InputConnector::run()`] finishes with an error log if it is not.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut errors = Vec::new();
+        config::required(&mut errors, "domain", &self.imap_domain);
+        config::required(&mut errors, "email", &self.email);
+        config::required(&mut errors, "password", &self.password);
+        ConfigError::check("ImapClient", errors)
+    }
+
+    /// Connect in a blocking thread to not block the runtime,
+    /// even if it is a `current_thread` runtime.
+    async fn blocking_connect(
+        &self,
+        engine: Option<EngineHandle>,
+    ) -> Result<Session<ImapStream>, Error> {
+        // The proxy handshake is done in the runtime, only the IMAP session is blocking.
+        let tcp = match Proxy::resolve(self.proxy.as_ref(), &self.imap_domain) {
+            Some(proxy) => {
+                let connection = proxy.connect(&self.imap_domain, self.imap_port());
+                let stream = time::timeout(self.connect_timeout, connection)
+                    .await
+                    .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))??;
+                Some(stream.into_std()?)
+            }
+            None => None,
+        };
+
+        let client = self.clone();
+        task::spawn_blocking(move || client.connect(tcp, engine.as_ref()))
+            .await
+            .unwrap()
+    }
+
+    fn connect(
+        &self,
+        tcp: Option<TcpStream>,
+        engine: Option<&EngineHandle>,
+    ) -> Result<Session<ImapStream>, Error> {
+        let tcp = match tcp {
+            Some(tcp) => {
+                tcp.set_nonblocking(false)?;
+                tcp
+            }
+            None => self.tcp_connect()?,
+        };
+        tcp.set_read_timeout(Some(self.timeout))?;
+        tcp.set_write_timeout(Some(self.timeout))?;
+        let stream = match self.insecure {
+            true => ImapStream::Plain(tcp),
+            false => ImapStream::tls(self.tls, &self.imap_domain, tcp)?,
+        };
+
+        let mut client = imap::Client::new(stream);
+        client.read_greeting()?;
+
+        client
+            .login(&self.email, &self.password)
+            .map_err(|(err, _)| {
+                if let Some(engine) = engine {
+                    engine.emit(Event::AuthFailed {
+                        connector: ConnectorKind::Input,
+                        error: err.to_string(),
+                    });
+                }
+                err
+            })
     }
 }
 
 #[async_trait]
 impl InputConnector for ImapClient {
-    async fn run(mut self: Box<Self>, sender: Sender) -> Result<(), ClosedChannel> {
-        tokio::task::spawn_blocking(move || {
-            let mut session = self.connect().unwrap();
-            loop {
-                std::thread::sleep(self.polling_time);
-
-                match read_inbox(&mut session) {
-                    Ok(Some(message)) => sender.blocking_send(message)?,
-                    Ok(None) => (),
-                    Err(err) => {
-                        log::warn!("{}", err);
-                        session = match self.connect() {
-                            Ok(session) => {
-                                log::info!("Connection restored");
-                                session
-                            }
-                            Err(err) => {
-                                log::error!("{}", err);
-                                continue;
-                            }
-                        }
+    fn describe(&self) -> String {
+        format!("ImapClient({} at {})", self.email, self.imap_domain)
+    }
+
+    async fn run(self: Box<Self>, sender: Sender) -> Result<(), crate::Error> {
+        self.validate()?;
+
+        let engine = EngineHandle::current();
+        let read_only = self.peek || engine.as_ref().is_some_and(|engine| engine.is_dry_run());
+        let mut session = self
+            .blocking_connect(engine.clone())
+            .await
+            .map_err(|err| match err {
+                // The server only answers NO to the login while connecting.
+                Error::No(response) => crate::Error::Auth(response),
+                err => crate::Error::connector("IMAP", err),
+            })?;
+
+        let folders = self.watched_folders();
+        let parsing = Parsing {
+            routing: self.routing,
+            parser: self.parser.clone(),
+        };
+        let mut cursors = UidCursors::load(self.cursor_file.clone(), !read_only);
+
+        // Index of the folder to read first, rotated to read the folders evenly.
+        let mut turn = 0;
+        loop {
+            time::sleep(self.polling_time).await;
+
+            let permit = sender.permit().await?;
+            let folders = folders.clone();
+            let parsing = parsing.clone();
+            let (returned_session, returned_cursors, result) = task::spawn_blocking(move || {
+                let mut result = Ok(None);
+                for index in 0..folders.len() {
+                    let folder = &folders[(turn + index) % folders.len()];
+                    result = read_folder(&mut session, folder, &mut cursors, read_only, &parsing);
+                    if !matches!(result, Ok(None)) {
+                        break;
+                    }
+                }
+                (session, cursors, result)
+            })
+            .await
+            .unwrap();
+
+            session = returned_session;
+            cursors = returned_cursors;
+            turn += 1;
+            let result = match result {
+                Ok(Some(fetched)) if !read_only => {
+                    // The email is only removed once the engine is done with the message.
+                    let acked = match fetched.message {
+                        Some(message) => permit.send_acked(message).wait().await,
+                        None => true,
+                    };
+                    if !acked {
+                        log::warn!("Email not processed, it will be read again");
+                        continue;
+                    }
+
+                    let (returned_session, returned_cursors, result) =
+                        task::spawn_blocking(move || {
+                            let result = remove_email(
+                                &mut session,
+                                &mut cursors,
+                                &fetched.folder,
+                                fetched.uid,
+                            );
+                            (session, cursors, result)
+                        })
+                        .await
+                        .unwrap();
+
+                    session = returned_session;
+                    cursors = returned_cursors;
+                    result
+                }
+                Ok(Some(fetched)) => {
+                    // Not persisted in read-only mode, so it does not touch the file system.
+                    cursors.advance(&fetched.folder, fetched.uid);
+                    if let Some(message) = fetched.message {
+                        permit.send(message);
                     }
+                    Ok(())
+                }
+                Ok(None) => Ok(()),
+                Err(err) => Err(err),
+            };
+
+            if let Err(err) = result {
+                log::warn!("{}", err);
+                match self.blocking_connect(engine.clone()).await {
+                    Ok(new_session) => {
+                        log::info!("Connection restored");
+                        session = new_session;
+                    }
+                    Err(err) => log::error!("{}", err),
                 }
             }
-        })
-        .await
-        .unwrap()
+        }
+    }
+}
+
+/// Email read from a folder, not removed yet.
+struct Fetched {
+    folder: String,
+    uid: u32,
+    message: Option<Message>,
+}
+
+/// Position in a folder: UID of the last processed email.
+/// UIDs are only meaningful while the folder `UIDVALIDITY` does not change.
+#[derive(Default)]
+struct UidCursor {
+    validity: Option<u32>,
+    last: u32,
+}
+
+impl UidCursor {
+    /// Restarts the cursor if the UIDs of the folder were reassigned.
+    fn check_validity(&mut self, validity: Option<u32>) {
+        if self.validity != validity {
+            if self.validity.is_some() {
+                log::warn!("Folder UIDVALIDITY changed, reading it from the beginning");
+            }
+            self.validity = validity;
+            self.last = 0;
+        }
     }
 }
 
-fn read_inbox<T: Read + Write>(session: &mut Session<T>) -> Result<Option<Message>, Error> {
-    session.select("INBOX")?;
+/// Cursors of the watched folders, optionally saved in a file.
+/// Each line of the file is: `<uid validity> <last uid> <folder name>`.
+/// Without `persist`, the file is only read and the cursors are advanced in memory.
+#[derive(Default)]
+struct UidCursors {
+    folders: HashMap<String, UidCursor>,
+    file: Option<PathBuf>,
+}
 
-    let emails = session.fetch("1", "RFC822")?;
+impl UidCursors {
+    fn load(file: Option<PathBuf>, persist: bool) -> UidCursors {
+        let content = file
+            .as_ref()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .unwrap_or_default();
 
-    if let Some(email) = emails.iter().next() {
-        session.store(format!("{}", email.message), "+FLAGS (\\Deleted)")?;
+        let folders = content
+            .lines()
+            .filter_map(|line| {
+                let mut values = line.splitn(3, ' ');
+                let validity = values.next()?.parse().ok()?;
+                let last = values.next()?.parse().ok()?;
+                let name = values.next().unwrap_or("INBOX").to_owned();
+                let cursor = UidCursor {
+                    validity: Some(validity),
+                    last,
+                };
+                Some((name, cursor))
+            })
+            .collect();
 
-        session.expunge()?;
+        UidCursors {
+            folders,
+            file: file.filter(|_| persist),
+        }
+    }
 
-        if let Some(body) = email.body() {
-            log::trace!(
-                "Raw email:\n{}",
-                std::str::from_utf8(body).unwrap_or("No utf8")
-            );
+    fn get(&mut self, folder: &str) -> &mut UidCursor {
+        self.folders.entry(folder.into()).or_default()
+    }
 
-            match mailparse::parse_mail(body) {
-                Ok(parsed) => return Ok(Some(email_to_message(parsed))),
-                Err(err) => log::error!("{}", err),
+    fn advance(&mut self, folder: &str, uid: u32) {
+        self.get(folder).last = uid;
+        if let Some(path) = &self.file {
+            let mut content = String::new();
+            for (name, cursor) in &self.folders {
+                let validity = cursor.validity.unwrap_or_default();
+                content += &format!("{} {} {}\n", validity, cursor.last, name);
+            }
+            if let Err(err) = std::fs::write(path, content) {
+                log::warn!(
+                    "Can not save the IMAP cursor in {}: {}",
+                    path.display(),
+                    err
+                );
             }
         }
     }
+}
+
+/// Reads the next email of the folder after its cursor.
+/// In `read_only` mode, the folder is not modified.
+/// The cursor is not advanced until the email is removed (or processed in `read_only` mode).
+fn read_folder<T: Read + Write>(
+    session: &mut Session<T>,
+    folder: &Folder,
+    cursors: &mut UidCursors,
+    read_only: bool,
+    parsing: &Parsing,
+) -> Result<Option<Fetched>, Error> {
+    let mailbox = match read_only {
+        true => session.examine(&folder.name)?,
+        false => session.select(&folder.name)?,
+    };
+    let cursor = cursors.get(&folder.name);
+    cursor.check_validity(mailbox.uid_validity);
+    let last = cursor.last;
+
+    // The range 'n:*' contains at least the greatest UID, even if it is lower than 'n'.
+    let uids = session.uid_search(format!("UID {}:*", last + 1))?;
+    let uid = match uids.into_iter().filter(|&uid| uid > last).min() {
+        Some(uid) => uid,
+        None => return Ok(None),
+    };
+
+    let query = match read_only {
+        true => "BODY.PEEK[]",
+        false => "RFC822",
+    };
+    let emails = session.uid_fetch(uid.to_string(), query)?;
+
+    let message = emails
+        .iter()
+        .next()
+        .and_then(|email| email.body())
+        .and_then(|body| parse_email(body, parsing))
+        .map(|mut message| {
+            message.service_name = format!("{}{}", folder.prefix, message.service_name);
+            message
+        });
 
-    Ok(None)
+    Ok(Some(Fetched {
+        folder: folder.name.clone(),
+        uid,
+        message,
+    }))
 }
 
-fn email_to_message(email: ParsedMail) -> Message {
+/// Removes an email read by [`read_folder()`] from its folder, that must be still selected.
+fn remove_email<T: Read + Write>(
+    session: &mut Session<T>,
+    cursors: &mut UidCursors,
+    folder: &str,
+    uid: u32,
+) -> Result<(), Error> {
+    session.uid_store(uid.to_string(), "+FLAGS (\\Deleted)")?;
+    session.expunge()?;
+    cursors.advance(folder, uid);
+    Ok(())
+}
+
+fn parse_email(body: &[u8], parsing: &Parsing) -> Option<Message> {
+    log::trace!(
+        "Raw email:\n{}",
+        std::str::from_utf8(body).unwrap_or("No utf8")
+    );
+
+    match mailparse::parse_mail(body) {
+        Ok(parsed) => {
+            let extension = match parsing.routing {
+                MailRouting::Subject => None,
+                MailRouting::AddressExtension => address_extension(&parsed),
+            };
+            let mut message = parsing.parser.parse(&parsed);
+            if let Some(extension) = extension {
+                if !message.service_name.is_empty() {
+                    message.args.insert(0, message.service_name);
+                }
+                message.service_name = extension;
+            }
+            Some(message)
+        }
+        Err(err) => {
+            log::error!("{}", err);
+            None
+        }
+    }
+}
+
+/// Extension of the first recipient address with one, i.e. `alarm` in `me+alarm@gmail.com`.
+fn address_extension(email: &ParsedMail) -> Option<String> {
+    ["Delivered-To", "To", "Cc"]
+        .iter()
+        .flat_map(|header| email.headers.get_all_values(header))
+        .filter_map(|value| mailparse::addrparse(&value).ok())
+        .flat_map(|list| list.to_vec())
+        .flat_map(|addr| match addr {
+            mailparse::MailAddr::Single(info) => vec![info],
+            mailparse::MailAddr::Group(group) => group.addrs,
+        })
+        .find_map(|info| {
+            let local = info.addr.split('@').next()?;
+            let (_, extension) = local.split_once('+')?;
+            Some(extension.to_owned()).filter(|extension| !extension.is_empty())
+        })
+}
+
+pub(crate) fn email_to_message(email: &ParsedMail) -> Message {
     let subject = email.headers.get_first_value("Subject").unwrap_or_default();
     let mut subject_args = subject.split_whitespace().map(|s| s.to_owned());
 
@@ -133,7 +743,10 @@ fn email_to_message(email: ParsedMail) -> Message {
         let content_disposition = part.get_content_disposition();
         if let DispositionType::Attachment = content_disposition.disposition {
             if let Some(filename) = content_disposition.params.get("filename") {
-                files.insert(filename.into(), part.get_body_raw().unwrap_or_default());
+                files.insert(
+                    filename.into(),
+                    part.get_body_raw().unwrap_or_default().into(),
+                );
             }
         }
     }
@@ -142,17 +755,55 @@ fn email_to_message(email: ParsedMail) -> Message {
         user: email
             .headers
             .get_first_value("From")
-            .map(|from_list| {
-                mailparse::addrparse(&from_list)
-                    .expect("Have a 'From' email")
-                    .extract_single_info()
-                    .expect("Have at least one 'From' email")
-                    .addr
-            })
+            .and_then(|from_list| mailparse::addrparse(&from_list).ok())
+            .and_then(|from_list| from_list.extract_single_info())
+            .map(|from| from.addr)
             .unwrap_or_default(),
         service_name: subject_args.next().unwrap_or_default(),
         args: subject_args.collect(),
         body,
         attached_data: files,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn hung_server() {
+        // Accepts the connection but never sends the greeting.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let client = ImapClient::default()
+            .domain("127.0.0.1")
+            .port(port)
+            .insecure()
+            .timeout(Duration::from_millis(100));
+
+        let connection = client.blocking_connect(None);
+        let result = time::timeout(Duration::from_secs(5), connection).await;
+        assert!(matches!(result, Ok(Err(_))));
+        drop(listener);
+    }
+
+    #[test]
+    fn read_only_cursors() {
+        let path =
+            std::env::temp_dir().join(format!("service-io-imap-cursor-{}", std::process::id()));
+        std::fs::write(&path, "7 10 INBOX\n").unwrap();
+
+        let mut cursors = UidCursors::load(Some(path.clone()), false);
+        assert_eq!(cursors.get("INBOX").last, 10);
+        cursors.advance("INBOX", 11);
+        assert_eq!(cursors.get("INBOX").last, 11);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "7 10 INBOX\n");
+
+        let mut cursors = UidCursors::load(Some(path.clone()), true);
+        cursors.advance("INBOX", 11);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "7 11 INBOX\n");
+        std::fs::remove_file(path).unwrap();
     }
 }
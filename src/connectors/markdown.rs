@@ -0,0 +1,414 @@
+use crate::channel::{self, Receiver, Sender};
+use crate::error::Error;
+use crate::interface::OutputConnector;
+use crate::message::format::HTML_BODY_KEY;
+use crate::message::Message;
+
+use async_trait::async_trait;
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
+
+/// How the Markdown bodies are rendered by [`MarkdownOutput`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkdownFormat {
+    /// The HTML version is added with the [`HTML_BODY_KEY`] and the body is left as plain text,
+    /// as [`SmtpClient`](super::SmtpClient) expects.
+    Html,
+    /// The body is written in the MarkdownV2 syntax of Telegram bots,
+    /// to be sent with `parse_mode` set to `MarkdownV2`.
+    TelegramV2,
+    /// The markup is removed from the body, as for [`DebugStdout`](super::DebugStdout)
+    /// or the SMS connectors.
+    Plain,
+}
+
+/// Output middleware that renders the bodies written in Markdown for the wrapped output.
+///
+/// Services can reply with Markdown, and each output shows it in the best way its transport can.
+/// Only the bodies with explicit Markdown markup (emphasis, headings, inline code,
+/// fenced code blocks or links) are rendered, so plain replies, as the output of a command,
+/// are passed untouched. Messages already having an HTML version
+/// (i.e. from a [`Document`](crate::message::format::Document)) are not rendered either.
+///
+/// # Example
+/// ```rust no_run
+/// use service_io::connectors::{ImapClient, MarkdownFormat, MarkdownOutput, SmtpClient};
+/// use service_io::engine::Engine;
+/// use service_io::services::Echo;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     Engine::default()
+///         .input(ImapClient::default() /* ... */)
+///         .output(MarkdownOutput::new(SmtpClient::default() /* ... */, MarkdownFormat::Html))
+///         .add_service("s-echo", Echo)
+///         .run()
+///         .await;
+/// }
+/// ```
+pub struct MarkdownOutput<O> {
+    output: O,
+    format: MarkdownFormat,
+}
+
+impl<O: OutputConnector + Send + 'static> MarkdownOutput<O> {
+    /// Wraps `output`, rendering the Markdown bodies in `format`.
+    pub fn new(output: O, format: MarkdownFormat) -> Self {
+        Self { output, format }
+    }
+}
+
+#[async_trait]
+impl<O: OutputConnector + Send + 'static> OutputConnector for MarkdownOutput<O> {
+    async fn connect(&mut self) -> Result<(), Error> {
+        self.output.connect().await
+    }
+
+    fn describe(&self) -> String {
+        format!("MarkdownOutput({})", self.output.describe())
+    }
+
+    async fn run(self: Box<Self>, mut receiver: Receiver) -> Result<(), Error> {
+        let MarkdownOutput { output, format } = *self;
+        let (sender, output_receiver) = channel::channel(1);
+
+        tokio::select! {
+            result = Box::new(output).run(output_receiver) => result,
+            result = forward(format, &mut receiver, &sender) => result,
+        }
+    }
+}
+
+async fn forward(
+    format: MarkdownFormat,
+    receiver: &mut Receiver,
+    sender: &Sender,
+) -> Result<(), Error> {
+    loop {
+        let message = receiver.recv().await?;
+        sender.send(render(format, message)).await?;
+    }
+}
+
+fn render(format: MarkdownFormat, mut message: Message) -> Message {
+    if message.metadata.contains_key(HTML_BODY_KEY) || !is_markdown(&message.body) {
+        return message;
+    }
+
+    match format {
+        MarkdownFormat::Html => {
+            let html = html(&message.body);
+            message.body = Renderer::new(Syntax::Plain).render(&message.body);
+            message.metadata.insert(HTML_BODY_KEY.into(), html);
+        }
+        MarkdownFormat::TelegramV2 => {
+            message.body = Renderer::new(Syntax::TelegramV2).render(&message.body);
+        }
+        MarkdownFormat::Plain => {
+            message.body = Renderer::new(Syntax::Plain).render(&message.body);
+        }
+    }
+    message
+}
+
+fn parser(text: &str) -> Parser<'_> {
+    Parser::new_ext(text, Options::ENABLE_STRIKETHROUGH)
+}
+
+/// Whether the text has explicit Markdown markup.
+fn is_markdown(text: &str) -> bool {
+    parser(text)
+        .into_offset_iter()
+        .any(|(event, range)| match event {
+            Event::Start(Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Link { .. }) => {
+                true
+            }
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(_))) => true,
+            // Only the "# Title" headings: an underlined line is usual in plain text.
+            Event::Start(Tag::Heading { .. }) => text[range].starts_with('#'),
+            Event::Code(_) => true,
+            _ => false,
+        })
+}
+
+fn html(text: &str) -> String {
+    // The raw HTML is escaped, the bodies can contain text of the users.
+    let events = parser(text).map(|event| match event {
+        Event::Html(html) | Event::InlineHtml(html) => Event::Text(html),
+        event => event,
+    });
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, events);
+    html
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Syntax {
+    Plain,
+    TelegramV2,
+}
+
+/// Renders Markdown as text in another syntax.
+struct Renderer {
+    syntax: Syntax,
+    text: String,
+    /// Next number of each nested list, `None` for the unordered ones.
+    lists: Vec<Option<u64>>,
+    /// Destination and start of the text of the links being rendered.
+    links: Vec<(String, usize)>,
+    code_block: Option<String>,
+    /// Right after the bullet of a list item, where the next paragraph begins.
+    item_start: bool,
+}
+
+impl Renderer {
+    fn new(syntax: Syntax) -> Self {
+        Self {
+            syntax,
+            text: String::new(),
+            lists: Vec::new(),
+            links: Vec::new(),
+            code_block: None,
+            item_start: false,
+        }
+    }
+
+    fn render(mut self, markdown: &str) -> String {
+        for event in parser(markdown) {
+            self.event(event);
+        }
+        self.text.trim_end().to_string()
+    }
+
+    fn telegram(&self) -> bool {
+        self.syntax == Syntax::TelegramV2
+    }
+
+    /// Separates a new block from the previous content.
+    fn block(&mut self) {
+        if self.item_start || self.text.is_empty() {
+            return;
+        }
+        let separator = match self.lists.is_empty() {
+            true => "\n\n",
+            false => "\n",
+        };
+        while !self.text.ends_with(separator) {
+            self.text.push('\n');
+        }
+        if !self.lists.is_empty() {
+            self.text.push_str(&"  ".repeat(self.lists.len()));
+        }
+    }
+
+    fn push_text(&mut self, text: &str) {
+        self.item_start = false;
+        let telegram = self.telegram();
+        match &mut self.code_block {
+            Some(code) => code.push_str(text),
+            None if telegram => self.text.push_str(&escape(text)),
+            None => self.text.push_str(text),
+        }
+    }
+
+    fn push_markup(&mut self, markup: &str) {
+        if self.telegram() {
+            self.text.push_str(markup);
+        }
+    }
+
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) | Event::Html(text) | Event::InlineHtml(text) => {
+                self.push_text(&text)
+            }
+            Event::Code(code) => {
+                self.item_start = false;
+                match self.telegram() {
+                    true => self.text.push_str(&format!("`{}`", escape_code(&code))),
+                    false => self.text.push_str(&code),
+                }
+            }
+            Event::SoftBreak | Event::HardBreak => self.push_text("\n"),
+            Event::Rule => {
+                self.block();
+                self.push_text("---");
+            }
+            Event::TaskListMarker(done) => match done {
+                true => self.push_text("[x] "),
+                false => self.push_text("[ ] "),
+            },
+            _ => (),
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph | Tag::BlockQuote(_) => self.block(),
+            Tag::Heading { .. } => {
+                self.block();
+                self.push_markup("*");
+            }
+            Tag::CodeBlock(_) => {
+                self.block();
+                self.code_block = Some(String::new());
+            }
+            Tag::List(first) => {
+                if self.lists.is_empty() {
+                    self.block();
+                }
+                self.lists.push(first);
+            }
+            Tag::Item => {
+                if !self.text.is_empty() {
+                    while !self.text.ends_with('\n') {
+                        self.text.push('\n');
+                    }
+                }
+                self.text.push_str(&"  ".repeat(self.lists.len() - 1));
+                let bullet = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    }
+                    _ => "- ".into(),
+                };
+                self.push_text(&bullet);
+                self.item_start = true;
+            }
+            Tag::Emphasis => self.push_markup("_"),
+            Tag::Strong => self.push_markup("*"),
+            Tag::Strikethrough => self.push_markup("~"),
+            Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. } => {
+                self.push_markup("[");
+                self.links.push((dest_url.to_string(), self.text.len()));
+            }
+            _ => (),
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Heading(_) => self.push_markup("*"),
+            TagEnd::CodeBlock => {
+                let code = self.code_block.take().unwrap_or_default();
+                let code = code.trim_end_matches('\n');
+                match self.telegram() {
+                    true => self
+                        .text
+                        .push_str(&format!("```\n{}\n```", escape_code(code))),
+                    false => {
+                        let indented = code.lines().map(|line| format!("    {}", line));
+                        self.text.push_str(&indented.collect::<Vec<_>>().join("\n"));
+                    }
+                }
+            }
+            TagEnd::List(_) => {
+                self.lists.pop();
+            }
+            TagEnd::Emphasis => self.push_markup("_"),
+            TagEnd::Strong => self.push_markup("*"),
+            TagEnd::Strikethrough => self.push_markup("~"),
+            TagEnd::Link | TagEnd::Image => {
+                let (url, start) = self.links.pop().unwrap_or_default();
+                match self.telegram() {
+                    true => self.text.push_str(&format!("]({})", escape_url(&url))),
+                    false if self.text[start..] != url => {
+                        self.text.push_str(&format!(" ({})", url));
+                    }
+                    false => (),
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Escapes the characters reserved by the MarkdownV2 of Telegram.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "_*[]()~`>#+-=|{}.!\\".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn escape_code(code: &str) -> String {
+    code.replace('\\', "\\\\").replace('`', "\\`")
+}
+
+fn escape_url(url: &str) -> String {
+    url.replace('\\', "\\\\").replace(')', "\\)")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::sync::mpsc;
+
+    const MARKDOWN: &str = "# Backup\n\n\
+        Finished **ok** in `12s`, see [the log](https://host/log).\n\n\
+        - disk_a\n- disk_b\n\n\
+        ```\nrsync -a\n```";
+
+    #[test]
+    fn detection() {
+        assert!(is_markdown(MARKDOWN));
+        assert!(is_markdown("*done*"));
+        assert!(!is_markdown("file_name_1 and file_name_2: 2 + 2 = 4"));
+        assert!(!is_markdown("Title\n-----\n    indented output\n- item"));
+    }
+
+    #[test]
+    fn plain() {
+        assert_eq!(
+            Renderer::new(Syntax::Plain).render(MARKDOWN),
+            "Backup\n\n\
+             Finished ok in 12s, see the log (https://host/log).\n\n\
+             - disk_a\n- disk_b\n\n    rsync -a"
+        );
+    }
+
+    #[test]
+    fn telegram() {
+        assert_eq!(
+            Renderer::new(Syntax::TelegramV2).render(MARKDOWN),
+            "*Backup*\n\n\
+             Finished *ok* in `12s`, see [the log](https://host/log)\\.\n\n\
+             \\- disk\\_a\n\\- disk\\_b\n\n```\nrsync -a\n```"
+        );
+    }
+
+    #[test]
+    fn html_escapes_raw_html() {
+        assert_eq!(
+            html("**<script>**"),
+            "<p><strong>&lt;script&gt;</strong></p>\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn render_for_output() {
+        let (output_sender, mut output_receiver) = mpsc::channel(1);
+        let output = MarkdownOutput::new(output_sender, MarkdownFormat::Html);
+        let (sender, receiver) = channel::channel(1);
+        tokio::spawn(Box::new(output).run(receiver));
+
+        let message = Message::default().user("user").body("1 < 2");
+        sender.send(message.clone()).await.unwrap();
+        assert_eq!(output_receiver.recv().await.unwrap(), message);
+
+        sender.send(message.body("_1 < 2_")).await.unwrap();
+        let delivered = output_receiver.recv().await.unwrap();
+        assert_eq!(delivered.body, "1 < 2");
+        assert_eq!(
+            delivered.metadata[HTML_BODY_KEY],
+            "<p><em>1 &lt; 2</em></p>\n"
+        );
+    }
+}
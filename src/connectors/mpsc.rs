@@ -1,4 +1,5 @@
-use crate::channel::{ClosedChannel, Receiver, Sender};
+use crate::channel::{Receiver, Sender};
+use crate::error::Error;
 use crate::interface::{InputConnector, OutputConnector};
 use crate::message::Message;
 
@@ -7,7 +8,7 @@ use tokio::sync::mpsc;
 
 #[async_trait]
 impl InputConnector for mpsc::Receiver<Message> {
-    async fn run(mut self: Box<Self>, sender: Sender) -> Result<(), ClosedChannel> {
+    async fn run(mut self: Box<Self>, sender: Sender) -> Result<(), Error> {
         loop {
             match self.recv().await {
                 Some(message) => sender.send(message).await?,
@@ -19,7 +20,7 @@ impl InputConnector for mpsc::Receiver<Message> {
 
 #[async_trait]
 impl OutputConnector for mpsc::Sender<Message> {
-    async fn run(self: Box<Self>, mut receiver: Receiver) -> Result<(), ClosedChannel> {
+    async fn run(self: Box<Self>, mut receiver: Receiver) -> Result<(), Error> {
         loop {
             let message = receiver.recv().await?;
             if self.send(message).await.is_err() {
@@ -0,0 +1,193 @@
+use crate::engine::{ConnectorKind, EngineHandle, Event};
+
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Time before the expiration when the access token is already considered expired.
+const EXPIRATION_MARGIN: Duration = Duration::from_secs(60);
+
+#[derive(Clone)]
+enum Grant {
+    RefreshToken(String),
+    ClientCredentials,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+/// OAuth2 credentials used by the connectors of REST APIs.
+///
+/// The access token is requested when needed and cached until it expires.
+/// The clones of an `OAuth2` share the cached token.
+#[derive(Clone)]
+pub struct OAuth2 {
+    token_url: String,
+    client_id: String,
+    client_secret: String,
+    grant: Grant,
+    scope: Option<String>,
+    token: Arc<Mutex<Option<(String, Instant)>>>,
+}
+
+impl OAuth2 {
+    /// Delegated flow: the access tokens are obtained from a `refresh_token`
+    /// previously authorized by the user.
+    pub fn refresh_token(
+        token_url: impl Into<String>,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+        refresh_token: impl Into<String>,
+    ) -> Self {
+        Self::new(
+            token_url,
+            client_id,
+            client_secret,
+            Grant::RefreshToken(refresh_token.into()),
+        )
+    }
+
+    /// Client credentials flow: the application is authorized by itself.
+    pub fn client_credentials(
+        token_url: impl Into<String>,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+    ) -> Self {
+        Self::new(
+            token_url,
+            client_id,
+            client_secret,
+            Grant::ClientCredentials,
+        )
+    }
+
+    /// Google credentials from a `refresh_token`.
+    pub fn google(
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+        refresh_token: impl Into<String>,
+    ) -> Self {
+        Self::refresh_token(
+            "https://oauth2.googleapis.com/token",
+            client_id,
+            client_secret,
+            refresh_token,
+        )
+    }
+
+    /// Microsoft identity platform credentials of an application registered in the `tenant`
+    /// (client credentials flow), with the default scope of Microsoft Graph.
+    pub fn microsoft_app(
+        tenant: &str,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+    ) -> Self {
+        Self::client_credentials(Self::microsoft_token_url(tenant), client_id, client_secret)
+            .scope("https://graph.microsoft.com/.default")
+    }
+
+    /// Microsoft identity platform credentials from a `refresh_token` (delegated flow).
+    pub fn microsoft(
+        tenant: &str,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+        refresh_token: impl Into<String>,
+    ) -> Self {
+        Self::refresh_token(
+            Self::microsoft_token_url(tenant),
+            client_id,
+            client_secret,
+            refresh_token,
+        )
+        .scope("https://graph.microsoft.com/.default offline_access")
+    }
+
+    fn microsoft_token_url(tenant: &str) -> String {
+        format!(
+            "https://login.microsoftonline.com/{}/oauth2/v2.0/token",
+            tenant
+        )
+    }
+
+    /// Scopes requested, separated by spaces.
+    pub fn scope(mut self, value: impl Into<String>) -> Self {
+        self.scope = Some(value.into());
+        self
+    }
+
+    fn new(
+        token_url: impl Into<String>,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+        grant: Grant,
+    ) -> Self {
+        Self {
+            token_url: token_url.into(),
+            client_id: client_id.into(),
+            client_secret: client_secret.into(),
+            grant,
+            scope: None,
+            token: Arc::default(),
+        }
+    }
+
+    /// Returns a valid access token, requesting a new one if the cached one expired.
+    pub(crate) async fn access_token(&self, http: &reqwest::Client) -> reqwest::Result<String> {
+        let mut token = self.token.lock().await;
+        if let Some((access_token, expiration)) = &*token {
+            if Instant::now() < *expiration {
+                return Ok(access_token.clone());
+            }
+        }
+
+        let mut params = vec![
+            ("client_id", self.client_id.as_str()),
+            ("client_secret", self.client_secret.as_str()),
+        ];
+        match &self.grant {
+            Grant::RefreshToken(refresh_token) => {
+                params.push(("grant_type", "refresh_token"));
+                params.push(("refresh_token", refresh_token));
+            }
+            Grant::ClientCredentials => params.push(("grant_type", "client_credentials")),
+        }
+        if let Some(scope) = &self.scope {
+            params.push(("scope", scope));
+        }
+
+        let response = http
+            .post(&self.token_url)
+            .form(&params)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<TokenResponse>()
+            .await?;
+
+        let lifetime = Duration::from_secs(response.expires_in.unwrap_or(3600));
+        let expiration = Instant::now() + lifetime.saturating_sub(EXPIRATION_MARGIN);
+        *token = Some((response.access_token.clone(), expiration));
+
+        Ok(response.access_token)
+    }
+
+    /// If the request was rejected because of the credentials, forgets the cached access token
+    /// and emits an [`Event::AuthFailed`].
+    pub(crate) async fn check_rejected(&self, err: &reqwest::Error, connector: ConnectorKind) {
+        if err.status() == Some(StatusCode::UNAUTHORIZED) {
+            *self.token.lock().await = None;
+            if let Some(engine) = EngineHandle::current() {
+                engine.emit(Event::AuthFailed {
+                    connector,
+                    error: err.to_string(),
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use crate::channel::{self, Receiver, Sender};
+use crate::error::Error;
+use crate::interface::OutputConnector;
+use crate::message::Message;
+
+use async_trait::async_trait;
+use tokio::time::{self, Instant};
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// How [`ProgressOutput`] delivers the progress messages of each request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressPolicy {
+    /// All of them are delivered.
+    All,
+    /// None of them are delivered, only the responses.
+    Drop,
+    /// At most one each interval is delivered, the rest are dropped.
+    RateLimit(Duration),
+    /// At most one each interval is delivered. The last one received during the interval
+    /// is delivered at its end, unless the response arrived before.
+    Collapse(Duration),
+}
+
+/// Output middleware that limits the progress messages of the services
+/// (see [`Message::progress()`]), so a slow request does not flood the user with them,
+/// as an email for each percentage.
+///
+/// The policy is applied to the progress of each user and service independently.
+/// The rest of messages are forwarded untouched.
+///
+/// # Example
+/// ```rust no_run
+/// use service_io::connectors::{ImapClient, ProgressOutput, ProgressPolicy, SmtpClient};
+/// use service_io::engine::Engine;
+/// use service_io::services::Process;
+///
+/// use std::time::Duration;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let policy = ProgressPolicy::Collapse(Duration::from_secs(60));
+///     Engine::default()
+///         .input(ImapClient::default() /* ... */)
+///         .output(ProgressOutput::new(SmtpClient::default() /* ... */, policy))
+///         .add_service("s-process", Process)
+///         .run()
+///         .await;
+/// }
+/// ```
+pub struct ProgressOutput<O> {
+    output: O,
+    policy: ProgressPolicy,
+}
+
+impl<O: OutputConnector + Send + 'static> ProgressOutput<O> {
+    /// Wraps `output`, delivering the progress messages by `policy`.
+    pub fn new(output: O, policy: ProgressPolicy) -> Self {
+        Self { output, policy }
+    }
+}
+
+#[async_trait]
+impl<O: OutputConnector + Send + 'static> OutputConnector for ProgressOutput<O> {
+    async fn connect(&mut self) -> Result<(), Error> {
+        self.output.connect().await
+    }
+
+    fn describe(&self) -> String {
+        format!("ProgressOutput({})", self.output.describe())
+    }
+
+    async fn run(self: Box<Self>, mut receiver: Receiver) -> Result<(), Error> {
+        let ProgressOutput { output, policy } = *self;
+        let (sender, output_receiver) = channel::channel(1);
+
+        tokio::select! {
+            result = Box::new(output).run(output_receiver) => result,
+            result = forward(policy, &mut receiver, &sender) => result,
+        }
+    }
+}
+
+/// Progress of a request, by user and service name.
+struct Progress {
+    delivered: Instant,
+    /// Last one received since delivered, waiting for the end of the interval.
+    held: Option<Message>,
+}
+
+async fn forward(
+    policy: ProgressPolicy,
+    receiver: &mut Receiver,
+    sender: &Sender,
+) -> Result<(), Error> {
+    let interval = match policy {
+        ProgressPolicy::All => return forward_filtered(receiver, sender, |_| true).await,
+        ProgressPolicy::Drop => {
+            return forward_filtered(receiver, sender, |message| !message.is_progress()).await
+        }
+        ProgressPolicy::RateLimit(interval) | ProgressPolicy::Collapse(interval) => interval,
+    };
+    let collapse = matches!(policy, ProgressPolicy::Collapse(_));
+
+    let mut progresses: HashMap<(String, String), Progress> = HashMap::new();
+    loop {
+        let next = progresses
+            .iter()
+            .filter(|(_, progress)| progress.held.is_some())
+            .min_by_key(|(_, progress)| progress.delivered)
+            .map(|(key, progress)| (key.clone(), progress.delivered + interval));
+
+        tokio::select! {
+            message = receiver.recv() => {
+                let message = message?;
+                let key = (message.user.clone(), message.service_name.clone());
+                if !message.is_progress() {
+                    progresses.remove(&key);
+                    sender.send(message).await?;
+                    continue;
+                }
+
+                // Forget the requests without held progress once their interval passed.
+                progresses.retain(|_, progress| {
+                    progress.held.is_some() || progress.delivered.elapsed() < interval
+                });
+                match progresses.get_mut(&key) {
+                    Some(progress) if collapse => progress.held = Some(message),
+                    Some(_) => log::trace!("Drop progress for '{}'", message.user),
+                    None => {
+                        let (delivered, held) = (Instant::now(), None);
+                        progresses.insert(key, Progress { delivered, held });
+                        sender.send(message).await?;
+                    }
+                }
+            }
+            _ = time::sleep_until(next.as_ref().map_or_else(Instant::now, |(_, at)| *at)), if next.is_some() => {
+                let (key, _) = next.unwrap();
+                let progress = progresses.get_mut(&key).unwrap();
+                progress.delivered = Instant::now();
+                if let Some(message) = progress.held.take() {
+                    sender.send(message).await?;
+                }
+            }
+        }
+    }
+}
+
+async fn forward_filtered(
+    receiver: &mut Receiver,
+    sender: &Sender,
+    filter: impl Fn(&Message) -> bool,
+) -> Result<(), Error> {
+    loop {
+        let message = receiver.recv().await?;
+        if filter(&message) {
+            sender.send(message).await?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::sync::mpsc;
+
+    async fn deliver(policy: ProgressPolicy, bodies: &[&str], wait: Duration) -> Vec<String> {
+        let (output_sender, mut output) = mpsc::channel(32);
+        let (sender, receiver) = channel::channel(32);
+        let progress = ProgressOutput::new(output_sender, policy);
+        tokio::spawn(Box::new(progress).run(receiver));
+
+        let request = Message::default().user("user").service_name("s-backup");
+        for body in bodies {
+            let message = match *body {
+                "done" => Message::response(&request),
+                _ => Message::progress(&request),
+            };
+            sender.send(message.body(*body)).await.unwrap();
+        }
+        time::sleep(wait).await;
+        drop(sender);
+
+        let mut bodies = Vec::new();
+        while let Some(message) = output.recv().await {
+            bodies.push(message.body);
+        }
+        bodies
+    }
+
+    #[tokio::test]
+    async fn policies() {
+        let bodies = ["10%", "20%", "30%"];
+        let interval = Duration::from_millis(50);
+        let wait = Duration::from_millis(100);
+
+        let all = deliver(ProgressPolicy::All, &bodies, wait).await;
+        assert_eq!(all, ["10%", "20%", "30%"]);
+        let dropped = deliver(ProgressPolicy::Drop, &["10%", "done"], wait).await;
+        assert_eq!(dropped, ["done"]);
+        let limited = deliver(ProgressPolicy::RateLimit(interval), &bodies, wait).await;
+        assert_eq!(limited, ["10%"]);
+        let collapsed = deliver(ProgressPolicy::Collapse(interval), &bodies, wait).await;
+        assert_eq!(collapsed, ["10%", "30%"]);
+
+        // The response arrived before the end of the interval.
+        let bodies = ["10%", "20%", "done"];
+        let collapsed = deliver(ProgressPolicy::Collapse(interval), &bodies, wait).await;
+        assert_eq!(collapsed, ["10%", "done"]);
+    }
+}
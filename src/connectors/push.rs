@@ -0,0 +1,331 @@
+use super::proxy::{http_client, Proxy};
+use crate::channel::Receiver;
+use crate::engine::{ConnectorKind, DeliveryReport, EngineHandle, Event};
+use crate::error::Error;
+use crate::interface::OutputConnector;
+use crate::message::Message;
+
+use async_trait::async_trait;
+
+use std::collections::HashMap;
+
+const NTFY_URL: &str = "https://ntfy.sh";
+const PUSHOVER_URL: &str = "https://api.pushover.net/1/messages.json";
+
+fn title(message: &Message) -> String {
+    let mut words = vec![message.service_name.as_str()];
+    words.extend(message.args.iter().map(|arg| arg.as_str()));
+    words.join(" ")
+}
+
+fn report(receiver: &Receiver, result: Result<(), reqwest::Error>, message: Message) {
+    let err = match result {
+        Ok(()) => return receiver.report(DeliveryReport::delivered(&message)),
+        Err(err) => err,
+    };
+
+    log::error!("Sending error: {}", err);
+    if let Some(401 | 403) = err.status().map(|status| status.as_u16()) {
+        if let Some(engine) = EngineHandle::current() {
+            engine.emit(Event::AuthFailed {
+                connector: ConnectorKind::Output,
+                error: err.to_string(),
+            });
+        }
+    }
+    receiver.report(DeliveryReport::failed(message, err));
+}
+
+/// Output connector that sends the messages as push notifications through [ntfy](https://ntfy.sh).
+///
+/// The notification title contains the service name and the arguments,
+/// and the notification text is the body.
+/// Each attached file is sent as an additional notification.
+///
+/// The messages of a user are published in the topic registered with [`NtfyOutput::topic()`],
+/// or in a topic named as the user if there is no one.
+#[derive(Clone, Default)]
+pub struct NtfyOutput {
+    server: Option<String>,
+    token: Option<String>,
+    topics: HashMap<String, String>,
+    proxy: Option<Proxy>,
+}
+
+impl NtfyOutput {
+    /// Reach the API through a proxy.
+    /// By default, the proxy of the environment is used, if any. See [`Proxy::from_env()`].
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Use a self-hosted ntfy server instead of `https://ntfy.sh`.
+    pub fn server(mut self, url: impl Into<String>) -> Self {
+        self.server = Some(url.into());
+        self
+    }
+
+    /// Access token for protected topics.
+    pub fn token(mut self, value: impl Into<String>) -> Self {
+        self.token = Some(value.into());
+        self
+    }
+
+    /// Publish the messages for `user` in `topic`.
+    pub fn topic(mut self, user: impl Into<String>, topic: impl Into<String>) -> Self {
+        self.topics.insert(user.into(), topic.into());
+        self
+    }
+
+    async fn send(&self, http: &reqwest::Client, message: &Message) -> reqwest::Result<()> {
+        let server = self.server.as_deref().unwrap_or(NTFY_URL);
+        let topic = self.topics.get(&message.user).unwrap_or(&message.user);
+        let url = format!("{}/{}", server.trim_end_matches('/'), topic);
+
+        let request = |builder: reqwest::RequestBuilder| match &self.token {
+            Some(token) => builder.bearer_auth(token),
+            None => builder,
+        };
+
+        request(http.post(&url))
+            .header("Title", title(message))
+            .body(message.body.clone())
+            .send()
+            .await?
+            .error_for_status()?;
+
+        for (filename, data) in &message.attached_data {
+            request(http.put(&url))
+                .header("Filename", filename)
+                .body(data.clone())
+                .send()
+                .await?
+                .error_for_status()?;
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl OutputConnector for NtfyOutput {
+    async fn run(self: Box<Self>, mut receiver: Receiver) -> Result<(), Error> {
+        let http = http_client(self.proxy.as_ref())
+            .build()
+            .map_err(|err| Error::connector("ntfy", err))?;
+        loop {
+            let message = receiver.recv().await?;
+            let result = self.send(&http, &message).await;
+            report(&receiver, result, message);
+        }
+    }
+}
+
+/// Output connector that sends the messages as push notifications through
+/// [Pushover](https://pushover.net).
+///
+/// The notification title contains the service name and the arguments,
+/// and the notification text is the body. The attached data is not sent.
+///
+/// The messages of a user are sent to the Pushover user key registered with
+/// [`PushoverOutput::user_key()`], or to the user itself as key if there is no one.
+#[derive(Clone)]
+pub struct PushoverOutput {
+    app_token: String,
+    user_keys: HashMap<String, String>,
+    proxy: Option<Proxy>,
+}
+
+impl PushoverOutput {
+    /// Sends the notifications from the application with the `app_token`.
+    pub fn new(app_token: impl Into<String>) -> Self {
+        Self {
+            app_token: app_token.into(),
+            user_keys: HashMap::default(),
+            proxy: None,
+        }
+    }
+
+    /// Reach the API through a proxy.
+    /// By default, the proxy of the environment is used, if any. See [`Proxy::from_env()`].
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Send the messages for `user` to the Pushover user `key`.
+    pub fn user_key(mut self, user: impl Into<String>, key: impl Into<String>) -> Self {
+        self.user_keys.insert(user.into(), key.into());
+        self
+    }
+
+    /// Form fields of the Pushover request.
+    fn form(&self, message: &Message) -> [(&'static str, String); 4] {
+        let user_key = self.user_keys.get(&message.user).unwrap_or(&message.user);
+
+        // Pushover does not accept empty texts.
+        let text = match message.body.is_empty() {
+            true => title(message),
+            false => message.body.clone(),
+        };
+
+        [
+            ("token", self.app_token.clone()),
+            ("user", user_key.clone()),
+            ("title", title(message)),
+            ("message", text),
+        ]
+    }
+
+    async fn send(&self, http: &reqwest::Client, message: &Message) -> reqwest::Result<()> {
+        http.post(PUSHOVER_URL)
+            .form(&self.form(message))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl OutputConnector for PushoverOutput {
+    async fn run(self: Box<Self>, mut receiver: Receiver) -> Result<(), Error> {
+        let http = http_client(self.proxy.as_ref())
+            .build()
+            .map_err(|err| Error::connector("Pushover", err))?;
+        loop {
+            let message = receiver.recv().await?;
+            let result = self.send(&http, &message).await;
+            report(&receiver, result, message);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use axum::extract::{Path, State};
+    use axum::http::{HeaderMap, Method, StatusCode};
+    use axum::routing::any;
+    use axum::Router;
+
+    use std::sync::{Arc, Mutex};
+
+    /// Method, topic, `Title` or `Filename` header, `Authorization` header and body.
+    type Published = (Method, String, String, String, String);
+
+    async fn ntfy_server() -> (String, Arc<Mutex<Vec<Published>>>) {
+        let published = Arc::new(Mutex::new(Vec::new()));
+
+        async fn publish(
+            State(published): State<Arc<Mutex<Vec<Published>>>>,
+            Path(topic): Path<String>,
+            method: Method,
+            headers: HeaderMap,
+            body: String,
+        ) -> StatusCode {
+            if topic == "protected" {
+                return StatusCode::FORBIDDEN;
+            }
+            let header = |name: &str| {
+                let value = headers.get(name).map(|value| value.to_str().unwrap());
+                value.unwrap_or_default().to_string()
+            };
+            let name = header(if method == Method::PUT {
+                "Filename"
+            } else {
+                "Title"
+            });
+            let auth = header("Authorization");
+            published
+                .lock()
+                .unwrap()
+                .push((method, topic, name, auth, body));
+            StatusCode::OK
+        }
+
+        let app = Router::new()
+            .route("/:topic", any(publish))
+            .with_state(published.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        (url, published)
+    }
+
+    fn message() -> Message {
+        Message::default()
+            .user("user")
+            .service_name("s-alarm")
+            .args(["disk"])
+            .body("90% used")
+    }
+
+    #[tokio::test]
+    async fn ntfy() {
+        let (url, published) = ntfy_server().await;
+        let output = NtfyOutput::default()
+            .server(url)
+            .token("1234")
+            .topic("user", "alerts");
+        let http = reqwest::Client::builder().no_proxy().build().unwrap();
+
+        let message = message().attach([("report.txt", b"data".to_vec())]);
+        output.send(&http, &message).await.unwrap();
+
+        let auth = String::from("Bearer 1234");
+        assert_eq!(
+            *published.lock().unwrap(),
+            [
+                (
+                    Method::POST,
+                    "alerts".into(),
+                    "s-alarm disk".into(),
+                    auth.clone(),
+                    "90% used".into(),
+                ),
+                (
+                    Method::PUT,
+                    "alerts".into(),
+                    "report.txt".into(),
+                    auth,
+                    "data".into(),
+                ),
+            ]
+        );
+
+        let message = message.user("protected");
+        let err = output.send(&http, &message).await.unwrap_err();
+        assert_eq!(err.status(), Some(reqwest::StatusCode::FORBIDDEN));
+    }
+
+    #[test]
+    fn pushover_form() {
+        let output = PushoverOutput::new("app-token").user_key("user", "user-key");
+
+        assert_eq!(
+            output.form(&message()),
+            [
+                ("token", "app-token".into()),
+                ("user", "user-key".into()),
+                ("title", "s-alarm disk".into()),
+                ("message", "90% used".into()),
+            ]
+        );
+
+        let message = message().user("other-key").body("");
+        assert_eq!(
+            output.form(&message),
+            [
+                ("token", "app-token".into()),
+                ("user", "other-key".into()),
+                ("title", "s-alarm disk".into()),
+                ("message", "s-alarm disk".into()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,205 @@
+use super::aws::{self, Credentials};
+use super::proxy::{http_client, Proxy};
+use super::smtp::message_to_email;
+use crate::channel::Receiver;
+use crate::engine::{ConnectorKind, DeliveryReport, EngineHandle, Event};
+use crate::error::Error;
+use crate::interface::OutputConnector;
+use crate::util::IntoOption;
+
+use async_trait::async_trait;
+use base64::engine::{general_purpose::STANDARD, Engine as _};
+use lettre::message::Mailbox;
+use serde_json::json;
+
+use std::time::SystemTime;
+
+const SEND_PATH: &str = "/v2/email/outbound-emails";
+
+/// Output connector that sends the messages as emails through the Amazon SES API (v2),
+/// authenticated with IAM credentials.
+/// The emails are built in the same way as [`SmtpClient`] does.
+///
+/// Useful in AWS-hosted deployments where the SMTP ports can not be used.
+///
+/// # Example
+/// ```rust no_run
+/// use service_io::connectors::{ImapClient, SesOutput};
+/// use service_io::engine::Engine;
+/// use service_io::services::Echo;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     Engine::default()
+///         .input(ImapClient::default() /* ... */)
+///         .output(
+///             SesOutput::from_env("eu-west-1", "service@domain.com")
+///                 .configuration_set("service-io")
+///                 .tag("app", "service-io"),
+///         )
+///         .add_service("s-echo", Echo)
+///         .run()
+///         .await;
+/// }
+/// ```
+///
+/// [`SmtpClient`]: super::SmtpClient
+#[derive(Clone)]
+pub struct SesOutput {
+    region: String,
+    credentials: Credentials,
+    email: String,
+    sender_name: Option<String>,
+    configuration_set: Option<String>,
+    tags: Vec<(String, String)>,
+    proxy: Option<Proxy>,
+}
+
+impl SesOutput {
+    /// Send from the verified identity `email` in the `region` (e.g. `eu-west-1`).
+    pub fn new(
+        region: impl Into<String>,
+        access_key_id: impl Into<String>,
+        secret_access_key: impl Into<String>,
+        email: impl Into<String>,
+    ) -> Self {
+        Self {
+            region: region.into(),
+            credentials: Credentials {
+                access_key_id: access_key_id.into(),
+                secret_access_key: secret_access_key.into(),
+                session_token: None,
+            },
+            email: email.into(),
+            sender_name: None,
+            configuration_set: None,
+            tags: Vec::new(),
+            proxy: None,
+        }
+    }
+
+    /// Reach the API through a proxy.
+    /// By default, the proxy of the environment is used, if any. See [`Proxy::from_env()`].
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Same as [`SesOutput::new()`] but reading the credentials from the
+    /// `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN` environment variables.
+    pub fn from_env(region: impl Into<String>, email: impl Into<String>) -> Self {
+        let var = |name| std::env::var(name).unwrap_or_default();
+        Self::new(
+            region,
+            var("AWS_ACCESS_KEY_ID"),
+            var("AWS_SECRET_ACCESS_KEY"),
+            email,
+        )
+        .session_token(std::env::var("AWS_SESSION_TOKEN").ok())
+    }
+
+    /// Session token of temporary credentials.
+    pub fn session_token(mut self, value: impl IntoOption<String>) -> Self {
+        self.credentials.session_token = value.into_some();
+        self
+    }
+
+    /// Name alias for the email
+    pub fn sender_name(mut self, value: impl IntoOption<String>) -> Self {
+        self.sender_name = value.into_some();
+        self
+    }
+
+    /// SES configuration set used to send the emails.
+    pub fn configuration_set(mut self, value: impl Into<String>) -> Self {
+        self.configuration_set = Some(value.into());
+        self
+    }
+
+    /// Add a tag to the sent emails.
+    pub fn tag(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.tags.push((name.into(), value.into()));
+        self
+    }
+
+    async fn send(&self, http: &reqwest::Client, email: lettre::Message) -> reqwest::Result<()> {
+        let mut payload = json!({
+            "FromEmailAddress": self.email,
+            "Content": { "Raw": { "Data": STANDARD.encode(email.formatted()) } },
+        });
+        if let Some(configuration_set) = &self.configuration_set {
+            payload["ConfigurationSetName"] = json!(configuration_set);
+        }
+        if !self.tags.is_empty() {
+            payload["EmailTags"] = self
+                .tags
+                .iter()
+                .map(|(name, value)| json!({ "Name": name, "Value": value }))
+                .collect();
+        }
+        let payload = payload.to_string();
+
+        let host = format!("email.{}.amazonaws.com", self.region);
+        let amz_date = aws::amz_date(SystemTime::now());
+        let mut headers = vec![
+            ("content-type", "application/json"),
+            ("host", host.as_str()),
+            ("x-amz-date", amz_date.as_str()),
+        ];
+        if let Some(token) = &self.credentials.session_token {
+            headers.push(("x-amz-security-token", token));
+        }
+
+        let request = aws::Request {
+            method: "POST",
+            path: SEND_PATH,
+            query: "",
+            headers: &headers,
+            payload: payload.as_bytes(),
+        };
+        let authorization =
+            aws::authorization(&request, &self.credentials, &self.region, "ses", &amz_date);
+
+        let mut builder = http
+            .post(format!("https://{}{}", host, SEND_PATH))
+            .header("authorization", authorization);
+        for (name, value) in headers.iter().filter(|(name, _)| *name != "host") {
+            builder = builder.header(*name, *value);
+        }
+
+        builder.body(payload).send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl OutputConnector for SesOutput {
+    async fn run(self: Box<Self>, mut receiver: Receiver) -> Result<(), Error> {
+        let address = super::config::email_address("SesOutput", &self.email)?;
+        let from = Mailbox::new(self.sender_name.clone(), address);
+        let http = http_client(self.proxy.as_ref())
+            .build()
+            .map_err(|err| Error::connector("SES", err))?;
+
+        loop {
+            let message = receiver.recv().await?;
+            if let Some(email) = message_to_email(message.clone(), from.clone()) {
+                match self.send(&http, email).await {
+                    Ok(()) => receiver.report(DeliveryReport::delivered(&message)),
+                    Err(err) => {
+                        log::error!("Sending error: {}", err);
+                        if let Some(401 | 403) = err.status().map(|status| status.as_u16()) {
+                            if let Some(engine) = EngineHandle::current() {
+                                engine.emit(Event::AuthFailed {
+                                    connector: ConnectorKind::Output,
+                                    error: err.to_string(),
+                                });
+                            }
+                        }
+                        receiver.report(DeliveryReport::failed(message, err));
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use super::proxy::{http_client, Proxy};
+use super::text::{message_to_text, text_to_message};
+use crate::channel::{ChannelEnd, ClosedChannel, Receiver, Sender};
+use crate::engine::{ConnectorKind, DeliveryReport, EngineHandle, Event};
+use crate::error::Error;
+use crate::interface::{InputConnector, OutputConnector};
+
+use async_trait::async_trait;
+use axum::extract::{Form, State};
+use axum::http::{HeaderMap, StatusCode, Uri};
+use axum::routing::post;
+use axum::Router;
+use base64::engine::{general_purpose::STANDARD, Engine as _};
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+const API_URL: &str = "https://api.twilio.com/2010-04-01/Accounts";
+const EMPTY_RESPONSE: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?><Response></Response>";
+
+/// Input connector that receives SMS or WhatsApp messages through a Twilio webhook.
+///
+/// It runs an HTTP server listening in the given address.
+/// The Twilio phone number must be configured to send its incoming messages webhook
+/// (as `HTTP POST`) to this server.
+/// The phone number of the sender (with the `whatsapp:` prefix for WhatsApp) is the user.
+/// The first line of the text is interpreted as the service name followed by
+/// the spaced-separated arguments, and the following lines are the body.
+/// The media files are not downloaded.
+///
+/// Without [`SmsInput::signature()`] anyone reaching the server could impersonate any user.
+#[derive(Clone)]
+pub struct SmsInput {
+    addr: String,
+    path: String,
+    signature: Option<(String, String)>,
+}
+
+struct WebhookState {
+    sender: Sender,
+    signature: Option<(String, String)>,
+    engine: Option<EngineHandle>,
+}
+
+impl SmsInput {
+    /// Listen the webhook requests in `addr` (e.g. `0.0.0.0:8080`).
+    pub fn new(addr: impl Into<String>) -> Self {
+        Self {
+            addr: addr.into(),
+            path: "/sms".into(),
+            signature: None,
+        }
+    }
+
+    /// Path of the webhook. By default `/sms`.
+    pub fn path(mut self, value: impl Into<String>) -> Self {
+        self.path = value.into();
+        self
+    }
+
+    /// Validate that the requests come from Twilio checking the `X-Twilio-Signature` header.
+    /// The `public_url` is the URL configured in Twilio as the webhook
+    /// (without the path, e.g. `https://service.domain.com`).
+    pub fn signature(
+        mut self,
+        public_url: impl Into<String>,
+        auth_token: impl Into<String>,
+    ) -> Self {
+        self.signature = Some((public_url.into(), auth_token.into()));
+        self
+    }
+}
+
+fn valid_signature(
+    url: &str,
+    auth_token: &str,
+    params: &BTreeMap<String, String>,
+    signature: &str,
+) -> bool {
+    let mut mac = Hmac::<Sha1>::new_from_slice(auth_token.as_bytes()).unwrap();
+    mac.update(url.as_bytes());
+    for (key, value) in params {
+        mac.update(key.as_bytes());
+        mac.update(value.as_bytes());
+    }
+
+    match STANDARD.decode(signature) {
+        Ok(signature) => mac.verify_slice(&signature).is_ok(),
+        Err(_) => false,
+    }
+}
+
+async fn webhook(
+    State(state): State<Arc<WebhookState>>,
+    uri: Uri,
+    headers: HeaderMap,
+    Form(params): Form<BTreeMap<String, String>>,
+) -> Result<&'static str, StatusCode> {
+    if let Some((public_url, auth_token)) = &state.signature {
+        let url = format!("{}{}", public_url.trim_end_matches('/'), uri);
+        let signature = headers
+            .get("X-Twilio-Signature")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+
+        if !valid_signature(&url, auth_token, &params, signature) {
+            log::warn!("Discarded SMS webhook request with an invalid signature");
+            if let Some(engine) = &state.engine {
+                engine.emit(Event::AuthFailed {
+                    connector: ConnectorKind::Input,
+                    error: "Invalid Twilio signature".into(),
+                });
+            }
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+
+    let user = params.get("From").cloned().unwrap_or_default();
+    let text = params.get("Body").cloned().unwrap_or_default();
+    if let Some(message) = text_to_message(user, &text) {
+        state
+            .sender
+            .send(message)
+            .await
+            .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?;
+    }
+
+    Ok(EMPTY_RESPONSE)
+}
+
+#[async_trait]
+impl InputConnector for SmsInput {
+    async fn run(self: Box<Self>, sender: Sender) -> Result<(), Error> {
+        let state = Arc::new(WebhookState {
+            sender,
+            signature: self.signature,
+            engine: EngineHandle::current(),
+        });
+
+        let app = Router::new()
+            .route(&self.path, post(webhook))
+            .with_state(state.clone());
+
+        let listener = tokio::net::TcpListener::bind(&self.addr)
+            .await
+            .map_err(|err| Error::connector("SMS webhook", err))?;
+        log::info!("Listening SMS webhook at {}{}", self.addr, self.path);
+
+        tokio::select! {
+            result = axum::serve(listener, app) => {
+                result.map_err(|err| Error::connector("SMS webhook", err))
+            }
+            _ = state.sender.0.closed() => Err(ClosedChannel::new(ChannelEnd::Receiver).into()),
+        }
+    }
+}
+
+/// Output connector that sends the messages as SMS or WhatsApp messages through the Twilio REST API.
+/// The text contains the service name and the arguments in the first line,
+/// and the body in the following lines. The attached data is not sent.
+///
+/// To send WhatsApp messages, use a `from` number with the `whatsapp:` prefix.
+#[derive(Clone)]
+pub struct SmsOutput {
+    account_sid: String,
+    auth_token: String,
+    from: String,
+    proxy: Option<Proxy>,
+}
+
+impl SmsOutput {
+    pub fn new(
+        account_sid: impl Into<String>,
+        auth_token: impl Into<String>,
+        from: impl Into<String>,
+    ) -> Self {
+        Self {
+            account_sid: account_sid.into(),
+            auth_token: auth_token.into(),
+            from: from.into(),
+            proxy: None,
+        }
+    }
+
+    /// Reach the API through a proxy.
+    /// By default, the proxy of the environment is used, if any. See [`Proxy::from_env()`].
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+}
+
+#[async_trait]
+impl OutputConnector for SmsOutput {
+    async fn run(self: Box<Self>, mut receiver: Receiver) -> Result<(), Error> {
+        let http = http_client(self.proxy.as_ref())
+            .build()
+            .map_err(|err| Error::connector("SMS", err))?;
+        let url = format!("{}/{}/Messages.json", API_URL, self.account_sid);
+
+        loop {
+            let message = receiver.recv().await?;
+            let text = message_to_text(&message);
+
+            let result = http
+                .post(&url)
+                .basic_auth(&self.account_sid, Some(&self.auth_token))
+                .form(&[
+                    ("From", self.from.as_str()),
+                    ("To", message.user.as_str()),
+                    ("Body", text.as_str()),
+                ])
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+
+            match result {
+                Ok(_) => receiver.report(DeliveryReport::delivered(&message)),
+                Err(err) => {
+                    log::error!("Sending error: {}", err);
+                    if err.status() == Some(reqwest::StatusCode::UNAUTHORIZED) {
+                        if let Some(engine) = EngineHandle::current() {
+                            engine.emit(Event::AuthFailed {
+                                connector: ConnectorKind::Output,
+                                error: err.to_string(),
+                            });
+                        }
+                    }
+                    receiver.report(DeliveryReport::failed(message, err));
+                }
+            }
+        }
+    }
+}
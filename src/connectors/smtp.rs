@@ -1,26 +1,162 @@
-use crate::channel::{ClosedChannel, Receiver};
+use super::config::{self, ConfigError, FieldError};
+use super::proxy::{Forwarding, Proxy};
+use super::tls::TlsBackend;
+use crate::channel::Receiver;
+use crate::engine::{ConnectorKind, DeliveryReport, EngineHandle, Event};
+use crate::error::Error;
 use crate::interface::OutputConnector;
-use crate::message::Message;
+use crate::message::{format, Message};
 use crate::util::IntoOption;
 
-use lettre::message::{header::ContentType, Attachment, Mailbox, MultiPart, SinglePart};
+use lettre::message::header::{
+    ContentTransferEncoding, ContentType, Header, HeaderName, HeaderValue,
+};
+use lettre::message::{Attachment, Body, Mailbox, MultiPart, SinglePart};
 use lettre::transport::smtp::authentication::Credentials;
+use lettre::transport::smtp::client::{Tls, TlsParameters};
 use lettre::{Address, AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
 
 use async_trait::async_trait;
 
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Metadata key with the priority of the email: `"high"`, `"normal"` or `"low"`.
+/// It is set as the `X-Priority` and `Importance` headers, understood by most email clients.
+pub const MAIL_PRIORITY_KEY: &str = "mail-priority";
+
+/// Prefix of the metadata keys added as headers to the email,
+/// i.e. the metadata `("mail-header-X-Ticket", "42")` adds the header `X-Ticket: 42`.
+pub const MAIL_HEADER_PREFIX: &str = "mail-header-";
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
 /// Output connector that acts as a SMTP client
 /// The service sends emails to the SMTP server.
 /// The service name is added as first word of the subject following by space.
 /// The arguments are added as a words to the subject separated by spaces.
-#[derive(Default, Clone)]
+/// Use [`SmtpClient::renderer()`] to customize it.
+///
+/// The email headers can be set by message with [`MAIL_PRIORITY_KEY`] and [`MAIL_HEADER_PREFIX`].
+///
+/// It authenticates in the server when the engine starts, so the input connector does not
+/// consume any message if the credentials are wrong.
+#[derive(Clone)]
 pub struct SmtpClient {
     smtp_domain: String,
     email: String,
     password: String,
     sender_name: Option<String>,
+    port: Option<u16>,
+    insecure: bool,
+    tls: TlsBackend,
+    proxy: Option<Proxy>,
+    timeout: Duration,
+    renderer: Arc<dyn MailRenderer>,
+    connection: Option<Connection>,
+}
+
+/// Transport authenticated by [`OutputConnector::connect()`].
+#[derive(Clone)]
+struct Connection {
+    mailer: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    _forwarding: Option<Arc<Forwarding>>,
+}
+
+impl Default for SmtpClient {
+    fn default() -> Self {
+        Self {
+            smtp_domain: String::default(),
+            email: String::default(),
+            password: String::default(),
+            sender_name: None,
+            port: None,
+            insecure: false,
+            tls: TlsBackend::default(),
+            proxy: None,
+            timeout: DEFAULT_TIMEOUT,
+            renderer: Arc::new(DefaultMailRenderer),
+            connection: None,
+        }
+    }
+}
+
+/// Body of an email.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MailBody {
+    Plain(String),
+    Html(String),
+    /// Both versions, the email client chooses which one to show.
+    Alternative {
+        plain: String,
+        html: String,
+    },
+}
+
+/// How an attached file is shown by the email client.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttachmentDisposition {
+    /// As a downloadable file.
+    #[default]
+    Attachment,
+    /// Embedded in the body, referenced from the HTML by its filename: `cid:<filename>`.
+    Inline,
 }
 
+/// Transforms a message into an email to send.
+/// Each method has a default that can be overridden.
+///
+/// ```rust
+/// use service_io::connectors::{MailBody, MailRenderer, SmtpClient};
+/// use service_io::message::Message;
+///
+/// struct Notification;
+///
+/// impl MailRenderer for Notification {
+///     fn subject(&self, message: &Message) -> String {
+///         format!("[{}] notification", message.service_name)
+///     }
+///
+///     fn body(&self, message: &Message) -> MailBody {
+///         MailBody::Html(format!("<p>{}</p>", message.body))
+///     }
+/// }
+///
+/// let client = SmtpClient::default().renderer(Notification);
+/// ```
+pub trait MailRenderer: Send + Sync {
+    /// By default, the service name followed by the arguments separated by spaces.
+    fn subject(&self, message: &Message) -> String {
+        format!("{} {}", message.service_name, message.args.join(" "))
+    }
+
+    /// By default, the message body as plain text,
+    /// along with its HTML version if the message has one (see [`format::HTML_BODY_KEY`]).
+    fn body(&self, message: &Message) -> MailBody {
+        match message.metadata.get(format::HTML_BODY_KEY) {
+            Some(html) => MailBody::Alternative {
+                plain: message.body.clone(),
+                html: html.clone(),
+            },
+            None => MailBody::Plain(message.body.clone()),
+        }
+    }
+
+    /// By default, [`AttachmentDisposition::Attachment`].
+    fn disposition(&self, _message: &Message, _filename: &str) -> AttachmentDisposition {
+        AttachmentDisposition::Attachment
+    }
+}
+
+/// Default [`MailRenderer`], the format understood by the default [`MailParser`].
+///
+/// [`MailParser`]: crate::connectors::MailParser
+#[derive(Default, Clone, Copy, Debug)]
+pub struct DefaultMailRenderer;
+
+impl MailRenderer for DefaultMailRenderer {}
+
 impl SmtpClient {
     pub fn domain(mut self, value: impl Into<String>) -> Self {
         self.smtp_domain = value.into();
@@ -42,33 +178,242 @@ impl SmtpClient {
         self.sender_name = value.into_some();
         self
     }
+
+    /// Port of the SMTP server. By default, 465.
+    pub fn port(mut self, value: u16) -> Self {
+        self.port = Some(value);
+        self
+    }
+
+    /// Connect without TLS. Only intended for local servers and testing.
+    pub fn insecure(mut self) -> Self {
+        self.insecure = true;
+        self
+    }
+
+    /// TLS implementation used to connect. By default, [`TlsBackend::default()`].
+    pub fn tls_backend(mut self, backend: TlsBackend) -> Self {
+        self.tls = backend;
+        self
+    }
+
+    /// Connect through a proxy.
+    /// By default, the proxy of the environment is used, if any. See [`Proxy::from_env()`].
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Maximum time to connect to the server and to wait for each of its responses.
+    /// By default, 60 seconds.
+    pub fn timeout(mut self, duration: Duration) -> Self {
+        self.timeout = duration;
+        self
+    }
+
+    /// Customize how the messages are transformed into emails.
+    /// By default, [`DefaultMailRenderer`].
+    pub fn renderer(mut self, renderer: impl MailRenderer + 'static) -> Self {
+        self.renderer = Arc::new(renderer);
+        self
+    }
+
+    /// Check that the configuration is complete and well-formed.
+    /// [`OutputConnector::run()`] finishes with an error log if it is not.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut errors = Vec::new();
+        config::required(&mut errors, "domain", &self.smtp_domain);
+        config::required(&mut errors, "password", &self.password);
+        match self.email.is_empty() {
+            true => errors.push(FieldError::Missing("email")),
+            false => {
+                if let Err(err) = self.email.parse::<Address>() {
+                    errors.push(FieldError::Malformed {
+                        field: "email",
+                        reason: err.to_string(),
+                    });
+                }
+            }
+        }
+        ConfigError::check("SmtpClient", errors)
+    }
 }
 
 #[async_trait]
 impl OutputConnector for SmtpClient {
-    async fn run(mut self: Box<Self>, mut receiver: Receiver) -> Result<(), ClosedChannel> {
+    fn describe(&self) -> String {
+        format!("SmtpClient({} at {})", self.email, self.smtp_domain)
+    }
+
+    async fn connect(&mut self) -> Result<(), Error> {
+        self.validate()?;
+
         let address = self.email.parse::<Address>().unwrap();
         let user = address.user().to_string();
-        let credentials = Credentials::new(user, self.password.into());
+        let credentials = Credentials::new(user, self.password.clone());
+
+        let from = Mailbox::new(self.sender_name.clone(), address);
+        let port = self.port.unwrap_or(match self.insecure {
+            true => 25,
+            false => 465,
+        });
+
+        // The SMTP transport can not use a proxy by itself,
+        // so it connects to a local port forwarded through the proxy.
+        let forwarding = match Proxy::resolve(self.proxy.as_ref(), &self.smtp_domain) {
+            Some(proxy) => match proxy.forward(&self.smtp_domain, port).await {
+                Ok(forwarding) => Some(forwarding),
+                Err(err) => return Err(Error::connector("SMTP proxy", err)),
+            },
+            None => None,
+        };
+        let (server, port) = match &forwarding {
+            Some(forwarding) => (forwarding.addr().ip().to_string(), forwarding.addr().port()),
+            None => (self.smtp_domain.clone(), port),
+        };
+
+        let builder = AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(server)
+            .port(port)
+            .timeout(Some(self.timeout));
+        let builder = match self.insecure {
+            true => builder,
+            false => {
+                let parameters = tls_parameters(self.tls, &self.smtp_domain).unwrap();
+                builder.tls(Tls::Wrapper(parameters))
+            }
+        };
+        let mailer = builder.credentials(credentials).build();
+
+        // Authenticates, so the wrong credentials are known before sending any email.
+        match mailer.test_connection().await {
+            Ok(true) => (),
+            Ok(false) => return Err(Error::connector("SMTP", "the server closed the connection")),
+            Err(err) if is_auth_error(&err) => return Err(Error::Auth(err.to_string())),
+            Err(err) => return Err(Error::connector("SMTP", err)),
+        }
 
-        let from = Mailbox::new(self.sender_name, address);
-        let mailer = AsyncSmtpTransport::<Tokio1Executor>::relay(self.smtp_domain.as_ref())
-            .unwrap()
-            .credentials(credentials)
-            .build();
+        self.connection = Some(Connection {
+            mailer,
+            from,
+            _forwarding: forwarding.map(Arc::new),
+        });
+        Ok(())
+    }
+
+    async fn run(mut self: Box<Self>, mut receiver: Receiver) -> Result<(), Error> {
+        if self.connection.is_none() {
+            self.connect().await?;
+        }
+        let Connection { mailer, from, .. } = self.connection.clone().unwrap();
+
+        let engine = EngineHandle::current();
 
         loop {
             let message = receiver.recv().await?;
-            if let Some(email) = message_to_email(message, from.clone()) {
-                if let Err(err) = mailer.send(email).await {
-                    log::error!("Sending error: {}", err);
+            if let Some(email) = render_email(message.clone(), from.clone(), &*self.renderer) {
+                match mailer.send(email).await {
+                    Ok(_) => receiver.report(DeliveryReport::delivered(&message)),
+                    Err(err) => {
+                        log::error!("Sending error: {}", err);
+                        if let Some(engine) = engine.as_ref().filter(|_| is_auth_error(&err)) {
+                            engine.emit(Event::AuthFailed {
+                                connector: ConnectorKind::Output,
+                                error: err.to_string(),
+                            });
+                        }
+                        receiver.report(DeliveryReport::failed(message, err));
+                    }
                 }
             }
         }
     }
 }
 
-fn message_to_email(message: Message, from: Mailbox) -> Option<lettre::Message> {
+/// 53x codes are authentication errors
+fn is_auth_error(err: &lettre::transport::smtp::Error) -> bool {
+    err.status()
+        .map(|code| code.to_string().starts_with("53"))
+        .unwrap_or(false)
+}
+
+/// Header with a name known only at runtime.
+#[derive(Clone)]
+struct RawHeader(HeaderValue);
+
+impl Header for RawHeader {
+    // Not used to write the header: the value already contains its name.
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii("X-Raw-Header".into()).unwrap()
+    }
+
+    fn parse(_: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Err("A raw header can not be parsed".into())
+    }
+
+    fn display(&self) -> HeaderValue {
+        self.0.clone()
+    }
+}
+
+/// Headers set by the message metadata.
+fn metadata_headers(message: &Message) -> Vec<RawHeader> {
+    let mut headers = Vec::new();
+    let mut add = |name: &str, value: &str| match HeaderName::new_from_ascii(name.into()) {
+        Ok(name) => headers.push(RawHeader(HeaderValue::new(name, value.into()))),
+        Err(err) => log::warn!("Header '{}': {}", name, err),
+    };
+
+    if let Some(priority) = message.metadata.get(MAIL_PRIORITY_KEY) {
+        let x_priority = match priority.as_str() {
+            "high" => Some("1 (Highest)"),
+            "normal" => Some("3 (Normal)"),
+            "low" => Some("5 (Lowest)"),
+            _ => None,
+        };
+        match x_priority {
+            Some(x_priority) => {
+                add("X-Priority", x_priority);
+                add("Importance", priority);
+            }
+            None => log::warn!("Unknown email priority '{}'", priority),
+        }
+    }
+
+    for (key, value) in &message.metadata {
+        if let Some(name) = key.strip_prefix(MAIL_HEADER_PREFIX) {
+            add(name, value);
+        }
+    }
+
+    headers
+}
+
+fn tls_parameters(
+    backend: TlsBackend,
+    domain: &str,
+) -> Result<TlsParameters, lettre::transport::smtp::Error> {
+    let builder = TlsParameters::builder(domain.into());
+    match backend {
+        #[cfg(feature = "native-tls")]
+        TlsBackend::NativeTls => builder.build_native(),
+        #[cfg(feature = "rustls")]
+        TlsBackend::Rustls => builder.build_rustls(),
+    }
+}
+
+#[cfg_attr(
+    not(any(test, feature = "testing", feature = "oauth2", feature = "http")),
+    allow(dead_code)
+)]
+pub(crate) fn message_to_email(message: Message, from: Mailbox) -> Option<lettre::Message> {
+    render_email(message, from, &DefaultMailRenderer)
+}
+
+fn render_email(
+    message: Message,
+    from: Mailbox,
+    renderer: &dyn MailRenderer,
+) -> Option<lettre::Message> {
     let to_address = message
         .user
         .parse::<Address>()
@@ -77,11 +422,18 @@ fn message_to_email(message: Message, from: Mailbox) -> Option<lettre::Message>
 
     let single_parts = message
         .attached_data
-        .into_iter()
+        .iter()
         .map(|(filename, filebody)| {
+            // Base64 keeps the binary content untouched, even its trailing line breaks.
+            let body =
+                Body::new_with_encoding(filebody.to_vec(), ContentTransferEncoding::Base64).ok()?;
+            let attachment = match renderer.disposition(&message, filename) {
+                AttachmentDisposition::Attachment => Attachment::new(filename.clone()),
+                AttachmentDisposition::Inline => Attachment::new_inline(filename.clone()),
+            };
             Some(
-                Attachment::new(filename).body(
-                    filebody,
+                attachment.body(
+                    body,
                     ContentType::parse("application/octet-stream")
                         .map_err(|err| log::error!("{}", err))
                         .ok()?,
@@ -90,18 +442,121 @@ fn message_to_email(message: Message, from: Mailbox) -> Option<lettre::Message>
         })
         .collect::<Vec<_>>();
 
-    let mut multipart = MultiPart::alternative().singlepart(SinglePart::plain(message.body));
+    let mut multipart = match renderer.body(&message) {
+        MailBody::Plain(plain) => MultiPart::alternative().singlepart(SinglePart::plain(plain)),
+        MailBody::Html(html) => MultiPart::alternative().singlepart(SinglePart::html(html)),
+        MailBody::Alternative { plain, html } => MultiPart::alternative_plain_html(plain, html),
+    };
     for single in single_parts {
         multipart = multipart.singlepart(single?);
     }
 
-    let subject = message.args.join(" ");
-
-    lettre::Message::builder()
+    let mut builder = lettre::Message::builder()
         .from(from)
         .to(Mailbox::new(None, to_address))
-        .subject(format!("{} {}", message.service_name, subject))
+        .subject(renderer.subject(&message));
+
+    for header in metadata_headers(&message) {
+        builder = builder.header(header);
+    }
+
+    builder
         .multipart(multipart)
         .map_err(|err| log::error!("{}", err))
         .ok()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate() {
+        let error = SmtpClient::default()
+            .email("no-email")
+            .validate()
+            .unwrap_err();
+        assert_eq!(error.connector, "SmtpClient");
+        assert_eq!(error.errors[0], FieldError::Missing("domain"));
+        assert_eq!(error.errors[1], FieldError::Missing("password"));
+        assert!(matches!(
+            error.errors[2],
+            FieldError::Malformed { field: "email", .. }
+        ));
+
+        let client = SmtpClient::default()
+            .domain("smtp.domain.com")
+            .email("service@domain.com")
+            .password("1234");
+        assert_eq!(client.validate(), Ok(()));
+    }
+
+    #[test]
+    fn renderer() {
+        struct Notification;
+
+        impl MailRenderer for Notification {
+            fn subject(&self, message: &Message) -> String {
+                format!("Alert from {}", message.service_name)
+            }
+
+            fn body(&self, message: &Message) -> MailBody {
+                MailBody::Html(format!("<b>{}</b>", message.body))
+            }
+
+            fn disposition(&self, _: &Message, _: &str) -> AttachmentDisposition {
+                AttachmentDisposition::Inline
+            }
+        }
+
+        let message = Message::default()
+            .user("user@domain.com")
+            .service_name("monitor")
+            .body("disk full")
+            .attach([("chart.png", vec![1, 2, 3])]);
+        let from = "service@domain.com".parse().unwrap();
+
+        let email = render_email(message, from, &Notification).unwrap();
+        let formatted = String::from_utf8(email.formatted()).unwrap();
+        assert!(formatted.contains("Subject: Alert from monitor"));
+        assert!(formatted.contains("Content-Type: text/html"));
+        assert!(formatted.contains("<b>disk full</b>"));
+        assert!(formatted.contains("Content-Disposition: inline"));
+        assert!(formatted.contains("Content-ID: <chart.png>"));
+    }
+
+    #[test]
+    fn html_body() {
+        let message = format::Document::default()
+            .text("disk full")
+            .apply(Message::default());
+
+        assert_eq!(
+            DefaultMailRenderer.body(&message),
+            MailBody::Alternative {
+                plain: "disk full".into(),
+                html: "<p>disk full</p>".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn priority_and_custom_headers() {
+        let message = Message::default()
+            .user("user@domain.com")
+            .service_name("monitor")
+            .metadata([
+                (MAIL_PRIORITY_KEY, "high"),
+                ("mail-header-X-Ticket", "42"),
+                ("mail-header-Bad Name", "ignored"),
+            ]);
+        let from = "service@domain.com".parse().unwrap();
+
+        let email = message_to_email(message, from).unwrap();
+        let formatted = String::from_utf8(email.formatted()).unwrap();
+        assert!(formatted.contains("X-Priority: 1 (Highest)\r\n"));
+        assert!(formatted.contains("Importance: high\r\n"));
+        assert!(formatted.contains("X-Ticket: 42\r\n"));
+        assert!(!formatted.contains("ignored"));
+    }
+}
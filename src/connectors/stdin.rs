@@ -1,8 +1,10 @@
-use crate::channel::{ClosedChannel, Sender};
+use crate::channel::Sender;
+use crate::error::Error;
 use crate::interface::InputConnector;
-use crate::message::Message;
+use crate::message::{wire, Message};
 
 use async_trait::async_trait;
+use tokio::sync::mpsc;
 
 use std::io::{self, BufRead};
 
@@ -11,29 +13,160 @@ use std::io::{self, BufRead};
 /// The first word of the line is interpreted as the service name.
 /// The following spaced-separated words are the arguments.
 /// Neither body nor attach fields are populated.
+///
+/// It finishes once the stdin is closed.
 pub struct UserStdin<N>(pub N);
 
 #[async_trait]
 impl<N: Into<String> + Send> InputConnector for UserStdin<N> {
-    async fn run(mut self: Box<Self>, sender: Sender) -> Result<(), ClosedChannel> {
-        let user_name = self.0.into();
-        loop {
-            let line =
-                tokio::task::spawn_blocking(|| io::stdin().lock().lines().next().unwrap().unwrap())
-                    .await
-                    .unwrap();
-
-            let mut words = line.split_whitespace();
-            if let Some(service) = words.next() {
-                let message = Message {
-                    user: user_name.clone(),
-                    service_name: service.into(),
-                    args: words.map(|s| s.into()).collect(),
-                    ..Default::default()
-                };
+    async fn run(mut self: Box<Self>, sender: Sender) -> Result<(), Error> {
+        read_user(io::BufReader::new(io::stdin()), self.0.into(), sender).await
+    }
+}
+
+async fn read_user(
+    reader: impl BufRead + Send + 'static,
+    user: String,
+    sender: Sender,
+) -> Result<(), Error> {
+    let mut lines = read_lines(reader);
+    while let Some(line) = lines.recv().await {
+        let line = line.map_err(|err| Error::connector("stdin", err))?;
+        let mut words = line.split_whitespace();
+        if let Some(service) = words.next() {
+            let message = Message {
+                user: user.clone(),
+                service_name: service.into(),
+                args: words.map(|s| s.into()).collect(),
+                ..Default::default()
+            };
+
+            sender.send(message).await?;
+        }
+    }
+    Ok(())
+}
 
+/// Reads from the stdin one message per line, encoded as JSON with the serde schema
+/// of [`Message`] (see [`wire::from_json()`]), so the scripts can send complete messages,
+/// as bodies or attachments (encoded as base64).
+/// The lines that are not valid messages are logged and skipped.
+///
+/// It finishes once the stdin is closed.
+///
+/// # Example
+/// ```rust no_run
+/// use service_io::connectors::{DebugStdout, JsonStdin};
+/// use service_io::engine::Engine;
+/// use service_io::services::Echo;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     // echo '{"service_name": "s-echo", "body": "hello"}' | my-engine
+///     Engine::default()
+///         .input(JsonStdin::default().user("script"))
+///         .output(DebugStdout)
+///         .add_service("s-echo", Echo)
+///         .run()
+///         .await;
+/// }
+/// ```
+///
+/// [`wire::from_json()`]: crate::message::wire::from_json()
+#[derive(Default)]
+pub struct JsonStdin {
+    user: Option<String>,
+}
+
+impl JsonStdin {
+    /// User of the messages that do not specify it.
+    pub fn user(mut self, user: impl Into<String>) -> Self {
+        self.user = Some(user.into());
+        self
+    }
+}
+
+#[async_trait]
+impl InputConnector for JsonStdin {
+    async fn run(self: Box<Self>, sender: Sender) -> Result<(), Error> {
+        read_json(io::BufReader::new(io::stdin()), self.user, sender).await
+    }
+}
+
+async fn read_json(
+    reader: impl BufRead + Send + 'static,
+    user: Option<String>,
+    sender: Sender,
+) -> Result<(), Error> {
+    let mut lines = read_lines(reader);
+    while let Some(line) = lines.recv().await {
+        let line = line.map_err(|err| Error::connector("stdin", err))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match wire::from_json(&line) {
+            Ok(mut message) => {
+                if let (true, Some(user)) = (message.user.is_empty(), &user) {
+                    message.user = user.clone();
+                }
                 sender.send(message).await?;
             }
+            Err(err) => log::error!("Skip invalid JSON message from stdin: {}", err),
+        }
+    }
+    Ok(())
+}
+
+/// Reads the lines out of the async runtime, since reading the stdin blocks.
+fn read_lines(reader: impl BufRead + Send + 'static) -> mpsc::Receiver<io::Result<String>> {
+    let (line_sender, lines) = mpsc::channel(1);
+    tokio::task::spawn_blocking(move || {
+        for line in reader.lines() {
+            if line_sender.blocking_send(line).is_err() {
+                break;
+            }
         }
+    });
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel;
+
+    #[tokio::test]
+    async fn user_lines() {
+        let (sender, mut receiver) = channel::channel(4);
+        let reader = io::Cursor::new(b"s-echo a b\n\n".to_vec());
+        read_user(reader, "user".into(), sender).await.unwrap();
+
+        let message = receiver.recv().await.unwrap();
+        assert_eq!(message.user, "user");
+        assert_eq!(message.service_name, "s-echo");
+        assert_eq!(message.args, ["a", "b"]);
+        assert!(receiver.recv().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn json_lines() {
+        let lines = concat!(
+            r#"{"service_name": "s-echo", "body": "hello", "attached_data": {"a.txt": "MTIzNA=="}}"#,
+            "\n\nnot json\n",
+            r#"{"user": "other", "service_name": "s-echo"}"#,
+            "\n",
+        );
+        let (sender, mut receiver) = channel::channel(4);
+        let reader = io::Cursor::new(lines.as_bytes().to_vec());
+        read_json(reader, Some("script".into()), sender)
+            .await
+            .unwrap();
+
+        let message = receiver.recv().await.unwrap();
+        assert_eq!(message.user, "script");
+        assert_eq!(message.body, "hello");
+        assert_eq!(message.attached_data["a.txt"], "1234");
+        assert_eq!(receiver.recv().await.unwrap().user, "other");
+        assert!(receiver.recv().await.is_err());
     }
 }
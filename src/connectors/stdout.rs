@@ -1,22 +1,159 @@
-use crate::channel::{ClosedChannel, Receiver};
+use crate::channel::Receiver;
+use crate::error::Error;
 use crate::interface::OutputConnector;
+use crate::message::Message;
 
-use tokio::io::AsyncWriteExt;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
 
 use async_trait::async_trait;
 
+use std::path::{Path, PathBuf};
+
 /// Print the message to the stdout.
-pub struct DebugStdout;
+///
+/// It can also write them to a file, and finish after a number of messages,
+/// i.e. to check the replies of the services from a script.
+///
+/// # Example
+/// ```rust no_run
+/// use service_io::connectors::{DebugStdout, JsonStdin};
+/// use service_io::engine::Engine;
+/// use service_io::services::Echo;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     Engine::default()
+///         .input(JsonStdin::default().user("script"))
+///         .output(
+///             DebugStdout::default()
+///                 .compact(true)
+///                 .timestamps(true)
+///                 .tee("replies.log")
+///                 .exit_after(1),
+///         )
+///         .add_service("s-echo", Echo)
+///         .run()
+///         .await;
+/// }
+/// ```
+#[derive(Default)]
+pub struct DebugStdout {
+    exit_after: Option<usize>,
+    tee: Option<PathBuf>,
+    timestamps: bool,
+    compact: bool,
+}
+
+/// The default [`DebugStdout`], printing every message to the stdout as it always did,
+/// so `.output(DebugStdout)` keeps working. Same as `DebugStdout::default()`.
+#[allow(non_upper_case_globals)]
+pub const DebugStdout: DebugStdout = DebugStdout {
+    exit_after: None,
+    tee: None,
+    timestamps: false,
+    compact: false,
+};
+
+impl DebugStdout {
+    /// Finish after printing `messages`, so the engine finishes too.
+    pub fn exit_after(mut self, messages: usize) -> Self {
+        self.exit_after = Some(messages);
+        self
+    }
+
+    /// Also append the messages to the file in `path`, created if it does not exist.
+    pub fn tee(mut self, path: impl Into<PathBuf>) -> Self {
+        self.tee = Some(path.into());
+        self
+    }
+
+    /// Prefix each message with the UTC time it was printed. By default, disabled.
+    pub fn timestamps(mut self, enabled: bool) -> Self {
+        self.timestamps = enabled;
+        self
+    }
+
+    /// Print each message in a single line. By default, disabled.
+    pub fn compact(mut self, enabled: bool) -> Self {
+        self.compact = enabled;
+        self
+    }
+
+    fn format(&self, message: &Message) -> String {
+        let message = match self.compact {
+            true => format!("{:?}\n", message),
+            false => format!("{:#?}\n", message),
+        };
+        match self.timestamps {
+            true => format!("[{}] {}", chrono::Utc::now().to_rfc3339(), message),
+            false => message,
+        }
+    }
+}
 
 #[async_trait]
 impl OutputConnector for DebugStdout {
-    async fn run(mut self: Box<Self>, mut receiver: Receiver) -> Result<(), ClosedChannel> {
-        loop {
+    async fn run(mut self: Box<Self>, mut receiver: Receiver) -> Result<(), Error> {
+        let mut file = match &self.tee {
+            Some(path) => Some(open(path).await?),
+            None => None,
+        };
+
+        let mut printed = 0;
+        while self.exit_after.is_none_or(|messages| printed < messages) {
             let message = receiver.recv().await?;
-            tokio::io::stdout()
-                .write_all(format!("{:#?}\n", message).as_bytes())
-                .await
-                .unwrap();
+            let text = self.format(&message);
+            write(&mut tokio::io::stdout(), &text).await?;
+            if let Some(file) = &mut file {
+                write(file, &text).await?;
+            }
+            printed += 1;
         }
+        Ok(())
+    }
+}
+
+async fn open(path: &Path) -> Result<File, Error> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+        .map_err(|err| Error::connector("stdout", err))
+}
+
+/// Writes the text flushing it, so it is visible as soon as possible.
+async fn write(writer: &mut (impl AsyncWrite + Unpin), text: &str) -> Result<(), Error> {
+    let written = async {
+        writer.write_all(text.as_bytes()).await?;
+        writer.flush().await
+    };
+    written.await.map_err(|err| Error::connector("stdout", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel;
+
+    #[tokio::test]
+    async fn tee_and_exit() {
+        let path = std::env::temp_dir().join(format!("service-io-stdout-{}", std::process::id()));
+        let output = DebugStdout::default()
+            .compact(true)
+            .tee(&path)
+            .exit_after(2);
+
+        let (sender, receiver) = channel::channel(4);
+        for body in ["1", "2", "3"] {
+            sender.send(Message::default().body(body)).await.unwrap();
+        }
+        Box::new(output).run(receiver).await.unwrap();
+
+        let content = tokio::fs::read_to_string(&path).await.unwrap();
+        tokio::fs::remove_file(&path).await.unwrap();
+        assert_eq!(content.lines().count(), 2);
+        assert!(content.contains(r#"body: "2""#));
     }
 }
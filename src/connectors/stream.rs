@@ -0,0 +1,64 @@
+use crate::channel::{Receiver, Sender};
+use crate::error::Error;
+use crate::interface::{InputConnector, OutputConnector};
+use crate::message::Message;
+
+use async_trait::async_trait;
+use futures::{Sink, SinkExt, Stream, StreamExt};
+
+/// Input connector that sends to the services the messages of any [`Stream`].
+/// The connector finishes when the stream ends.
+///
+/// # Example
+/// ```rust
+/// use service_io::connectors::StreamInput;
+/// use service_io::message::Message;
+///
+/// let input = StreamInput(futures::stream::iter([
+///     Message::default().user("user").service_name("s-echo"),
+/// ]));
+/// ```
+pub struct StreamInput<S>(pub S);
+
+#[async_trait]
+impl<S> InputConnector for StreamInput<S>
+where
+    S: Stream<Item = Message> + Send + 'static,
+{
+    async fn run(self: Box<Self>, sender: Sender) -> Result<(), Error> {
+        let mut stream = Box::pin(self.0);
+        while let Some(message) = stream.next().await {
+            sender.send(message).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Output connector that feeds the output messages into any [`Sink`].
+/// The connector finishes when the sink fails.
+///
+/// # Example
+/// ```rust
+/// use service_io::connectors::SinkOutput;
+/// use service_io::message::Message;
+///
+/// let (sender, receiver) = futures::channel::mpsc::channel::<Message>(32);
+/// let output = SinkOutput(sender);
+/// ```
+pub struct SinkOutput<S>(pub S);
+
+#[async_trait]
+impl<S> OutputConnector for SinkOutput<S>
+where
+    S: Sink<Message> + Send + 'static,
+{
+    async fn run(self: Box<Self>, mut receiver: Receiver) -> Result<(), Error> {
+        let mut sink = Box::pin(self.0);
+        loop {
+            let message = receiver.recv().await?;
+            if sink.send(message).await.is_err() {
+                break Ok(());
+            }
+        }
+    }
+}
@@ -0,0 +1,47 @@
+//! Conversions between messages and plain texts, for the connectors of chat-like channels.
+
+use crate::message::Message;
+
+/// The first line of the `text` contains the service name and the arguments separated by spaces.
+/// The following lines are the body.
+pub(super) fn text_to_message(user: impl Into<String>, text: &str) -> Option<Message> {
+    let (header, body) = text.split_once('\n').unwrap_or((text, ""));
+    let mut words = header.split_whitespace();
+    let service_name = words.next()?;
+
+    Some(Message {
+        user: user.into(),
+        service_name: service_name.into(),
+        args: words.map(|word| word.into()).collect(),
+        body: body.trim().into(),
+        ..Default::default()
+    })
+}
+
+/// Inverse of [`text_to_message()`]. The attached data is not represented.
+pub(super) fn message_to_text(message: &Message) -> String {
+    let mut header = vec![message.service_name.as_str()];
+    header.extend(message.args.iter().map(|arg| arg.as_str()));
+
+    let header = header.join(" ");
+    match message.body.is_empty() {
+        true => header,
+        false => format!("{}\n{}", header, message.body),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_conversion() {
+        let message = text_to_message("user", "s-test arg0 arg1\nline0\nline1").unwrap();
+        assert_eq!(message.service_name, "s-test");
+        assert_eq!(message.args, ["arg0", "arg1"]);
+        assert_eq!(message.body, "line0\nline1");
+        assert_eq!(message_to_text(&message), "s-test arg0 arg1\nline0\nline1");
+
+        assert!(text_to_message("user", "  \nbody").is_none());
+    }
+}
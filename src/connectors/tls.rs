@@ -0,0 +1,42 @@
+/// TLS implementation used by the connectors to encrypt their connections.
+///
+/// The available implementations depend on the enabled features:
+/// `native-tls` (enabled by default) and `rustls`.
+/// By default, the connectors use `native-tls` if it is enabled, and `rustls` otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TlsBackend {
+    /// TLS library of the system, i.e. OpenSSL on Linux.
+    #[cfg(feature = "native-tls")]
+    NativeTls,
+
+    /// Pure Rust implementation that trusts the Mozilla root certificates.
+    /// It does not depend on system libraries,
+    /// so it can be used to build static binaries (i.e. for musl targets or scratch containers).
+    #[cfg(feature = "rustls")]
+    Rustls,
+}
+
+impl Default for TlsBackend {
+    fn default() -> Self {
+        #[cfg(feature = "native-tls")]
+        return TlsBackend::NativeTls;
+
+        #[cfg(not(feature = "native-tls"))]
+        return TlsBackend::Rustls;
+    }
+}
+
+/// Client configuration of `rustls` with the Mozilla root certificates.
+#[cfg(all(feature = "rustls", any(feature = "email", feature = "bridge")))]
+pub(crate) fn rustls_config() -> std::sync::Arc<rustls::ClientConfig> {
+    let roots = rustls::RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    let provider = rustls::crypto::ring::default_provider();
+    let config = rustls::ClientConfig::builder_with_provider(provider.into())
+        .with_safe_default_protocol_versions()
+        .expect("ring supports the default protocol versions")
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    std::sync::Arc::new(config)
+}
@@ -0,0 +1,245 @@
+use crate::channel::{self, Receiver, Sender};
+use crate::error::Error;
+use crate::i18n;
+use crate::interface::OutputConnector;
+use crate::message::Message;
+use crate::storage::Blobstore;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Output middleware that uploads the oversized bodies and attachments to a [`Blobstore`]
+/// and replaces them by download links, before passing the message to the wrapped output.
+/// The store must provide URLs through [`Blobstore::url()`].
+/// This avoids that the size limits of the email and chat transports truncate the results.
+///
+/// # Example
+/// ```rust no_run
+/// use service_io::connectors::{ImapClient, SmtpClient, UploadOutput};
+/// use service_io::engine::Engine;
+/// use service_io::services::Process;
+/// use service_io::storage::S3Bucket;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     Engine::default()
+///         .input(ImapClient::default() /* ... */)
+///         .output(
+///             UploadOutput::new(
+///                 SmtpClient::default() /* ... */,
+///                 S3Bucket::from_env("eu-west-1", "service-io-outputs"),
+///             )
+///             .max_body(10_000)
+///             .max_attachment(5_000_000),
+///         )
+///         .add_service("s-process", Process)
+///         .run()
+///         .await;
+/// }
+/// ```
+pub struct UploadOutput<O> {
+    output: O,
+    oversized: Oversized,
+}
+
+/// Uploads the oversized content of the messages.
+struct Oversized {
+    store: Arc<dyn Blobstore>,
+    max_body: Option<usize>,
+    max_attachment: Option<usize>,
+}
+
+impl<O: OutputConnector + Send + 'static> UploadOutput<O> {
+    /// Wraps `output`. By default, nothing is uploaded until a limit is set.
+    pub fn new(output: O, store: impl Blobstore + 'static) -> Self {
+        Self {
+            output,
+            oversized: Oversized {
+                store: Arc::new(store),
+                max_body: None,
+                max_attachment: None,
+            },
+        }
+    }
+
+    /// Bodies bigger than `bytes` are uploaded.
+    pub fn max_body(mut self, bytes: usize) -> Self {
+        self.oversized.max_body = Some(bytes);
+        self
+    }
+
+    /// Attachments bigger than `bytes` are uploaded.
+    pub fn max_attachment(mut self, bytes: usize) -> Self {
+        self.oversized.max_attachment = Some(bytes);
+        self
+    }
+}
+
+impl Oversized {
+    /// Stores `data` and returns the URL to download it.
+    async fn store(&self, key: &str, data: Bytes) -> io::Result<String> {
+        let url = self.store.url(key).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                "The store does not provide URLs",
+            )
+        })?;
+        self.store.put(key, data).await?;
+        Ok(url)
+    }
+
+    async fn upload(&self, mut message: Message) -> Message {
+        let prefix = unique_prefix();
+        let mut links = Vec::new();
+
+        if self.max_body.is_some_and(|max| message.body.len() > max) {
+            let name = format!("{}-body.txt", prefix);
+            match self.store(&name, message.body.clone().into()).await {
+                Ok(url) => message.body = i18n::text_with(&message, "upload-body", [url]),
+                Err(err) => log::error!("Body upload failed: {}", err),
+            }
+        }
+
+        if let Some(max) = self.max_attachment {
+            let oversized = message
+                .attached_data
+                .iter()
+                .filter(|(_, data)| data.len() > max)
+                .map(|(filename, data)| (filename.clone(), data.clone()))
+                .collect::<Vec<_>>();
+
+            for (filename, data) in oversized {
+                let name = format!("{}-{}", prefix, filename);
+                match self.store(&name, data).await {
+                    Ok(url) => {
+                        message.attached_data.remove(&filename);
+                        links.push(i18n::text_with(
+                            &message,
+                            "upload-attachment",
+                            [filename, url],
+                        ));
+                    }
+                    Err(err) => log::error!("Attachment '{}' upload failed: {}", filename, err),
+                }
+            }
+        }
+
+        if !links.is_empty() {
+            if !message.body.is_empty() {
+                message.body.push_str("\n\n");
+            }
+            message.body.push_str(&links.join("\n"));
+        }
+
+        message
+    }
+}
+
+/// Prefix to not overwrite previous uploads with the same names.
+fn unique_prefix() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("{:x}{:04x}", time.as_millis(), count % 0x10000)
+}
+
+#[async_trait]
+impl<O: OutputConnector + Send + 'static> OutputConnector for UploadOutput<O> {
+    async fn connect(&mut self) -> Result<(), Error> {
+        self.output.connect().await
+    }
+
+    fn describe(&self) -> String {
+        format!("UploadOutput({})", self.output.describe())
+    }
+
+    async fn run(self: Box<Self>, mut receiver: Receiver) -> Result<(), Error> {
+        let UploadOutput { output, oversized } = *self;
+        let (sender, output_receiver) = channel::channel(1);
+
+        tokio::select! {
+            result = Box::new(output).run(output_receiver) => result,
+            result = forward(&oversized, &mut receiver, &sender) => result,
+        }
+    }
+}
+
+async fn forward(
+    oversized: &Oversized,
+    receiver: &mut Receiver,
+    sender: &Sender,
+) -> Result<(), Error> {
+    loop {
+        let message = receiver.recv().await?;
+        sender.send(oversized.upload(message).await).await?;
+    }
+}
+
+#[cfg(all(test, feature = "http"))]
+mod tests {
+    use super::*;
+    use crate::storage::WebDav;
+
+    use axum::body::Bytes as Body;
+    use axum::extract::{Path, State};
+    use axum::routing::put;
+    use axum::Router;
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn upload_oversized() {
+        let (upload_sender, mut uploads) = mpsc::unbounded_channel();
+        let router = Router::new()
+            .route(
+                "/files/:name",
+                put(
+                    |State(sender): State<mpsc::UnboundedSender<(String, Body)>>,
+                     Path(name): Path<String>,
+                     body: Body| async move {
+                        sender.send((name, body)).unwrap();
+                    },
+                ),
+            )
+            .with_state(upload_sender);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/files", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        let (output_sender, mut output_receiver) = mpsc::channel(1);
+        let output = UploadOutput::new(output_sender, WebDav::new(&url))
+            .max_body(10)
+            .max_attachment(3);
+        let (sender, receiver) = channel::channel(1);
+        tokio::spawn(Box::new(output).run(receiver));
+
+        let message = Message::default()
+            .user("user")
+            .body("too large body")
+            .attach([("small", vec![1]), ("big", vec![2; 4])]);
+        sender.send(message).await.unwrap();
+        let delivered = output_receiver.recv().await.unwrap();
+
+        let mut uploaded = [uploads.recv().await.unwrap(), uploads.recv().await.unwrap()];
+        uploaded.sort_by_key(|(name, _)| name.ends_with("body.txt"));
+        let (big_name, big) = &uploaded[0];
+        let (body_name, body) = &uploaded[1];
+        assert!(big_name.ends_with("-big"));
+        assert_eq!(big, &vec![2; 4]);
+        assert_eq!(body, "too large body".as_bytes());
+
+        let expected_body = format!(
+            "The reply is too large, download it from: {}/{}\n\n\
+             The file 'big' is too large, download it from: {}/{}",
+            url, body_name, url, big_name
+        );
+        assert_eq!(delivered.body, expected_body);
+        assert_eq!(delivered.attached_data.len(), 1);
+        assert!(delivered.attached_data.contains_key("small"));
+    }
+}
@@ -0,0 +1,19 @@
+use crate::channel::Receiver;
+use crate::error::Error;
+use crate::interface::OutputConnector;
+use crate::message::Message;
+
+use async_trait::async_trait;
+use tokio::sync::watch;
+
+/// Keeps the latest output message, that can be observed from any [`watch::Receiver`].
+/// The value is `None` until the first message is received.
+#[async_trait]
+impl OutputConnector for watch::Sender<Option<Message>> {
+    async fn run(self: Box<Self>, mut receiver: Receiver) -> Result<(), Error> {
+        loop {
+            let message = receiver.recv().await?;
+            self.send_replace(Some(message));
+        }
+    }
+}
@@ -1,54 +1,157 @@
 //! Main entity of `service-io`.
 //! Connects input, output, and services and run them.
 
-use crate::channel::{ClosedChannel, Receiver, Sender};
-use crate::interface::{InputConnector, OutputConnector, Service};
+mod ack;
+mod alias;
+mod deadline;
+mod description;
+mod event;
+mod handle;
+mod maintenance;
+mod memory;
+mod operator;
+mod readiness;
+mod scanner;
+mod temp;
+mod verification;
+mod whitelist;
+
+pub(crate) use ack::ACK_KEY;
+pub use ack::{Ack, AckMode};
+pub use description::{EngineDescription, ServiceDescription};
+pub use event::{ConnectorKind, DeliveryReport, DropReason, Event, Events, StopReason};
+pub use handle::EngineHandle;
+pub use maintenance::MaintenanceWindow;
+pub use operator::OPERATOR_SERVICE_NAME;
+pub use readiness::Component;
+pub use scanner::{
+    AttachmentScanner, BlockExtensions, MaxAttachmentSize, ScanVerdict, SCAN_NOTE_PREFIX,
+};
+pub use verification::Verification;
+
+pub(crate) use temp::{TempDir, TempDirs};
+
+use crate::channel::{Receiver, RecvHook, Sender};
+use crate::cluster::SharedQueue;
+use crate::connectors::{ConfigError, FallbackOutput, FieldError};
+use crate::error::Error;
+use crate::i18n;
+use crate::interface::{
+    DuplexConnector, InputConnector, OutputConnector, Service, STOP_HOOKS_TIMEOUT,
+};
 use crate::message::Message;
+use crate::services::settings;
+use crate::state::KeyValueStore;
+use crate::time;
+use crate::users::UserRegistry;
 
+use futures::future::FutureExt;
 use tokio::{
-    sync::mpsc,
-    task::{JoinError, JoinHandle},
+    sync::mpsc::{
+        self,
+        error::{SendError, TrySendError},
+    },
+    task::JoinHandle,
 };
+use tokio_util::task::TaskTracker;
+
+use alias::Alias;
+use deadline::Deadlines;
+use maintenance::Maintenance;
+use operator::Operator;
+use scanner::Scanners;
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+const CLUSTER_RETRY_TIME: Duration = Duration::from_secs(1);
 
-use std::collections::{HashMap, HashSet};
+type InputMapping = Box<dyn Fn(Message) -> Message + Send>;
+type InputFiltering = Box<dyn Fn(&Message) -> bool + Send>;
+type OutputMapping = Box<dyn Fn(Message) -> Message + Send>;
 
 struct ServiceConfig {
     name: String,
     service: Box<dyn Service + Send>,
-    whitelist: Option<HashSet<String>>,
 }
 
 struct ServiceHandle {
     input_sender: mpsc::Sender<Message>,
-    whitelist: Option<HashSet<String>>,
 }
 
 impl ServiceHandle {
-    async fn process_message(&self, message: Message) {
-        let allowed = match &self.whitelist {
-            Some(whitelist) => whitelist.contains(&message.user),
-            None => true,
-        };
+    fn allows(message: &Message, users: Option<&UserRegistry>, engine: &EngineHandle) -> bool {
+        let allowed = engine
+            .whitelists()
+            .allows(&message.service_name, &message.user)
+            && users.is_none_or(|users| users.allows(&message.user, &message.service_name));
 
-        if allowed {
-            let user = message.user.clone();
-            let service_name = message.service_name.clone();
-            let args = message.args.join(" ");
-            match self.input_sender.send(message).await {
-                Ok(()) => log::info!(
-                    "Processing message from '{}' for service '{}' with args '{}'",
-                    user,
-                    service_name,
-                    args
-                ),
-                Err(_) => log::warn!("Drop message for removed service '{}'", service_name),
-            }
-        } else {
+        if !allowed {
             log::warn!(
                 "Drop message for service '{}' not allowed for user '{}'",
                 message.service_name,
                 message.user,
             );
+            engine.emit(Event::MessageDropped {
+                user: message.user.clone(),
+                service_name: message.service_name.clone(),
+                reason: DropReason::NotAllowed,
+            });
+        }
+
+        allowed
+    }
+
+    fn log_processing(message: &Message) {
+        log::info!(
+            "Processing message from '{}' for service '{}' with args '{}'",
+            message.user,
+            message.service_name,
+            message.args.join(" ")
+        );
+        for (name, data) in &message.attached_data {
+            log::info!(
+                "Attachment '{}' of {} bytes with checksum {}",
+                name,
+                data.len(),
+                message.checksum(name).unwrap_or("-")
+            );
+        }
+    }
+
+    fn drop_for_service_down(mut message: Message, engine: &EngineHandle) {
+        engine.acks().cancel(&mut message);
+        log::warn!(
+            "Drop message for removed service '{}'",
+            message.service_name
+        );
+        engine.emit(Event::MessageDropped {
+            user: message.user,
+            service_name: message.service_name,
+            reason: DropReason::ServiceDown,
+        });
+    }
+}
+
+/// Output used instead of the real one in dry-run mode.
+struct DryRunOutput;
+
+#[async_trait::async_trait]
+impl OutputConnector for DryRunOutput {
+    async fn run(self: Box<Self>, mut receiver: Receiver) -> Result<(), Error> {
+        let engine = EngineHandle::current();
+        loop {
+            let message = receiver.recv().await?;
+            log::info!("Dry run, message not delivered: {:?}", message);
+            if let Some(engine) = &engine {
+                engine.emit(Event::DeliverySuppressed {
+                    message: Box::new(message),
+                });
+            }
         }
     }
 }
@@ -93,12 +196,36 @@ impl ServiceHandle {
 pub struct Engine {
     input: Option<Box<dyn InputConnector + Send>>,
     output: Option<Box<dyn OutputConnector + Send>>,
-    input_mapping: Option<Box<dyn Fn(Message) -> Message + Send>>,
-    input_filtering: Option<Box<dyn Fn(&Message) -> bool + Send>>,
+    input_mapping: Option<InputMapping>,
+    input_filtering: Option<InputFiltering>,
+    output_mapping: Option<OutputMapping>,
+    input_scanners: Scanners,
+    output_scanners: Scanners,
+    checksums: bool,
+    aliases: HashMap<String, Alias>,
+    default_service: Option<String>,
+    language: Option<String>,
+    timezone: Option<String>,
+    user_languages: HashMap<String, String>,
+    operator: Option<String>,
+    deadline: Option<Duration>,
+    maintenance: Vec<MaintenanceWindow>,
+    cluster: Option<Arc<dyn SharedQueue>>,
+    users: Option<UserRegistry>,
+    verification: Option<Verification>,
+    settings: Option<Arc<dyn KeyValueStore>>,
+    ack_mode: AckMode,
+    dependencies: Vec<(Component, Component)>,
+    handle: EngineHandle,
     service_configs: Vec<ServiceConfig>,
 }
 
 impl Engine {
+    /// Returns a handle to interact with the engine once it is running.
+    pub fn handle(&self) -> EngineHandle {
+        self.handle.clone()
+    }
+
     /// Set an input connector for this engine that will be run after calling [`Engine::run()`].
     ///
     /// Default connectors can be found in [`connectors`].
@@ -115,12 +242,37 @@ impl Engine {
     /// Default connectors can be found in [`connectors`].
     /// This call is mandatory in order to run the engine.
     ///
+    /// The messages are passed to the output connector in the order the services sent them.
+    /// To deliver several messages at once keeping the order for each user,
+    /// see [`ConcurrentOutput`].
+    ///
     /// [`connectors`]: crate::connectors
+    /// [`ConcurrentOutput`]: crate::connectors::ConcurrentOutput
     pub fn output(mut self, output: impl OutputConnector + Send + 'static) -> Engine {
         self.output = Some(Box::new(output));
         self
     }
 
+    /// Set an output connector that delivers through `fallback` while `primary` is failing.
+    /// It is equivalent to call [`Engine::output()`] with a [`FallbackOutput`],
+    /// that allows to configure when to switch between them.
+    ///
+    /// [`FallbackOutput`]: crate::connectors::FallbackOutput
+    pub fn output_with_fallback(
+        self,
+        primary: impl OutputConnector + Send + 'static,
+        fallback: impl OutputConnector + Send + 'static,
+    ) -> Engine {
+        self.output(FallbackOutput::new(primary, fallback))
+    }
+
+    /// Set both, the input and output connectors, from a [`DuplexConnector`].
+    /// It is equivalent to call [`Engine::input()`] and [`Engine::output()`] with its halves.
+    pub fn connector(self, connector: impl DuplexConnector) -> Engine {
+        let (input, output) = connector.split();
+        self.input(input).output(output)
+    }
+
     /// Maps the message processed by the input connector into other message before checking the
     /// destination service the message is for.
     ///
@@ -194,6 +346,354 @@ impl Engine {
         self
     }
 
+    /// Maps the messages sent by the services (and the engine notifications)
+    /// into other messages just before passing them to the output connector.
+    ///
+    /// # Example
+    /// ```rust no_run
+    /// use service_io::connectors::{ImapClient, SmtpClient};
+    /// use service_io::engine::Engine;
+    /// use service_io::services::Process;
+    /// use service_io::message::util::{self, AttachmentCompression};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     Engine::default()
+    ///         .input(
+    ///             ImapClient::default()
+    ///                 .domain("imap.domain.com")
+    ///                 .email("service@domain.com")
+    ///                 .password("1234"),
+    ///         )
+    ///         .output(
+    ///             SmtpClient::default()
+    ///                 .domain("smtp.domain.com")
+    ///                 .email("service@domain.com")
+    ///                 .password("1234"),
+    ///         )
+    ///         // Attachments bigger than 1MB in total are sent in a zip file
+    ///         .map_output(util::compress_attachments(1_000_000, AttachmentCompression::Zip))
+    ///         .add_service("s-process", Process)
+    ///         .run()
+    ///         .await;
+    /// }
+    /// ```
+    pub fn map_output(mut self, mapping: impl Fn(Message) -> Message + Send + 'static) -> Engine {
+        self.output_mapping = Some(Box::new(mapping));
+        self
+    }
+
+    /// Add a scanner for the attachments of the input messages, run before the messages
+    /// reach the services. Several scanners can be added, they run in the order they were added.
+    ///
+    /// # Example
+    /// ```rust no_run
+    /// use service_io::connectors::{ImapClient, SmtpClient};
+    /// use service_io::engine::{BlockExtensions, Engine, MaxAttachmentSize};
+    /// use service_io::services::Echo;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     Engine::default()
+    ///         .input(ImapClient::default() /* ... */)
+    ///         .output(SmtpClient::default() /* ... */)
+    ///         .attachment_scanner(BlockExtensions::new(["exe", "bat"]))
+    ///         .attachment_scanner(MaxAttachmentSize(5_000_000))
+    ///         .add_service("s-echo", Echo)
+    ///         .run()
+    ///         .await;
+    /// }
+    /// ```
+    pub fn attachment_scanner(
+        mut self,
+        scanner: impl AttachmentScanner + Send + Sync + 'static,
+    ) -> Engine {
+        self.input_scanners.push(Box::new(scanner));
+        self
+    }
+
+    /// Add a scanner for the attachments of the messages sent to the output connector.
+    /// They run after the mapping set by [`Engine::map_output`].
+    /// See [`Engine::attachment_scanner()`].
+    pub fn output_attachment_scanner(
+        mut self,
+        scanner: impl AttachmentScanner + Send + Sync + 'static,
+    ) -> Engine {
+        self.output_scanners.push(Box::new(scanner));
+        self
+    }
+
+    /// Add an alias to the engine. If the [`Message::service_name`] value matches with the `alias`,
+    /// the message is expanded into the `command` before looking for the destination service.
+    /// The alias is applied after the methods set by [`Engine::map_input`] and [`Engine::filter_input`].
+    ///
+    /// The first word of the `command` is the service name and the following words are
+    /// the arguments. The arguments can reference the arguments written by the user:
+    /// `$1`, `$2`, ... for a specific argument and `$@` for all of them.
+    /// If no reference is used, the arguments written by the user are appended at the end.
+    ///
+    /// # Example
+    /// ```rust no_run
+    /// use service_io::connectors::{ImapClient, SmtpClient};
+    /// use service_io::engine::Engine;
+    /// use service_io::services::{Alarm, Process, PublicIp};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     Engine::default()
+    ///         .input(
+    ///             ImapClient::default()
+    ///                 .domain("imap.domain.com")
+    ///                 .email("service@domain.com")
+    ///                 .password("1234"),
+    ///         )
+    ///         .output(
+    ///             SmtpClient::default()
+    ///                 .domain("smtp.domain.com")
+    ///                 .email("service@domain.com")
+    ///                 .password("1234"),
+    ///         )
+    ///         // "ip" is now the same as "s-public-ip"
+    ///         .alias("ip", "s-public-ip")
+    ///         // "ls /home" is now the same as "s-process ls -l /home"
+    ///         .alias("ls", "s-process ls -l")
+    ///         // "remind 5 tea" is now the same as "s-alarm tea 5"
+    ///         .alias("remind", "s-alarm $2 $1")
+    ///         .add_service("s-public-ip", PublicIp::default())
+    ///         .add_service("s-process", Process)
+    ///         .add_service("s-alarm", Alarm)
+    ///         .run()
+    ///         .await;
+    /// }
+    /// ```
+    pub fn alias(mut self, alias: impl Into<String>, command: impl AsRef<str>) -> Engine {
+        self.aliases
+            .insert(alias.into(), Alias::new(command.as_ref()));
+        self
+    }
+
+    /// Send the messages for unknown services to the service registered with `name`
+    /// (i.e. an [`AutoReply`] explaining the available commands), instead of dropping them.
+    /// The unknown service name is inserted as the first argument of the message.
+    ///
+    /// # Example
+    /// ```rust no_run
+    /// use service_io::connectors::{ImapClient, SmtpClient};
+    /// use service_io::engine::Engine;
+    /// use service_io::services::{AutoReply, Echo};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     Engine::default()
+    ///         .input(ImapClient::default() /* ... */)
+    ///         .output(SmtpClient::default() /* ... */)
+    ///         .default_service("s-auto-reply")
+    ///         .add_service("s-auto-reply", AutoReply::new("Unknown command '{command}'"))
+    ///         .add_service("s-echo", Echo)
+    ///         .run()
+    ///         .await;
+    /// }
+    /// ```
+    ///
+    /// [`AutoReply`]: crate::services::AutoReply
+    pub fn default_service(mut self, name: impl Into<String>) -> Engine {
+        self.default_service = Some(name.into());
+        self
+    }
+
+    /// Set the language of the incoming messages that have not specified any language.
+    /// The language is written in the [`Message::metadata`] with the [`i18n::LANGUAGE_KEY`]
+    /// and used by the services to reply in that language.
+    ///
+    /// See [`i18n`] for more information.
+    pub fn language(mut self, language: impl Into<String>) -> Engine {
+        self.language = Some(language.into());
+        self
+    }
+
+    /// Similar to [`Engine::language()`] but only for the messages of a specific user.
+    /// It has preference over the language set by [`Engine::language()`].
+    pub fn user_language(mut self, user: impl Into<String>, language: impl Into<String>) -> Engine {
+        self.user_languages.insert(user.into(), language.into());
+        self
+    }
+
+    /// Set the timezone of the incoming messages that have not specified any timezone,
+    /// as an IANA name (i.e. `Europe/Madrid`).
+    /// The timezone is written in the [`Message::metadata`] with the [`time::TIMEZONE_KEY`]
+    /// and used by the services to interpret and show times.
+    ///
+    /// See [`time`] for more information.
+    pub fn timezone(mut self, timezone: impl Into<String>) -> Engine {
+        self.timezone = Some(timezone.into());
+        self
+    }
+
+    /// Notify an operator `user` about the engine failures through the output connector:
+    /// services or connectors that panicked, connectors that failed to authenticate,
+    /// and repeated delivery failures.
+    ///
+    /// The notifications are sent with [`OPERATOR_SERVICE_NAME`] as service name,
+    /// `alert` as argument and the description of the failure as body.
+    pub fn notify_operator(mut self, user: impl Into<String>) -> Engine {
+        self.operator = Some(user.into());
+        self
+    }
+
+    /// Set a deadline for the services to reply each incoming message.
+    /// If a service does not send any message to the user before the deadline expires,
+    /// the engine sends a timeout notification to the user, so they know that the service
+    /// is still alive, and emits an [`Event::DeadlineExpired`].
+    ///
+    /// A request is considered replied once the service sends a message with the same
+    /// [`Message::user`] and [`Message::service_name`] as the request,
+    /// except for the [`Message::progress()`] messages.
+    /// Take into account that services that reply later by design (as an alarm)
+    /// will always expire the deadline.
+    pub fn deadline(mut self, timeout: Duration) -> Engine {
+        self.deadline = Some(timeout);
+        self
+    }
+
+    /// Add a maintenance window, during which only the services it allows run.
+    /// The messages for the rest of the services are held, and processed once it ends.
+    /// If the window allows no services, the input connector is paused instead.
+    ///
+    /// # Example
+    /// ```rust no_run
+    /// use service_io::connectors::{ImapClient, SmtpClient};
+    /// use service_io::engine::{Engine, MaintenanceWindow};
+    /// use service_io::services::{Admin, Process};
+    ///
+    /// use chrono::NaiveTime;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let backup = MaintenanceWindow::daily(
+    ///         NaiveTime::from_hms_opt(3, 0, 0).unwrap(),
+    ///         NaiveTime::from_hms_opt(4, 0, 0).unwrap(),
+    ///     )
+    ///     .allow("s-admin");
+    ///
+    ///     Engine::default()
+    ///         .input(ImapClient::default() /* ... */)
+    ///         .output(SmtpClient::default() /* ... */)
+    ///         .maintenance(backup)
+    ///         .add_service_for("s-admin", Admin, ["admin@domain.com"])
+    ///         .add_service("s-process", Process)
+    ///         .run()
+    ///         .await;
+    /// }
+    /// ```
+    pub fn maintenance(mut self, window: MaintenanceWindow) -> Engine {
+        self.maintenance.push(window);
+        self
+    }
+
+    /// Add the SHA-256 checksum of the attachments to the metadata of the input messages,
+    /// before they reach the services, and of the messages sent to the output connector.
+    /// The checksums of the attachments passed through are checked before the delivery,
+    /// logging the mismatches. See [`Message::add_checksums()`].
+    pub fn attachment_checksums(mut self, enabled: bool) -> Engine {
+        self.checksums = enabled;
+        self
+    }
+
+    /// Run the engine without side effects, to validate a new configuration safely
+    /// against production inputs.
+    ///
+    /// The output messages are not delivered by the output connector (that is not even run).
+    /// Instead, they are logged and emitted as [`Event::DeliverySuppressed`].
+    /// The input connectors avoid their destructive actions,
+    /// i.e. [`ImapClient`] does not remove the emails.
+    ///
+    /// [`ImapClient`]: crate::connectors::ImapClient
+    pub fn dry_run(self, enabled: bool) -> Engine {
+        self.handle.set_dry_run(enabled);
+        self
+    }
+
+    /// Run this engine as an instance of a cluster that shares the `queue`.
+    ///
+    /// The input messages are pushed into the `queue` after applying the mappings, filters,
+    /// aliases and languages of this engine. Then, any instance of the cluster can pop them and
+    /// dispatch them to its services. The replies are delivered by the output connector of the
+    /// instance that processed the message.
+    ///
+    /// See [`cluster`] for more information.
+    ///
+    /// [`cluster`]: crate::cluster
+    pub fn cluster(mut self, queue: impl SharedQueue + 'static) -> Engine {
+        self.cluster = Some(Arc::new(queue));
+        self
+    }
+
+    /// Limit the bytes (body and attached data) of the messages queued in the services and
+    /// in the output connector. Once exceeded, no more input messages are read until
+    /// the services and the output connector consume part of them.
+    ///
+    /// It avoids running out of memory if a burst of heavy messages arrives
+    /// while the output connector is slow, i.e. emails with big attachments.
+    /// The limit can be exceeded by the last message read.
+    pub fn memory_budget(self, bytes: usize) -> Engine {
+        self.handle.memory().set_limit(bytes);
+        self
+    }
+
+    /// Set when the messages sent by the input connector with [`Sender::send_acked()`]
+    /// are acknowledged. By default, [`AckMode::Accepted`].
+    ///
+    /// In cluster mode, the messages are acknowledged once they are in the shared queue.
+    pub fn ack_mode(mut self, mode: AckMode) -> Engine {
+        self.ack_mode = mode;
+        self
+    }
+
+    /// Start the `component` once the `dependency` is ready:
+    /// connectors once their `connect()` finished, and services once their
+    /// [`Service::on_start()`] finished.
+    /// If the `dependency` fails to start, the `component` does not start either.
+    ///
+    /// The input connector always depends on the output connector, so no message is consumed
+    /// (i.e. removed from the IMAP server) until the replies can be delivered.
+    /// Use [`EngineHandle::ready()`] to know when all the components are ready.
+    ///
+    /// # Example
+    /// ```rust no_run
+    /// use service_io::connectors::{ImapClient, SmtpClient};
+    /// use service_io::engine::{Component, Engine};
+    /// use service_io::services::{Echo, Process};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     Engine::default()
+    ///         .input(ImapClient::default() /* ... */)
+    ///         .output(SmtpClient::default() /* ... */)
+    ///         .add_service("s-echo", Echo)
+    ///         .add_service("s-process", Process)
+    ///         // No email is read until "s-echo" is started
+    ///         .depends_on(Component::Input, Component::Service("s-echo".into()))
+    ///         .run()
+    ///         .await;
+    /// }
+    /// ```
+    pub fn depends_on(mut self, component: Component, dependency: Component) -> Engine {
+        self.dependencies.push((component, dependency));
+        self
+    }
+
+    /// Dependencies of each component, including the implicit ones.
+    fn dependency_map(&self) -> HashMap<Component, Vec<Component>> {
+        let mut dependencies = HashMap::from([(Component::Input, vec![Component::Output])]);
+        for (component, dependency) in &self.dependencies {
+            dependencies
+                .entry(component.clone())
+                .or_default()
+                .push(dependency.clone());
+        }
+        dependencies
+    }
+
     /// Add a service to the engine registered with a `name`. If the [`Message::service_name`] value
     /// matches with this `name`, the message will be redirected to the service.
     ///
@@ -210,7 +710,6 @@ impl Engine {
         self.service_configs.push(ServiceConfig {
             name: name.into(),
             service: Box::new(service),
-            whitelist: None,
         });
         self
     }
@@ -253,103 +752,858 @@ impl Engine {
         service: impl Service + Send + 'static,
         whitelist: impl IntoIterator<Item = S>,
     ) -> Engine {
+        let name = name.into();
+        let whitelist = whitelist.into_iter().map(|s| s.into()).collect();
+        self.handle.whitelists().set(name.clone(), whitelist);
         self.service_configs.push(ServiceConfig {
-            name: name.into(),
+            name,
             service: Box::new(service),
-            whitelist: Some(whitelist.into_iter().map(|s| s.into()).collect()),
         });
         self
     }
 
-    /// Run asynchronously the input, output and all services configured for this engine.
-    /// The engine will run until all services finished or the input/output connector finalizes.
-    pub async fn run(self) {
-        log::info!("Initializing engine...");
-
-        let (input_sender, mut input_receiver) = mpsc::channel(32);
-        Self::load_input(self.input.unwrap(), input_sender);
-
-        let (output_sender, output_receiver) = mpsc::channel(32);
-        let mut output_task = Self::load_output(self.output.unwrap(), output_receiver);
-
-        let services = Self::load_services(self.service_configs, output_sender);
-
-        loop {
-            tokio::select! {
-                Some(message) = input_receiver.recv() => {
-                    let message = match &self.input_mapping {
-                        Some(map) => map(message),
-                        None => message,
-                    };
-
-                    let allowed = match &self.input_filtering {
-                        Some(filter) => filter(&message),
-                        None => true,
-                    };
-
-                    if allowed {
-                        match services.get(&message.service_name) {
-                            Some(handle) => handle.process_message(message).await,
-                            None => log::trace!(
-                                "Drop Message from {} for unknown service '{}'",
-                                message.user,
-                                message.service_name
-                            ),
-                        }
-                    }
-                }
-                _ = &mut output_task => break,
-                else => break,
-            }
-        }
+    /// Control which services each user can use by their roles.
+    /// The messages of users without a role granting access to the service are discarded,
+    /// as the ones of users not in the whitelist of the service.
+    ///
+    /// See [`users`] for more information.
+    ///
+    /// [`users`]: crate::users
+    pub fn users(mut self, registry: UserRegistry) -> Engine {
+        self.users = Some(registry);
+        self
     }
 
-    fn load_input(
-        input: Box<dyn InputConnector + Send>,
-        sender: mpsc::Sender<Message>,
-    ) -> JoinHandle<()> {
-        tokio::spawn(async move {
-            log::info!("Loading input connector");
-
-            let result = tokio::spawn(async move { input.run(Sender(sender)).await }).await;
+    /// Require the users writing for the first time to verify their address
+    /// replying to a challenge before their messages are delivered to the services.
+    ///
+    /// See [`Verification`] for more information.
+    pub fn verify_users(mut self, verification: Verification) -> Engine {
+        self.verification = Some(verification);
+        self
+    }
 
-            Self::log_join_result(result, "Input connector");
-        })
+    /// Add the preferences set by each user with the [`Settings`] service to the
+    /// [`Message::metadata`] of their messages, replacing the values set by the input connector
+    /// or by [`Engine::language()`].
+    /// The `store` must be shared with the [`Settings`] service.
+    ///
+    /// [`Settings`]: crate::services::Settings
+    pub fn user_settings(mut self, store: impl KeyValueStore + 'static) -> Engine {
+        self.settings = Some(Arc::new(store));
+        self
     }
 
-    fn load_output(
-        output: Box<dyn OutputConnector + Send>,
-        receiver: mpsc::Receiver<Message>,
-    ) -> JoinHandle<()> {
+    /// Persist the whitelists of the services in a `store`,
+    /// so the changes done at runtime by [`EngineHandle::allow_user()`] and
+    /// [`EngineHandle::disallow_user()`] survive restarts.
+    ///
+    /// When the engine starts, the whitelists found in the store replace the ones
+    /// set by [`Engine::add_service_for()`].
+    ///
+    /// # Example
+    /// ```rust no_run
+    /// use service_io::connectors::{DebugStdout, UserStdin};
+    /// use service_io::engine::Engine;
+    /// use service_io::services::{Admin, Process};
+    /// use service_io::state::{MemoryStore, Scoped};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     Engine::default()
+    ///         .input(UserStdin("admin@domain.com"))
+    ///         .output(DebugStdout)
+    ///         .whitelist_store(Scoped::new(MemoryStore::default(), "whitelist"))
+    ///         // "s-admin allow s-process user@domain.com" grants access to s-process
+    ///         .add_service_for("s-admin", Admin, ["admin@domain.com"])
+    ///         .add_service_for("s-process", Process, ["admin@domain.com"])
+    ///         .run()
+    ///         .await;
+    /// }
+    /// ```
+    pub fn whitelist_store(self, store: impl KeyValueStore + 'static) -> Engine {
+        self.handle.whitelists().set_store(Arc::new(store));
+        self
+    }
+
+    /// Keep the state of the services in a `store`, accessible by the [`SimpleService`]s
+    /// through [`Context::state()`], each one under the name it was registered with.
+    /// By default, the state is kept in memory.
+    ///
+    /// # Example
+    /// ```rust no_run
+    /// use service_io::connectors::{ImapClient, SmtpClient};
+    /// use service_io::engine::Engine;
+    /// use service_io::services::Echo;
+    /// use service_io::state::FileStore;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     Engine::default()
+    ///         .input(ImapClient::default() /* ... */)
+    ///         .output(SmtpClient::default() /* ... */)
+    ///         .state_store(FileStore::new("state.json"))
+    ///         .add_service("s-echo", Echo)
+    ///         .run()
+    ///         .await;
+    /// }
+    /// ```
+    ///
+    /// [`SimpleService`]: crate::interface::SimpleService
+    /// [`Context::state()`]: crate::interface::Context::state()
+    pub fn state_store(self, store: impl KeyValueStore + 'static) -> Engine {
+        self.handle.set_state_store(Arc::new(store));
+        self
+    }
+
+    /// Maximum number of entries of the [`Cache`] shared by the services. By default, 10000.
+    /// See [`Context::cache()`].
+    ///
+    /// [`Cache`]: crate::cache::Cache
+    /// [`Context::cache()`]: crate::interface::Context::cache()
+    pub fn cache_capacity(self, entries: usize) -> Engine {
+        self.handle.cache().set_capacity(entries);
+        self
+    }
+
+    /// Directory where the temporary directories of the requests are created,
+    /// by default the one of the system. See [`Context::temp_dir()`].
+    /// They are created inside a `service-io-<pid>` subdirectory,
+    /// removed when the engine finishes.
+    ///
+    /// [`Context::temp_dir()`]: crate::interface::Context::temp_dir()
+    pub fn temp_dir(self, path: impl Into<PathBuf>) -> Engine {
+        self.handle.temp_dirs().set_root(path.into());
+        self
+    }
+
+    /// Description of the configuration of the engine, without secrets,
+    /// useful for tooling or to compare configurations.
+    /// Use [`EngineHandle::describe()`] to get it while the engine runs.
+    ///
+    /// # Example
+    /// ```rust
+    /// use service_io::connectors::{DebugStdout, UserStdin};
+    /// use service_io::engine::Engine;
+    /// use service_io::services::Echo;
+    ///
+    /// let description = Engine::default()
+    ///     .input(UserStdin("user"))
+    ///     .output(DebugStdout)
+    ///     .language("es")
+    ///     .add_service_for("s-echo", Echo, ["user"])
+    ///     .describe();
+    ///
+    /// assert_eq!(description.input.as_deref(), Some("UserStdin"));
+    /// assert_eq!(description.services[0].kind, "Echo");
+    /// assert_eq!(description.services[0].whitelist, Some(vec!["user".into()]));
+    /// assert_eq!(description.language.as_deref(), Some("es"));
+    /// ```
+    pub fn describe(&self) -> EngineDescription {
+        let services = self
+            .service_configs
+            .iter()
+            .map(|config| ServiceDescription {
+                name: config.name.clone(),
+                kind: config.service.describe(),
+                whitelist: self.handle.whitelists().users(&config.name),
+            })
+            .collect();
+
+        EngineDescription {
+            input: self.input.as_ref().map(|input| (**input).describe()),
+            output: self.output.as_ref().map(|output| (**output).describe()),
+            services,
+            aliases: self
+                .aliases
+                .iter()
+                .map(|(alias, command)| (alias.clone(), command.command()))
+                .collect(),
+            default_service: self.default_service.clone(),
+            language: self.language.clone(),
+            timezone: self.timezone.clone(),
+            user_languages: self.user_languages.clone().into_iter().collect(),
+            operator: self.operator.clone(),
+            deadline_millis: self.deadline.map(|deadline| deadline.as_millis() as u64),
+            dry_run: self.handle.is_dry_run(),
+            attachment_checksums: self.checksums,
+            ack_mode: self.ack_mode,
+            dependencies: self
+                .dependencies
+                .iter()
+                .map(|(component, dependency)| (component.to_string(), dependency.to_string()))
+                .collect(),
+            maintenance_windows: self.maintenance.len(),
+            clustered: self.cluster.is_some(),
+            verified_users: self.verification.is_some(),
+        }
+    }
+
+    /// Engine configured with the options of a `description`.
+    /// The connectors, services and the options that can not be described
+    /// (i.e. the cluster queue) must be set afterwards.
+    ///
+    /// # Example
+    /// ```rust
+    /// use service_io::engine::{Engine, EngineDescription};
+    ///
+    /// let json = r#"{"language": "es", "aliases": {"ip": "s-public-ip"}}"#;
+    /// let description: EngineDescription = serde_json::from_str(json).unwrap();
+    ///
+    /// let engine = Engine::from_description(&description);
+    /// assert_eq!(engine.describe().aliases["ip"], "s-public-ip");
+    /// ```
+    pub fn from_description(description: &EngineDescription) -> Engine {
+        let mut engine = Engine::default()
+            .dry_run(description.dry_run)
+            .attachment_checksums(description.attachment_checksums)
+            .ack_mode(description.ack_mode);
+        for (alias, command) in &description.aliases {
+            engine = engine.alias(alias, command);
+        }
+        for (user, language) in &description.user_languages {
+            engine = engine.user_language(user, language);
+        }
+        engine.default_service = description.default_service.clone();
+        engine.language = description.language.clone();
+        engine.timezone = description.timezone.clone();
+        engine.operator = description.operator.clone();
+        engine.deadline = description.deadline_millis.map(Duration::from_millis);
+        engine
+    }
+
+    /// Run asynchronously the input, output and all services configured for this engine.
+    /// The engine will run until all services finished, the input/output connector finalizes,
+    /// or [`EngineHandle::shutdown()`] is called.
+    ///
+    /// Once the engine finishes, the work pending in services is cancelled.
+    /// See [`Receiver::cancellation_token()`].
+    ///
+    /// If the engine is not properly configured (see [`Engine::validate()`]),
+    /// the error is logged and the engine does not run.
+    /// Use [`Engine::try_run()`] to get the error.
+    pub async fn run(self) {
+        if let Err(err) = self.try_run().await {
+            log::error!("{}", err);
+        }
+    }
+
+    /// Check that the engine has the connectors required to run:
+    /// an input connector and an output connector (not needed in dry-run mode),
+    /// and that the dependencies of [`Engine::depends_on()`] can be satisfied.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut errors = Vec::new();
+        if self.input.is_none() {
+            errors.push(FieldError::Missing("input connector"));
+        }
+        if self.output.is_none() && !self.handle.is_dry_run() {
+            errors.push(FieldError::Missing("output connector"));
+        }
+        for (component, dependency) in &self.dependencies {
+            for component in [component, dependency] {
+                if let Component::Service(name) = component {
+                    if !self
+                        .service_configs
+                        .iter()
+                        .any(|config| &config.name == name)
+                    {
+                        errors.push(FieldError::Malformed {
+                            field: "dependencies",
+                            reason: format!("unknown {}", component),
+                        });
+                    }
+                }
+            }
+        }
+        if let Some(name) = &self.default_service {
+            if !self
+                .service_configs
+                .iter()
+                .any(|config| &config.name == name)
+            {
+                errors.push(FieldError::Malformed {
+                    field: "default service",
+                    reason: format!("unknown service '{}'", name),
+                });
+            }
+        }
+        if let Some(component) = readiness::find_cycle(&self.dependency_map()) {
+            errors.push(FieldError::Malformed {
+                field: "dependencies",
+                reason: format!("the {} depends on itself", component),
+            });
+        }
+        ConfigError::check("Engine", errors)
+    }
+
+    /// Same as [`Engine::run()`] but returning an error if the engine is not properly
+    /// configured, instead of logging it.
+    ///
+    /// # Example
+    /// ```rust
+    /// use service_io::connectors::{ConfigError, FieldError, UserStdin};
+    /// use service_io::engine::Engine;
+    /// use service_io::services::Echo;
+    /// use service_io::Error;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let result = Engine::default()
+    ///         .input(UserStdin("user"))
+    ///         .add_service("s-echo", Echo)
+    ///         .try_run()
+    ///         .await;
+    ///
+    ///     let expected = ConfigError {
+    ///         connector: "Engine",
+    ///         errors: vec![FieldError::Missing("output connector")],
+    ///     };
+    ///     assert!(matches!(result, Err(Error::Config(error)) if error == expected));
+    /// }
+    /// ```
+    pub async fn try_run(mut self) -> Result<(), Error> {
+        self.validate()?;
+        self.handle.set_description(self.describe());
+
+        let _shutdown_guard = self.handle.shutdown_token().clone().drop_guard();
+
+        log::info!("Initializing engine...");
+
+        let handle = self.handle();
+        if let Err(err) = handle.whitelists().load().await {
+            log::error!("Whitelists could not be loaded: {}", err);
+        }
+        if let Some(users) = self.users.clone() {
+            if let Err(err) = users.load().await {
+                log::error!("Users could not be loaded: {}", err);
+            }
+        }
+
+        let mut operator = self.operator.take().map(Operator::new);
+        let mut events = self.handle.events();
+        let mut deadlines = self.deadline.map(Deadlines::new);
+        let mut maintenance = Maintenance::new(std::mem::take(&mut self.maintenance));
+
+        let dependencies = self.dependency_map();
+        let components = [Component::Input, Component::Output]
+            .into_iter()
+            .chain(
+                self.service_configs
+                    .iter()
+                    .map(|config| Component::Service(config.name.clone())),
+            )
+            .collect();
+        self.handle.readiness().expect(components);
+        let depends = |component| dependencies.get(&component).cloned().unwrap_or_default();
+
+        // With a memory budget, the messages waiting in the input queue are also limited.
+        let input_capacity = match self.handle.memory().is_limited() {
+            true => 1,
+            false => 32,
+        };
+        let (input_sender, mut input_receiver) = mpsc::channel(input_capacity);
+        Self::load_input(
+            self.input.take().unwrap(),
+            input_sender,
+            depends(Component::Input),
+            self.handle(),
+        );
+
+        let output = match self.output.take() {
+            Some(output) if !self.handle.is_dry_run() => output,
+            _ => Box::new(DryRunOutput),
+        };
+        let (output_sender, output_receiver) = mpsc::channel(32);
+        let mut output_task = Self::load_output(
+            output,
+            output_receiver,
+            depends(Component::Output),
+            self.handle(),
+        );
+        let mut output_sender = Some(output_sender);
+
+        let (services_sender, mut services_receiver) = mpsc::channel(32);
+        let stop_hooks = TaskTracker::new();
+        let services = Self::load_services(
+            std::mem::take(&mut self.service_configs),
+            services_sender,
+            &dependencies,
+            self.handle(),
+            &stop_hooks,
+        );
+
+        let (cluster_sender, mut cluster_receiver) = match self.cluster.take() {
+            Some(queue) => {
+                let (sender, receiver) = Self::load_cluster(queue, self.handle());
+                (Some(sender), Some(receiver))
+            }
+            None => (None, None),
+        };
+
+        // Message waiting for space in the queue of its service.
+        // Meanwhile, no more input messages are read, so the input connector will be paused
+        // once the input queue is full.
+        let mut pending: Option<(Message, mpsc::Sender<Message>)> = None;
+
+        loop {
+            // The messages held during a maintenance go before the new ones.
+            if pending.is_none() {
+                if let Some(message) = maintenance.release() {
+                    pending = self.dispatch(message, &services, &mut deadlines);
+                    continue;
+                }
+            }
+
+            let reservation = pending
+                .as_ref()
+                .map(|(_, sender)| sender.clone().reserve_owned());
+            let maintenance_end = maintenance.remaining();
+
+            tokio::select! {
+                Some(message) = input_receiver.recv(),
+                    if pending.is_none()
+                        && !self.handle.memory().exceeded()
+                        && !maintenance.pauses_input() =>
+                {
+                    let message = match (self.prepare(message), &self.verification) {
+                        (Some(message), Some(verification)) => {
+                            let ack_id = ack::id(&message).cloned();
+                            let mut checked = verification.check(message, &self.handle).await;
+                            if let Some(ack_id) = ack_id {
+                                match &mut checked.deliver {
+                                    Some(message) if ack::id(message) == Some(&ack_id) => (),
+                                    // Held or replaced by the held message
+                                    Some(message) => {
+                                        self.handle.acks().cancel(message);
+                                        self.handle.acks().resolve_id(&ack_id);
+                                    }
+                                    None => self.handle.acks().resolve_id(&ack_id),
+                                }
+                            }
+                            if let (Some(reply), Some(sender)) = (checked.reply, &output_sender) {
+                                let reply = self.prepare_output(reply);
+                                Self::deliver(reply, sender, &self.handle, &self.output_scanners, self.checksums).await;
+                            }
+                            checked.deliver
+                        }
+                        (message, _) => message,
+                    };
+
+                    let message = match (message, &self.settings) {
+                        (Some(message), Some(store)) => {
+                            Some(settings::apply_user_settings(&**store, message).await)
+                        }
+                        (message, _) => message,
+                    };
+
+                    let message = match message {
+                        Some(message) => {
+                            let mut message = scanner::scan(&self.input_scanners, message).await;
+                            if self.checksums {
+                                message.add_checksums();
+                            }
+                            Some(message)
+                        }
+                        None => None,
+                    };
+
+                    if let Some(message) = message {
+                        match &cluster_sender {
+                            Some(sender) => {
+                                // The push task acknowledges the message once it is pushed,
+                                // and only finishes along with the engine.
+                                sender.send(message).await.ok();
+                            }
+                            None => {
+                                if let Some(message) = maintenance.hold(message) {
+                                    pending = self.dispatch(message, &services, &mut deadlines);
+                                }
+                            }
+                        }
+                    }
+                }
+                Some(message) = async { cluster_receiver.as_mut().unwrap().recv().await },
+                    if cluster_receiver.is_some()
+                        && pending.is_none()
+                        && !self.handle.memory().exceeded() =>
+                {
+                    if let Some(message) = maintenance.hold(message) {
+                        pending = self.dispatch(message, &services, &mut deadlines);
+                    }
+                }
+                reserved = async { reservation.unwrap().await }, if pending.is_some() => {
+                    let (message, _) = pending.take().unwrap();
+                    match reserved {
+                        Ok(permit) => {
+                            let message = self.accept(message);
+                            ServiceHandle::log_processing(&message);
+                            if let Some(deadlines) = &mut deadlines {
+                                deadlines.start(&message);
+                            }
+                            permit.send(message);
+                        }
+                        Err(_) => ServiceHandle::drop_for_service_down(message, &self.handle),
+                    }
+                }
+                message = services_receiver.recv(), if output_sender.is_some() => {
+                    match message {
+                        Some(mut message) => {
+                            if let Some(deadlines) = &mut deadlines {
+                                deadlines.resolve(&message);
+                            }
+                            // A progress message is not the reply the ack waits for.
+                            if !message.is_progress() {
+                                self.handle.acks().resolve(&mut message);
+                            }
+                            if let Some(sender) = &output_sender {
+                                let message = self.prepare_output(message);
+                                Self::deliver(message, sender, &self.handle, &self.output_scanners, self.checksums).await;
+                            }
+                        }
+                        // All services finished, so no more output messages.
+                        None => output_sender = None,
+                    }
+                }
+                Ok(event) = events.recv(), if operator.is_some() => {
+                    let notification = operator
+                        .as_mut()
+                        .and_then(|operator| operator.notification(&event));
+
+                    if let (Some(message), Some(sender)) = (notification, &output_sender) {
+                        let message = self.prepare_output(message);
+                        Self::deliver(message, sender, &self.handle, &self.output_scanners, self.checksums).await;
+                    }
+                }
+                Some(notification) = async { deadlines.as_mut().unwrap().expired().await },
+                    if deadlines.is_some() =>
+                {
+                    log::warn!(
+                        "Service '{}' did not reply to '{}' before the deadline",
+                        notification.service_name,
+                        notification.user
+                    );
+                    self.handle.emit(Event::DeadlineExpired {
+                        user: notification.user.clone(),
+                        service_name: notification.service_name.clone(),
+                    });
+                    if let Some(sender) = &output_sender {
+                        let notification = self.prepare_output(notification);
+                        Self::deliver(notification, sender, &self.handle, &self.output_scanners, self.checksums).await;
+                    }
+                }
+                _ = tokio::time::sleep(maintenance_end.unwrap_or_default()),
+                    if maintenance_end.is_some() =>
+                {
+                    log::info!("Maintenance finished");
+                }
+                // Input paused until memory is released.
+                _ = self.handle.memory().released(), if self.handle.memory().exceeded() => (),
+                _ = &mut output_task => break,
+                _ = self.handle.shutdown_token().cancelled() => break,
+                else => break,
+            }
+        }
+
+        // Closing the queues of the services, so they finish and run their stop hooks.
+        drop(services);
+        stop_hooks.close();
+        if tokio::time::timeout(STOP_HOOKS_TIMEOUT, stop_hooks.wait())
+            .await
+            .is_err()
+        {
+            log::warn!("The stop hooks of the services did not finish in time");
+        }
+
+        self.handle.acks().clear();
+        self.handle.temp_dirs().clear();
+        Ok(())
+    }
+
+    /// Acknowledge the message if the [`AckMode`] only requires to be in the service queue,
+    /// and account it in the memory budget.
+    fn accept(&self, mut message: Message) -> Message {
+        if self.ack_mode == AckMode::Accepted {
+            self.handle.acks().resolve(&mut message);
+        }
+        self.handle.memory().acquire(&message);
+        message
+    }
+
+    /// Send the message to its service.
+    /// If the queue of the service is full, the message is returned as pending.
+    fn dispatch(
+        &self,
+        message: Message,
+        services: &HashMap<String, ServiceHandle>,
+        deadlines: &mut Option<Deadlines>,
+    ) -> Option<(Message, mpsc::Sender<Message>)> {
+        let (message, service) = self.lookup(message, services)?;
+        match service.input_sender.try_reserve() {
+            Ok(permit) => {
+                let message = self.accept(message);
+                ServiceHandle::log_processing(&message);
+                if let Some(deadlines) = deadlines {
+                    deadlines.start(&message);
+                }
+                permit.send(message);
+                None
+            }
+            Err(TrySendError::Full(())) => Some((message, service.input_sender.clone())),
+            Err(TrySendError::Closed(())) => {
+                ServiceHandle::drop_for_service_down(message, &self.handle);
+                None
+            }
+        }
+    }
+
+    /// Apply the mapping, filtering, aliases and languages to an input message.
+    fn prepare(&self, message: Message) -> Option<Message> {
+        let mut message = match &self.input_mapping {
+            Some(map) => map(message),
+            None => message,
+        };
+
+        let allowed = match &self.input_filtering {
+            Some(filter) => filter(&message),
+            None => true,
+        };
+
+        if !allowed {
+            self.handle.acks().resolve(&mut message);
+            self.handle.emit(Event::MessageDropped {
+                user: message.user,
+                service_name: message.service_name,
+                reason: DropReason::Filtered,
+            });
+            return None;
+        }
+
+        let message = match self.aliases.get(&message.service_name) {
+            Some(alias) => alias.expand(message),
+            None => message,
+        };
+
+        Some(self.apply_timezone(self.apply_language(message)))
+    }
+
+    fn lookup<'a>(
+        &self,
+        mut message: Message,
+        services: &'a HashMap<String, ServiceHandle>,
+    ) -> Option<(Message, &'a ServiceHandle)> {
+        if let (None, Some(default)) = (services.get(&message.service_name), &self.default_service)
+        {
+            let unknown = std::mem::replace(&mut message.service_name, default.clone());
+            message.args.insert(0, unknown);
+        }
+
+        match services.get(&message.service_name) {
+            Some(service) => {
+                match ServiceHandle::allows(&message, self.users.as_ref(), &self.handle) {
+                    true => Some((message, service)),
+                    false => {
+                        self.handle.acks().resolve(&mut message);
+                        None
+                    }
+                }
+            }
+            None => {
+                self.handle.acks().resolve(&mut message);
+                log::trace!(
+                    "Drop Message from {} for unknown service '{}'",
+                    message.user,
+                    message.service_name
+                );
+                self.handle.emit(Event::MessageDropped {
+                    user: message.user,
+                    service_name: message.service_name,
+                    reason: DropReason::UnknownService,
+                });
+                None
+            }
+        }
+    }
+
+    fn prepare_output(&self, message: Message) -> Message {
+        match &self.output_mapping {
+            Some(map) => map(message),
+            None => message,
+        }
+    }
+
+    async fn deliver(
+        mut message: Message,
+        output_sender: &mpsc::Sender<Message>,
+        engine: &EngineHandle,
+        scanners: &Scanners,
+        checksums: bool,
+    ) {
+        // Replies built from a tracked request (i.e. notifications) copy its ack id.
+        ack::detach(&mut message);
+        for name in message.corrupted_attachments() {
+            log::warn!(
+                "Attachment '{}' from service '{}' for '{}' does not match its checksum",
+                name,
+                message.service_name,
+                message.user
+            );
+        }
+        let mut message = scanner::scan(scanners, message).await;
+        if checksums {
+            message.add_checksums();
+        }
+        engine.memory().acquire(&message);
+        if let Err(SendError(message)) = output_sender.send(message).await {
+            engine.memory().release(&message);
+            log::warn!(
+                "Drop message from service '{}' for '{}': output connector down",
+                message.service_name,
+                message.user
+            );
+            engine.emit(Event::DeliveryFailed {
+                user: message.user,
+                service_name: message.service_name,
+            });
+        }
+    }
+
+    fn apply_language(&self, mut message: Message) -> Message {
+        if !message.metadata.contains_key(i18n::LANGUAGE_KEY) {
+            let language = self
+                .user_languages
+                .get(&message.user)
+                .or(self.language.as_ref());
+
+            if let Some(language) = language {
+                message
+                    .metadata
+                    .insert(i18n::LANGUAGE_KEY.into(), language.clone());
+            }
+        }
+        message
+    }
+
+    fn apply_timezone(&self, mut message: Message) -> Message {
+        if let Some(timezone) = &self.timezone {
+            if !message.metadata.contains_key(time::TIMEZONE_KEY) {
+                message
+                    .metadata
+                    .insert(time::TIMEZONE_KEY.into(), timezone.clone());
+            }
+        }
+        message
+    }
+
+    fn load_input(
+        mut input: Box<dyn InputConnector + Send>,
+        sender: mpsc::Sender<Message>,
+        dependencies: Vec<Component>,
+        engine: EngineHandle,
+    ) -> JoinHandle<()> {
         tokio::spawn(async move {
-            log::info!("Loading output connector");
+            log::info!("Loading input connector");
+            let connect = input.connect();
+            if !Self::start(Component::Input, &dependencies, connect, &engine).await {
+                engine.emit(Event::ConnectorDisconnected {
+                    connector: ConnectorKind::Input,
+                    reason: StopReason::Failed,
+                });
+                return;
+            }
+            engine.emit(Event::ConnectorConnected {
+                connector: ConnectorKind::Input,
+            });
+
+            let task = engine.clone().scope(input.run(Sender(sender)));
+            let reason = Self::supervise(task, "Input connector").await;
+            engine.emit(Event::ConnectorDisconnected {
+                connector: ConnectorKind::Input,
+                reason,
+            });
+        })
+    }
 
-            let result = tokio::spawn(async move { output.run(Receiver(receiver)).await }).await;
+    fn load_output(
+        mut output: Box<dyn OutputConnector + Send>,
+        receiver: mpsc::Receiver<Message>,
+        dependencies: Vec<Component>,
+        engine: EngineHandle,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            log::info!("Loading output connector");
+            let connect = output.connect();
+            if !Self::start(Component::Output, &dependencies, connect, &engine).await {
+                engine.emit(Event::ConnectorDisconnected {
+                    connector: ConnectorKind::Output,
+                    reason: StopReason::Failed,
+                });
+                return;
+            }
+            engine.emit(Event::ConnectorConnected {
+                connector: ConnectorKind::Output,
+            });
 
-            Self::log_join_result(result, "Output connector");
+            let token = engine.shutdown_token().clone();
+            let receiver = Receiver(receiver, token, Some(Self::release_hook(&engine)));
+            let task = engine.clone().scope(output.run(receiver));
+            let reason = Self::supervise(task, "Output connector").await;
+            engine.emit(Event::ConnectorDisconnected {
+                connector: ConnectorKind::Output,
+                reason,
+            });
         })
     }
 
     fn load_service(
-        service: Box<dyn Service + Send>,
+        mut service: Box<dyn Service + Send>,
         receiver: mpsc::Receiver<Message>,
         sender: mpsc::Sender<Message>,
         name: String,
+        dependencies: Vec<Component>,
+        engine: EngineHandle,
+        stop_hooks: TaskTracker,
     ) -> JoinHandle<()> {
         tokio::spawn(async move {
             log::info!("Loading service '{}'", name);
+            let component = Component::Service(name.clone());
+            if !Self::start(component, &dependencies, service.on_start(), &engine).await {
+                let reason = StopReason::Failed;
+                engine.emit(Event::ServiceStopped { name, reason });
+                return;
+            }
+            // The engine waits for the stop hooks before finishing.
+            let stop_hook = service.on_stop().map(|hook| (hook, stop_hooks.token()));
+            engine.emit(Event::ServiceStarted { name: name.clone() });
 
-            let result =
-                tokio::spawn(async move { service.run(Receiver(receiver), Sender(sender)).await })
-                    .await;
-
-            Self::log_join_result(result, &format!("Service '{}'", name));
+            let token = engine.shutdown_token().clone();
+            let receiver = Receiver(receiver, token, Some(Self::release_hook(&engine)));
+            let task = engine
+                .clone()
+                .scope(service.run(receiver, Sender(sender)))
+                .map(|result| result.map_err(Error::from));
+            let reason = Self::supervise(task, &format!("Service '{}'", name)).await;
+            if let Some((hook, _token)) = stop_hook {
+                let hook = engine.clone().scope(AssertUnwindSafe(hook).catch_unwind());
+                if hook.await.is_err() {
+                    log::error!("Stop hook of service '{}' panicked", name);
+                }
+            }
+            engine.emit(Event::ServiceStopped { name, reason });
         })
     }
 
+    /// Release from the memory budget the messages taken from a queue.
+    fn release_hook(engine: &EngineHandle) -> RecvHook<Message> {
+        let engine = engine.clone();
+        Arc::new(move |message| engine.memory().release(message))
+    }
+
     fn load_services(
         configs: Vec<ServiceConfig>,
         output_sender: mpsc::Sender<Message>,
+        dependencies: &HashMap<Component, Vec<Component>>,
+        engine: EngineHandle,
+        stop_hooks: &TaskTracker,
     ) -> HashMap<String, ServiceHandle> {
         let services = configs
             .into_iter()
@@ -357,203 +1611,2229 @@ impl Engine {
                 let (input_sender, input_receiver) = mpsc::channel(32);
                 let output_sender = output_sender.clone();
                 let service_name = config.name.clone();
+                let service_dependencies = dependencies
+                    .get(&Component::Service(config.name.clone()))
+                    .cloned()
+                    .unwrap_or_default();
 
-                Self::load_service(config.service, input_receiver, output_sender, service_name);
+                Self::load_service(
+                    config.service,
+                    input_receiver,
+                    output_sender,
+                    service_name,
+                    service_dependencies,
+                    engine.clone(),
+                    stop_hooks.clone(),
+                );
 
-                (
-                    config.name,
-                    ServiceHandle {
-                        whitelist: config.whitelist,
-                        input_sender,
-                    },
-                )
+                (config.name, ServiceHandle { input_sender })
             })
             .collect();
 
         drop(output_sender);
 
-        services
+        services
+    }
+
+    /// Spawn the tasks that push the input messages into the shared `queue` and pop
+    /// the messages to dispatch.
+    fn load_cluster(
+        queue: Arc<dyn SharedQueue>,
+        engine: EngineHandle,
+    ) -> (mpsc::Sender<Message>, mpsc::Receiver<Message>) {
+        let (push_sender, mut push_receiver) = mpsc::channel::<Message>(32);
+        let push_queue = queue.clone();
+        let push_engine = engine.clone();
+        tokio::spawn(engine.clone().scope(async move {
+            while let Some(mut message) = push_receiver.recv().await {
+                let ack_id = ack::detach(&mut message);
+                while let Err(err) = push_queue.push(message.clone()).await {
+                    log::error!("Cluster queue push: {}", err);
+                    tokio::time::sleep(CLUSTER_RETRY_TIME).await;
+                }
+                if let Some(ack_id) = ack_id {
+                    push_engine.acks().resolve_id(&ack_id);
+                }
+            }
+        }));
+
+        // Only one message is popped in advance, the rest remain available for other instances.
+        let (pop_sender, pop_receiver) = mpsc::channel(1);
+        tokio::spawn(engine.clone().scope(async move {
+            loop {
+                let permit = match pop_sender.reserve().await {
+                    Ok(permit) => permit,
+                    Err(_) => break,
+                };
+                loop {
+                    match queue.pop().await {
+                        Ok(mut message) => {
+                            // The queue is told when the engine is done with the message.
+                            let popped = message.clone();
+                            let ack = engine.acks().register(&mut message);
+                            let queue = queue.clone();
+                            tokio::spawn(async move {
+                                let processed = ack.wait().await;
+                                if let Err(err) = queue.done(&popped, processed).await {
+                                    log::error!("Cluster queue done: {}", err);
+                                }
+                            });
+                            break permit.send(message);
+                        }
+                        Err(err) => {
+                            log::error!("Cluster queue pop: {}", err);
+                            tokio::time::sleep(CLUSTER_RETRY_TIME).await;
+                        }
+                    }
+                }
+            }
+        }));
+
+        (push_sender, pop_receiver)
+    }
+
+    /// Run the `startup` of the `component` once its `dependencies` are ready,
+    /// recording whether it is ready.
+    async fn start(
+        component: Component,
+        dependencies: &[Component],
+        startup: impl Future<Output = Result<(), Error>>,
+        engine: &EngineHandle,
+    ) -> bool {
+        for dependency in dependencies {
+            log::info!("The {} waits for the {}", component, dependency);
+            let ready = tokio::select! {
+                ready = engine.readiness().wait(dependency) => ready,
+                _ = engine.shutdown_token().cancelled() => false,
+            };
+            if !ready {
+                log::error!(
+                    "The {} can not start: the {} is not ready",
+                    component,
+                    dependency
+                );
+                engine.readiness().set(component, false);
+                return false;
+            }
+        }
+
+        let ready = match engine.clone().scope(startup).await {
+            Ok(()) => true,
+            Err(err) => {
+                log::error!("The {} could not start: {}", component, err);
+                false
+            }
+        };
+        engine.readiness().set(component, ready);
+        ready
+    }
+
+    /// Run the task in the current tokio task, catching its panics.
+    async fn supervise(task: impl Future<Output = Result<(), Error>>, name: &str) -> StopReason {
+        match AssertUnwindSafe(task).catch_unwind().await {
+            Ok(Ok(())) => {
+                log::info!("{} down (finished)", name);
+                StopReason::Finished
+            }
+            Ok(Err(Error::Channel(_))) => {
+                log::info!("{} down (disconnected)", name);
+                StopReason::Disconnected
+            }
+            Ok(Err(err)) => {
+                log::error!("{} down ({})", name, err);
+                StopReason::Failed
+            }
+            Err(_) => {
+                log::error!("{} down (panicked)", name);
+                StopReason::Panicked
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::{ChannelEnd, ClosedChannel};
+    use crate::interface::StopHook;
+    use crate::message::util;
+    use crate::services::Echo;
+
+    use async_trait::async_trait;
+    use bytes::Bytes;
+    use tokio::sync::oneshot;
+    use tokio::time::timeout;
+
+    use std::collections::HashSet;
+    use std::time::Duration;
+
+    #[derive(Clone)]
+    pub struct EchoOnce;
+
+    #[async_trait]
+    impl Service for EchoOnce {
+        async fn run(
+            self: Box<Self>,
+            mut input: Receiver,
+            output: Sender,
+        ) -> Result<(), ClosedChannel> {
+            let message = input.recv().await?;
+            output.send(message).await
+        }
+    }
+
+    fn build_message(user: &str, service: &str) -> Message {
+        Message {
+            user: user.into(),
+            service_name: service.into(),
+            args: vec!["arg0".into(), "arg1".into()],
+            body: "abcd".into(),
+            attached_data: [
+                ("file1".to_string(), Bytes::from_static(b"1234")),
+                ("file2".to_string(), Bytes::from_static(b"5678")),
+            ]
+            .into_iter()
+            .collect(),
+            metadata: HashMap::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn echo() {
+        let (input_sender, input_receiver) = mpsc::channel(32);
+        let (output_sender, mut output_receiver) = mpsc::channel(32);
+
+        let task = tokio::spawn(async move {
+            Engine::default()
+                .input(input_receiver)
+                .output(output_sender)
+                .add_service("s-test", EchoOnce)
+                .run()
+                .await;
+        });
+
+        let message = build_message("user_0", "s-test");
+        input_sender.send(message.clone()).await.unwrap();
+        assert_eq!(Some(message), output_receiver.recv().await);
+
+        task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn echo_with_duplex_connector() {
+        let (input_sender, input_receiver) = mpsc::channel(32);
+        let (output_sender, mut output_receiver) = mpsc::channel(32);
+
+        let task = tokio::spawn(async move {
+            Engine::default()
+                .connector((input_receiver, output_sender))
+                .add_service("s-test", EchoOnce)
+                .run()
+                .await;
+        });
+
+        let message = build_message("user_0", "s-test");
+        input_sender.send(message.clone()).await.unwrap();
+        assert_eq!(Some(message), output_receiver.recv().await);
+
+        task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn echo_with_stream_and_sink() {
+        use crate::connectors::{SinkOutput, StreamInput};
+        use futures::StreamExt;
+
+        let messages: Vec<Message> = (0..3)
+            .map(|i| build_message(&format!("user_{}", i), "s-test"))
+            .collect();
+
+        let (output_sender, output_receiver) = futures::channel::mpsc::channel(32);
+
+        tokio::spawn(
+            Engine::default()
+                .input(StreamInput(futures::stream::iter(messages.clone())))
+                .output(SinkOutput(output_sender))
+                .add_service("s-test", Echo)
+                .run(),
+        );
+
+        let received: Vec<Message> = output_receiver.take(3).collect().await;
+        assert_eq!(messages, received);
+    }
+
+    #[tokio::test]
+    async fn echo_with_broadcast_and_watch() {
+        use tokio::sync::{broadcast, watch};
+
+        let (input_sender, input_receiver) = broadcast::channel(32);
+        let (output_sender, mut output_receiver_0) = broadcast::channel(32);
+        let mut output_receiver_1 = output_sender.subscribe();
+        let (latest_sender, mut latest_receiver) = watch::channel(None);
+
+        tokio::spawn(
+            Engine::default()
+                .input(input_receiver)
+                .output(output_sender)
+                .add_service("s-test", Echo)
+                .run(),
+        );
+
+        tokio::spawn(
+            Engine::default()
+                .input(input_sender.subscribe())
+                .output(latest_sender)
+                .add_service("s-test", Echo)
+                .run(),
+        );
+
+        let message = build_message("user_0", "s-test");
+        input_sender.send(message.clone()).unwrap();
+        assert_eq!(message, output_receiver_0.recv().await.unwrap());
+        assert_eq!(message, output_receiver_1.recv().await.unwrap());
+
+        latest_receiver.changed().await.unwrap();
+        assert_eq!(Some(message), *latest_receiver.borrow());
+    }
+
+    #[tokio::test]
+    async fn echo_with_input_mapping() {
+        let (input_sender, input_receiver) = mpsc::channel(32);
+        let (output_sender, mut output_receiver) = mpsc::channel(32);
+
+        let task = tokio::spawn(async move {
+            Engine::default()
+                .input(input_receiver)
+                .output(output_sender)
+                .map_input(util::service_name_first_char_to_lowercase)
+                .add_service("s-test", EchoOnce)
+                .run()
+                .await;
+        });
+
+        let message = build_message("user_0", "S-test");
+        input_sender.send(message.clone()).await.unwrap();
+        assert_eq!(
+            Some(util::service_name_first_char_to_lowercase(message)),
+            output_receiver.recv().await
+        );
+
+        task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn echo_with_output_mapping() {
+        let (input_sender, input_receiver) = mpsc::channel(32);
+        let (output_sender, mut output_receiver) = mpsc::channel(32);
+
+        tokio::spawn(async move {
+            Engine::default()
+                .input(input_receiver)
+                .output(output_sender)
+                .map_output(|message| message.body("mapped"))
+                .add_service("s-test", EchoOnce)
+                .run()
+                .await;
+        });
+
+        let message = build_message("user_0", "s-test");
+        input_sender.send(message.clone()).await.unwrap();
+        assert_eq!(Some(message.body("mapped")), output_receiver.recv().await);
+    }
+
+    #[tokio::test]
+    async fn echo_with_input_filtering() {
+        let (input_sender, input_receiver) = mpsc::channel(32);
+        let (output_sender, mut output_receiver) = mpsc::channel(32);
+
+        tokio::spawn(async move {
+            Engine::default()
+                .input(input_receiver)
+                .output(output_sender)
+                .filter_input(|message| !message.service_name.starts_with("s-"))
+                .add_service("s-test", EchoOnce)
+                .run()
+                .await;
+        });
+
+        let message = build_message("user_0", "s-test");
+        input_sender.send(message.clone()).await.unwrap();
+        assert!(timeout(Duration::from_millis(100), output_receiver.recv())
+            .await
+            .is_err());
+    }
+
+    struct Stamp;
+
+    #[async_trait]
+    impl AttachmentScanner for Stamp {
+        async fn scan(&self, _: &Message, name: &str, data: &Bytes) -> ScanVerdict {
+            match name {
+                "b.TXT" => ScanVerdict::Replace(Bytes::from(format!("{:?}", data))),
+                _ => ScanVerdict::Annotate("seen".into()),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn attachment_scanners() {
+        let (input_sender, input_receiver) = mpsc::channel(32);
+        let (output_sender, mut output_receiver) = mpsc::channel(32);
+
+        tokio::spawn(
+            Engine::default()
+                .input(input_receiver)
+                .output(output_sender)
+                .attachment_scanner(BlockExtensions::new([".exe"]))
+                .attachment_scanner(MaxAttachmentSize(4))
+                .output_attachment_scanner(Stamp)
+                .add_service("s-test", Echo)
+                .run(),
+        );
+
+        let message = Message::default()
+            .user("user")
+            .service_name("s-test")
+            .attach([
+                ("a.EXE", b"1234".to_vec()),
+                ("b.TXT", b"1234".to_vec()),
+                ("c.bin", b"12345".to_vec()),
+            ]);
+        input_sender.send(message).await.unwrap();
+
+        let message = output_receiver.recv().await.unwrap();
+        let names = message.attached_data.keys().collect::<Vec<_>>();
+        assert_eq!(names, ["b.TXT"]);
+        assert_eq!(message.attached_data["b.TXT"], "b\"1234\"");
+        assert_eq!(
+            message.metadata["scan-note:a.EXE"],
+            "blocked file type '.EXE'"
+        );
+        assert_eq!(message.metadata["scan-note:c.bin"], "larger than 4 bytes");
+        assert!(!message.metadata.contains_key("scan-note:b.TXT"));
+    }
+
+    #[tokio::test]
+    async fn attachment_checksums() {
+        let (input_sender, input_receiver) = mpsc::channel(32);
+        let (output_sender, mut output_receiver) = mpsc::channel(32);
+
+        let engine = Engine::default()
+            .input(input_receiver)
+            .output(output_sender)
+            .attachment_checksums(true)
+            .map_output(|mut message| {
+                message
+                    .attached_data
+                    .insert("new".into(), Bytes::from_static(b"5678"));
+                message
+            })
+            .add_service("s-test", Echo);
+        assert!(engine.describe().attachment_checksums);
+        tokio::spawn(engine.run());
+
+        let message = build_message("user", "s-test");
+        input_sender.send(message.clone()).await.unwrap();
+
+        let output = output_receiver.recv().await.unwrap();
+        assert!(output.corrupted_attachments().is_empty());
+        for name in ["file1", "file2", "new"] {
+            assert_eq!(output.checksum(name).map(str::len), Some(64));
+        }
+        assert_ne!(output.checksum("file1"), output.checksum("file2"));
+    }
+
+    #[tokio::test]
+    async fn no_services() {
+        let (_input_sender, input_receiver) = mpsc::channel(32);
+        let (output_sender, mut _output_receiver) = mpsc::channel(32);
+
+        tokio::spawn(async move {
+            Engine::default()
+                .input(input_receiver)
+                .output(output_sender)
+                .run()
+                .await;
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn missing_connectors() {
+        let result = Engine::default()
+            .add_service("s-echo", Echo)
+            .try_run()
+            .await;
+        let expected = ConfigError {
+            connector: "Engine",
+            errors: vec![
+                FieldError::Missing("input connector"),
+                FieldError::Missing("output connector"),
+            ],
+        };
+        assert!(matches!(result, Err(Error::Config(error)) if error == expected));
+
+        let (_input_sender, input_receiver) = mpsc::channel::<Message>(32);
+        let engine = Engine::default().input(input_receiver).dry_run(true);
+        assert!(engine.validate().is_ok());
+    }
+
+    #[tokio::test]
+    async fn invalid_dependencies() {
+        let (_input_sender, input_receiver) = mpsc::channel::<Message>(32);
+        let (output_sender, _output_receiver) = mpsc::channel(32);
+        let result = Engine::default()
+            .input(input_receiver)
+            .output(output_sender)
+            .add_service("s-echo", Echo)
+            .depends_on(Component::Service("s-echo".into()), Component::Input)
+            .depends_on(Component::Output, Component::Service("s-echo".into()))
+            .depends_on(Component::Output, Component::Service("s-none".into()))
+            .validate();
+
+        let errors = result.unwrap_err().errors;
+        assert_eq!(errors.len(), 2);
+        assert_eq!(
+            errors[0].to_string(),
+            "malformed dependencies: unknown service 's-none'"
+        );
+        assert!(errors[1].to_string().ends_with("depends on itself"));
+    }
+
+    #[tokio::test]
+    async fn service_not_found() {
+        let (input_sender, input_receiver) = mpsc::channel(32);
+        let (output_sender, mut output_receiver) = mpsc::channel(32);
+
+        tokio::spawn(async move {
+            Engine::default()
+                .input(input_receiver)
+                .output(output_sender)
+                .add_service("s-test", EchoOnce)
+                .run()
+                .await;
+        });
+
+        let message = build_message("user_0", "unknown");
+        input_sender.send(message.clone()).await.unwrap();
+        assert!(timeout(Duration::from_millis(100), output_receiver.recv())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn whitelist() {
+        let (input_sender, input_receiver) = mpsc::channel(32);
+        let (output_sender, mut output_receiver) = mpsc::channel(32);
+
+        let task = tokio::spawn(async move {
+            Engine::default()
+                .input(input_receiver)
+                .output(output_sender)
+                .add_service_for("s-test", EchoOnce, ["user_allowed"])
+                .run()
+                .await;
+        });
+
+        let message = build_message("user_not_allowed", "s-test");
+        input_sender.send(message.clone()).await.unwrap();
+        assert!(timeout(Duration::from_millis(100), output_receiver.recv())
+            .await
+            .is_err());
+
+        let message = build_message("user_allowed", "s-test");
+        input_sender.send(message.clone()).await.unwrap();
+        assert_eq!(Some(message), output_receiver.recv().await);
+
+        task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn runtime_whitelist() {
+        use crate::services::Admin;
+        use crate::state::{KeyValueStore, MemoryStore};
+
+        let store = MemoryStore::default();
+        store
+            .set("s-test", r#"["stored_user"]"#.into())
+            .await
+            .unwrap();
+
+        let (input_sender, input_receiver) = mpsc::channel(32);
+        let (output_sender, mut output_receiver) = mpsc::channel(32);
+
+        let engine = Engine::default()
+            .input(input_receiver)
+            .output(output_sender)
+            .whitelist_store(store.clone())
+            .add_service_for("s-admin", Admin, ["admin"])
+            .add_service_for("s-test", Echo, ["user_allowed"]);
+        let handle = engine.handle();
+        tokio::spawn(engine.run());
+
+        // The persisted whitelist replaces the configured one.
+        let message = build_message("user_allowed", "s-test");
+        input_sender.send(message).await.unwrap();
+        let message = build_message("stored_user", "s-test");
+        input_sender.send(message.clone()).await.unwrap();
+        assert_eq!(Some(message), output_receiver.recv().await);
+
+        let request = Message::default()
+            .user("admin")
+            .service_name("s-admin")
+            .args(["allow", "s-test", "new_user"]);
+        input_sender.send(request).await.unwrap();
+        assert_eq!(output_receiver.recv().await.unwrap().body, "done");
+
+        let message = build_message("new_user", "s-test");
+        input_sender.send(message.clone()).await.unwrap();
+        assert_eq!(Some(message), output_receiver.recv().await);
+
+        assert_eq!(
+            store.get("s-test").await.unwrap().unwrap(),
+            r#"["new_user","stored_user"]"#
+        );
+
+        assert!(handle.disallow_user("s-test", "new_user").await.unwrap());
+        assert!(!handle.allow_user("s-admin", "admin").await.unwrap());
+        assert_eq!(handle.whitelist("s-test").unwrap(), ["stored_user"]);
+        assert_eq!(handle.whitelist("unknown"), None);
+
+        handle.shutdown();
+    }
+
+    #[tokio::test]
+    async fn describe() {
+        use crate::services::Admin;
+
+        let (input_sender, input_receiver) = mpsc::channel(32);
+        let (output_sender, mut output_receiver) = mpsc::channel(32);
+
+        let engine = Engine::default()
+            .input(input_receiver)
+            .output(output_sender)
+            .alias("e", "s-echo $@")
+            .deadline(Duration::from_secs(5))
+            .add_service_for("s-admin", Admin, ["admin"])
+            .add_service("s-echo", Echo);
+        let description = engine.describe();
+        let handle = engine.handle();
+        assert_eq!(handle.describe(), None);
+        tokio::spawn(engine.run());
+
+        let request = Message::default()
+            .user("admin")
+            .service_name("s-admin")
+            .args(["describe"]);
+        input_sender.send(request).await.unwrap();
+        let response = output_receiver.recv().await.unwrap();
+        let described: EngineDescription = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(described, description);
+        assert_eq!(described.output.as_deref(), Some("Sender"));
+        assert_eq!(described.aliases["e"], "s-echo $@");
+        assert_eq!(described.deadline_millis, Some(5000));
+
+        handle.allow_user("s-admin", "other").await.unwrap();
+        let whitelist = handle.describe().unwrap().services[0].whitelist.clone();
+        assert_eq!(whitelist.unwrap(), ["admin", "other"]);
+
+        let options = Engine::from_description(&description).describe();
+        assert_eq!(options.aliases, description.aliases);
+        assert_eq!(options.deadline_millis, description.deadline_millis);
+        assert!(options.services.is_empty());
+
+        handle.shutdown();
+    }
+
+    #[tokio::test]
+    async fn default_service() {
+        use crate::services::AutoReply;
+
+        let (input_sender, input_receiver) = mpsc::channel(32);
+        let (output_sender, mut output_receiver) = mpsc::channel(32);
+
+        let engine = Engine::default()
+            .input(input_receiver)
+            .output(output_sender)
+            .default_service("s-auto-reply")
+            .add_service("s-auto-reply", AutoReply::new("{command}:\n{services}"))
+            .add_service_for("s-admin", Echo, ["admin"])
+            .add_service("s-echo", Echo);
+        assert_eq!(
+            engine.describe().default_service.as_deref(),
+            Some("s-auto-reply")
+        );
+        tokio::spawn(engine.run());
+
+        let request = Message::default()
+            .user("user")
+            .service_name("s-unknown")
+            .args(["arg"]);
+        input_sender.send(request).await.unwrap();
+        let response = output_receiver.recv().await.unwrap();
+        assert_eq!(response.service_name, "s-auto-reply");
+        assert_eq!(response.body, "s-unknown arg:\ns-echo");
+
+        let request = Message::default().user("user").service_name("s-echo");
+        input_sender.send(request).await.unwrap();
+        assert_eq!(output_receiver.recv().await.unwrap().service_name, "s-echo");
+    }
+
+    #[test]
+    fn unknown_default_service() {
+        let engine = Engine::default()
+            .input(mpsc::channel::<Message>(1).1)
+            .output(mpsc::channel(1).0)
+            .default_service("s-missing")
+            .add_service("s-echo", Echo);
+        assert!(engine.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn user_roles() {
+        let (input_sender, input_receiver) = mpsc::channel(32);
+        let (output_sender, mut output_receiver) = mpsc::channel(32);
+
+        let users = UserRegistry::default()
+            .role("family", ["s-test"])
+            .user("sister", ["family"]);
+
+        tokio::spawn(
+            Engine::default()
+                .input(input_receiver)
+                .output(output_sender)
+                .users(users.clone())
+                .add_service("s-test", Echo)
+                .run(),
+        );
+
+        let message = build_message("friend", "s-test");
+        input_sender.send(message).await.unwrap();
+        let message = build_message("sister", "s-test");
+        input_sender.send(message.clone()).await.unwrap();
+        assert_eq!(Some(message), output_receiver.recv().await);
+
+        users.assign("friend", "family").await.unwrap();
+        let message = build_message("friend", "s-test");
+        input_sender.send(message.clone()).await.unwrap();
+        assert_eq!(Some(message), output_receiver.recv().await);
+    }
+
+    #[tokio::test]
+    async fn user_verification() {
+        use crate::state::MemoryStore;
+
+        let (input_sender, input_receiver) = mpsc::channel(32);
+        let (output_sender, mut output_receiver) = mpsc::channel(32);
+
+        let engine = Engine::default()
+            .input(input_receiver)
+            .output(output_sender)
+            .verify_users(Verification::new(MemoryStore::default()).trust(["trusted"]))
+            .add_service("s-test", Echo);
+        let mut events = engine.handle().events();
+        tokio::spawn(engine.run());
+
+        let message = build_message("trusted", "s-test");
+        input_sender.send(message.clone()).await.unwrap();
+        assert_eq!(Some(message), output_receiver.recv().await);
+
+        let held = build_message("user", "s-test");
+        input_sender.send(held.clone()).await.unwrap();
+        let challenge = output_receiver.recv().await.unwrap();
+        assert_eq!(challenge.user, "user");
+        let code = challenge.body.rsplit(' ').next().unwrap().to_string();
+        assert_eq!(code.len(), 6);
+
+        let wrong = build_message("user", "Re: s-test").body("000");
+        input_sender.send(wrong).await.unwrap();
+        let challenge = output_receiver.recv().await.unwrap();
+        assert!(challenge.body.ends_with(&code));
+
+        let reply = build_message("user", "Re: s-test").body(format!("{}\n\n> Reply", code));
+        input_sender.send(reply).await.unwrap();
+        let confirmation = output_receiver.recv().await.unwrap();
+        assert_eq!(confirmation.body, "Your address has been verified");
+        assert_eq!(Some(held), output_receiver.recv().await);
+
+        let message = build_message("user", "s-test");
+        input_sender.send(message.clone()).await.unwrap();
+        assert_eq!(Some(message), output_receiver.recv().await);
+
+        loop {
+            if let Event::UserVerified { user } = events.recv().await.unwrap() {
+                break assert_eq!(user, "user");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn user_verification_attempts() {
+        use crate::state::MemoryStore;
+
+        let (input_sender, input_receiver) = mpsc::channel(32);
+        let (output_sender, mut output_receiver) = mpsc::channel(32);
+
+        let verification = Verification::new(MemoryStore::default())
+            .code_length(3)
+            .max_attempts(2);
+        tokio::spawn(
+            Engine::default()
+                .input(input_receiver)
+                .output(output_sender)
+                .verify_users(verification)
+                .add_service("s-test", Echo)
+                .run(),
+        );
+
+        input_sender
+            .send(build_message("user", "s-test"))
+            .await
+            .unwrap();
+        let challenge = output_receiver.recv().await.unwrap();
+        let code = challenge.body.rsplit(' ').next().unwrap().to_string();
+
+        let candidates = (0..1000).map(|n| format!("{:03}", n)).collect::<Vec<_>>();
+        let guess =
+            build_message("user", "Re: s-test").body(format!("codes: {}", candidates.join(" ")));
+        input_sender.send(guess).await.unwrap();
+        let challenge = output_receiver.recv().await.unwrap();
+        assert!(challenge.body.ends_with(&code));
+
+        let wrong = build_message("user", "Re: s-test").body("none");
+        input_sender.send(wrong).await.unwrap();
+        let challenge = output_receiver.recv().await.unwrap();
+        let new_code = challenge.body.rsplit(' ').next().unwrap().to_string();
+
+        let old = build_message("user", "Re: s-test").body(code.clone());
+        input_sender.send(old).await.unwrap();
+        let challenge = output_receiver.recv().await.unwrap();
+        if code != new_code {
+            assert!(challenge.body.ends_with(&new_code));
+        }
+    }
+
+    #[tokio::test]
+    async fn user_settings() {
+        use crate::services::Settings;
+        use crate::state::MemoryStore;
+        use crate::time::TIMEZONE_KEY;
+
+        let (input_sender, input_receiver) = mpsc::channel(32);
+        let (output_sender, mut output_receiver) = mpsc::channel(32);
+
+        let store = MemoryStore::default();
+        tokio::spawn(
+            Engine::default()
+                .input(input_receiver)
+                .output(output_sender)
+                .language("en")
+                .user_settings(store.clone())
+                .add_service("s-settings", Settings::new(store))
+                .add_service("s-test", Echo)
+                .run(),
+        );
+
+        for args in [
+            ["set", "language", "es"],
+            ["set", "timezone", "Europe/Madrid"],
+        ] {
+            let request = Message::default()
+                .user("user")
+                .service_name("s-settings")
+                .args(args);
+            input_sender.send(request).await.unwrap();
+            output_receiver.recv().await.unwrap();
+        }
+
+        let request = Message::default()
+            .user("user")
+            .service_name("s-settings")
+            .args(["set", "color", "blue"]);
+        input_sender.send(request).await.unwrap();
+        let response = output_receiver.recv().await.unwrap();
+        assert_eq!(response.args, ["error de formato"]);
+
+        let message = build_message("user", "s-test");
+        input_sender.send(message).await.unwrap();
+        let echoed = output_receiver.recv().await.unwrap();
+        assert_eq!(echoed.metadata[i18n::LANGUAGE_KEY], "es");
+        assert_eq!(echoed.metadata[TIMEZONE_KEY], "Europe/Madrid");
+
+        let message = build_message("other_user", "s-test");
+        input_sender.send(message).await.unwrap();
+        let echoed = output_receiver.recv().await.unwrap();
+        assert_eq!(echoed.metadata[i18n::LANGUAGE_KEY], "en");
+    }
+
+    #[tokio::test]
+    async fn alias() {
+        let (input_sender, input_receiver) = mpsc::channel(32);
+        let (output_sender, mut output_receiver) = mpsc::channel(32);
+
+        let task = tokio::spawn(async move {
+            Engine::default()
+                .input(input_receiver)
+                .output(output_sender)
+                .alias("t", "s-test arg")
+                .add_service("s-test", EchoOnce)
+                .run()
+                .await;
+        });
+
+        let message = build_message("user_0", "t");
+        input_sender.send(message.clone()).await.unwrap();
+
+        let expected = message.service_name("s-test").args(["arg", "arg0", "arg1"]);
+        assert_eq!(Some(expected), output_receiver.recv().await);
+
+        task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn alias_with_template() {
+        let (input_sender, input_receiver) = mpsc::channel(32);
+        let (output_sender, mut output_receiver) = mpsc::channel(32);
+
+        let task = tokio::spawn(async move {
+            Engine::default()
+                .input(input_receiver)
+                .output(output_sender)
+                .alias("t", "s-test $2 arg $1 $3")
+                .add_service("s-test", EchoOnce)
+                .run()
+                .await;
+        });
+
+        let message = build_message("user_0", "t");
+        input_sender.send(message.clone()).await.unwrap();
+
+        let expected = message.service_name("s-test").args(["arg1", "arg", "arg0"]);
+        assert_eq!(Some(expected), output_receiver.recv().await);
+
+        task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn language() {
+        let (input_sender, input_receiver) = mpsc::channel(32);
+        let (output_sender, mut output_receiver) = mpsc::channel(32);
+
+        tokio::spawn(async move {
+            Engine::default()
+                .input(input_receiver)
+                .output(output_sender)
+                .language("en")
+                .user_language("user_es", "es")
+                .add_service("s-test", Echo)
+                .run()
+                .await;
+        });
+
+        let message = build_message("user_0", "s-test");
+        input_sender.send(message.clone()).await.unwrap();
+        let received = output_receiver.recv().await.unwrap();
+        assert_eq!(i18n::language(&received), "en");
+
+        let message = build_message("user_es", "s-test");
+        input_sender.send(message.clone()).await.unwrap();
+        let received = output_receiver.recv().await.unwrap();
+        assert_eq!(i18n::language(&received), "es");
+
+        let message = build_message("user_es", "s-test").metadata([(i18n::LANGUAGE_KEY, "fr")]);
+        input_sender.send(message.clone()).await.unwrap();
+        let received = output_receiver.recv().await.unwrap();
+        assert_eq!(i18n::language(&received), "fr");
+    }
+
+    struct Panicking;
+
+    #[async_trait]
+    impl Service for Panicking {
+        async fn run(
+            self: Box<Self>,
+            mut input: Receiver,
+            _output: Sender,
+        ) -> Result<(), ClosedChannel> {
+            input.recv().await?;
+            panic!("inner service panicked");
+        }
+    }
+
+    #[tokio::test]
+    async fn wrapped_service_panic() {
+        use crate::services::Router;
+
+        let (input_sender, input_receiver) = mpsc::channel(32);
+        let (output_sender, _output_receiver) = mpsc::channel(32);
+
+        let engine = Engine::default()
+            .input(input_receiver)
+            .output(output_sender)
+            .add_service("s-router", Router::default().route("panic", Panicking));
+        let mut events = engine.handle().events();
+        tokio::spawn(engine.run());
+
+        let message = Message::default()
+            .user("user")
+            .service_name("s-router")
+            .args(["panic"]);
+        input_sender.send(message).await.unwrap();
+
+        let expected = Event::ServiceStopped {
+            name: "Route 'panic' (Panicking)".into(),
+            reason: StopReason::Panicked,
+        };
+        loop {
+            let event = timeout(Duration::from_secs(5), events.recv()).await;
+            if event.unwrap().unwrap() == expected {
+                break;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn events() {
+        let (input_sender, input_receiver) = mpsc::channel(32);
+        let (output_sender, mut output_receiver) = mpsc::channel(32);
+
+        let engine = Engine::default()
+            .input(input_receiver)
+            .output(output_sender)
+            .add_service_for("s-test", EchoOnce, ["user_allowed"]);
+
+        let mut events = engine.handle().events();
+        let task = tokio::spawn(engine.run());
+
+        let message = build_message("user_0", "unknown");
+        input_sender.send(message).await.unwrap();
+
+        let message = build_message("user_not_allowed", "s-test");
+        input_sender.send(message).await.unwrap();
+
+        let message = build_message("user_allowed", "s-test");
+        input_sender.send(message.clone()).await.unwrap();
+        assert_eq!(Some(message), output_receiver.recv().await);
+
+        task.await.unwrap();
+
+        let mut received = Vec::new();
+        while let Ok(Ok(event)) = timeout(Duration::from_millis(100), events.recv()).await {
+            received.push(event);
+        }
+
+        let expected = [
+            Event::ServiceStarted {
+                name: "s-test".into(),
+            },
+            Event::MessageDropped {
+                user: "user_0".into(),
+                service_name: "unknown".into(),
+                reason: DropReason::UnknownService,
+            },
+            Event::MessageDropped {
+                user: "user_not_allowed".into(),
+                service_name: "s-test".into(),
+                reason: DropReason::NotAllowed,
+            },
+            Event::ServiceStopped {
+                name: "s-test".into(),
+                reason: StopReason::Finished,
+            },
+            Event::ConnectorDisconnected {
+                connector: ConnectorKind::Output,
+                reason: StopReason::Disconnected,
+            },
+        ];
+
+        for event in expected {
+            assert!(received.contains(&event), "Missing event: {:?}", event);
+        }
+    }
+
+    struct RejectedInput;
+
+    #[async_trait]
+    impl InputConnector for RejectedInput {
+        async fn run(self: Box<Self>, _: Sender) -> Result<(), Error> {
+            Err(Error::Auth("invalid password".into()))
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_connector() {
+        let (output_sender, _output_receiver) = mpsc::channel(32);
+        let engine = Engine::default().input(RejectedInput).output(output_sender);
+
+        let handle = engine.handle();
+        let mut events = handle.events();
+        let task = tokio::spawn(engine.run());
+
+        let expected = Event::ConnectorDisconnected {
+            connector: ConnectorKind::Input,
+            reason: StopReason::Failed,
+        };
+        while events.recv().await.unwrap() != expected {}
+
+        handle.shutdown();
+        task.await.unwrap();
+    }
+
+    /// Echoes one message, logging its lifecycle.
+    struct Lifecycle {
+        log: Arc<std::sync::Mutex<Vec<&'static str>>>,
+        fail_start: bool,
+    }
+
+    #[async_trait]
+    impl Service for Lifecycle {
+        async fn on_start(&mut self) -> Result<(), Error> {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            if self.fail_start {
+                return Err(Error::service("database unreachable"));
+            }
+            self.log.lock().unwrap().push("start");
+            Ok(())
+        }
+
+        fn on_stop(&mut self) -> Option<StopHook> {
+            let log = self.log.clone();
+            Some(Box::pin(async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                log.lock().unwrap().push("stop");
+            }))
+        }
+
+        async fn run(
+            self: Box<Self>,
+            mut input: Receiver,
+            output: Sender,
+        ) -> Result<(), ClosedChannel> {
+            let message = input.recv().await?;
+            self.log.lock().unwrap().push("run");
+            output.send(message).await
+        }
+    }
+
+    #[tokio::test]
+    async fn lifecycle_hooks() {
+        let (input_sender, input_receiver) = mpsc::channel(32);
+        let (output_sender, mut output_receiver) = mpsc::channel(32);
+        let log = Arc::default();
+
+        let task = tokio::spawn(
+            Engine::default()
+                .input(input_receiver)
+                .output(output_sender)
+                .add_service(
+                    "s-test",
+                    Lifecycle {
+                        log: Arc::clone(&log),
+                        fail_start: false,
+                    },
+                )
+                .run(),
+        );
+
+        let message = build_message("user_0", "s-test");
+        input_sender.send(message.clone()).await.unwrap();
+        assert_eq!(Some(message), output_receiver.recv().await);
+
+        task.await.unwrap();
+        assert_eq!(*log.lock().unwrap(), ["start", "run", "stop"]);
+    }
+
+    #[tokio::test]
+    async fn failed_start() {
+        let (_input_sender, input_receiver) = mpsc::channel(32);
+        let (output_sender, _output_receiver) = mpsc::channel(32);
+        let log = Arc::default();
+        let engine = Engine::default()
+            .input(input_receiver)
+            .output(output_sender)
+            .add_service(
+                "s-test",
+                Lifecycle {
+                    log: Arc::clone(&log),
+                    fail_start: true,
+                },
+            );
+
+        let mut events = engine.handle().events();
+        let task = tokio::spawn(engine.run());
+
+        let expected = Event::ServiceStopped {
+            name: "s-test".into(),
+            reason: StopReason::Failed,
+        };
+        while events.recv().await.unwrap() != expected {}
+
+        task.await.unwrap();
+        assert!(log.lock().unwrap().is_empty());
+    }
+
+    /// Output that fails to deliver the messages with a "fail" body.
+    pub struct ReportingOutput(mpsc::Sender<Message>);
+
+    #[async_trait]
+    impl OutputConnector for ReportingOutput {
+        async fn run(self: Box<Self>, mut receiver: Receiver) -> Result<(), Error> {
+            loop {
+                let message = receiver.recv().await?;
+                match message.body.as_str() {
+                    "fail" => receiver.report(DeliveryReport::failed(message, "unreachable")),
+                    _ => {
+                        receiver.report(DeliveryReport::delivered(&message));
+                        self.0
+                            .send(message)
+                            .await
+                            .map_err(|_| ClosedChannel::new(ChannelEnd::Receiver))?;
+                    }
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn delivery_reports() {
+        let (input_sender, input_receiver) = mpsc::channel(32);
+        let (output_sender, mut output_receiver) = mpsc::channel(32);
+
+        let engine = Engine::default()
+            .input(input_receiver)
+            .output(ReportingOutput(output_sender))
+            .add_service("s-echo", Echo);
+
+        let handle = engine.handle();
+        let mut events = handle.events();
+        let task = tokio::spawn(engine.run());
+
+        let failed = build_message("user_0", "s-echo").body("fail");
+        input_sender.send(failed.clone()).await.unwrap();
+        let delivered = build_message("user_1", "s-echo");
+        input_sender.send(delivered.clone()).await.unwrap();
+        assert_eq!(Some(delivered), output_receiver.recv().await);
+
+        handle.shutdown();
+        task.await.unwrap();
+
+        let mut received = Vec::new();
+        while let Ok(Ok(event)) = timeout(Duration::from_millis(100), events.recv()).await {
+            received.push(event);
+        }
+
+        let expected = [
+            Event::DeliveryFailed {
+                user: "user_0".into(),
+                service_name: "s-echo".into(),
+            },
+            Event::DeliveryError {
+                message: Box::new(failed),
+                error: "unreachable".into(),
+            },
+            Event::MessageDelivered {
+                user: "user_1".into(),
+                service_name: "s-echo".into(),
+            },
+        ];
+
+        for event in expected {
+            assert!(received.contains(&event), "Missing event: {:?}", event);
+        }
+    }
+
+    /// Input that sends the messages one by one, waiting for their acknowledgments.
+    pub struct AckedInput(Vec<Message>, mpsc::Sender<bool>);
+
+    #[async_trait]
+    impl InputConnector for AckedInput {
+        async fn run(self: Box<Self>, sender: Sender) -> Result<(), Error> {
+            for message in self.0 {
+                let ack = sender.send_acked(message).await?;
+                self.1.send(ack.wait().await).await.ok();
+            }
+            std::future::pending().await
+        }
+    }
+
+    #[tokio::test]
+    async fn ack_accepted() {
+        let (output_sender, mut output_receiver) = mpsc::channel(32);
+        let (ack_sender, mut ack_receiver) = mpsc::channel(32);
+
+        let messages = vec![
+            build_message("user_0", "s-echo"),
+            build_message("user_0", "unknown"),
+        ];
+
+        tokio::spawn(
+            Engine::default()
+                .input(AckedInput(messages, ack_sender))
+                .output(output_sender)
+                .add_service("s-echo", Echo)
+                .run(),
+        );
+
+        assert_eq!(ack_receiver.recv().await, Some(true));
+        assert_eq!(ack_receiver.recv().await, Some(true));
+
+        let response = output_receiver.recv().await.unwrap();
+        assert!(response.metadata.is_empty());
+    }
+
+    #[tokio::test]
+    async fn ack_replied() {
+        let (output_sender, mut output_receiver) = mpsc::channel(32);
+        let (ack_sender, mut ack_receiver) = mpsc::channel(32);
+
+        let messages = vec![
+            build_message("user_0", "s-echo"),
+            build_message("user_0", "s-mute"),
+        ];
+
+        let engine = Engine::default()
+            .input(AckedInput(messages, ack_sender))
+            .output(output_sender)
+            .ack_mode(AckMode::Replied)
+            .add_service("s-echo", Echo)
+            .add_service("s-mute", Mute);
+
+        let handle = engine.handle();
+        let task = tokio::spawn(engine.run());
+
+        assert_eq!(ack_receiver.recv().await, Some(true));
+        assert!(output_receiver.recv().await.is_some());
+
+        // Never replied, so it is not acknowledged.
+        let waiting = timeout(Duration::from_millis(100), ack_receiver.recv()).await;
+        assert!(waiting.is_err());
+
+        handle.shutdown();
+        task.await.unwrap();
+        assert_eq!(ack_receiver.recv().await, Some(false));
+    }
+
+    #[tokio::test]
+    async fn ack_id_not_delivered() {
+        use crate::state::MemoryStore;
+
+        let (output_sender, mut output_receiver) = mpsc::channel(32);
+        let (ack_sender, _ack_receiver) = mpsc::channel(32);
+
+        let messages = vec![
+            build_message("unverified", "s-echo"),
+            build_message("trusted", "s-mute"),
+        ];
+
+        tokio::spawn(
+            Engine::default()
+                .input(AckedInput(messages, ack_sender))
+                .output(output_sender)
+                .ack_mode(AckMode::Replied)
+                .deadline(Duration::from_millis(50))
+                .verify_users(Verification::new(MemoryStore::default()).trust(["trusted"]))
+                .add_service("s-echo", Echo)
+                .add_service("s-mute", Mute)
+                .run(),
+        );
+
+        let challenge = output_receiver.recv().await.unwrap();
+        assert_eq!(challenge.user, "unverified");
+        assert_eq!(ack::id(&challenge), None);
+
+        let notification = output_receiver.recv().await.unwrap();
+        assert_eq!(notification.args, ["timeout"]);
+        assert_eq!(ack::id(&notification), None);
+    }
+
+    /// Output that takes a while to connect.
+    pub struct SlowConnectOutput(mpsc::Sender<Message>);
+
+    #[async_trait]
+    impl OutputConnector for SlowConnectOutput {
+        async fn connect(&mut self) -> Result<(), Error> {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Ok(())
+        }
+
+        async fn run(self: Box<Self>, receiver: Receiver) -> Result<(), Error> {
+            Box::new(self.0).run(receiver).await
+        }
+    }
+
+    #[tokio::test]
+    async fn startup_order() {
+        let (input_sender, input_receiver) = mpsc::channel(32);
+        let (output_sender, mut output_receiver) = mpsc::channel(32);
+        let engine = Engine::default()
+            .input(input_receiver)
+            .output(SlowConnectOutput(output_sender))
+            .add_service("s-test", Echo)
+            .depends_on(Component::Service("s-test".into()), Component::Output);
+
+        let handle = engine.handle();
+        let mut events = handle.events();
+        let task = tokio::spawn(engine.run());
+        assert!(handle.ready().await);
+
+        let started = [
+            Event::ConnectorConnected {
+                connector: ConnectorKind::Output,
+            },
+            Event::ConnectorConnected {
+                connector: ConnectorKind::Input,
+            },
+            Event::ServiceStarted {
+                name: "s-test".into(),
+            },
+        ];
+        let mut received = Vec::new();
+        while received.len() < started.len() {
+            received.push(events.recv().await.unwrap());
+        }
+        assert_eq!(received[0], started[0]);
+        assert!(started[1..].iter().all(|event| received.contains(event)));
+
+        let message = build_message("user_0", "s-test");
+        input_sender.send(message.clone()).await.unwrap();
+        assert_eq!(Some(message), output_receiver.recv().await);
+
+        handle.shutdown();
+        task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_dependency() {
+        let (_input_sender, input_receiver) = mpsc::channel(32);
+        let (output_sender, _output_receiver) = mpsc::channel(32);
+        let log = Arc::default();
+        let engine = Engine::default()
+            .input(input_receiver)
+            .output(output_sender)
+            .add_service(
+                "s-test",
+                Lifecycle {
+                    log: Arc::clone(&log),
+                    fail_start: true,
+                },
+            )
+            .depends_on(Component::Input, Component::Service("s-test".into()));
+
+        let handle = engine.handle();
+        let mut events = handle.events();
+        let task = tokio::spawn(engine.run());
+        assert!(!handle.ready().await);
+
+        let expected = Event::ConnectorDisconnected {
+            connector: ConnectorKind::Input,
+            reason: StopReason::Failed,
+        };
+        while events.recv().await.unwrap() != expected {}
+
+        task.await.unwrap();
+    }
+
+    /// Output that does not read any message until it is started.
+    pub struct StartedOutput(oneshot::Receiver<()>, mpsc::Sender<Message>);
+
+    #[async_trait]
+    impl OutputConnector for StartedOutput {
+        async fn run(self: Box<Self>, mut receiver: Receiver) -> Result<(), Error> {
+            self.0.await.ok();
+            loop {
+                let message = receiver.recv().await?;
+                self.1
+                    .send(message)
+                    .await
+                    .map_err(|_| ClosedChannel::new(ChannelEnd::Receiver))?;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn memory_budget() {
+        let (input_sender, input_receiver) = mpsc::channel(32);
+        let (output_sender, mut output_receiver) = mpsc::channel(32);
+        let (start_sender, start_receiver) = oneshot::channel();
+
+        let message = build_message("user_0", "s-echo");
+        tokio::spawn(
+            Engine::default()
+                .input(input_receiver)
+                .output(StartedOutput(start_receiver, output_sender))
+                .memory_budget(message.size() * 2)
+                .add_service("s-echo", Echo)
+                .run(),
+        );
+
+        for _ in 0..10 {
+            input_sender.send(message.clone()).await.unwrap();
+        }
+
+        // Only two replies fit in the budget while the output is not reading.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(input_sender.max_capacity() - input_sender.capacity() > 0);
+
+        start_sender.send(()).unwrap();
+        for _ in 0..10 {
+            assert!(output_receiver.recv().await.is_some());
+        }
+    }
+
+    #[derive(Clone)]
+    pub struct Panic;
+
+    #[async_trait]
+    impl Service for Panic {
+        async fn run(self: Box<Self>, _: Receiver, _: Sender) -> Result<(), ClosedChannel> {
+            panic!("Service panicked");
+        }
+    }
+
+    #[tokio::test]
+    async fn notify_operator() {
+        let (_input_sender, input_receiver) = mpsc::channel(32);
+        let (output_sender, mut output_receiver) = mpsc::channel(32);
+
+        tokio::spawn(async move {
+            Engine::default()
+                .input(input_receiver)
+                .output(output_sender)
+                .notify_operator("operator")
+                .add_service("s-panic", Panic)
+                .add_service("s-echo", Echo)
+                .run()
+                .await;
+        });
+
+        let message = output_receiver.recv().await.unwrap();
+        assert_eq!(message.user, "operator");
+        assert_eq!(message.service_name, OPERATOR_SERVICE_NAME);
+        assert!(message.body.contains("s-panic"));
+    }
+
+    #[derive(Clone)]
+    pub struct Flood(usize);
+
+    #[async_trait]
+    impl Service for Flood {
+        async fn run(
+            self: Box<Self>,
+            mut input: Receiver,
+            output: Sender,
+        ) -> Result<(), ClosedChannel> {
+            loop {
+                let message = input.recv().await?;
+                for _ in 0..self.0 {
+                    output.send(message.clone()).await?;
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn full_service_queues() {
+        const MESSAGES: usize = 100;
+        const COPIES: usize = 100;
+
+        let (input_sender, input_receiver) = mpsc::channel(32);
+        let (output_sender, mut output_receiver) = mpsc::channel(32);
+
+        tokio::spawn(async move {
+            Engine::default()
+                .input(input_receiver)
+                .output(output_sender)
+                .add_service("s-test", Flood(COPIES))
+                .run()
+                .await;
+        });
+
+        tokio::spawn(async move {
+            for _ in 0..MESSAGES {
+                let message = build_message("user_0", "s-test");
+                input_sender.send(message).await.unwrap();
+            }
+        });
+
+        let all_received = async {
+            for _ in 0..MESSAGES * COPIES {
+                output_receiver.recv().await.unwrap();
+            }
+        };
+
+        assert!(timeout(Duration::from_secs(5), all_received).await.is_ok());
     }
 
-    fn log_join_result(result: Result<Result<(), ClosedChannel>, JoinError>, name: &str) {
-        match result {
-            Ok(Ok(())) => log::info!("{} down (finished)", name),
-            Ok(Err(_)) => log::info!("{} down (disconnected)", name),
-            Err(_) => log::error!("{} down (panicked)", name),
-        }
-    }
-}
+    #[tokio::test]
+    async fn shutdown() {
+        let (_input_sender, input_receiver) = mpsc::channel(32);
+        let (output_sender, _output_receiver) = mpsc::channel(32);
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::channel::ClosedChannel;
-    use crate::message::util;
+        let engine = Engine::default()
+            .input(input_receiver)
+            .output(output_sender)
+            .add_service("s-echo", Echo);
 
-    use async_trait::async_trait;
-    use tokio::time::timeout;
+        let handle = engine.handle();
+        let task = tokio::spawn(engine.run());
 
-    use std::time::Duration;
+        handle.shutdown();
+        assert!(timeout(Duration::from_millis(100), task).await.is_ok());
+    }
 
     #[derive(Clone)]
-    pub struct EchoOnce;
+    pub struct Mute;
 
     #[async_trait]
-    impl Service for EchoOnce {
+    impl Service for Mute {
         async fn run(
             self: Box<Self>,
             mut input: Receiver,
-            output: Sender,
+            _output: Sender,
         ) -> Result<(), ClosedChannel> {
-            let message = input.recv().await?;
-            output.send(message).await
-        }
-    }
-
-    fn build_message(user: &str, service: &str) -> Message {
-        Message {
-            user: user.into(),
-            service_name: service.into(),
-            args: vec!["arg0".into(), "arg1".into()],
-            body: "abcd".into(),
-            attached_data: [
-                ("file1".to_string(), b"1234".to_vec()),
-                ("file2".to_string(), b"5678".to_vec()),
-            ]
-            .into_iter()
-            .collect(),
+            loop {
+                input.recv().await?;
+            }
         }
     }
 
     #[tokio::test]
-    async fn echo() {
+    async fn deadline() {
         let (input_sender, input_receiver) = mpsc::channel(32);
         let (output_sender, mut output_receiver) = mpsc::channel(32);
 
-        let task = tokio::spawn(async move {
+        tokio::spawn(async move {
             Engine::default()
                 .input(input_receiver)
                 .output(output_sender)
-                .add_service("s-test", EchoOnce)
+                .deadline(Duration::from_millis(50))
+                .add_service("s-echo", Echo)
+                .add_service("s-mute", Mute)
                 .run()
                 .await;
         });
 
-        let message = build_message("user_0", "s-test");
+        let message = build_message("user_0", "s-echo");
         input_sender.send(message.clone()).await.unwrap();
         assert_eq!(Some(message), output_receiver.recv().await);
+        assert!(timeout(Duration::from_millis(100), output_receiver.recv())
+            .await
+            .is_err());
 
-        task.await.unwrap();
+        let message = build_message("user_0", "s-mute");
+        input_sender.send(message.clone()).await.unwrap();
+        let notification = output_receiver.recv().await.unwrap();
+        assert_eq!(notification.user, "user_0");
+        assert_eq!(notification.service_name, "s-mute");
+        assert_eq!(notification.args, ["timeout"]);
+    }
+
+    #[derive(Clone)]
+    pub struct OnlyProgress;
+
+    #[async_trait]
+    impl Service for OnlyProgress {
+        async fn run(
+            self: Box<Self>,
+            mut input: Receiver,
+            output: Sender,
+        ) -> Result<(), ClosedChannel> {
+            loop {
+                let request = input.recv().await?;
+                output
+                    .send(Message::progress(&request).body("working"))
+                    .await?;
+            }
+        }
     }
 
     #[tokio::test]
-    async fn echo_with_input_mapping() {
+    async fn deadline_progress() {
         let (input_sender, input_receiver) = mpsc::channel(32);
         let (output_sender, mut output_receiver) = mpsc::channel(32);
 
-        let task = tokio::spawn(async move {
+        tokio::spawn(async move {
             Engine::default()
                 .input(input_receiver)
                 .output(output_sender)
-                .map_input(util::service_name_first_char_to_lowercase)
-                .add_service("s-test", EchoOnce)
+                .deadline(Duration::from_millis(50))
+                .add_service("s-progress", OnlyProgress)
                 .run()
                 .await;
         });
 
-        let message = build_message("user_0", "S-test");
+        let message = build_message("user_0", "s-progress");
         input_sender.send(message.clone()).await.unwrap();
-        assert_eq!(
-            Some(util::service_name_first_char_to_lowercase(message)),
-            output_receiver.recv().await
+        let progress = output_receiver.recv().await.unwrap();
+        assert!(progress.is_progress());
+
+        let notification = output_receiver.recv().await.unwrap();
+        assert_eq!(notification.service_name, "s-progress");
+        assert_eq!(notification.args, ["timeout"]);
+    }
+
+    #[tokio::test]
+    async fn ack_replied_progress() {
+        let (output_sender, mut output_receiver) = mpsc::channel(32);
+        let (ack_sender, mut ack_receiver) = mpsc::channel(32);
+
+        tokio::spawn(
+            Engine::default()
+                .input(AckedInput(
+                    vec![build_message("user_0", "s-progress")],
+                    ack_sender,
+                ))
+                .output(output_sender)
+                .ack_mode(AckMode::Replied)
+                .add_service("s-progress", OnlyProgress)
+                .run(),
         );
 
-        task.await.unwrap();
+        assert!(output_receiver.recv().await.unwrap().is_progress());
+
+        // Only progress, so it is not acknowledged.
+        let waiting = timeout(Duration::from_millis(100), ack_receiver.recv()).await;
+        assert!(waiting.is_err());
     }
 
     #[tokio::test]
-    async fn echo_with_input_filtering() {
+    async fn maintenance() {
         let (input_sender, input_receiver) = mpsc::channel(32);
         let (output_sender, mut output_receiver) = mpsc::channel(32);
 
+        let now = chrono::Utc::now().time();
+        let window = MaintenanceWindow::daily(now, now + chrono::Duration::milliseconds(200))
+            .allow("s-allowed");
         tokio::spawn(async move {
             Engine::default()
                 .input(input_receiver)
                 .output(output_sender)
-                .filter_input(|message| !message.service_name.starts_with("s-"))
-                .add_service("s-test", EchoOnce)
+                .maintenance(window)
+                .add_service("s-echo", Echo)
+                .add_service("s-allowed", Echo)
                 .run()
                 .await;
         });
 
-        let message = build_message("user_0", "s-test");
-        input_sender.send(message.clone()).await.unwrap();
-        assert!(timeout(Duration::from_millis(100), output_receiver.recv())
+        let held = build_message("user_0", "s-echo");
+        input_sender.send(held.clone()).await.unwrap();
+        let allowed = build_message("user_0", "s-allowed");
+        input_sender.send(allowed.clone()).await.unwrap();
+        assert_eq!(Some(allowed), output_receiver.recv().await);
+        assert!(timeout(Duration::from_millis(50), output_receiver.recv())
             .await
             .is_err());
+
+        // Processed once the window ends.
+        assert_eq!(Some(held), output_receiver.recv().await);
     }
 
     #[tokio::test]
-    async fn no_services() {
-        let (_input_sender, input_receiver) = mpsc::channel(32);
-        let (output_sender, mut _output_receiver) = mpsc::channel(32);
+    async fn cluster() {
+        let queue = crate::cluster::MemoryQueue::default();
+        let (input_sender, input_receiver) = mpsc::channel(32);
+        let (_idle_sender, idle_receiver) = mpsc::channel(32);
+        let (output_sender, mut output_receiver) = mpsc::channel(32);
 
-        tokio::spawn(async move {
-            Engine::default()
-                .input(input_receiver)
-                .output(output_sender)
-                .run()
-                .await;
-        })
-        .await
-        .unwrap();
+        for (input, queue) in [(input_receiver, queue.clone()), (idle_receiver, queue)] {
+            let output_sender = output_sender.clone();
+            tokio::spawn(async move {
+                Engine::default()
+                    .input(input)
+                    .output(output_sender)
+                    .cluster(queue)
+                    .add_service("s-test", Echo)
+                    .run()
+                    .await;
+            });
+        }
+
+        for i in 0..10 {
+            let message = build_message(&format!("user_{}", i), "s-test");
+            input_sender.send(message).await.unwrap();
+        }
+
+        let mut users = HashSet::new();
+        for _ in 0..10 {
+            let message = output_receiver.recv().await.unwrap();
+            users.insert(message.user);
+        }
+        assert_eq!(users.len(), 10);
+    }
+
+    /// Queue that rejects the pushes while it is down.
+    struct UnreachableQueue(
+        crate::cluster::MemoryQueue,
+        Arc<std::sync::atomic::AtomicBool>,
+    );
+
+    #[async_trait]
+    impl SharedQueue for UnreachableQueue {
+        async fn push(&self, message: Message) -> std::io::Result<()> {
+            match self.1.load(std::sync::atomic::Ordering::Relaxed) {
+                true => Err(std::io::Error::other("unreachable")),
+                false => self.0.push(message).await,
+            }
+        }
+
+        async fn pop(&self) -> std::io::Result<Message> {
+            self.0.pop().await
+        }
     }
 
     #[tokio::test]
-    async fn service_not_found() {
-        let (input_sender, input_receiver) = mpsc::channel(32);
+    async fn cluster_ack_once_pushed() {
         let (output_sender, mut output_receiver) = mpsc::channel(32);
+        let (ack_sender, mut ack_receiver) = mpsc::channel(32);
 
-        tokio::spawn(async move {
+        let down = Arc::new(std::sync::atomic::AtomicBool::new(true));
+        let queue = UnreachableQueue(Default::default(), down.clone());
+        let message = build_message("user_0", "s-echo");
+        tokio::spawn(
             Engine::default()
-                .input(input_receiver)
+                .input(AckedInput(vec![message], ack_sender))
                 .output(output_sender)
-                .add_service("s-test", EchoOnce)
-                .run()
-                .await;
-        });
+                .cluster(queue)
+                .add_service("s-echo", Echo)
+                .run(),
+        );
 
-        let message = build_message("user_0", "unknown");
-        input_sender.send(message.clone()).await.unwrap();
-        assert!(timeout(Duration::from_millis(100), output_receiver.recv())
-            .await
-            .is_err());
+        let pending = timeout(Duration::from_millis(200), ack_receiver.recv()).await;
+        assert!(pending.is_err());
+
+        down.store(false, std::sync::atomic::Ordering::Relaxed);
+        assert_eq!(ack_receiver.recv().await, Some(true));
+        let response = output_receiver.recv().await.unwrap();
+        assert!(response.metadata.is_empty());
+    }
+
+    /// Queue that reports when the engine is done with the popped messages.
+    struct ReportingQueue(
+        crate::cluster::MemoryQueue,
+        mpsc::UnboundedSender<(String, bool)>,
+    );
+
+    #[async_trait]
+    impl SharedQueue for ReportingQueue {
+        async fn push(&self, message: Message) -> std::io::Result<()> {
+            self.0.push(message).await
+        }
+
+        async fn pop(&self) -> std::io::Result<Message> {
+            self.0.pop().await
+        }
+
+        async fn done(&self, message: &Message, processed: bool) -> std::io::Result<()> {
+            self.1.send((message.service_name.clone(), processed)).ok();
+            Ok(())
+        }
     }
 
     #[tokio::test]
-    async fn whitelist() {
+    async fn cluster_done() {
         let (input_sender, input_receiver) = mpsc::channel(32);
         let (output_sender, mut output_receiver) = mpsc::channel(32);
+        let (done_sender, mut done_receiver) = mpsc::unbounded_channel();
 
-        let task = tokio::spawn(async move {
+        tokio::spawn(
             Engine::default()
                 .input(input_receiver)
                 .output(output_sender)
-                .add_service_for("s-test", EchoOnce, ["user_allowed"])
-                .run()
-                .await;
-        });
+                .cluster(ReportingQueue(Default::default(), done_sender))
+                .add_service("s-test", Echo)
+                .run(),
+        );
 
-        let message = build_message("user_not_allowed", "s-test");
-        input_sender.send(message.clone()).await.unwrap();
-        assert!(timeout(Duration::from_millis(100), output_receiver.recv())
+        let message = build_message("user_0", "s-test");
+        input_sender.send(message).await.unwrap();
+        assert_eq!(
+            done_receiver.recv().await,
+            Some(("s-test".to_string(), true))
+        );
+        let response = output_receiver.recv().await.unwrap();
+        assert!(response.metadata.is_empty());
+    }
+
+    #[cfg(feature = "email")]
+    #[tokio::test]
+    async fn email() {
+        use crate::connectors::{ImapClient, SmtpClient};
+        use crate::testing::{MockImapServer, MockSmtpServer};
+
+        let imap = MockImapServer::start().await;
+        let smtp = MockSmtpServer::start().await;
+
+        tokio::spawn(
+            Engine::default()
+                .input(
+                    ImapClient::default()
+                        .domain("127.0.0.1")
+                        .port(imap.port())
+                        .insecure()
+                        .email("service@domain.com")
+                        .password("1234")
+                        .polling_time(Duration::from_millis(10)),
+                )
+                .output(
+                    SmtpClient::default()
+                        .domain("127.0.0.1")
+                        .port(smtp.port())
+                        .insecure()
+                        .email("service@domain.com")
+                        .password("1234"),
+                )
+                .add_service("s-test", Echo)
+                .run(),
+        );
+
+        let message = build_message("user@domain.com", "s-test");
+        imap.push(message.clone());
+
+        let reply = timeout(Duration::from_secs(5), smtp.recv()).await.unwrap();
+        assert!(imap.is_empty());
+        assert_eq!(reply.user, message.user);
+        assert_eq!(reply.service_name, message.service_name);
+        assert_eq!(reply.args, message.args);
+        assert_eq!(reply.body.trim_end(), message.body);
+        assert_eq!(reply.attached_data, message.attached_data);
+    }
+
+    #[cfg(feature = "email")]
+    #[tokio::test]
+    async fn email_peek() {
+        use crate::connectors::{ImapClient, SmtpClient};
+        use crate::testing::{MockImapServer, MockSmtpServer};
+
+        let imap = MockImapServer::start().await;
+        let smtp = MockSmtpServer::start().await;
+
+        tokio::spawn(
+            Engine::default()
+                .input(
+                    ImapClient::default()
+                        .domain("127.0.0.1")
+                        .port(imap.port())
+                        .insecure()
+                        .email("service@domain.com")
+                        .password("1234")
+                        .polling_time(Duration::from_millis(10))
+                        .peek(true),
+                )
+                .output(
+                    SmtpClient::default()
+                        .domain("127.0.0.1")
+                        .port(smtp.port())
+                        .insecure()
+                        .email("service@domain.com")
+                        .password("1234"),
+                )
+                .add_service("s-test", Echo)
+                .run(),
+        );
+
+        let message = build_message("user@domain.com", "s-test");
+        imap.push(message.clone());
+
+        let reply = timeout(Duration::from_secs(5), smtp.recv()).await.unwrap();
+        assert_eq!(reply.user, message.user);
+        assert_eq!(imap.len(), 1);
+
+        // The same email is not read twice
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(smtp.try_recv().await.is_none());
+    }
+
+    #[cfg(feature = "email")]
+    #[tokio::test]
+    async fn email_cursor() {
+        use crate::connectors::{ImapClient, SmtpClient};
+        use crate::testing::{MockImapServer, MockSmtpServer};
+
+        let imap = MockImapServer::start().await;
+        let smtp = MockSmtpServer::start().await;
+
+        // Cursor of a previous run that already processed the email with UID 1
+        let cursor_file = std::env::temp_dir().join("service-io-test-imap-cursor");
+        std::fs::write(&cursor_file, "1 1\n").unwrap();
+
+        imap.push(build_message("user_1@domain.com", "s-test"));
+        imap.push(build_message("user_2@domain.com", "s-test"));
+
+        tokio::spawn(
+            Engine::default()
+                .input(
+                    ImapClient::default()
+                        .domain("127.0.0.1")
+                        .port(imap.port())
+                        .insecure()
+                        .email("service@domain.com")
+                        .password("1234")
+                        .polling_time(Duration::from_millis(10))
+                        .peek(true)
+                        .cursor_file(&cursor_file),
+                )
+                .output(
+                    SmtpClient::default()
+                        .domain("127.0.0.1")
+                        .port(smtp.port())
+                        .insecure()
+                        .email("service@domain.com")
+                        .password("1234"),
+                )
+                .add_service("s-test", Echo)
+                .run(),
+        );
+
+        let reply = timeout(Duration::from_secs(5), smtp.recv()).await.unwrap();
+        assert_eq!(reply.user, "user_2@domain.com");
+
+        // Another client removes an email: the following ones are not skipped
+        imap.pop();
+        imap.push(build_message("user_3@domain.com", "s-test"));
+
+        let reply = timeout(Duration::from_secs(5), smtp.recv()).await.unwrap();
+        assert_eq!(reply.user, "user_3@domain.com");
+
+        // Peeking does not move the cursor of the real runs
+        assert_eq!(std::fs::read_to_string(&cursor_file).unwrap(), "1 1\n");
+
+        std::fs::remove_file(cursor_file).unwrap();
+    }
+
+    #[cfg(feature = "email")]
+    #[tokio::test]
+    async fn email_folders() {
+        use crate::connectors::{ImapClient, SmtpClient};
+        use crate::testing::{MockImapServer, MockSmtpServer};
+
+        let imap = MockImapServer::start().await;
+        let smtp = MockSmtpServer::start().await;
+
+        tokio::spawn(
+            Engine::default()
+                .input(
+                    ImapClient::default()
+                        .domain("127.0.0.1")
+                        .port(imap.port())
+                        .insecure()
+                        .email("service@domain.com")
+                        .password("1234")
+                        .polling_time(Duration::from_millis(10))
+                        .folder("INBOX", "")
+                        .folder("bots", "bot-"),
+                )
+                .output(
+                    SmtpClient::default()
+                        .domain("127.0.0.1")
+                        .port(smtp.port())
+                        .insecure()
+                        .email("service@domain.com")
+                        .password("1234"),
+                )
+                .add_service("s-test", Echo)
+                .add_service("bot-s-test", Echo)
+                .run(),
+        );
+
+        imap.push(build_message("user@domain.com", "s-test"));
+        imap.push_to("bots", build_message("bot@domain.com", "s-test"));
+
+        let mut replies = Vec::new();
+        for _ in 0..2 {
+            let reply = timeout(Duration::from_secs(5), smtp.recv()).await.unwrap();
+            replies.push((reply.user, reply.service_name));
+        }
+        replies.sort();
+
+        assert_eq!(
+            replies,
+            [
+                ("bot@domain.com".into(), "bot-s-test".into()),
+                ("user@domain.com".into(), "s-test".into()),
+            ]
+        );
+        assert!(imap.is_empty());
+        assert_eq!(imap.len_of("bots"), 0);
+    }
+
+    #[cfg(feature = "email")]
+    #[tokio::test]
+    async fn email_address_extension() {
+        use crate::connectors::{ImapClient, MailRouting, SmtpClient};
+        use crate::testing::{MockImapServer, MockSmtpServer};
+
+        let imap = MockImapServer::start().await;
+        let smtp = MockSmtpServer::start().await;
+
+        tokio::spawn(
+            Engine::default()
+                .input(
+                    ImapClient::default()
+                        .domain("127.0.0.1")
+                        .port(imap.port())
+                        .insecure()
+                        .email("service@domain.com")
+                        .password("1234")
+                        .polling_time(Duration::from_millis(10))
+                        .routing(MailRouting::AddressExtension),
+                )
+                .output(
+                    SmtpClient::default()
+                        .domain("127.0.0.1")
+                        .port(smtp.port())
+                        .insecure()
+                        .email("service@domain.com")
+                        .password("1234"),
+                )
+                .add_service("s-test", Echo)
+                .run(),
+        );
+
+        imap.push_raw(
+            "From: user@domain.com\r\n\
+             To: service+s-test@domain.com\r\n\
+             Subject: arg1 arg2\r\n\
+             \r\n\
+             body\r\n",
+        );
+
+        let reply = timeout(Duration::from_secs(5), smtp.recv()).await.unwrap();
+        assert_eq!(reply.user, "user@domain.com");
+        assert_eq!(reply.service_name, "s-test");
+        assert_eq!(reply.args, ["arg1", "arg2"]);
+    }
+
+    #[cfg(feature = "email")]
+    #[tokio::test]
+    async fn email_parser() {
+        use crate::connectors::{
+            DefaultMailParser, ImapClient, MailParser, ParsedMail, SmtpClient,
+        };
+        use crate::testing::{MockImapServer, MockSmtpServer};
+
+        let imap = MockImapServer::start().await;
+        let smtp = MockSmtpServer::start().await;
+
+        tokio::spawn(
+            Engine::default()
+                .input(
+                    ImapClient::default()
+                        .domain("127.0.0.1")
+                        .port(imap.port())
+                        .insecure()
+                        .email("service@domain.com")
+                        .password("1234")
+                        .polling_time(Duration::from_millis(10))
+                        .parser(|email: &ParsedMail| {
+                            let mut message = DefaultMailParser.parse(email);
+                            let args = message.body.lines().map(|line| line.trim().to_owned());
+                            message.args = args.collect();
+                            message
+                        }),
+                )
+                .output(
+                    SmtpClient::default()
+                        .domain("127.0.0.1")
+                        .port(smtp.port())
+                        .insecure()
+                        .email("service@domain.com")
+                        .password("1234"),
+                )
+                .add_service("s-test", Echo)
+                .run(),
+        );
+
+        imap.push(
+            Message::default()
+                .user("user@domain.com")
+                .service_name("s-test")
+                .body("key1=value1\nkey2=value2"),
+        );
+
+        let reply = timeout(Duration::from_secs(5), smtp.recv()).await.unwrap();
+        assert_eq!(reply.service_name, "s-test");
+        assert_eq!(reply.args, ["key1=value1", "key2=value2"]);
+    }
+
+    #[cfg(feature = "email")]
+    #[tokio::test]
+    async fn email_dry_run() {
+        use crate::connectors::{ImapClient, SmtpClient};
+        use crate::testing::{MockImapServer, MockSmtpServer};
+
+        let imap = MockImapServer::start().await;
+        let smtp = MockSmtpServer::start().await;
+
+        let engine = Engine::default()
+            .input(
+                ImapClient::default()
+                    .domain("127.0.0.1")
+                    .port(imap.port())
+                    .insecure()
+                    .email("service@domain.com")
+                    .password("1234")
+                    .polling_time(Duration::from_millis(10)),
+            )
+            .output(
+                SmtpClient::default()
+                    .domain("127.0.0.1")
+                    .port(smtp.port())
+                    .insecure()
+                    .email("service@domain.com")
+                    .password("1234"),
+            )
+            .add_service("s-test", Echo)
+            .dry_run(true);
+
+        let mut events = engine.handle().events();
+        tokio::spawn(engine.run());
+
+        let message = build_message("user@domain.com", "s-test");
+        imap.push(message.clone());
+        imap.push(message.clone().user("other@domain.com"));
+
+        for user in ["user@domain.com", "other@domain.com"] {
+            let reply = timeout(Duration::from_secs(5), async {
+                loop {
+                    if let Event::DeliverySuppressed { message } = events.recv().await.unwrap() {
+                        break message;
+                    }
+                }
+            })
             .await
-            .is_err());
+            .unwrap();
+            assert_eq!(reply.user, user);
+            assert_eq!(reply.service_name, message.service_name);
+        }
 
-        let message = build_message("user_allowed", "s-test");
-        input_sender.send(message.clone()).await.unwrap();
-        assert_eq!(Some(message), output_receiver.recv().await);
+        assert_eq!(imap.len(), 2);
+        assert!(smtp.try_recv().await.is_none());
+    }
 
-        task.await.unwrap();
+    #[tokio::test]
+    async fn generator() {
+        use crate::connectors::GeneratorInput;
+
+        let (output_sender, mut output_receiver) = mpsc::channel(32);
+
+        tokio::spawn(
+            Engine::default()
+                .input(
+                    GeneratorInput::new(|count| {
+                        build_message(&format!("user_{}", count), "s-test")
+                    })
+                    .every(Duration::from_millis(1))
+                    .limit(3),
+                )
+                .output(output_sender)
+                .add_service("s-test", Echo)
+                .run(),
+        );
+
+        for i in 0..3 {
+            let message = output_receiver.recv().await.unwrap();
+            assert_eq!(message.user, format!("user_{}", i));
+        }
     }
 }
@@ -0,0 +1,127 @@
+use crate::message::Message;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Key of [`Message::metadata`] used to track the acknowledged messages inside the engine.
+/// It is removed when the message is delivered to the output connector,
+/// even from the replies that copied it from their request.
+pub(crate) const ACK_KEY: &str = "ack-id";
+
+/// When the engine acknowledges a message sent with [`Sender::send_acked()`].
+///
+/// [`Sender::send_acked()`]: crate::channel::Sender::send_acked()
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AckMode {
+    /// Once the message is in the queue of its service.
+    #[default]
+    Accepted,
+
+    /// Once a reply of the service to the message is passed to the output connector.
+    /// Use it only if the services always reply,
+    /// otherwise the input connector waiting for the acknowledgment never continues.
+    Replied,
+}
+
+/// Acknowledgment of a message sent by an input connector with [`Sender::send_acked()`].
+/// Wait for it before removing the message from its origin (i.e. deleting an email),
+/// so the message is not lost if the engine stops before processing it.
+///
+/// [`Sender::send_acked()`]: crate::channel::Sender::send_acked()
+pub struct Ack(Option<oneshot::Receiver<()>>);
+
+impl Ack {
+    /// Acknowledgment already resolved, used when there is no engine tracking the message.
+    pub(crate) fn resolved() -> Ack {
+        Ack(None)
+    }
+
+    /// Wait until the engine is done with the message.
+    ///
+    /// Returns `true` if the message was processed according to the [`AckMode`],
+    /// or it was discarded on purpose (i.e. filtered or sent to an unknown service).
+    /// Returns `false` if the message could not be processed (i.e. its service is down or
+    /// the engine stopped), so it should be kept to be read again.
+    pub async fn wait(self) -> bool {
+        match self.0 {
+            Some(receiver) => receiver.await.is_ok(),
+            None => true,
+        }
+    }
+}
+
+/// Messages waiting for their acknowledgment.
+pub(crate) struct Acks {
+    pending: Mutex<HashMap<String, oneshot::Sender<()>>>,
+    next_id: AtomicU64,
+    // Avoid resolving a message by the id of a message from a previous run,
+    // i.e. a message held by the verification.
+    run_id: u64,
+}
+
+impl Default for Acks {
+    fn default() -> Self {
+        Acks {
+            pending: Mutex::default(),
+            next_id: AtomicU64::default(),
+            run_id: rand::random(),
+        }
+    }
+}
+
+impl Acks {
+    /// Track the message until it is resolved.
+    pub fn register(&self, message: &mut Message) -> Ack {
+        let next_id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let id = format!("{:x}-{}", self.run_id, next_id);
+        let (sender, receiver) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id.clone(), sender);
+        message.metadata.insert(ACK_KEY.into(), id);
+        Ack(Some(receiver))
+    }
+
+    /// Acknowledge the message.
+    pub fn resolve(&self, message: &mut Message) {
+        if let Some(sender) = self.take(message) {
+            sender.send(()).ok();
+        }
+    }
+
+    /// Acknowledge the message with the id given by [`id()`].
+    pub fn resolve_id(&self, id: &str) {
+        if let Some(sender) = self.pending.lock().unwrap().remove(id) {
+            sender.send(()).ok();
+        }
+    }
+
+    /// The message will not be acknowledged.
+    pub fn cancel(&self, message: &mut Message) {
+        self.take(message);
+    }
+
+    /// Cancel all the pending messages.
+    pub fn clear(&self) {
+        self.pending.lock().unwrap().clear();
+    }
+
+    fn take(&self, message: &mut Message) -> Option<oneshot::Sender<()>> {
+        let id = message.metadata.remove(ACK_KEY)?;
+        self.pending.lock().unwrap().remove(&id)
+    }
+}
+
+/// Remove the acknowledgment id from the message, to resolve it later with [`Acks::resolve_id()`]
+/// (i.e. once the message leaves this engine).
+pub(crate) fn detach(message: &mut Message) -> Option<String> {
+    message.metadata.remove(ACK_KEY)
+}
+
+/// Acknowledgment id of the message, if it is tracked.
+pub(crate) fn id(message: &Message) -> Option<&String> {
+    message.metadata.get(ACK_KEY)
+}
@@ -0,0 +1,68 @@
+use crate::message::Message;
+
+/// Command an alias is expanded into.
+/// The first word is the service name, the following words are the argument template.
+///
+/// Each template argument can be:
+/// - `$N`: replaced by the N-th argument (starting at `$1`) written by the user.
+/// - `$@`: replaced by all the arguments written by the user.
+/// - Any other value is used as it is.
+///
+/// If the template has no placeholders, the user arguments are appended at the end.
+pub(crate) struct Alias {
+    service_name: String,
+    template: Vec<String>,
+}
+
+impl Alias {
+    pub fn new(command: &str) -> Alias {
+        let mut words = command.split_whitespace().map(|s| s.to_owned());
+        Alias {
+            service_name: words.next().unwrap_or_default(),
+            template: words.collect(),
+        }
+    }
+
+    /// Command the alias was created from.
+    pub fn command(&self) -> String {
+        std::iter::once(&self.service_name)
+            .chain(&self.template)
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    pub fn expand(&self, mut message: Message) -> Message {
+        let has_placeholders = self.template.iter().any(|arg| is_placeholder(arg));
+
+        let mut args = Vec::new();
+        for arg in &self.template {
+            if arg == "$@" {
+                args.extend(message.args.iter().cloned());
+            } else if let Some(index) = placeholder_index(arg) {
+                args.extend(message.args.get(index - 1).cloned());
+            } else {
+                args.push(arg.clone());
+            }
+        }
+
+        if !has_placeholders {
+            args.append(&mut message.args);
+        }
+
+        message.service_name = self.service_name.clone();
+        message.args = args;
+        message
+    }
+}
+
+fn placeholder_index(arg: &str) -> Option<usize> {
+    arg.strip_prefix('$')?
+        .parse()
+        .ok()
+        .filter(|&index| index > 0)
+}
+
+fn is_placeholder(arg: &str) -> bool {
+    arg == "$@" || placeholder_index(arg).is_some()
+}
@@ -0,0 +1,76 @@
+use crate::i18n;
+use crate::message::Message;
+
+use futures::StreamExt;
+use tokio_util::time::{delay_queue, DelayQueue};
+
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+/// Tracks the time the services take to reply the requests.
+/// A request is considered replied when the service sends any message
+/// for the same user and service name.
+pub(crate) struct Deadlines {
+    timeout: Duration,
+    queue: DelayQueue<Message>,
+    pending: HashMap<(String, String), VecDeque<delay_queue::Key>>,
+}
+
+impl Deadlines {
+    pub fn new(timeout: Duration) -> Deadlines {
+        Deadlines {
+            timeout,
+            queue: DelayQueue::new(),
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Start the deadline of a request processed by a service.
+    pub fn start(&mut self, request: &Message) {
+        let notification = Message::response(request)
+            .args([i18n::text(request, "timeout")])
+            .body(i18n::text(request, "deadline-expired"));
+
+        let key = self.queue.insert(notification, self.timeout);
+        self.pending
+            .entry((request.user.clone(), request.service_name.clone()))
+            .or_default()
+            .push_back(key);
+    }
+
+    /// Resolve the oldest request that the response is replying.
+    /// Progress messages do not resolve any request.
+    pub fn resolve(&mut self, response: &Message) {
+        if response.is_progress() {
+            return;
+        }
+
+        let id = (response.user.clone(), response.service_name.clone());
+        if let Some(keys) = self.pending.get_mut(&id) {
+            if let Some(key) = keys.pop_front() {
+                self.queue.remove(&key);
+            }
+            if keys.is_empty() {
+                self.pending.remove(&id);
+            }
+        }
+    }
+
+    /// Wait until the next deadline expires, returning the notification for the user.
+    /// Returns `None` if there are no pending requests.
+    pub async fn expired(&mut self) -> Option<Message> {
+        let expired = self.queue.next().await?;
+        let key = expired.key();
+        let notification = expired.into_inner();
+
+        let id = (notification.user.clone(), notification.service_name.clone());
+        if let Some(keys) = self.pending.get_mut(&id) {
+            keys.retain(|pending_key| *pending_key != key);
+            if keys.is_empty() {
+                self.pending.remove(&id);
+            }
+        }
+
+        Some(notification)
+    }
+}
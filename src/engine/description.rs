@@ -0,0 +1,50 @@
+use super::ack::AckMode;
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+
+/// Serializable description of the configuration of an [`Engine`], without secrets.
+/// Obtained by [`Engine::describe()`] or, while it runs, by [`EngineHandle::describe()`].
+///
+/// [`Engine`]: crate::engine::Engine
+/// [`Engine::describe()`]: crate::engine::Engine::describe()
+/// [`EngineHandle::describe()`]: crate::engine::EngineHandle::describe()
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EngineDescription {
+    /// See [`InputConnector::describe()`](crate::interface::InputConnector::describe()).
+    pub input: Option<String>,
+    /// See [`OutputConnector::describe()`](crate::interface::OutputConnector::describe()).
+    pub output: Option<String>,
+    pub services: Vec<ServiceDescription>,
+    /// Command of each alias.
+    pub aliases: BTreeMap<String, String>,
+    /// See [`Engine::default_service()`](crate::engine::Engine::default_service()).
+    pub default_service: Option<String>,
+    pub language: Option<String>,
+    pub timezone: Option<String>,
+    pub user_languages: BTreeMap<String, String>,
+    pub operator: Option<String>,
+    pub deadline_millis: Option<u64>,
+    pub dry_run: bool,
+    pub attachment_checksums: bool,
+    pub ack_mode: AckMode,
+    /// Pairs of component and the component it depends on.
+    pub dependencies: Vec<(String, String)>,
+    pub maintenance_windows: usize,
+    pub clustered: bool,
+    pub verified_users: bool,
+}
+
+/// Description of a service registered in an engine. See [`EngineDescription`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServiceDescription {
+    /// Name the service was registered with.
+    pub name: String,
+    /// See [`Service::describe()`](crate::interface::Service::describe()).
+    pub kind: String,
+    /// Users allowed to use the service, or `None` if all of them are allowed.
+    pub whitelist: Option<Vec<String>>,
+}
@@ -0,0 +1,302 @@
+use crate::channel::{ChannelEnd, ClosedChannel};
+use crate::message::Message;
+
+use tokio::sync::broadcast;
+
+use std::fmt;
+
+/// Connector side an [`Event`] is related to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectorKind {
+    Input,
+    Output,
+}
+
+/// Reason why a connector or a service stopped running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// It finished by itself returning `Ok(())`.
+    Finished,
+
+    /// It finished because some of its channels were closed.
+    Disconnected,
+
+    /// It crashed with a panic.
+    Panicked,
+
+    /// It finished returning an [`Error`](crate::Error) other than a closed channel.
+    Failed,
+}
+
+/// Reason why a message was dropped by the engine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropReason {
+    /// The message was rejected by the filter set by [`Engine::filter_input()`].
+    ///
+    /// [`Engine::filter_input()`]: crate::engine::Engine::filter_input()
+    Filtered,
+
+    /// There is no service registered with the message service name.
+    UnknownService,
+
+    /// The user is not in the whitelist of the service.
+    NotAllowed,
+
+    /// The service is no longer running.
+    ServiceDown,
+
+    /// The user has not been verified yet. See [`Engine::verify_users()`].
+    ///
+    /// [`Engine::verify_users()`]: crate::engine::Engine::verify_users()
+    Unverified,
+}
+
+/// Result of delivering a message, reported by the output connectors
+/// with [`Receiver::report()`].
+///
+/// [`Receiver::report()`]: crate::channel::Receiver::report()
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeliveryReport {
+    /// The message reached the user.
+    Delivered { user: String, service_name: String },
+
+    /// The message could not be delivered.
+    Failed {
+        message: Box<Message>,
+        error: String,
+    },
+}
+
+impl DeliveryReport {
+    /// Report of a delivered message.
+    pub fn delivered(message: &Message) -> Self {
+        DeliveryReport::Delivered {
+            user: message.user.clone(),
+            service_name: message.service_name.clone(),
+        }
+    }
+
+    /// Report of a message that could not be delivered because of `error`.
+    pub fn failed(message: Message, error: impl fmt::Display) -> Self {
+        DeliveryReport::Failed {
+            message: Box::new(message),
+            error: error.to_string(),
+        }
+    }
+}
+
+/// Lifecycle notification emitted by the engine.
+/// They can be received from [`EngineHandle::events()`].
+///
+/// [`EngineHandle::events()`]: crate::engine::EngineHandle::events()
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// The connector was loaded and it is running.
+    ConnectorConnected { connector: ConnectorKind },
+
+    /// The connector is no longer running.
+    ConnectorDisconnected {
+        connector: ConnectorKind,
+        reason: StopReason,
+    },
+
+    /// The service was loaded and it is running.
+    ServiceStarted { name: String },
+
+    /// The service is no longer running.
+    /// A [`StopReason::Panicked`] reason means the service crashed.
+    ServiceStopped { name: String, reason: StopReason },
+
+    /// An incoming message was not delivered to any service.
+    MessageDropped {
+        user: String,
+        service_name: String,
+        reason: DropReason,
+    },
+
+    /// The service did not reply to the user before the deadline.
+    /// See [`Engine::deadline()`].
+    ///
+    /// [`Engine::deadline()`]: crate::engine::Engine::deadline()
+    DeadlineExpired { user: String, service_name: String },
+
+    /// An outgoing message could not be delivered.
+    DeliveryFailed { user: String, service_name: String },
+
+    /// The output connector confirmed that a message was delivered.
+    /// See [`Receiver::report()`].
+    ///
+    /// [`Receiver::report()`]: crate::channel::Receiver::report()
+    MessageDelivered { user: String, service_name: String },
+
+    /// The output connector failed to deliver a message.
+    /// It carries the message, so it can be retried or stored as a dead letter.
+    /// A [`Event::DeliveryFailed`] is also emitted. See [`Receiver::report()`].
+    ///
+    /// [`Receiver::report()`]: crate::channel::Receiver::report()
+    DeliveryError {
+        message: Box<Message>,
+        error: String,
+    },
+
+    /// The connector could not authenticate against its server.
+    AuthFailed {
+        connector: ConnectorKind,
+        error: String,
+    },
+
+    /// An outgoing message was not delivered because the engine runs in dry-run mode.
+    /// See [`Engine::dry_run()`].
+    ///
+    /// [`Engine::dry_run()`]: crate::engine::Engine::dry_run()
+    DeliverySuppressed { message: Box<Message> },
+
+    /// The connector switched to another one of its list, `active` being its position
+    /// (`0` for the primary one). See [`FailoverInput`] and [`FallbackOutput`].
+    ///
+    /// [`FailoverInput`]: crate::connectors::FailoverInput
+    /// [`FallbackOutput`]: crate::connectors::FallbackOutput
+    ConnectorFailover {
+        connector: ConnectorKind,
+        active: usize,
+    },
+
+    /// The user replied to the verification challenge with the right code.
+    /// See [`Engine::verify_users()`].
+    ///
+    /// [`Engine::verify_users()`]: crate::engine::Engine::verify_users()
+    UserVerified { user: String },
+}
+
+impl fmt::Display for ConnectorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectorKind::Input => write!(f, "input"),
+            ConnectorKind::Output => write!(f, "output"),
+        }
+    }
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::Finished => write!(f, "finished"),
+            StopReason::Disconnected => write!(f, "disconnected"),
+            StopReason::Panicked => write!(f, "panicked"),
+            StopReason::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+impl fmt::Display for DropReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DropReason::Filtered => write!(f, "filtered"),
+            DropReason::UnknownService => write!(f, "unknown service"),
+            DropReason::NotAllowed => write!(f, "user not allowed"),
+            DropReason::ServiceDown => write!(f, "service down"),
+            DropReason::Unverified => write!(f, "user not verified"),
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::ConnectorConnected { connector } => {
+                write!(f, "The {} connector is connected", connector)
+            }
+            Event::ConnectorDisconnected { connector, reason } => {
+                write!(f, "The {} connector is down ({})", connector, reason)
+            }
+            Event::ServiceStarted { name } => write!(f, "The service '{}' started", name),
+            Event::ServiceStopped { name, reason } => {
+                write!(f, "The service '{}' is down ({})", name, reason)
+            }
+            Event::MessageDropped {
+                user,
+                service_name,
+                reason,
+            } => write!(
+                f,
+                "Message from '{}' for service '{}' dropped ({})",
+                user, service_name, reason
+            ),
+            Event::DeadlineExpired { user, service_name } => write!(
+                f,
+                "Service '{}' did not reply to '{}' before the deadline",
+                service_name, user
+            ),
+            Event::DeliveryFailed { user, service_name } => write!(
+                f,
+                "Message from service '{}' for '{}' not delivered",
+                service_name, user
+            ),
+            Event::MessageDelivered { user, service_name } => write!(
+                f,
+                "Message from service '{}' for '{}' delivered",
+                service_name, user
+            ),
+            Event::DeliveryError { message, error } => write!(
+                f,
+                "Message from service '{}' for '{}' not delivered: {}",
+                message.service_name, message.user, error
+            ),
+            Event::AuthFailed { connector, error } => {
+                write!(
+                    f,
+                    "The {} connector failed to authenticate: {}",
+                    connector, error
+                )
+            }
+            Event::DeliverySuppressed { message } => write!(
+                f,
+                "Message from service '{}' for '{}' not delivered (dry run)",
+                message.service_name, message.user
+            ),
+            Event::ConnectorFailover {
+                connector,
+                active: 0,
+            } => {
+                write!(
+                    f,
+                    "The {} connector switched back to the primary",
+                    connector
+                )
+            }
+            Event::ConnectorFailover { connector, active } => {
+                write!(
+                    f,
+                    "The {} connector switched to the fallback {}",
+                    connector, active
+                )
+            }
+            Event::UserVerified { user } => write!(f, "The user '{}' has been verified", user),
+        }
+    }
+}
+
+/// Receiver of the [`Event`]s emitted by the engine.
+/// Created by [`EngineHandle::events()`].
+///
+/// [`EngineHandle::events()`]: crate::engine::EngineHandle::events()
+pub struct Events(pub(crate) broadcast::Receiver<Event>);
+
+impl Events {
+    /// Receive asynchronously the next event.
+    ///
+    /// If the receiver is not fast enough reading events, the oldest ones are discarded.
+    pub async fn recv(&mut self) -> Result<Event, ClosedChannel> {
+        loop {
+            match self.0.recv().await {
+                Ok(event) => break Ok(event),
+                Err(broadcast::error::RecvError::Lagged(lost)) => {
+                    log::warn!("{} events lost by a slow events receiver", lost)
+                }
+                Err(broadcast::error::RecvError::Closed) => {
+                    break Err(ClosedChannel::new(ChannelEnd::Sender))
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,319 @@
+use super::ack::Acks;
+use super::description::EngineDescription;
+use super::event::{DeliveryReport, Event, Events, StopReason};
+use super::memory::MemoryBudget;
+use super::readiness::Readiness;
+use super::temp::TempDirs;
+use super::whitelist::Whitelists;
+use crate::cache::Cache;
+use crate::services::JobRegistry;
+use crate::state::KeyValueStore;
+
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use std::future::Future;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+const EVENTS_CAPACITY: usize = 128;
+
+tokio::task_local! {
+    static CURRENT_ENGINE: EngineHandle;
+}
+
+/// Handle to interact with an [`Engine`] from outside of it.
+/// It can be obtained by [`Engine::handle()`] before running the engine
+/// and be used while the engine is running.
+///
+/// [`Engine`]: crate::engine::Engine
+/// [`Engine::handle()`]: crate::engine::Engine::handle()
+#[derive(Clone)]
+pub struct EngineHandle {
+    events: broadcast::Sender<Event>,
+    shutdown: CancellationToken,
+    dry_run: Arc<AtomicBool>,
+    whitelists: Arc<Whitelists>,
+    acks: Arc<Acks>,
+    memory: Arc<MemoryBudget>,
+    readiness: Arc<Readiness>,
+    state: Arc<Mutex<Option<Arc<dyn KeyValueStore>>>>,
+    jobs: Arc<JobRegistry>,
+    description: Arc<Mutex<Option<EngineDescription>>>,
+    temp_dirs: Arc<TempDirs>,
+    cache: Arc<Cache>,
+}
+
+impl Default for EngineHandle {
+    fn default() -> Self {
+        EngineHandle {
+            events: broadcast::channel(EVENTS_CAPACITY).0,
+            shutdown: CancellationToken::new(),
+            dry_run: Arc::default(),
+            whitelists: Arc::default(),
+            acks: Arc::default(),
+            memory: Arc::default(),
+            readiness: Arc::default(),
+            state: Arc::default(),
+            jobs: Arc::default(),
+            description: Arc::default(),
+            temp_dirs: Arc::default(),
+            cache: Arc::default(),
+        }
+    }
+}
+
+impl EngineHandle {
+    /// Subscribe to the [`Event`]s emitted by the engine from now.
+    ///
+    /// # Example
+    /// ```rust no_run
+    /// use service_io::connectors::{DebugStdout, UserStdin};
+    /// use service_io::engine::{Engine, Event};
+    /// use service_io::services::Echo;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let engine = Engine::default()
+    ///         .input(UserStdin("user"))
+    ///         .output(DebugStdout)
+    ///         .add_service("s-echo", Echo);
+    ///
+    ///     let mut events = engine.handle().events();
+    ///     tokio::spawn(async move {
+    ///         while let Ok(event) = events.recv().await {
+    ///             if let Event::ServiceStopped { name, reason } = event {
+    ///                 println!("Alert! service {} down: {:?}", name, reason);
+    ///             }
+    ///         }
+    ///     });
+    ///
+    ///     engine.run().await;
+    /// }
+    /// ```
+    pub fn events(&self) -> Events {
+        Events(self.events.subscribe())
+    }
+
+    /// Stop the engine.
+    /// [`Engine::run()`] will finish, and the services will be notified through
+    /// [`Receiver::cancellation_token()`] to abort their pending work.
+    ///
+    /// [`Engine::run()`]: crate::engine::Engine::run()
+    /// [`Receiver::cancellation_token()`]: crate::channel::Receiver::cancellation_token()
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
+
+    /// Waits until the engine is ready: the connectors are connected and the services started.
+    /// See [`Engine::depends_on()`].
+    ///
+    /// Returns `false` if any of them failed to start, or the engine finished before.
+    ///
+    /// # Example
+    /// ```rust no_run
+    /// use service_io::connectors::{ImapClient, SmtpClient};
+    /// use service_io::engine::Engine;
+    /// use service_io::services::Echo;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let engine = Engine::default()
+    ///         .input(ImapClient::default() /* ... */)
+    ///         .output(SmtpClient::default() /* ... */)
+    ///         .add_service("s-echo", Echo);
+    ///
+    ///     let handle = engine.handle();
+    ///     tokio::spawn(async move {
+    ///         match handle.ready().await {
+    ///             true => println!("Ready to receive emails"),
+    ///             false => println!("The engine could not start"),
+    ///         }
+    ///     });
+    ///
+    ///     engine.run().await;
+    /// }
+    /// ```
+    ///
+    /// [`Engine::depends_on()`]: crate::engine::Engine::depends_on()
+    pub async fn ready(&self) -> bool {
+        tokio::select! {
+            ready = self.readiness.wait_all() => ready,
+            _ = self.shutdown.cancelled() => false,
+        }
+    }
+
+    /// Returns `true` if the engine runs in dry-run mode.
+    /// See [`Engine::dry_run()`].
+    ///
+    /// [`Engine::dry_run()`]: crate::engine::Engine::dry_run()
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run.load(Ordering::Relaxed)
+    }
+
+    /// Users allowed to use the service, or `None` if the service has no whitelist
+    /// (all users are allowed).
+    /// See [`Engine::add_service_for()`].
+    ///
+    /// [`Engine::add_service_for()`]: crate::engine::Engine::add_service_for()
+    pub fn whitelist(&self, service_name: &str) -> Option<Vec<String>> {
+        self.whitelists.users(service_name)
+    }
+
+    /// Add a user to the whitelist of a service while the engine is running.
+    /// The change is persisted if the engine has a [`Engine::whitelist_store()`].
+    ///
+    /// Returns `false` if the service has no whitelist or the user was already in it.
+    ///
+    /// [`Engine::whitelist_store()`]: crate::engine::Engine::whitelist_store()
+    pub async fn allow_user(&self, service_name: &str, user: &str) -> io::Result<bool> {
+        self.whitelists
+            .update(service_name, |whitelist| whitelist.insert(user.into()))
+            .await
+    }
+
+    /// Remove a user from the whitelist of a service while the engine is running.
+    /// The change is persisted if the engine has a [`Engine::whitelist_store()`].
+    ///
+    /// Returns `false` if the service has no whitelist or the user was not in it.
+    ///
+    /// [`Engine::whitelist_store()`]: crate::engine::Engine::whitelist_store()
+    pub async fn disallow_user(&self, service_name: &str, user: &str) -> io::Result<bool> {
+        self.whitelists
+            .update(service_name, |whitelist| whitelist.remove(user))
+            .await
+    }
+
+    /// Description of the configuration of the running engine, with the current whitelists.
+    /// Returns `None` if the engine is not running yet.
+    /// See [`Engine::describe()`].
+    ///
+    /// [`Engine::describe()`]: crate::engine::Engine::describe()
+    pub fn describe(&self) -> Option<EngineDescription> {
+        let mut description = self.description.lock().unwrap().clone()?;
+        for service in &mut description.services {
+            service.whitelist = self.whitelists.users(&service.name);
+        }
+        Some(description)
+    }
+
+    pub(crate) fn set_description(&self, description: EngineDescription) {
+        *self.description.lock().unwrap() = Some(description);
+    }
+
+    pub(crate) fn whitelists(&self) -> &Whitelists {
+        &self.whitelists
+    }
+
+    pub(crate) fn acks(&self) -> &Acks {
+        &self.acks
+    }
+
+    pub(crate) fn memory(&self) -> &MemoryBudget {
+        &self.memory
+    }
+
+    pub(crate) fn readiness(&self) -> &Readiness {
+        &self.readiness
+    }
+
+    /// Store of the state of the services. See [`Engine::state_store()`].
+    ///
+    /// [`Engine::state_store()`]: crate::engine::Engine::state_store()
+    pub(crate) fn state_store(&self) -> Option<Arc<dyn KeyValueStore>> {
+        self.state.lock().unwrap().clone()
+    }
+
+    pub(crate) fn jobs(&self) -> &Arc<JobRegistry> {
+        &self.jobs
+    }
+
+    /// Cache shared by the services. See [`Context::cache()`].
+    ///
+    /// [`Context::cache()`]: crate::interface::Context::cache()
+    pub fn cache(&self) -> &Arc<Cache> {
+        &self.cache
+    }
+
+    pub(crate) fn temp_dirs(&self) -> &Arc<TempDirs> {
+        &self.temp_dirs
+    }
+
+    pub(crate) fn set_state_store(&self, store: Arc<dyn KeyValueStore>) {
+        *self.state.lock().unwrap() = Some(store);
+    }
+
+    pub(crate) fn set_dry_run(&self, enabled: bool) {
+        self.dry_run.store(enabled, Ordering::Relaxed);
+    }
+
+    pub(crate) fn shutdown_token(&self) -> &CancellationToken {
+        &self.shutdown
+    }
+
+    pub(crate) fn emit(&self, event: Event) {
+        log::trace!("Event: {:?}", event);
+        // Error only means that there are no receivers listening
+        self.events.send(event).ok();
+    }
+
+    /// Collect the result of delivering a message as events.
+    pub(crate) fn report(&self, report: DeliveryReport) {
+        match report {
+            DeliveryReport::Delivered { user, service_name } => {
+                self.emit(Event::MessageDelivered { user, service_name })
+            }
+            DeliveryReport::Failed { message, error } => {
+                self.emit(Event::DeliveryFailed {
+                    user: message.user.clone(),
+                    service_name: message.service_name.clone(),
+                });
+                self.emit(Event::DeliveryError { message, error });
+            }
+        }
+    }
+
+    /// Handle of the engine that is running the current connector or service task.
+    /// Used by the connectors to report events they are aware of.
+    pub(crate) fn current() -> Option<EngineHandle> {
+        CURRENT_ENGINE.try_with(|engine| engine.clone()).ok()
+    }
+
+    /// Run a connector or service task allowing it to get this handle by
+    /// [`EngineHandle::current()`].
+    pub(crate) async fn scope<F: Future>(self, task: F) -> F::Output {
+        CURRENT_ENGINE.scope(self, task).await
+    }
+
+    /// Spawn a task of a service wrapping other services (i.e. one of its inner services)
+    /// in the current engine, if any, so they share its state, jobs, temporary directories
+    /// and cache.
+    /// If the task panics, it is reported as a [`Event::ServiceStopped`] named `name`.
+    pub(crate) fn spawn<F>(name: impl Into<String>, task: F) -> JoinHandle<()>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let name = name.into();
+        let engine = Self::current();
+        let task = match engine.clone() {
+            Some(engine) => tokio::spawn(engine.scope(task)),
+            None => tokio::spawn(task),
+        };
+
+        tokio::spawn(async move {
+            match task.await {
+                Err(err) if err.is_panic() => {
+                    log::error!("{} down (panicked)", name);
+                    if let Some(engine) = engine {
+                        let reason = StopReason::Panicked;
+                        engine.emit(Event::ServiceStopped { name, reason });
+                    }
+                }
+                _ => (),
+            }
+        })
+    }
+}
@@ -0,0 +1,141 @@
+use crate::message::Message;
+
+use chrono::{DateTime, NaiveTime, Utc};
+use chrono_tz::Tz;
+
+use std::collections::{HashSet, VecDeque};
+use std::time::Duration;
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Daily period of time during which the engine only runs some services,
+/// i.e. to not disturb a nightly backup.
+/// See [`Engine::maintenance()`].
+///
+/// # Example
+/// ```rust
+/// use service_io::engine::MaintenanceWindow;
+///
+/// use chrono::NaiveTime;
+///
+/// // From 23:30 to 01:00 in Madrid, only s-admin runs.
+/// let window = MaintenanceWindow::daily(
+///     NaiveTime::from_hms_opt(23, 30, 0).unwrap(),
+///     NaiveTime::from_hms_opt(1, 0, 0).unwrap(),
+/// )
+/// .timezone(chrono_tz::Europe::Madrid)
+/// .allow("s-admin");
+/// ```
+///
+/// [`Engine::maintenance()`]: crate::engine::Engine::maintenance()
+#[derive(Debug, Clone)]
+pub struct MaintenanceWindow {
+    start: NaiveTime,
+    end: NaiveTime,
+    timezone: Tz,
+    allowed: HashSet<String>,
+}
+
+impl MaintenanceWindow {
+    /// Window from `start` to `end` each day. If `end` is before `start`,
+    /// the window finishes the next day.
+    pub fn daily(start: NaiveTime, end: NaiveTime) -> Self {
+        Self {
+            start,
+            end,
+            timezone: Tz::UTC,
+            allowed: HashSet::new(),
+        }
+    }
+
+    /// Timezone of the `start` and `end` times. By default, UTC.
+    pub fn timezone(mut self, timezone: Tz) -> Self {
+        self.timezone = timezone;
+        self
+    }
+
+    /// Allow a service to run during the window.
+    /// If no service is allowed, the input connector is paused.
+    pub fn allow(mut self, service_name: impl Into<String>) -> Self {
+        self.allowed.insert(service_name.into());
+        self
+    }
+
+    /// Time until the window ends, or `None` if it is not active at `now`.
+    fn remaining(&self, now: DateTime<Utc>) -> Option<Duration> {
+        let time = now.with_timezone(&self.timezone).time();
+        let active = match self.start <= self.end {
+            true => self.start <= time && time < self.end,
+            false => self.start <= time || time < self.end,
+        };
+        if !active {
+            return None;
+        }
+        let remaining = (self.end - time).to_std().unwrap_or_else(|_| {
+            // The window finishes the next day.
+            DAY - (time - self.end).to_std().unwrap_or_default()
+        });
+        Some(remaining)
+    }
+}
+
+/// Holds the messages for the services not allowed during the active maintenance windows,
+/// until they end.
+pub(crate) struct Maintenance {
+    windows: Vec<MaintenanceWindow>,
+    held: VecDeque<Message>,
+}
+
+impl Maintenance {
+    pub fn new(windows: Vec<MaintenanceWindow>) -> Self {
+        Self {
+            windows,
+            held: VecDeque::new(),
+        }
+    }
+
+    fn active(&self) -> impl Iterator<Item = &MaintenanceWindow> {
+        let now = Utc::now();
+        self.windows
+            .iter()
+            .filter(move |window| window.remaining(now).is_some())
+    }
+
+    /// Time until the active windows end, or `None` if there is no active window.
+    pub fn remaining(&self) -> Option<Duration> {
+        let now = Utc::now();
+        self.windows
+            .iter()
+            .filter_map(|window| window.remaining(now))
+            .min()
+    }
+
+    /// The input is paused while a window without allowed services is active.
+    pub fn pauses_input(&self) -> bool {
+        self.active().any(|window| window.allowed.is_empty())
+    }
+
+    /// Returns the message if its service can run now, otherwise it is held.
+    pub fn hold(&mut self, message: Message) -> Option<Message> {
+        let allowed = self
+            .active()
+            .all(|window| window.allowed.contains(&message.service_name));
+        if allowed {
+            return Some(message);
+        }
+        log::info!(
+            "Hold message for service '{}' until the maintenance ends",
+            message.service_name
+        );
+        self.held.push_back(message);
+        None
+    }
+
+    /// Next held message, once no window is active.
+    pub fn release(&mut self) -> Option<Message> {
+        match self.remaining() {
+            Some(_) => None,
+            None => self.held.pop_front(),
+        }
+    }
+}
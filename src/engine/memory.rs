@@ -0,0 +1,53 @@
+use crate::message::Message;
+
+use tokio::sync::Notify;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Bytes of the messages queued in the services and in the output connector.
+/// See [`Engine::memory_budget()`].
+///
+/// [`Engine::memory_budget()`]: crate::engine::Engine::memory_budget()
+pub(crate) struct MemoryBudget {
+    limit: AtomicUsize,
+    used: AtomicUsize,
+    released: Notify,
+}
+
+impl Default for MemoryBudget {
+    fn default() -> Self {
+        MemoryBudget {
+            limit: AtomicUsize::new(usize::MAX),
+            used: AtomicUsize::default(),
+            released: Notify::new(),
+        }
+    }
+}
+
+impl MemoryBudget {
+    pub fn set_limit(&self, bytes: usize) {
+        self.limit.store(bytes, Ordering::Relaxed);
+    }
+
+    pub fn is_limited(&self) -> bool {
+        self.limit.load(Ordering::Relaxed) != usize::MAX
+    }
+
+    pub fn acquire(&self, message: &Message) {
+        self.used.fetch_add(message.size(), Ordering::Relaxed);
+    }
+
+    pub fn release(&self, message: &Message) {
+        self.used.fetch_sub(message.size(), Ordering::Relaxed);
+        self.released.notify_one();
+    }
+
+    pub fn exceeded(&self) -> bool {
+        self.used.load(Ordering::Relaxed) >= self.limit.load(Ordering::Relaxed)
+    }
+
+    /// Wait until some memory is released.
+    pub async fn released(&self) {
+        self.released.notified().await
+    }
+}
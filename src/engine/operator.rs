@@ -0,0 +1,57 @@
+use super::event::{Event, StopReason};
+use crate::message::Message;
+
+/// Service name of the messages the engine sends to the operator.
+pub const OPERATOR_SERVICE_NAME: &str = "service-io";
+
+/// Number of delivery failures needed to notify the operator.
+const DELIVERY_FAILURES_TO_NOTIFY: usize = 3;
+
+/// Transforms the relevant engine events into messages for the operator.
+pub(crate) struct Operator {
+    user: String,
+    delivery_failures: usize,
+}
+
+impl Operator {
+    pub fn new(user: String) -> Operator {
+        Operator {
+            user,
+            delivery_failures: 0,
+        }
+    }
+
+    pub fn notification(&mut self, event: &Event) -> Option<Message> {
+        let notify = match event {
+            Event::ConnectorDisconnected { reason, .. } => {
+                matches!(reason, StopReason::Panicked | StopReason::Failed)
+            }
+            Event::ServiceStopped { reason, .. } => *reason == StopReason::Panicked,
+            Event::AuthFailed { .. } => true,
+            Event::ConnectorFailover { .. } => true,
+            Event::DeliveryFailed { user, service_name } => {
+                // Avoid notifying about the failures of the notifications themselves
+                if *user == self.user && service_name == OPERATOR_SERVICE_NAME {
+                    false
+                } else {
+                    self.delivery_failures += 1;
+                    if self.delivery_failures == DELIVERY_FAILURES_TO_NOTIFY {
+                        self.delivery_failures = 0;
+                        true
+                    } else {
+                        false
+                    }
+                }
+            }
+            _ => false,
+        };
+
+        notify.then(|| {
+            Message::default()
+                .user(self.user.clone())
+                .service_name(OPERATOR_SERVICE_NAME)
+                .args(["alert"])
+                .body(event.to_string())
+        })
+    }
+}
@@ -0,0 +1,122 @@
+use tokio::sync::watch;
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// Part of the engine whose startup can be waited.
+/// See [`Engine::depends_on()`].
+///
+/// [`Engine::depends_on()`]: crate::engine::Engine::depends_on()
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Component {
+    /// The input connector, ready once its [`InputConnector::connect()`] finished.
+    ///
+    /// [`InputConnector::connect()`]: crate::interface::InputConnector::connect()
+    Input,
+
+    /// The output connector, ready once its [`OutputConnector::connect()`] finished.
+    ///
+    /// [`OutputConnector::connect()`]: crate::interface::OutputConnector::connect()
+    Output,
+
+    /// The service registered with this name, ready once its [`Service::on_start()`] finished.
+    ///
+    /// [`Service::on_start()`]: crate::interface::Service::on_start()
+    Service(String),
+}
+
+impl fmt::Display for Component {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Component::Input => write!(f, "input connector"),
+            Component::Output => write!(f, "output connector"),
+            Component::Service(name) => write!(f, "service '{}'", name),
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    /// Components of the running engine, unknown until it runs.
+    expected: Option<Vec<Component>>,
+    /// Components that finished their startup, and whether they succeeded.
+    started: HashMap<Component, bool>,
+}
+
+impl State {
+    fn failed(&self) -> bool {
+        self.started.values().any(|ready| !ready)
+    }
+
+    fn completed(&self) -> bool {
+        self.expected
+            .as_ref()
+            .is_some_and(|expected| expected.iter().all(|c| self.started.contains_key(c)))
+    }
+}
+
+/// Startup of the components of the engine.
+pub(crate) struct Readiness(watch::Sender<State>);
+
+impl Default for Readiness {
+    fn default() -> Self {
+        Readiness(watch::channel(State::default()).0)
+    }
+}
+
+impl Readiness {
+    /// Set the components that the running engine starts.
+    pub fn expect(&self, components: Vec<Component>) {
+        self.0
+            .send_modify(|state| state.expected = Some(components));
+    }
+
+    /// Record the end of the startup of a component.
+    pub fn set(&self, component: Component, ready: bool) {
+        self.0.send_modify(|state| {
+            state.started.insert(component, ready);
+        });
+    }
+
+    /// Waits until the component finished its startup, returning if it is ready.
+    pub async fn wait(&self, component: &Component) -> bool {
+        let mut receiver = self.0.subscribe();
+        let state = receiver
+            .wait_for(|state| state.started.contains_key(component))
+            .await;
+        state.is_ok_and(|state| state.started[component])
+    }
+
+    /// Waits until all the components are ready, or any of them failed.
+    pub async fn wait_all(&self) -> bool {
+        let mut receiver = self.0.subscribe();
+        let state = receiver
+            .wait_for(|state| state.completed() || state.failed())
+            .await;
+        state.is_ok_and(|state| !state.failed())
+    }
+}
+
+/// Returns a component that depends on itself through the `dependencies`, if any.
+pub(crate) fn find_cycle(dependencies: &HashMap<Component, Vec<Component>>) -> Option<&Component> {
+    fn visit<'a>(
+        component: &'a Component,
+        dependencies: &'a HashMap<Component, Vec<Component>>,
+        path: &mut HashSet<&'a Component>,
+    ) -> bool {
+        if !path.insert(component) {
+            return true;
+        }
+        let cycle = dependencies
+            .get(component)
+            .into_iter()
+            .flatten()
+            .any(|dependency| visit(dependency, dependencies, path));
+        path.remove(component);
+        cycle
+    }
+
+    dependencies
+        .keys()
+        .find(|component| visit(component, dependencies, &mut HashSet::new()))
+}
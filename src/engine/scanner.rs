@@ -0,0 +1,146 @@
+use crate::message::{Message, CHECKSUM_PREFIX};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+
+use std::collections::HashSet;
+
+/// Prefix of the [`Message::metadata`] keys with the notes of the scanners about an attachment,
+/// followed by the attachment name.
+pub const SCAN_NOTE_PREFIX: &str = "scan-note:";
+
+/// Decision of an [`AttachmentScanner`] about an attachment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanVerdict {
+    /// Keep the attachment unchanged.
+    Clean,
+
+    /// Remove the attachment from the message. The reason is added as a note.
+    Drop(String),
+
+    /// Replace the content of the attachment, i.e. by a cleaned version.
+    Replace(Bytes),
+
+    /// Keep the attachment unchanged, adding a note.
+    Annotate(String),
+}
+
+/// Inspects the attachments of the messages before they reach the services
+/// or the output connector, i.e. for blocking file types or running an antivirus.
+///
+/// The notes of [`ScanVerdict::Drop`] and [`ScanVerdict::Annotate`] are added to the
+/// metadata of the message under the key [`SCAN_NOTE_PREFIX`] followed by the attachment name.
+///
+/// Set it in the engine with [`Engine::attachment_scanner()`] or
+/// [`Engine::output_attachment_scanner()`].
+///
+/// [`Engine::attachment_scanner()`]: crate::engine::Engine::attachment_scanner()
+/// [`Engine::output_attachment_scanner()`]: crate::engine::Engine::output_attachment_scanner()
+#[async_trait]
+pub trait AttachmentScanner {
+    /// Scan the attachment `name` of `message`.
+    async fn scan(&self, message: &Message, name: &str, data: &Bytes) -> ScanVerdict;
+}
+
+/// Drops the attachments with any of the given file extensions, case insensitive.
+///
+/// # Example
+/// ```rust
+/// use service_io::engine::BlockExtensions;
+///
+/// let scanner = BlockExtensions::new(["exe", "bat", "js"]);
+/// ```
+pub struct BlockExtensions {
+    extensions: HashSet<String>,
+}
+
+impl BlockExtensions {
+    pub fn new(extensions: impl IntoIterator<Item = impl AsRef<str>>) -> BlockExtensions {
+        BlockExtensions {
+            extensions: extensions
+                .into_iter()
+                .map(|extension| extension.as_ref().trim_start_matches('.').to_lowercase())
+                .collect(),
+        }
+    }
+}
+
+#[async_trait]
+impl AttachmentScanner for BlockExtensions {
+    async fn scan(&self, _message: &Message, name: &str, _data: &Bytes) -> ScanVerdict {
+        match name.rsplit_once('.') {
+            Some((_, extension)) if self.extensions.contains(&extension.to_lowercase()) => {
+                ScanVerdict::Drop(format!("blocked file type '.{}'", extension))
+            }
+            _ => ScanVerdict::Clean,
+        }
+    }
+}
+
+/// Drops the attachments bigger than the given number of bytes.
+pub struct MaxAttachmentSize(pub usize);
+
+#[async_trait]
+impl AttachmentScanner for MaxAttachmentSize {
+    async fn scan(&self, _message: &Message, _name: &str, data: &Bytes) -> ScanVerdict {
+        match data.len() > self.0 {
+            true => ScanVerdict::Drop(format!("larger than {} bytes", self.0)),
+            false => ScanVerdict::Clean,
+        }
+    }
+}
+
+pub(crate) type Scanners = Vec<Box<dyn AttachmentScanner + Send + Sync>>;
+
+/// Runs the scanners in order over each attachment of the message.
+/// Once an attachment is dropped, the following scanners do not see it.
+pub(crate) async fn scan(scanners: &Scanners, mut message: Message) -> Message {
+    if scanners.is_empty() || message.attached_data.is_empty() {
+        return message;
+    }
+
+    let mut names = message.attached_data.keys().cloned().collect::<Vec<_>>();
+    names.sort();
+    for name in names {
+        for scanner in scanners {
+            let Some(data) = message.attached_data.get(&name) else {
+                break;
+            };
+            match scanner.scan(&message, &name, data).await {
+                ScanVerdict::Clean => (),
+                ScanVerdict::Drop(reason) => {
+                    log::warn!(
+                        "Drop attachment '{}' of '{}': {}",
+                        name,
+                        message.user,
+                        reason
+                    );
+                    message.attached_data.remove(&name);
+                    message.metadata.remove(&checksum_key(&name));
+                    add_note(&mut message, &name, reason);
+                }
+                ScanVerdict::Replace(data) => {
+                    message.attached_data.insert(name.clone(), data);
+                    message.metadata.remove(&checksum_key(&name));
+                }
+                ScanVerdict::Annotate(note) => add_note(&mut message, &name, note),
+            }
+        }
+    }
+    message
+}
+
+fn checksum_key(name: &str) -> String {
+    format!("{}{}", CHECKSUM_PREFIX, name)
+}
+
+fn add_note(message: &mut Message, name: &str, note: String) {
+    message
+        .metadata
+        .entry(format!("{}{}", SCAN_NOTE_PREFIX, name))
+        .and_modify(|notes| {
+            notes.push_str("; ");
+            notes.push_str(&note);
+        })
+        .or_insert(note);
+}
@@ -0,0 +1,77 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::{fs, io, process};
+
+/// Temporary directories of the requests processed by the services.
+/// They are created under a directory of the process, removed when the engine finishes.
+/// See [`Engine::temp_dir()`].
+///
+/// [`Engine::temp_dir()`]: crate::engine::Engine::temp_dir()
+pub(crate) struct TempDirs {
+    root: Mutex<PathBuf>,
+    next: AtomicU64,
+}
+
+impl Default for TempDirs {
+    fn default() -> Self {
+        TempDirs {
+            root: Mutex::new(std::env::temp_dir()),
+            next: AtomicU64::default(),
+        }
+    }
+}
+
+impl TempDirs {
+    pub fn set_root(&self, root: PathBuf) {
+        *self.root.lock().unwrap() = root;
+    }
+
+    /// Directory of this process, so several processes can share the same root.
+    fn base(&self) -> PathBuf {
+        let root = self.root.lock().unwrap();
+        root.join(format!("service-io-{}", process::id()))
+    }
+
+    /// Creates a new empty directory, removed when the returned [`TempDir`] is dropped.
+    pub async fn create(&self, service_name: &str) -> io::Result<TempDir> {
+        let name = service_name
+            .chars()
+            .map(|c| match c.is_ascii_alphanumeric() || c == '-' {
+                true => c,
+                false => '_',
+            })
+            .collect::<String>();
+        let id = self.next.fetch_add(1, Ordering::Relaxed);
+        let path = self.base().join(format!("{}-{}", name, id));
+        tokio::fs::create_dir_all(&path).await?;
+        Ok(TempDir(path))
+    }
+
+    /// Removes the directory of this process, along with the directories not dropped yet.
+    pub fn clear(&self) {
+        let base = self.base();
+        match fs::remove_dir_all(&base) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                log::warn!("Could not remove '{}': {}", base.display(), err);
+            }
+            _ => (),
+        }
+    }
+}
+
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_dir_all(&self.0) {
+            log::warn!("Could not remove '{}': {}", self.0.display(), err);
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use super::event::{DropReason, Event};
+use super::handle::EngineHandle;
+use crate::i18n;
+use crate::message::{wire, Message};
+use crate::state::KeyValueStore;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashSet;
+use std::io;
+use std::sync::Arc;
+
+const VERIFIED_PREFIX: &str = "verified/";
+const PENDING_PREFIX: &str = "pending/";
+
+/// Opt-in flow for the users writing for the first time, to protect an engine that is
+/// publicly reachable (e.g. an email address) from spoofed or unwanted senders.
+///
+/// When a message arrives from a user not verified yet, the engine holds it and replies with
+/// a challenge containing a code. Once the user replies with a message starting with that code,
+/// the user is marked as verified and the held message is delivered to its service.
+/// The messages received meanwhile are not delivered and the challenge is sent again.
+/// After too many failed attempts the code is replaced by a new one.
+///
+/// The verified users and pending challenges are kept in a [`KeyValueStore`].
+/// Set it in the engine with [`Engine::verify_users()`].
+///
+/// # Example
+/// ```rust no_run
+/// use service_io::connectors::{ImapClient, SmtpClient};
+/// use service_io::engine::{Engine, Verification};
+/// use service_io::services::Echo;
+/// use service_io::state::FileStore;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     Engine::default()
+///         .input(ImapClient::default() /* ... */)
+///         .output(SmtpClient::default() /* ... */)
+///         .verify_users(
+///             Verification::new(FileStore::new("verification.json")).trust(["me@domain.com"]),
+///         )
+///         .add_service("s-echo", Echo)
+///         .run()
+///         .await;
+/// }
+/// ```
+///
+/// [`Engine::verify_users()`]: crate::engine::Engine::verify_users()
+pub struct Verification {
+    store: Arc<dyn KeyValueStore>,
+    trusted: HashSet<String>,
+    code_length: usize,
+    max_attempts: u32,
+}
+
+/// Challenge sent to a user, waiting for the code.
+#[derive(Serialize, Deserialize)]
+struct Pending {
+    code: String,
+    message: String,
+    #[serde(default)]
+    failed_attempts: u32,
+}
+
+/// Result of checking an input message.
+#[derive(Default)]
+pub(crate) struct Checked {
+    /// Message to deliver to its service.
+    pub deliver: Option<Message>,
+
+    /// Message to send to the user through the output connector.
+    pub reply: Option<Message>,
+}
+
+impl Verification {
+    /// Keep the verification state in `store`.
+    pub fn new(store: impl KeyValueStore + 'static) -> Self {
+        Self {
+            store: Arc::new(store),
+            trusted: HashSet::new(),
+            code_length: 6,
+            max_attempts: 3,
+        }
+    }
+
+    /// Users that never need to be verified.
+    pub fn trust<S: Into<String>>(mut self, users: impl IntoIterator<Item = S>) -> Self {
+        self.trusted.extend(users.into_iter().map(|s| s.into()));
+        self
+    }
+
+    /// Number of digits of the codes. By default 6.
+    pub fn code_length(mut self, length: usize) -> Self {
+        self.code_length = length;
+        self
+    }
+
+    /// Failed replies allowed before the code is replaced by a new one. By default 3.
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = attempts.max(1);
+        self
+    }
+
+    fn new_code(&self) -> String {
+        let mut rng = rand::thread_rng();
+        (0..self.code_length)
+            .map(|_| char::from(b'0' + rng.gen_range(0..10)))
+            .collect()
+    }
+
+    /// Decide what to do with an input message.
+    /// If the state can not be accessed, the message is not delivered.
+    pub(crate) async fn check(&self, message: Message, engine: &EngineHandle) -> Checked {
+        if self.trusted.contains(&message.user) {
+            return Checked {
+                deliver: Some(message),
+                reply: None,
+            };
+        }
+
+        let user = message.user.clone();
+        let service_name = message.service_name.clone();
+        match self.process(message).await {
+            Ok(checked) => {
+                match &checked.deliver {
+                    None => engine.emit(Event::MessageDropped {
+                        user,
+                        service_name,
+                        reason: DropReason::Unverified,
+                    }),
+                    Some(_) if checked.reply.is_some() => {
+                        log::info!("User '{}' verified", user);
+                        engine.emit(Event::UserVerified { user });
+                    }
+                    Some(_) => (),
+                }
+                checked
+            }
+            Err(err) => {
+                log::error!("Verification of '{}' failed: {}", user, err);
+                engine.emit(Event::MessageDropped {
+                    user,
+                    service_name,
+                    reason: DropReason::Unverified,
+                });
+                Checked::default()
+            }
+        }
+    }
+
+    async fn process(&self, message: Message) -> io::Result<Checked> {
+        let verified_key = format!("{}{}", VERIFIED_PREFIX, message.user);
+        if self.store.get(&verified_key).await?.is_some() {
+            return Ok(Checked {
+                deliver: Some(message),
+                reply: None,
+            });
+        }
+
+        let pending_key = format!("{}{}", PENDING_PREFIX, message.user);
+        let mut pending = match self.store.get(&pending_key).await? {
+            Some(value) => serde_json::from_str::<Pending>(&value)?,
+            None => {
+                let pending = Pending {
+                    code: self.new_code(),
+                    message: wire::to_json(&message),
+                    failed_attempts: 0,
+                };
+                log::info!("Verification requested for '{}'", message.user);
+                self.store
+                    .set(&pending_key, serde_json::to_string(&pending)?)
+                    .await?;
+                return Ok(Checked {
+                    deliver: None,
+                    reply: Some(challenge(&message, &pending.code)),
+                });
+            }
+        };
+
+        if !starts_with_code(&message, &pending.code) {
+            pending.failed_attempts += 1;
+            if pending.failed_attempts >= self.max_attempts {
+                log::info!("Verification code of '{}' replaced", message.user);
+                pending.code = self.new_code();
+                pending.failed_attempts = 0;
+            }
+            self.store
+                .set(&pending_key, serde_json::to_string(&pending)?)
+                .await?;
+            return Ok(Checked {
+                deliver: None,
+                reply: Some(challenge(&message, &pending.code)),
+            });
+        }
+
+        self.store.set(&verified_key, String::new()).await?;
+        self.store.remove(&pending_key).await?;
+
+        let reply = Message::response(&message).body(i18n::text(&message, "verification-done"));
+        Ok(Checked {
+            deliver: Some(wire::from_json(&pending.message)?),
+            reply: Some(reply),
+        })
+    }
+}
+
+fn challenge(message: &Message, code: &str) -> Message {
+    Message::response(message).body(i18n::text_with(message, "verification-required", [code]))
+}
+
+/// The code must be the first word of the reply, or its first argument.
+fn starts_with_code(message: &Message, code: &str) -> bool {
+    message.args.first().map(String::as_str) == Some(code)
+        || message.body.split_whitespace().next() == Some(code)
+}
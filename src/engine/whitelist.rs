@@ -0,0 +1,90 @@
+use crate::state::KeyValueStore;
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Whitelists of the services, shared by the engine and its handles
+/// so they can be modified at runtime.
+#[derive(Default)]
+pub(crate) struct Whitelists {
+    lists: RwLock<HashMap<String, HashSet<String>>>,
+    store: Mutex<Option<Arc<dyn KeyValueStore>>>,
+}
+
+impl Whitelists {
+    pub fn set(&self, service_name: String, users: HashSet<String>) {
+        self.lists.write().unwrap().insert(service_name, users);
+    }
+
+    pub fn set_store(&self, store: Arc<dyn KeyValueStore>) {
+        *self.store.lock().unwrap() = Some(store);
+    }
+
+    /// Services without whitelist allow all users.
+    pub fn allows(&self, service_name: &str, user: &str) -> bool {
+        match self.lists.read().unwrap().get(service_name) {
+            Some(whitelist) => whitelist.contains(user),
+            None => true,
+        }
+    }
+
+    pub fn users(&self, service_name: &str) -> Option<Vec<String>> {
+        let lists = self.lists.read().unwrap();
+        let mut users = lists.get(service_name)?.iter().cloned().collect::<Vec<_>>();
+        users.sort();
+        Some(users)
+    }
+
+    /// Modify the whitelist of a service, persisting it if `modify` returns `true`.
+    /// Returns `false` without calling `modify` if the service has no whitelist.
+    pub async fn update(
+        &self,
+        service_name: &str,
+        modify: impl FnOnce(&mut HashSet<String>) -> bool,
+    ) -> io::Result<bool> {
+        let modified = match self.lists.write().unwrap().get_mut(service_name) {
+            Some(whitelist) => modify(whitelist),
+            None => return Ok(false),
+        };
+
+        if modified {
+            self.persist(service_name).await?;
+        }
+        Ok(modified)
+    }
+
+    async fn persist(&self, service_name: &str) -> io::Result<()> {
+        let store = self.store.lock().unwrap().clone();
+        if let Some(store) = store {
+            let users = self.users(service_name).unwrap_or_default();
+            store
+                .set(service_name, serde_json::to_string(&users)?)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Replace the configured whitelists by the persisted ones, if any.
+    pub async fn load(&self) -> io::Result<()> {
+        let store = self.store.lock().unwrap().clone();
+        let Some(store) = store else {
+            return Ok(());
+        };
+
+        let service_names = self
+            .lists
+            .read()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        for service_name in service_names {
+            if let Some(value) = store.get(&service_name).await? {
+                let users = serde_json::from_str::<HashSet<String>>(&value)?;
+                self.set(service_name, users);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+//! Error types of the connectors, the engine and the services.
+
+use crate::channel::ClosedChannel;
+use crate::connectors::ConfigError;
+use crate::i18n;
+use crate::message::Message;
+
+/// Reason why a connector, a service or the engine stopped.
+///
+/// The connectors return it from their `run()` instead of panicking or finishing silently,
+/// so the engine can report why they stopped (see [`Event::ConnectorDisconnected`]).
+///
+/// [`Event::ConnectorDisconnected`]: crate::engine::Event::ConnectorDisconnected
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// A channel with the engine was closed, usually because the engine finished.
+    #[error(transparent)]
+    Channel(#[from] ClosedChannel),
+
+    /// The connection with the server of a connector failed.
+    #[error("{connector} connection failed: {source}")]
+    Connector {
+        connector: &'static str,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    /// The server rejected the credentials.
+    #[error("authentication failed: {0}")]
+    Auth(String),
+
+    /// Data received from a server could not be understood.
+    #[error("parse error: {0}")]
+    Parse(String),
+
+    /// A service could not start.
+    #[error("service failed: {0}")]
+    Service(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    /// The configuration is not valid.
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+}
+
+impl Error {
+    /// Error of the connection of the `connector` (e.g. `"IMAP"`).
+    pub fn connector(
+        connector: &'static str,
+        source: impl Into<Box<dyn std::error::Error + Send + Sync>>,
+    ) -> Self {
+        Error::Connector {
+            connector,
+            source: source.into(),
+        }
+    }
+
+    /// Error of a service, i.e. in its [`Service::on_start()`].
+    ///
+    /// [`Service::on_start()`]: crate::interface::Service::on_start()
+    pub fn service(source: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+        Error::Service(source.into())
+    }
+}
+
+/// Error processing a request in a [`SimpleService`], replied to the user.
+///
+/// [`SimpleService`]: crate::interface::SimpleService
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ServiceError {
+    /// The request has not the expected format, described by the text.
+    #[error("{0}")]
+    Format(String),
+
+    /// The request could not be processed.
+    #[error("{0}")]
+    Failed(String),
+}
+
+impl ServiceError {
+    /// Response to the `request` telling the error.
+    pub fn reply(&self, request: &Message) -> Message {
+        let arg = match self {
+            ServiceError::Format(_) => "format-error",
+            ServiceError::Failed(err) => {
+                log::error!("Service '{}' failed: {}", request.service_name, err);
+                "error"
+            }
+        };
+        Message::response(request)
+            .args([i18n::text(request, arg)])
+            .body(self.to_string())
+    }
+}
@@ -0,0 +1,328 @@
+//! Localization of the texts that services reply to the users.
+//!
+//! The texts are stored in a catalog shared by the whole application, indexed by language and key.
+//! The language of each message is read from [`Message::metadata`] using the [`LANGUAGE_KEY`],
+//! that can be set by the input connector or by the engine.
+//! See [`Engine::language()`] and [`Engine::user_language()`].
+//!
+//! The built-in services use this catalog, so you can translate them to your language
+//! or use it in your own services.
+//!
+//! # Example
+//! ```rust
+//! use service_io::i18n;
+//! use service_io::message::Message;
+//!
+//! i18n::add_texts("fr", [("format-error", "erreur de format")]);
+//!
+//! let request = Message::default().metadata([(i18n::LANGUAGE_KEY, "fr")]);
+//! assert_eq!(i18n::text(&request, "format-error"), "erreur de format");
+//!
+//! // Non translated keys fallback to the default language
+//! i18n::add_texts("en", [("my-greeting", "Hello {}!")]);
+//! assert_eq!(i18n::text_with(&request, "my-greeting", ["user"]), "Hello user!");
+//! ```
+//!
+//! [`Engine::language()`]: crate::engine::Engine::language()
+//! [`Engine::user_language()`]: crate::engine::Engine::user_language()
+
+use crate::message::Message;
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::{OnceLock, RwLock};
+
+/// Key of [`Message::metadata`] where the language of the message is specified.
+pub const LANGUAGE_KEY: &str = "language";
+
+/// Language used when the message does not specify any language
+/// or when a text is not translated to the message language.
+pub const DEFAULT_LANGUAGE: &str = "en";
+
+type Catalog = HashMap<String, HashMap<String, String>>;
+
+const BUILTIN_TEXTS: &[(&str, &[(&str, &str)])] = &[
+    (
+        "en",
+        &[
+            ("error", "error"),
+            ("format-error", "format error"),
+            (
+                "alarm-expected-args",
+                "Expected args: <name> <minutes: POSITIVE_NUMBER | time: [YYYY-MM-DD] HH:MM>",
+            ),
+            ("process-no-process", "You need to specify a process to run"),
+            ("process-terminated", "Terminated ({}): {}"),
+            ("process-failed", "Error while running: {}"),
+            ("process-running", "Running as job {}: {}"),
+            ("process-killed", "killed"),
+            ("process-unknown-job", "Unknown job '{}'"),
+            ("public-ip-failed", "Failed to get IP address"),
+            ("public-ip-changed", "The public IP changed"),
+            ("tunnel-expected-args", "Expected args: start | stop | status"),
+            ("tunnel-open", "Tunnel open at {}"),
+            ("tunnel-no-url", "Tunnel open, but its public URL is unknown"),
+            ("tunnel-not-running", "The tunnel is not open"),
+            ("tunnel-stopped", "Tunnel closed"),
+            ("tunnel-exited", "The tunnel finished unexpectedly"),
+            ("tunnel-failed", "Unable to open the tunnel: {}"),
+            (
+                "systemd-expected-args",
+                "Expected args: <status | start | stop | restart> <unit>",
+            ),
+            ("systemd-not-allowed", "The unit '{}' is not allowed"),
+            ("systemd-failed", "Unable to control '{}': {}"),
+            ("capture-expected-args", "Expected args: <{}>"),
+            ("capture-done", "Capture of '{}'"),
+            ("capture-failed", "Capture of '{}' failed: {}"),
+            ("router-expected-subcommands", "Expected subcommands: {}"),
+            ("protected-wrong-code", "Wrong or missing code"),
+            ("totp-expected-args", "Expected args: <{}>"),
+            ("totp-code", "{} (valid for {} more seconds)"),
+            ("totp-unknown", "Unknown secret '{}'"),
+            ("totp-failed", "Unable to read the secret '{}'"),
+            (
+                "protected-locked",
+                "Too many wrong codes, try again in {} minutes",
+            ),
+            (
+                "breaker-unavailable",
+                "Service temporarily unavailable, try again later",
+            ),
+            ("retry-failed", "Failed after {} attempts: {}"),
+            (
+                "jobs-expected-args",
+                "Expected args: list | status <id> | cancel <id>",
+            ),
+            ("jobs-status", "#{} {} '{}' running for {} seconds"),
+            ("jobs-empty", "No running jobs"),
+            ("jobs-cancelled", "Job {} cancelled"),
+            ("jobs-unknown", "Unknown job '{}'"),
+            ("aggregate-no-reply", "No reply in time"),
+            ("aggregate-down", "The service is not running"),
+            ("plugin-failed", "The plugin failed processing the request"),
+            ("script-failed", "The script failed processing the request"),
+            (
+                "external-failed",
+                "The external process failed processing the request",
+            ),
+            ("timeout", "timeout"),
+            (
+                "deadline-expired",
+                "The service did not reply in time. Your request could be still in process.",
+            ),
+            (
+                "upload-body",
+                "The reply is too large, download it from: {}",
+            ),
+            (
+                "upload-attachment",
+                "The file '{}' is too large, download it from: {}",
+            ),
+            (
+                "admin-expected-args",
+                "Expected args: allow <service> <user> | disallow <service> <user> | whitelist <service> | describe",
+            ),
+            ("admin-done", "done"),
+            ("admin-not-modified", "The whitelist was not modified"),
+            ("admin-no-whitelist", "The service has no whitelist"),
+            ("admin-failed", "The operation could not be completed"),
+            (
+                "verification-required",
+                "Before using the services you need to verify your address. \
+                 Reply to this message starting with the code: {}",
+            ),
+            ("verification-done", "Your address has been verified"),
+            (
+                "settings-expected-args",
+                "Expected args: set <key> <value> | unset <key> | show. Keys: {}",
+            ),
+            ("settings-unknown-key", "Unknown setting '{}'. Keys: {}"),
+            ("settings-invalid-timezone", "Unknown timezone '{}'. Example: Europe/Madrid"),
+            ("settings-saved", "saved"),
+            ("settings-empty", "No settings"),
+            ("settings-failed", "The settings could not be accessed"),
+        ],
+    ),
+    (
+        "es",
+        &[
+            ("error", "error"),
+            ("format-error", "error de formato"),
+            (
+                "alarm-expected-args",
+                "Argumentos esperados: <nombre> <minutos: NUMERO_POSITIVO | hora: [AAAA-MM-DD] HH:MM>",
+            ),
+            (
+                "process-no-process",
+                "Necesitas especificar un proceso a ejecutar",
+            ),
+            ("process-terminated", "Terminado ({}): {}"),
+            ("process-failed", "Error mientras se ejecutaba: {}"),
+            ("process-running", "Ejecutándose como tarea {}: {}"),
+            ("process-killed", "terminado a la fuerza"),
+            ("process-unknown-job", "Tarea '{}' desconocida"),
+            ("public-ip-failed", "No se pudo obtener la dirección IP"),
+            ("public-ip-changed", "La IP pública cambió"),
+            ("tunnel-expected-args", "Argumentos esperados: start | stop | status"),
+            ("tunnel-open", "Túnel abierto en {}"),
+            ("tunnel-no-url", "Túnel abierto, pero su URL pública es desconocida"),
+            ("tunnel-not-running", "El túnel no está abierto"),
+            ("tunnel-stopped", "Túnel cerrado"),
+            ("tunnel-exited", "El túnel terminó inesperadamente"),
+            ("tunnel-failed", "No se pudo abrir el túnel: {}"),
+            (
+                "systemd-expected-args",
+                "Argumentos esperados: <status | start | stop | restart> <unidad>",
+            ),
+            ("systemd-not-allowed", "La unidad '{}' no está permitida"),
+            ("systemd-failed", "No se pudo controlar '{}': {}"),
+            ("capture-expected-args", "Argumentos esperados: <{}>"),
+            ("capture-done", "Captura de '{}'"),
+            ("capture-failed", "La captura de '{}' falló: {}"),
+            ("router-expected-subcommands", "Subcomandos esperados: {}"),
+            ("protected-wrong-code", "Código incorrecto o ausente"),
+            ("totp-expected-args", "Argumentos esperados: <{}>"),
+            ("totp-code", "{} (válido durante {} segundos más)"),
+            ("totp-unknown", "Secreto '{}' desconocido"),
+            ("totp-failed", "No se pudo leer el secreto '{}'"),
+            (
+                "protected-locked",
+                "Demasiados códigos incorrectos, inténtalo de nuevo en {} minutos",
+            ),
+            (
+                "breaker-unavailable",
+                "Servicio no disponible temporalmente, inténtalo más tarde",
+            ),
+            ("retry-failed", "Falló tras {} intentos: {}"),
+            (
+                "jobs-expected-args",
+                "Argumentos esperados: list | status <id> | cancel <id>",
+            ),
+            ("jobs-status", "#{} {} '{}' ejecutándose desde hace {} segundos"),
+            ("jobs-empty", "No hay tareas en ejecución"),
+            ("jobs-cancelled", "Tarea {} cancelada"),
+            ("jobs-unknown", "Tarea '{}' desconocida"),
+            ("aggregate-no-reply", "Sin respuesta a tiempo"),
+            ("aggregate-down", "El servicio no está en ejecución"),
+            ("plugin-failed", "El plugin falló procesando la petición"),
+            ("script-failed", "El script falló procesando la petición"),
+            (
+                "external-failed",
+                "El proceso externo falló procesando la petición",
+            ),
+            ("timeout", "tiempo agotado"),
+            (
+                "deadline-expired",
+                "El servicio no respondió a tiempo. Tu petición podría seguir en proceso.",
+            ),
+            (
+                "upload-body",
+                "La respuesta es demasiado grande, descárgala de: {}",
+            ),
+            (
+                "upload-attachment",
+                "El fichero '{}' es demasiado grande, descárgalo de: {}",
+            ),
+            (
+                "admin-expected-args",
+                "Argumentos esperados: allow <servicio> <usuario> | disallow <servicio> <usuario> | whitelist <servicio> | describe",
+            ),
+            ("admin-done", "hecho"),
+            ("admin-not-modified", "La lista de usuarios no se ha modificado"),
+            ("admin-no-whitelist", "El servicio no tiene lista de usuarios"),
+            ("admin-failed", "No se pudo completar la operación"),
+            (
+                "verification-required",
+                "Antes de usar los servicios necesitas verificar tu dirección. \
+                 Responde a este mensaje empezando por el código: {}",
+            ),
+            ("verification-done", "Tu dirección ha sido verificada"),
+            (
+                "settings-expected-args",
+                "Argumentos esperados: set <clave> <valor> | unset <clave> | show. Claves: {}",
+            ),
+            ("settings-unknown-key", "Ajuste desconocido '{}'. Claves: {}"),
+            (
+                "settings-invalid-timezone",
+                "Zona horaria desconocida '{}'. Ejemplo: Europe/Madrid",
+            ),
+            ("settings-saved", "guardado"),
+            ("settings-empty", "Sin ajustes"),
+            ("settings-failed", "No se pudo acceder a los ajustes"),
+        ],
+    ),
+];
+
+fn catalog() -> &'static RwLock<Catalog> {
+    static CATALOG: OnceLock<RwLock<Catalog>> = OnceLock::new();
+    CATALOG.get_or_init(|| {
+        let catalog = BUILTIN_TEXTS
+            .iter()
+            .map(|(language, texts)| {
+                let texts = texts
+                    .iter()
+                    .map(|(key, text)| (key.to_string(), text.to_string()))
+                    .collect();
+                (language.to_string(), texts)
+            })
+            .collect();
+
+        RwLock::new(catalog)
+    })
+}
+
+/// Add or replace texts of the catalog for a language.
+/// Texts can contain `{}` placeholders to be filled by [`text_with()`].
+pub fn add_texts<K: Into<String>, V: Into<String>>(
+    language: impl Into<String>,
+    texts: impl IntoIterator<Item = (K, V)>,
+) {
+    let mut catalog = catalog().write().unwrap();
+    catalog.entry(language.into()).or_default().extend(
+        texts
+            .into_iter()
+            .map(|(key, text)| (key.into(), text.into())),
+    );
+}
+
+/// Language of the message, or [`DEFAULT_LANGUAGE`] if it is not specified.
+pub fn language(message: &Message) -> &str {
+    message
+        .metadata
+        .get(LANGUAGE_KEY)
+        .map(|language| language.as_str())
+        .unwrap_or(DEFAULT_LANGUAGE)
+}
+
+/// Text of the catalog for the `key` in the language of the message.
+/// If the text is not translated, the text in [`DEFAULT_LANGUAGE`] is used.
+/// If the text does not exist in any of them, the `key` itself is returned.
+pub fn text(message: &Message, key: &str) -> String {
+    let catalog = catalog().read().unwrap();
+    [language(message), DEFAULT_LANGUAGE]
+        .iter()
+        .find_map(|language| catalog.get(*language)?.get(key))
+        .cloned()
+        .unwrap_or_else(|| key.into())
+}
+
+/// Similar to [`text()`] but filling each `{}` placeholder of the text with the `params`
+/// in order.
+pub fn text_with(
+    message: &Message,
+    key: &str,
+    params: impl IntoIterator<Item = impl Display>,
+) -> String {
+    let text = text(message, key);
+    let mut pieces = text.split("{}");
+    let mut result = pieces.next().unwrap_or_default().to_string();
+    let mut params = params.into_iter();
+    for piece in pieces {
+        if let Some(param) = params.next() {
+            result += &param.to_string();
+        }
+        result += piece;
+    }
+    result
+}
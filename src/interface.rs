@@ -1,18 +1,40 @@
-//! Traits for building [`InputConnector`], [`OutputConnector`], and [`Service`]
+//! Traits for building [`InputConnector`], [`OutputConnector`], [`DuplexConnector`],
+//! [`Service`] and [`SimpleService`], along with the [`Context`] of the latter.
 //!
 //! [`InputConnector`]: interface::InputConnector
 //! [`OutputConnector`]: interface::OutputConnector
+//! [`DuplexConnector`]: interface::DuplexConnector
 //! [`Service`]: interface::Service
+//! [`SimpleService`]: interface::SimpleService
+//! [`Context`]: interface::Context
 
+use crate::cache::Cache;
 use crate::channel::{ClosedChannel, Receiver, Sender};
+use crate::engine::{EngineHandle, TempDir, TempDirs};
+use crate::error::{Error, ServiceError};
+use crate::message::Message;
+use crate::services::{Job, JobRegistry};
+use crate::state::{KeyValueStore, MemoryStore, Scoped};
+use crate::time;
+use crate::util;
 
 use async_trait::async_trait;
+use chrono::DateTime;
+use chrono_tz::Tz;
+use futures::future::BoxFuture;
+use tokio::sync::OnceCell;
+
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 
 /// Implement an input connector.
 /// An input connector is in change of creating [`Message`] and sending asynchronously
 /// to the services.
 ///
 /// If the sender return a [`ClosedChannel`] error, it is expected to propagate this error.
+/// Other unrecoverable problems (e.g. rejected credentials) are reported returning an [`Error`].
 ///
 /// See default implementations in [`connectors`]
 ///
@@ -24,8 +46,9 @@ use async_trait::async_trait;
 /// # Example
 /// ```rust
 /// use service_io::interface::{InputConnector};
-/// use service_io::channel::{ClosedChannel, Sender};
+/// use service_io::channel::Sender;
 /// use service_io::message::{Message};
+/// use service_io::Error;
 ///
 /// use async_trait::async_trait;
 ///
@@ -33,7 +56,7 @@ use async_trait::async_trait;
 ///
 /// #[async_trait]
 /// impl InputConnector for MyInput {
-///     async fn run(self: Box<Self>, sender: Sender) -> Result<(), ClosedChannel> {
+///     async fn run(self: Box<Self>, sender: Sender) -> Result<(), Error> {
 ///          // Load phase
 ///          // ...
 ///          loop {
@@ -49,7 +72,27 @@ use async_trait::async_trait;
 /// ```
 #[async_trait]
 pub trait InputConnector {
-    async fn run(self: Box<Self>, sender: Sender) -> Result<(), ClosedChannel>;
+    /// Connects before [`InputConnector::run()`] (e.g. logs in the server).
+    /// By default, it does nothing.
+    ///
+    /// The engine considers the connector ready once it finished.
+    /// If it fails, the connector does not run.
+    /// See [`Engine::depends_on()`].
+    ///
+    /// [`Engine::depends_on()`]: crate::engine::Engine::depends_on()
+    async fn connect(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Short description of the connector, without secrets, shown by [`Engine::describe()`].
+    /// By default, the name of its type.
+    ///
+    /// [`Engine::describe()`]: crate::engine::Engine::describe()
+    fn describe(&self) -> String {
+        util::type_name::<Self>().into()
+    }
+
+    async fn run(self: Box<Self>, sender: Sender) -> Result<(), Error>;
 }
 
 /// Implement an output connector.
@@ -57,6 +100,7 @@ pub trait InputConnector {
 /// and deliver them.
 ///
 /// If the receiver return a [`ClosedChannel`] error, it is expected to propagate this error.
+/// Other unrecoverable problems (e.g. rejected credentials) are reported returning an [`Error`].
 ///
 /// See default implementations in [`connectors`]
 ///
@@ -69,7 +113,8 @@ pub trait InputConnector {
 /// ```rust
 ///
 /// use service_io::interface::{OutputConnector};
-/// use service_io::channel::{ClosedChannel, Receiver};
+/// use service_io::channel::Receiver;
+/// use service_io::Error;
 ///
 /// use async_trait::async_trait;
 ///
@@ -77,7 +122,7 @@ pub trait InputConnector {
 ///
 /// #[async_trait]
 /// impl OutputConnector for MyOutput {
-///     async fn run(self: Box<Self>, mut receiver: Receiver) -> Result<(), ClosedChannel> {
+///     async fn run(self: Box<Self>, mut receiver: Receiver) -> Result<(), Error> {
 ///          // Load phase
 ///          // ...
 ///          loop {
@@ -92,7 +137,73 @@ pub trait InputConnector {
 /// ```
 #[async_trait]
 pub trait OutputConnector {
-    async fn run(self: Box<Self>, receiver: Receiver) -> Result<(), ClosedChannel>;
+    /// Connects before [`OutputConnector::run()`] (e.g. logs in the server),
+    /// so the problems are known before any message is consumed from the input.
+    /// By default, it does nothing.
+    ///
+    /// The engine considers the connector ready once it finished.
+    /// If it fails, the connector does not run.
+    /// See [`Engine::depends_on()`].
+    ///
+    /// [`Engine::depends_on()`]: crate::engine::Engine::depends_on()
+    async fn connect(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Short description of the connector, without secrets, shown by [`Engine::describe()`].
+    /// By default, the name of its type.
+    ///
+    /// [`Engine::describe()`]: crate::engine::Engine::describe()
+    fn describe(&self) -> String {
+        util::type_name::<Self>().into()
+    }
+
+    async fn run(self: Box<Self>, receiver: Receiver) -> Result<(), Error>;
+}
+
+/// Implement a connector that acts as input and output at the same time.
+/// A duplex connector is configured once and split into linked input and output halves,
+/// that can share the configuration, the authentication, or the connection state.
+///
+/// Use it with [`Engine::connector()`].
+/// Any pair of input and output connectors is also a duplex connector.
+///
+/// [`Engine::connector()`]: crate::engine::Engine::connector
+///
+/// # Example
+/// ```rust
+/// use service_io::interface::{DuplexConnector};
+/// use service_io::connectors::{UserStdin, DebugStdout};
+///
+/// struct MyConsole;
+///
+/// impl DuplexConnector for MyConsole {
+///     type Input = UserStdin<String>;
+///     type Output = DebugStdout;
+///
+///     fn split(self) -> (UserStdin<String>, DebugStdout) {
+///         (UserStdin("user".into()), DebugStdout)
+///     }
+/// }
+/// ```
+pub trait DuplexConnector {
+    type Input: InputConnector + Send + 'static;
+    type Output: OutputConnector + Send + 'static;
+
+    fn split(self) -> (Self::Input, Self::Output);
+}
+
+impl<I, O> DuplexConnector for (I, O)
+where
+    I: InputConnector + Send + 'static,
+    O: OutputConnector + Send + 'static,
+{
+    type Input = I;
+    type Output = O;
+
+    fn split(self) -> (I, O) {
+        self
+    }
 }
 
 /// Implement a service.
@@ -102,6 +213,9 @@ pub trait OutputConnector {
 /// If both, sender or receiver return a [`ClosedChannel`] error,
 /// it is expected to propagate this error.
 ///
+/// The setup and cleanup of the service can be done in the [`Service::on_start()`]
+/// and [`Service::on_stop()`] hooks, awaited by the engine.
+///
 /// See default implementations in [`services`]
 ///
 /// Do not forget to add the [`mod@async_trait`] crate when implement this trait
@@ -137,5 +251,445 @@ pub trait OutputConnector {
 /// ```
 #[async_trait]
 pub trait Service {
+    /// Prepares the service before [`Service::run()`] (e.g. opens a database or warms a cache).
+    /// By default, it does nothing.
+    ///
+    /// The messages for the service wait in its queue meanwhile.
+    /// If it fails, the service does not run, and the engine reports it as failed.
+    async fn on_start(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Short description of the service, without secrets, shown by [`Engine::describe()`].
+    /// By default, the name of its type.
+    ///
+    /// [`Engine::describe()`]: crate::engine::Engine::describe()
+    fn describe(&self) -> String {
+        util::type_name::<Self>().into()
+    }
+
+    /// Cleanup to do once the service stopped, even if it panicked (e.g. flush its state).
+    /// By default, none.
+    ///
+    /// The engine takes it after [`Service::on_start()`], because [`Service::run()`] consumes
+    /// the service, so the hook must own what it cleans (e.g. an `Arc` shared with the service).
+    /// The engine waits for the hooks before finishing, up to [`STOP_HOOKS_TIMEOUT`].
+    ///
+    /// # Example
+    /// ```rust
+    /// use service_io::interface::{Service, StopHook};
+    /// use service_io::channel::{ClosedChannel, Receiver, Sender};
+    ///
+    /// use async_trait::async_trait;
+    ///
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// struct Counter(Arc<Mutex<u64>>);
+    ///
+    /// #[async_trait]
+    /// impl Service for Counter {
+    ///     fn on_stop(&mut self) -> Option<StopHook> {
+    ///         let count = self.0.clone();
+    ///         Some(Box::pin(async move {
+    ///             println!("{} messages processed", count.lock().unwrap());
+    ///         }))
+    ///     }
+    ///
+    ///     async fn run(self: Box<Self>, mut input: Receiver, _: Sender) -> Result<(), ClosedChannel> {
+    ///         loop {
+    ///             input.recv().await?;
+    ///             *self.0.lock().unwrap() += 1;
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    fn on_stop(&mut self) -> Option<StopHook> {
+        None
+    }
+
     async fn run(self: Box<Self>, input: Receiver, output: Sender) -> Result<(), ClosedChannel>;
 }
+
+/// Cleanup of a service returned by [`Service::on_stop()`].
+pub type StopHook = BoxFuture<'static, ()>;
+
+/// Maximum time the engine waits for the [`Service::on_stop()`] hooks when it finishes.
+pub const STOP_HOOKS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Implement a service that processes each request by itself, without handling the channels.
+/// Every type implementing it is also a [`Service`], that processes the requests in order
+/// and replies with the returned messages.
+///
+/// A [`ServiceError`] is replied to the user: a [`ServiceError::Format`] with a `format error`
+/// arg, and a [`ServiceError::Failed`] with an `error` arg.
+///
+/// Each request comes with its [`Context`], giving access to what the services usually need
+/// besides the request: its state, the engine, the time of the user, or a way to tell the user
+/// how the request is going.
+///
+/// Do not forget to add the [`mod@async_trait`] crate when implement this trait
+///
+/// # Example
+/// ```rust
+/// use service_io::interface::{Context, SimpleService};
+/// use service_io::message::Message;
+/// use service_io::error::ServiceError;
+///
+/// use async_trait::async_trait;
+///
+/// struct Sum;
+///
+/// #[async_trait]
+/// impl SimpleService for Sum {
+///     async fn handle(
+///         &self,
+///         request: Message,
+///         _context: &Context,
+///     ) -> Result<Vec<Message>, ServiceError> {
+///         let sum = request
+///             .args
+///             .iter()
+///             .map(|arg| arg.parse::<i64>())
+///             .sum::<Result<i64, _>>()
+///             .map_err(|_| ServiceError::Format("Expected numbers".into()))?;
+///
+///         Ok(vec![Message::response(&request).body(sum.to_string())])
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait SimpleService {
+    async fn handle(
+        &self,
+        request: Message,
+        context: &Context,
+    ) -> Result<Vec<Message>, ServiceError>;
+}
+
+/// Information and utilities given to a [`SimpleService`] along with each request.
+///
+/// # Example
+/// ```rust
+/// use service_io::interface::{Context, SimpleService};
+/// use service_io::message::Message;
+/// use service_io::error::ServiceError;
+/// use service_io::state::KeyValueStore;
+///
+/// use async_trait::async_trait;
+///
+/// /// Counts the requests of each user.
+/// struct Counter;
+///
+/// #[async_trait]
+/// impl SimpleService for Counter {
+///     async fn handle(
+///         &self,
+///         request: Message,
+///         context: &Context,
+///     ) -> Result<Vec<Message>, ServiceError> {
+///         context.progress("Counting...").await;
+///
+///         let state = context.state();
+///         let count = match state.get(&request.user).await {
+///             Ok(count) => count.and_then(|count| count.parse().ok()).unwrap_or(0) + 1,
+///             Err(err) => return Err(ServiceError::Failed(err.to_string())),
+///         };
+///         if let Err(err) = state.set(&request.user, count.to_string()).await {
+///             return Err(ServiceError::Failed(err.to_string()));
+///         }
+///
+///         let body = format!("Request {} at {}", count, context.now().format("%H:%M"));
+///         Ok(vec![Message::response(&request).body(body)])
+///     }
+/// }
+/// ```
+pub struct Context {
+    request: Message,
+    state: Scoped<Arc<dyn KeyValueStore>>,
+    engine: Option<EngineHandle>,
+    jobs: Arc<JobRegistry>,
+    output: Sender,
+    temp_dirs: Arc<TempDirs>,
+    temp_dir: OnceCell<TempDir>,
+    cache: Arc<Cache>,
+}
+
+impl Context {
+    /// Name the service was registered with.
+    /// See [`Engine::add_service()`].
+    ///
+    /// [`Engine::add_service()`]: crate::engine::Engine::add_service()
+    pub fn service_name(&self) -> &str {
+        &self.request.service_name
+    }
+
+    /// Engine running the service, or `None` if it runs out of an engine (e.g. in tests).
+    /// Useful to know if it is a dry-run, or the users allowed to use a service.
+    pub fn engine(&self) -> Option<&EngineHandle> {
+        self.engine.as_ref()
+    }
+
+    /// State of the service, scoped by its name so it does not collide with other services.
+    /// It is kept in the [`Engine::state_store()`].
+    ///
+    /// [`Engine::state_store()`]: crate::engine::Engine::state_store()
+    pub fn state(&self) -> &impl KeyValueStore {
+        &self.state
+    }
+
+    /// Cache shared by all the services of the engine, to reuse slow responses for a while.
+    /// Prefix the keys with the service name to not collide with other services,
+    /// unless the values are intended to be shared. See [`Engine::cache_capacity()`].
+    ///
+    /// [`Engine::cache_capacity()`]: crate::engine::Engine::cache_capacity()
+    pub fn cache(&self) -> &Cache {
+        &self.cache
+    }
+
+    /// Long-running requests of all the services, that the users follow with the [`Jobs`]
+    /// service.
+    ///
+    /// [`Jobs`]: crate::services::Jobs
+    pub fn jobs(&self) -> &Arc<JobRegistry> {
+        &self.jobs
+    }
+
+    /// Registers the request as a job with a `description`, running until the [`Job`] is dropped.
+    /// Tell the user its [`Job::id()`], so they can follow or cancel it.
+    pub fn start_job(&self, description: impl Into<String>) -> Job {
+        let request = &self.request;
+        self.jobs
+            .start(&request.user, &request.service_name, description)
+    }
+
+    /// Empty directory for the files of this request, i.e. to pass the attachments to a program.
+    /// It is created on the first call and removed along with its content once the request
+    /// is processed, even if the service panics. See [`Engine::temp_dir()`].
+    ///
+    /// [`Engine::temp_dir()`]: crate::engine::Engine::temp_dir()
+    pub async fn temp_dir(&self) -> io::Result<&Path> {
+        let dir = self
+            .temp_dir
+            .get_or_try_init(|| self.temp_dirs.create(&self.request.service_name))
+            .await?;
+        Ok(dir.path())
+    }
+
+    /// Current time in the timezone of the user. See [`time`].
+    pub fn now(&self) -> DateTime<Tz> {
+        time::now(&self.request)
+    }
+
+    /// Sends to the user a message with the `body` before the response,
+    /// to let them know that the request is being processed.
+    /// See [`Message::progress()`].
+    pub async fn progress(&self, body: impl Into<String>) {
+        let message = Message::progress(&self.request).body(body);
+        if self.output.send(message).await.is_err() {
+            log::warn!("Drop progress of a finished service");
+        }
+    }
+}
+
+#[async_trait]
+impl<S: SimpleService + Send + Sync + 'static> Service for S {
+    async fn run(
+        self: Box<Self>,
+        mut input: Receiver,
+        output: Sender,
+    ) -> Result<(), ClosedChannel> {
+        let engine = EngineHandle::current();
+        let store = engine
+            .as_ref()
+            .and_then(EngineHandle::state_store)
+            .unwrap_or_else(|| Arc::new(MemoryStore::default()));
+        let jobs = engine
+            .as_ref()
+            .map_or_else(Arc::default, |engine| engine.jobs().clone());
+        let temp_dirs = engine
+            .as_ref()
+            .map_or_else(Arc::default, |engine| engine.temp_dirs().clone());
+        let cache = engine
+            .as_ref()
+            .map_or_else(Arc::default, |engine| engine.cache().clone());
+
+        loop {
+            let request = input.recv().await?;
+            let context = Context {
+                state: Scoped::new(store.clone(), &request.service_name),
+                request: request.clone(),
+                engine: engine.clone(),
+                jobs: jobs.clone(),
+                output: output.clone(),
+                temp_dirs: temp_dirs.clone(),
+                temp_dir: OnceCell::new(),
+                cache: cache.clone(),
+            };
+            let responses = match self.handle(request.clone(), &context).await {
+                Ok(responses) => responses,
+                Err(err) => vec![err.reply(&request)],
+            };
+            for response in responses {
+                output.send(response).await?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel;
+
+    struct Parse;
+
+    #[async_trait]
+    impl SimpleService for Parse {
+        async fn handle(
+            &self,
+            request: Message,
+            _context: &Context,
+        ) -> Result<Vec<Message>, ServiceError> {
+            match request.body.as_str() {
+                "" => Err(ServiceError::Format("Expected a number".into())),
+                body => match body.parse::<u32>() {
+                    Ok(number) => Ok(vec![Message::response(&request).body(number.to_string())]),
+                    Err(err) => Err(ServiceError::Failed(err.to_string())),
+                },
+            }
+        }
+    }
+
+    /// Counts the requests of each user, notifying the progress before.
+    struct Counter;
+
+    #[async_trait]
+    impl SimpleService for Counter {
+        async fn handle(
+            &self,
+            request: Message,
+            context: &Context,
+        ) -> Result<Vec<Message>, ServiceError> {
+            context.progress("counting").await;
+            let count = context.state().get(&request.user).await.unwrap();
+            let count = count.map_or(0, |count| count.parse::<u32>().unwrap()) + 1;
+            let count = count.to_string();
+            context
+                .state()
+                .set(&request.user, count.clone())
+                .await
+                .unwrap();
+            Ok(vec![Message::response(&request).body(count)])
+        }
+    }
+
+    /// Writes the body in a temporary file, replying with its path.
+    struct Save;
+
+    #[async_trait]
+    impl SimpleService for Save {
+        async fn handle(
+            &self,
+            request: Message,
+            context: &Context,
+        ) -> Result<Vec<Message>, ServiceError> {
+            let dir = context.temp_dir().await.unwrap();
+            assert_eq!(context.temp_dir().await.unwrap(), dir);
+            let path = dir.join("body.txt");
+            tokio::fs::write(&path, &request.body).await.unwrap();
+            Ok(vec![
+                Message::response(&request).body(path.to_string_lossy())
+            ])
+        }
+    }
+
+    #[tokio::test]
+    async fn temp_dir() {
+        let root = std::env::temp_dir().join(format!("service-io-temp-{}", std::process::id()));
+        let engine = EngineHandle::default();
+        engine.temp_dirs().set_root(root.clone());
+
+        let (input, service_input) = channel::channel(4);
+        let (service_output, mut output) = channel::channel(4);
+        let service = Box::new(Save).run(service_input, service_output);
+        tokio::spawn(engine.clone().scope(service));
+
+        let request = Message::default().service_name("s-save/files").body("1234");
+        input.send(request.clone()).await.unwrap();
+        let first = output.recv().await.unwrap().body;
+        assert!(first.starts_with(&*root.to_string_lossy()));
+        assert!(first.contains("s-save_files-"));
+
+        // Once the next request is processed, the directory of the previous one is removed.
+        input.send(request).await.unwrap();
+        let second = output.recv().await.unwrap().body;
+        assert_ne!(first, second);
+        assert!(!Path::new(&first).exists());
+
+        engine.temp_dirs().clear();
+        let base = Path::new(&second).parent().unwrap();
+        assert!(!base.exists());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn context() {
+        let store = MemoryStore::default();
+        let engine = EngineHandle::default();
+        engine.set_state_store(Arc::new(store.clone()));
+
+        let (input, service_input) = channel::channel(4);
+        let (service_output, mut output) = channel::channel(4);
+        let service = Box::new(Counter).run(service_input, service_output);
+        tokio::spawn(engine.scope(service));
+
+        for count in ["1", "2"] {
+            let request = Message::default().user("user").service_name("s-counter");
+            input.send(request).await.unwrap();
+            assert_eq!(output.recv().await.unwrap().body, "counting");
+            assert_eq!(output.recv().await.unwrap().body, count);
+        }
+        assert_eq!(store.keys("").await.unwrap(), ["s-counter/user"]);
+    }
+
+    #[tokio::test]
+    async fn wrapped_context() {
+        use crate::services::Router;
+
+        let store = MemoryStore::default();
+        let engine = EngineHandle::default();
+        engine.set_state_store(Arc::new(store.clone()));
+
+        let (input, service_input) = channel::channel(4);
+        let (service_output, mut output) = channel::channel(4);
+        let service = Box::new(Router::default().route("count", Counter));
+        tokio::spawn(engine.scope(service.run(service_input, service_output)));
+
+        let request = Message::default()
+            .user("user")
+            .service_name("s-router")
+            .args(["count"]);
+        input.send(request).await.unwrap();
+        assert_eq!(output.recv().await.unwrap().body, "counting");
+        assert_eq!(output.recv().await.unwrap().body, "1");
+        assert_eq!(store.keys("").await.unwrap(), ["s-router/user"]);
+    }
+
+    #[tokio::test]
+    async fn simple_service() {
+        let (input, service_input) = channel::channel(4);
+        let (service_output, mut output) = channel::channel(4);
+        tokio::spawn(Box::new(Parse).run(service_input, service_output));
+
+        input.send(Message::default().body("42")).await.unwrap();
+        assert_eq!(output.recv().await.unwrap().body, "42");
+
+        input.send(Message::default()).await.unwrap();
+        let response = output.recv().await.unwrap();
+        assert_eq!(response.args, ["format error"]);
+        assert_eq!(response.body, "Expected a number");
+
+        input.send(Message::default().body("-1")).await.unwrap();
+        assert_eq!(output.recv().await.unwrap().args, ["error"]);
+    }
+}
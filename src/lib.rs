@@ -5,13 +5,44 @@
 // Tells rustdoc where is the README to compile and test the rust code found there
 doc_comment::doctest!("../README.md");
 
+#[cfg(all(
+    feature = "email",
+    not(any(feature = "native-tls", feature = "rustls"))
+))]
+compile_error!("The `email` feature needs a TLS backend: enable `native-tls` or `rustls`");
+
+#[cfg(all(
+    feature = "bridge",
+    not(any(feature = "native-tls", feature = "rustls"))
+))]
+compile_error!("The `bridge` feature needs a TLS backend: enable `native-tls` or `rustls`");
+
 pub mod channel;
+pub mod error;
+pub use error::Error;
 pub mod interface;
 pub mod message;
 
 pub mod engine;
 
+pub mod i18n;
+
+pub mod time;
+
+pub mod cluster;
+
+pub mod storage;
+
+pub mod state;
+
+pub mod cache;
+
+pub mod users;
+
 pub mod connectors;
 pub mod services;
 
 pub mod util;
+
+#[cfg(all(any(test, feature = "testing"), feature = "email"))]
+pub mod testing;
@@ -1,6 +1,33 @@
 //! Common data shared among input/output/services and utilities related to it.
 
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
 use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Key of [`Message::metadata`] marking the intermediate messages a service sends to tell the
+/// user how a slow request is going, before its response.
+/// See [`Message::progress()`].
+pub const PROGRESS_KEY: &str = "progress";
+
+/// Prefix of the [`Message::metadata`] keys with the SHA-256 checksum of an attachment,
+/// followed by the attachment name. The checksum is written in lowercase hexadecimal.
+/// See [`Message::add_checksums()`].
+pub const CHECKSUM_PREFIX: &str = "sha256:";
+
+/// Keys of [`Message::metadata`] copied by [`Message::response()`].
+/// The acknowledgment id is copied so the engine can know which request was replied.
+const CONTEXT_KEYS: &[&str] = &[
+    crate::i18n::LANGUAGE_KEY,
+    crate::time::TIMEZONE_KEY,
+    crate::services::OUTPUT_CHANNEL_KEY,
+    crate::engine::ACK_KEY,
+    #[cfg(feature = "http")]
+    crate::connectors::GITHUB_ISSUE_KEY,
+];
 
 /// Common data shared among input/output/services.
 /// This is the language `service-io` talk.
@@ -21,7 +48,10 @@ use std::collections::HashMap;
 ///     ]);
 /// ```
 ///
-#[derive(Default, Debug, Clone, PartialEq)]
+/// The message can be serialized/deserialized with [`serde`].
+/// The attached data is represented as base64 strings.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Message {
     /// The user this message is related to.
     /// If the message is in the input side, this user means the originator of the message.
@@ -46,22 +76,35 @@ pub struct Message {
 
     /// Attached content of the message.
     /// Each service implementation will understand these values in their own way.
-    pub attached_data: HashMap<String, Vec<u8>>,
+    ///
+    /// The content is stored as [`Bytes`], so cloning the message does not copy it.
+    #[serde(with = "base64_data")]
+    pub attached_data: HashMap<String, Bytes>,
+
+    /// Additional key-value information of the message not intended to be the content itself,
+    /// as the language the user speaks.
+    /// Each input/output/service implementation will understand these values in their own way.
+    pub metadata: HashMap<String, String>,
 }
 
 impl Message {
     /// Sugar to perform a response of a received message.
-    /// Creates an empty message with same [`Message::user`]
-    /// and [`Message::service_name`] as the passed message.
+    /// Creates an empty message with same [`Message::user`] and [`Message::service_name`]
+    /// as the passed message, and the context keys of its [`Message::metadata`]:
+    /// the language, timezone and output channel of the user
+    /// (see [`LANGUAGE_KEY`], [`TIMEZONE_KEY`] and [`OUTPUT_CHANNEL_KEY`]),
+    /// and the place to reply (i.e. the GitHub issue of the request).
     ///
     /// # Example
     /// ```rust
+    /// use service_io::i18n::LANGUAGE_KEY;
     /// use service_io::message::Message;
     ///
     /// let request = Message::default()
     ///     .user("user_01")
     ///     .service_name("my_service")
-    ///     .body("1234");
+    ///     .body("1234")
+    ///     .metadata([(LANGUAGE_KEY, "es"), ("mail-priority", "high")]);
     ///
     /// let response = Message::response(&request);
     ///
@@ -69,17 +112,57 @@ impl Message {
     /// assert_eq!(request.user, response.user);
     /// assert_eq!(request.service_name, response.service_name);
     ///
-    /// // But other fields as body are not copied.
+    /// // Also the context of the request, as the language of the user
+    /// assert_eq!(response.metadata[LANGUAGE_KEY], "es");
+    ///
+    /// // But other fields as body or other metadata are not copied.
     /// assert_ne!(request.body, response.body);
+    /// assert!(!response.metadata.contains_key("mail-priority"));
     /// ```
+    ///
+    /// [`LANGUAGE_KEY`]: crate::i18n::LANGUAGE_KEY
+    /// [`TIMEZONE_KEY`]: crate::time::TIMEZONE_KEY
+    /// [`OUTPUT_CHANNEL_KEY`]: crate::services::OUTPUT_CHANNEL_KEY
     pub fn response(message: &Message) -> Message {
+        let metadata = CONTEXT_KEYS
+            .iter()
+            .filter_map(|&key| Some((key.to_string(), message.metadata.get(key)?.clone())))
+            .collect();
         Message {
             user: message.user.clone(),
             service_name: message.service_name.clone(),
+            metadata,
             ..Default::default()
         }
     }
 
+    /// Similar to [`Message::response()`] but marked with the [`PROGRESS_KEY`]
+    /// as an intermediate message of a request that is still being processed
+    /// (i.e. "50% done").
+    /// The outputs can rate-limit them, see [`ProgressOutput`].
+    ///
+    /// # Example
+    /// ```rust
+    /// use service_io::message::Message;
+    ///
+    /// let request = Message::default().user("user_01").service_name("s-backup");
+    /// let progress = Message::progress(&request).body("50% done");
+    /// assert!(progress.is_progress());
+    /// assert!(!Message::response(&progress).is_progress());
+    /// ```
+    ///
+    /// [`ProgressOutput`]: crate::connectors::ProgressOutput
+    pub fn progress(message: &Message) -> Message {
+        let mut progress = Message::response(message);
+        progress.metadata.insert(PROGRESS_KEY.into(), "true".into());
+        progress
+    }
+
+    /// Returns `true` if the message was created by [`Message::progress()`].
+    pub fn is_progress(&self) -> bool {
+        self.metadata.contains_key(PROGRESS_KEY)
+    }
+
     /// Set a user for the message
     pub fn user(mut self, user: impl Into<String>) -> Self {
         self.user = user.into();
@@ -105,22 +188,221 @@ impl Message {
     }
 
     /// Set attached data for the message
-    pub fn attach<S: Into<String>>(
+    pub fn attach<S: Into<String>, D: Into<Bytes>>(
         mut self,
-        attached: impl IntoIterator<Item = (S, Vec<u8>)>,
+        attached: impl IntoIterator<Item = (S, D)>,
     ) -> Self {
         self.attached_data = attached
             .into_iter()
-            .map(|(name, data)| (name.into(), data))
+            .map(|(name, data)| (name.into(), data.into()))
             .collect();
         self
     }
+
+    /// Argument at `index`.
+    pub fn arg(&self, index: usize) -> Result<&str, ArgError> {
+        self.args
+            .get(index)
+            .map(|arg| arg.as_str())
+            .ok_or(ArgError::Missing { index })
+    }
+
+    /// Arguments as string slices, useful to match them as a slice pattern.
+    ///
+    /// # Example
+    /// ```rust
+    /// use service_io::message::Message;
+    ///
+    /// let request = Message::default().args(["add", "5"]);
+    ///
+    /// match request.args_str().as_slice() {
+    ///     ["add", value] => assert_eq!(*value, "5"),
+    ///     _ => unreachable!(),
+    /// }
+    /// ```
+    pub fn args_str(&self) -> Vec<&str> {
+        self.args.iter().map(|arg| arg.as_str()).collect()
+    }
+
+    /// Argument at `index` parsed as `T`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use service_io::message::{ArgError, Message};
+    ///
+    /// let request = Message::default().args(["alarm", "15"]);
+    ///
+    /// assert_eq!(request.parse_arg::<u64>(1), Ok(15));
+    /// assert_eq!(request.parse_arg::<u64>(2), Err(ArgError::Missing { index: 2 }));
+    /// assert_eq!(
+    ///     request.parse_arg::<u64>(0).unwrap_err().to_string(),
+    ///     "Invalid argument 0 'alarm': invalid digit found in string"
+    /// );
+    /// ```
+    pub fn parse_arg<T>(&self, index: usize) -> Result<T, ArgError>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        let value = self.arg(index)?;
+        value.parse().map_err(|err: T::Err| ArgError::Invalid {
+            index,
+            value: value.into(),
+            reason: err.to_string(),
+        })
+    }
+
+    /// Set metadata for the message
+    pub fn metadata<K: Into<String>, V: Into<String>>(
+        mut self,
+        metadata: impl IntoIterator<Item = (K, V)>,
+    ) -> Self {
+        self.metadata = metadata
+            .into_iter()
+            .map(|(key, value)| (key.into(), value.into()))
+            .collect();
+        self
+    }
+
+    /// Checksum of the attachment `name`, if any. See [`Message::add_checksums()`].
+    pub fn checksum(&self, name: &str) -> Option<&str> {
+        self.metadata
+            .get(&format!("{}{}", CHECKSUM_PREFIX, name))
+            .map(|checksum| checksum.as_str())
+    }
+
+    /// Adds the SHA-256 checksum of the attachments that have not one yet
+    /// into the metadata, with the [`CHECKSUM_PREFIX`].
+    /// The engine does it for the input messages and for the messages sent to the output,
+    /// so the services and the output connectors can check the integrity of the attachments.
+    ///
+    /// # Example
+    /// ```rust
+    /// use service_io::message::Message;
+    ///
+    /// let mut message = Message::default().attach([("file.txt", b"1234".to_vec())]);
+    /// message.add_checksums();
+    /// assert_eq!(
+    ///     message.checksum("file.txt"),
+    ///     Some("03ac674216f3e15c761ee1a5e255f067953623c8b388b4459e13f978d7c846f4")
+    /// );
+    ///
+    /// message.attached_data.insert("file.txt".into(), b"5678".to_vec().into());
+    /// assert_eq!(message.corrupted_attachments(), ["file.txt"]);
+    /// ```
+    pub fn add_checksums(&mut self) {
+        for (name, data) in &self.attached_data {
+            self.metadata
+                .entry(format!("{}{}", CHECKSUM_PREFIX, name))
+                .or_insert_with(|| sha256_hex(data));
+        }
+    }
+
+    /// Names of the attachments whose content does not match their checksum.
+    /// The attachments without checksum are not checked.
+    pub fn corrupted_attachments(&self) -> Vec<&str> {
+        let mut names = self
+            .attached_data
+            .iter()
+            .filter(|(name, data)| {
+                self.checksum(name)
+                    .is_some_and(|checksum| checksum != sha256_hex(data))
+            })
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    /// Bytes of the body and the attached data.
+    pub fn size(&self) -> usize {
+        self.body.len()
+            + self
+                .attached_data
+                .values()
+                .map(|data| data.len())
+                .sum::<usize>()
+    }
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Error accessing an argument of a [`Message`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgError {
+    /// There is no argument at the index.
+    Missing { index: usize },
+
+    /// The argument at the index could not be parsed.
+    Invalid {
+        index: usize,
+        value: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for ArgError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArgError::Missing { index } => write!(f, "Missing argument {}", index),
+            ArgError::Invalid {
+                index,
+                value,
+                reason,
+            } => write!(f, "Invalid argument {} '{}': {}", index, value, reason),
+        }
+    }
+}
+
+impl std::error::Error for ArgError {}
+
+mod base64_data {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use bytes::Bytes;
+    use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+    use std::collections::HashMap;
+
+    pub fn serialize<S: Serializer>(
+        data: &HashMap<String, Bytes>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        data.iter()
+            .map(|(name, content)| (name, STANDARD.encode(content)))
+            .collect::<HashMap<_, _>>()
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<String, Bytes>, D::Error> {
+        HashMap::<String, String>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(name, content)| {
+                let content = STANDARD.decode(content).map_err(de::Error::custom)?;
+                Ok((name, Bytes::from(content)))
+            })
+            .collect()
+    }
 }
 
 /// Utilities related to the `Message`
 pub mod util {
     use super::Message;
 
+    use bytes::Bytes;
+    use flate2::write::GzEncoder;
+    use zip::write::SimpleFileOptions;
+    use zip::{CompressionMethod, ZipWriter};
+
+    use std::collections::HashMap;
+    use std::io::{Cursor, Write};
+
     /// Modify the [`Message::service_name`] value to make the first letter lowercase.
     ///
     /// This utility can be used in [`Engine::map_input()`] to send always
@@ -140,4 +422,786 @@ pub mod util {
         };
         message
     }
+
+    /// Name of the file that contains all the attachments
+    /// when compressed with [`AttachmentCompression::Zip`].
+    pub const ZIP_ATTACHMENT_NAME: &str = "attachments.zip";
+
+    /// How [`compress_attachments()`] compresses the attachments.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum AttachmentCompression {
+        /// All the attachments in a single zip file, named [`ZIP_ATTACHMENT_NAME`],
+        /// if their total size exceeds the threshold.
+        Zip,
+
+        /// Each attachment that exceeds the threshold is gzipped,
+        /// adding the `.gz` extension to its name.
+        Gzip,
+    }
+
+    /// Creates a mapping that compresses the attachments bigger than `threshold` bytes.
+    ///
+    /// This utility can be used in [`Engine::map_output()`] to avoid that SMTP relays and
+    /// other transports reject large attachments.
+    ///
+    /// [`Engine::map_output()`]: crate::engine::Engine::map_output()
+    pub fn compress_attachments(
+        threshold: usize,
+        compression: AttachmentCompression,
+    ) -> impl Fn(Message) -> Message + Send + Sync + 'static {
+        move |mut message| {
+            let compressed = match compression {
+                AttachmentCompression::Zip => {
+                    let total = message
+                        .attached_data
+                        .values()
+                        .map(Bytes::len)
+                        .sum::<usize>();
+                    match total > threshold {
+                        true => zip(&message.attached_data)
+                            .map(|data| HashMap::from([(ZIP_ATTACHMENT_NAME.into(), data)])),
+                        false => Ok(message.attached_data.clone()),
+                    }
+                }
+                AttachmentCompression::Gzip => message
+                    .attached_data
+                    .iter()
+                    .map(|(name, data)| match data.len() > threshold {
+                        true => Ok((format!("{}.gz", name), gzip(data)?)),
+                        false => Ok((name.clone(), data.clone())),
+                    })
+                    .collect(),
+            };
+
+            match compressed {
+                Ok(attached_data) => message.attached_data = attached_data,
+                Err(err) => log::error!("Attachments can not be compressed: {}", err),
+            }
+            message
+        }
+    }
+
+    fn zip(files: &HashMap<String, Bytes>) -> std::io::Result<Bytes> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        let mut names = files.keys().collect::<Vec<_>>();
+        names.sort();
+        for name in names {
+            writer.start_file(name.as_str(), options)?;
+            writer.write_all(&files[name])?;
+        }
+
+        Ok(writer.finish()?.into_inner().into())
+    }
+
+    fn gzip(data: &[u8]) -> std::io::Result<Bytes> {
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data)?;
+        Ok(encoder.finish()?.into())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        use flate2::read::GzDecoder;
+        use zip::ZipArchive;
+
+        use std::io::Read;
+
+        fn attached() -> Message {
+            Message::default().attach([("small", vec![1; 10]), ("big", vec![2; 100])])
+        }
+
+        #[test]
+        fn zip_attachments() {
+            let message = compress_attachments(50, AttachmentCompression::Zip)(attached());
+            assert_eq!(message.attached_data.len(), 1);
+
+            let zip = Cursor::new(message.attached_data[ZIP_ATTACHMENT_NAME].to_vec());
+            let mut archive = ZipArchive::new(zip).unwrap();
+            let mut content = Vec::new();
+            archive
+                .by_name("big")
+                .unwrap()
+                .read_to_end(&mut content)
+                .unwrap();
+            assert_eq!(content, vec![2; 100]);
+            assert_eq!(archive.by_name("small").unwrap().size(), 10);
+
+            let message = compress_attachments(200, AttachmentCompression::Zip)(attached());
+            assert_eq!(message, attached());
+        }
+
+        #[test]
+        fn gzip_attachments() {
+            let message = compress_attachments(50, AttachmentCompression::Gzip)(attached());
+            assert_eq!(message.attached_data["small"], vec![1; 10]);
+
+            let mut content = Vec::new();
+            GzDecoder::new(&message.attached_data["big.gz"][..])
+                .read_to_end(&mut content)
+                .unwrap();
+            assert_eq!(content, vec![2; 100]);
+        }
+    }
+}
+
+/// Versioned encoding of the [`Message`] used to exchange messages with other engines
+/// and to store them, so the data written by older versions of `service-io` remains readable.
+///
+/// A message is encoded as its [`serde`] JSON representation with an additional `"version"`
+/// field, or as bytes where the first byte is the version followed by the JSON representation.
+/// Big messages can be compressed (see [`Compression`]).
+/// The compatibility rules are:
+/// - The unknown fields are ignored, so older versions can read newer messages
+///   while the version is compatible.
+/// - The missing fields take their default value.
+/// - The data without version (plain JSON written before this format existed)
+///   is read as version `0`.
+/// - The data is written with the lowest version able to represent it:
+///   `1` for plain messages and `2` for compressed messages.
+/// - The data with a version greater than [`VERSION`] is rejected with
+///   [`WireError::UnsupportedVersion`], because its meaning could have changed.
+///
+/// # Example
+/// ```rust
+/// use service_io::message::{wire, Message};
+///
+/// let message = Message::default().user("user_01").body("hello");
+///
+/// let line = wire::to_json(&message);
+/// assert_eq!(wire::from_json(&line).unwrap(), message);
+///
+/// let bytes = wire::to_bytes(&message);
+/// assert_eq!(bytes[0], 1);
+/// assert_eq!(wire::from_bytes(&bytes).unwrap(), message);
+///
+/// let compressed = wire::to_bytes_compressed(&message, wire::Compression::zstd(0));
+/// assert_eq!(compressed[0], 2);
+/// assert_eq!(wire::from_bytes(&compressed).unwrap(), message);
+/// ```
+///
+/// [`Compression`]: wire::Compression
+/// [`VERSION`]: wire::VERSION
+/// [`WireError::UnsupportedVersion`]: wire::WireError::UnsupportedVersion
+pub mod wire {
+    use super::Message;
+
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{Deserialize, Serialize};
+    use serde_json::Value;
+
+    use std::fmt;
+    use std::io::{self, Read, Write};
+
+    /// Greatest version of the format readable by this version of `service-io`.
+    pub const VERSION: u8 = 2;
+
+    const PLAIN_VERSION: u8 = 1;
+    const COMPRESSED_VERSION: u8 = 2;
+
+    const VERSION_FIELD: &str = "version";
+
+    /// Maximum size of a decompressed message read by [`from_json()`] and [`from_bytes()`].
+    /// Use [`from_json_limited()`] or [`from_bytes_limited()`] to choose another limit.
+    pub const MAX_DECOMPRESSED_SIZE: usize = 64 * 1024 * 1024;
+
+    /// Error decoding a message.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum WireError {
+        /// The data was written by a newer and incompatible version.
+        UnsupportedVersion(u8),
+
+        /// The data is not a valid message.
+        Invalid(String),
+    }
+
+    impl fmt::Display for WireError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                WireError::UnsupportedVersion(version) => write!(
+                    f,
+                    "Unsupported message version {} (greater than {})",
+                    version, VERSION
+                ),
+                WireError::Invalid(reason) => write!(f, "Invalid message: {}", reason),
+            }
+        }
+    }
+
+    impl std::error::Error for WireError {}
+
+    impl From<WireError> for io::Error {
+        fn from(err: WireError) -> io::Error {
+            io::Error::new(io::ErrorKind::InvalidData, err)
+        }
+    }
+
+    fn invalid(err: impl fmt::Display) -> WireError {
+        WireError::Invalid(err.to_string())
+    }
+
+    /// Compression algorithm of a compressed message.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum Codec {
+        Gzip,
+        Zstd,
+    }
+
+    impl Codec {
+        fn id(self) -> u8 {
+            match self {
+                Codec::Gzip => 1,
+                Codec::Zstd => 2,
+            }
+        }
+
+        fn from_id(id: u8) -> Option<Codec> {
+            match id {
+                1 => Some(Codec::Gzip),
+                2 => Some(Codec::Zstd),
+                _ => None,
+            }
+        }
+
+        fn compress(self, data: &[u8]) -> Vec<u8> {
+            match self {
+                Codec::Gzip => {
+                    let mut encoder =
+                        flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                    encoder.write_all(data).expect("Writing in memory");
+                    encoder.finish().expect("Writing in memory")
+                }
+                Codec::Zstd => zstd::encode_all(data, 0).expect("Writing in memory"),
+            }
+        }
+
+        /// Fails if the decompressed data is greater than `limit` bytes,
+        /// so a small message can not expand to exhaust the memory.
+        fn decompress(self, data: &[u8], limit: usize) -> io::Result<Vec<u8>> {
+            let cap = limit as u64 + 1;
+            let mut decompressed = Vec::new();
+            match self {
+                Codec::Gzip => flate2::read::GzDecoder::new(data)
+                    .take(cap)
+                    .read_to_end(&mut decompressed)?,
+                Codec::Zstd => zstd::stream::read::Decoder::new(data)?
+                    .take(cap)
+                    .read_to_end(&mut decompressed)?,
+            };
+            if decompressed.len() > limit {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Decompressed message greater than {} bytes", limit),
+                ));
+            }
+            Ok(decompressed)
+        }
+    }
+
+    /// Compresses the messages whose encoded size is greater than a threshold,
+    /// mainly those with big bodies or attachments.
+    /// Smaller messages are written as plain messages,
+    /// because the compression would not save enough to pay for its cost.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Compression {
+        codec: Codec,
+        threshold: usize,
+    }
+
+    impl Compression {
+        /// Use gzip for messages bigger than `threshold` bytes.
+        pub fn gzip(threshold: usize) -> Self {
+            Self {
+                codec: Codec::Gzip,
+                threshold,
+            }
+        }
+
+        /// Use zstd for messages bigger than `threshold` bytes.
+        /// It is faster than gzip with similar compression ratios.
+        pub fn zstd(threshold: usize) -> Self {
+            Self {
+                codec: Codec::Zstd,
+                threshold,
+            }
+        }
+
+        fn codec_for(&self, data: &[u8]) -> Option<Codec> {
+            (data.len() > self.threshold).then_some(self.codec)
+        }
+    }
+
+    /// JSON representation of a compressed message.
+    #[derive(Serialize, Deserialize)]
+    struct Compressed {
+        version: u8,
+        compression: Codec,
+        data: String,
+    }
+
+    /// Encodes the message as a JSON object in a single line.
+    pub fn to_json(message: &Message) -> String {
+        let mut value = serde_json::to_value(message).expect("A message is always serializable");
+        value[VERSION_FIELD] = PLAIN_VERSION.into();
+        value.to_string()
+    }
+
+    /// Like [`to_json()`] but compressing the message according to `compression`.
+    /// The compressed content is encoded in base64 inside the JSON object.
+    pub fn to_json_compressed(message: &Message, compression: Compression) -> String {
+        let data = serde_json::to_vec(message).expect("A message is always serializable");
+        match compression.codec_for(&data) {
+            Some(codec) => serde_json::to_string(&Compressed {
+                version: COMPRESSED_VERSION,
+                compression: codec,
+                data: STANDARD.encode(codec.compress(&data)),
+            })
+            .expect("Always serializable"),
+            None => to_json(message),
+        }
+    }
+
+    /// Decodes a message encoded by [`to_json()`] or [`to_json_compressed()`]
+    /// of this or a previous version.
+    /// The compressed messages can not exceed [`MAX_DECOMPRESSED_SIZE`] once decompressed.
+    pub fn from_json(data: &str) -> Result<Message, WireError> {
+        from_json_limited(data, MAX_DECOMPRESSED_SIZE)
+    }
+
+    /// Like [`from_json()`] but the compressed messages can not exceed
+    /// `max_size` bytes once decompressed.
+    pub fn from_json_limited(data: &str, max_size: usize) -> Result<Message, WireError> {
+        let mut value: Value = serde_json::from_str(data).map_err(invalid)?;
+        let version = match value.get(VERSION_FIELD) {
+            None => 0,
+            Some(version) => version
+                .as_u64()
+                .and_then(|version| u8::try_from(version).ok())
+                .ok_or_else(|| WireError::Invalid(format!("Wrong version: {}", version)))?,
+        };
+
+        match version {
+            COMPRESSED_VERSION => {
+                let compressed: Compressed = serde_json::from_value(value).map_err(invalid)?;
+                let data = STANDARD.decode(compressed.data).map_err(invalid)?;
+                let data = compressed
+                    .compression
+                    .decompress(&data, max_size)
+                    .map_err(invalid)?;
+                serde_json::from_slice(&data).map_err(invalid)
+            }
+            version if version > VERSION => Err(WireError::UnsupportedVersion(version)),
+            _ => {
+                if let Some(object) = value.as_object_mut() {
+                    object.remove(VERSION_FIELD);
+                }
+                serde_json::from_value(value).map_err(invalid)
+            }
+        }
+    }
+
+    /// Encodes the message as the version byte followed by the JSON representation.
+    pub fn to_bytes(message: &Message) -> Vec<u8> {
+        let mut data = vec![PLAIN_VERSION];
+        serde_json::to_writer(&mut data, message).expect("A message is always serializable");
+        data
+    }
+
+    /// Like [`to_bytes()`] but compressing the message according to `compression`.
+    /// The compressed data is the version byte, a byte identifying the [`Codec`],
+    /// and the compressed JSON representation.
+    pub fn to_bytes_compressed(message: &Message, compression: Compression) -> Vec<u8> {
+        let data = serde_json::to_vec(message).expect("A message is always serializable");
+        match compression.codec_for(&data) {
+            Some(codec) => {
+                let mut compressed = vec![COMPRESSED_VERSION, codec.id()];
+                compressed.extend(codec.compress(&data));
+                compressed
+            }
+            None => to_bytes(message),
+        }
+    }
+
+    /// Decodes a message encoded by [`to_bytes()`] or [`to_bytes_compressed()`]
+    /// of this or a previous version.
+    /// The compressed messages can not exceed [`MAX_DECOMPRESSED_SIZE`] once decompressed.
+    pub fn from_bytes(data: &[u8]) -> Result<Message, WireError> {
+        from_bytes_limited(data, MAX_DECOMPRESSED_SIZE)
+    }
+
+    /// Like [`from_bytes()`] but the compressed messages can not exceed
+    /// `max_size` bytes once decompressed.
+    pub fn from_bytes_limited(data: &[u8], max_size: usize) -> Result<Message, WireError> {
+        match data.first() {
+            // Plain JSON written without version.
+            Some(b'{') => serde_json::from_slice(data).map_err(invalid),
+            Some(&COMPRESSED_VERSION) => {
+                let codec = data
+                    .get(1)
+                    .and_then(|&id| Codec::from_id(id))
+                    .ok_or_else(|| WireError::Invalid("Unknown compression".into()))?;
+                let data = codec.decompress(&data[2..], max_size).map_err(invalid)?;
+                serde_json::from_slice(&data).map_err(invalid)
+            }
+            Some(&version) if version > VERSION => Err(WireError::UnsupportedVersion(version)),
+            Some(_) => serde_json::from_slice(&data[1..]).map_err(invalid),
+            None => Err(WireError::Invalid("Empty data".into())),
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn message() -> Message {
+            Message::default()
+                .user("user")
+                .service_name("s-echo")
+                .args(["a", "b"])
+                .attach([("file", vec![1, 2, 3])])
+        }
+
+        #[test]
+        fn json_compatibility() {
+            let legacy = serde_json::to_string(&message()).unwrap();
+            assert_eq!(from_json(&legacy).unwrap(), message());
+
+            let newer = r#"{"version":1,"user":"user","new_field":3}"#;
+            assert_eq!(from_json(newer).unwrap(), Message::default().user("user"));
+
+            let unsupported = r#"{"version":200,"user":"user"}"#;
+            assert_eq!(
+                from_json(unsupported).unwrap_err(),
+                WireError::UnsupportedVersion(200)
+            );
+        }
+
+        #[test]
+        fn bytes_compatibility() {
+            assert_eq!(from_bytes(&to_bytes(&message())).unwrap(), message());
+
+            let legacy = serde_json::to_vec(&message()).unwrap();
+            assert_eq!(from_bytes(&legacy).unwrap(), message());
+
+            let mut unsupported = to_bytes(&message());
+            unsupported[0] = VERSION + 1;
+            assert_eq!(
+                from_bytes(&unsupported).unwrap_err(),
+                WireError::UnsupportedVersion(VERSION + 1)
+            );
+            assert!(matches!(from_bytes(&[]), Err(WireError::Invalid(_))));
+        }
+
+        #[test]
+        fn compression() {
+            let big = message().body("a".repeat(10_000));
+            for compression in [Compression::gzip(1000), Compression::zstd(1000)] {
+                let line = to_json_compressed(&big, compression);
+                assert!(line.len() < 1000);
+                assert!(line.starts_with(r#"{"version":2"#));
+                assert_eq!(from_json(&line).unwrap(), big);
+
+                let bytes = to_bytes_compressed(&big, compression);
+                assert!(bytes.len() < 1000);
+                assert_eq!(from_bytes(&bytes).unwrap(), big);
+
+                let small = to_bytes_compressed(&message(), compression);
+                assert_eq!(small, to_bytes(&message()));
+            }
+        }
+
+        #[test]
+        fn decompression_limit() {
+            let big = message().body("a".repeat(10_000));
+            for compression in [Compression::gzip(1000), Compression::zstd(1000)] {
+                let line = to_json_compressed(&big, compression);
+                assert!(matches!(
+                    from_json_limited(&line, 5_000),
+                    Err(WireError::Invalid(_))
+                ));
+                assert_eq!(from_json_limited(&line, 20_000).unwrap(), big);
+
+                let bytes = to_bytes_compressed(&big, compression);
+                assert!(matches!(
+                    from_bytes_limited(&bytes, 5_000),
+                    Err(WireError::Invalid(_))
+                ));
+            }
+        }
+    }
+}
+
+/// Helpers to render structured replies (tables, key-value lists and code blocks)
+/// that look decent in every transport.
+///
+/// A [`Document`] is rendered as plain text in the [`Message::body`], and as HTML in the
+/// [`Message::metadata`] with the [`HTML_BODY_KEY`], so the output connectors able to show HTML
+/// (as [`SmtpClient`]) can use it instead.
+///
+/// # Example
+/// ```rust
+/// use service_io::message::format::{Document, HTML_BODY_KEY};
+/// use service_io::message::Message;
+///
+/// let request = Message::default().user("user_01").service_name("s-sysinfo");
+/// let response = Document::default()
+///     .text("Disk usage")
+///     .table(["Mount", "Used"], [["/", "40%"], ["/home", "75%"]])
+///     .key_values([("CPU", "12%"), ("Memory", "3.2 GB")])
+///     .apply(Message::response(&request));
+///
+/// assert_eq!(
+///     response.body,
+///     "Disk usage\n\nMount  Used\n-----  ----\n/      40%\n/home  75%\n\nCPU:    12%\nMemory: 3.2 GB"
+/// );
+/// assert!(response.metadata[HTML_BODY_KEY].contains("<td>/home</td>"));
+/// ```
+///
+/// [`Document`]: format::Document
+/// [`HTML_BODY_KEY`]: format::HTML_BODY_KEY
+/// [`SmtpClient`]: crate::connectors::SmtpClient
+pub mod format {
+    use super::Message;
+
+    use std::fmt::Write;
+
+    /// Key of [`Message::metadata`] with the HTML version of the [`Message::body`].
+    pub const HTML_BODY_KEY: &str = "html-body";
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    enum Block {
+        Text(String),
+        Table {
+            header: Vec<String>,
+            rows: Vec<Vec<String>>,
+        },
+        KeyValues(Vec<(String, String)>),
+        Code(String),
+    }
+
+    /// Sequence of blocks to render as a message body.
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct Document {
+        blocks: Vec<Block>,
+    }
+
+    impl Document {
+        /// Add a paragraph.
+        pub fn text(mut self, text: impl Into<String>) -> Self {
+            self.blocks.push(Block::Text(text.into()));
+            self
+        }
+
+        /// Add a table. The rows shorter than the header are filled with empty cells.
+        pub fn table<H, R, C>(
+            mut self,
+            header: impl IntoIterator<Item = H>,
+            rows: impl IntoIterator<Item = R>,
+        ) -> Self
+        where
+            H: Into<String>,
+            R: IntoIterator<Item = C>,
+            C: Into<String>,
+        {
+            self.blocks.push(Block::Table {
+                header: header.into_iter().map(|h| h.into()).collect(),
+                rows: rows
+                    .into_iter()
+                    .map(|row| row.into_iter().map(|c| c.into()).collect())
+                    .collect(),
+            });
+            self
+        }
+
+        /// Add a list of `key: value` lines.
+        pub fn key_values<K: Into<String>, V: Into<String>>(
+            mut self,
+            pairs: impl IntoIterator<Item = (K, V)>,
+        ) -> Self {
+            self.blocks.push(Block::KeyValues(
+                pairs
+                    .into_iter()
+                    .map(|(key, value)| (key.into(), value.into()))
+                    .collect(),
+            ));
+            self
+        }
+
+        /// Add preformatted text, as the output of a command.
+        pub fn code(mut self, code: impl Into<String>) -> Self {
+            self.blocks.push(Block::Code(code.into()));
+            self
+        }
+
+        /// Render as plain text, with the columns aligned for monospace fonts.
+        pub fn plain(&self) -> String {
+            self.blocks
+                .iter()
+                .map(|block| match block {
+                    Block::Text(text) => text.clone(),
+                    Block::Table { header, rows } => plain_table(header, rows),
+                    Block::KeyValues(pairs) => {
+                        let width = pairs.iter().map(|(k, _)| width(k)).max().unwrap_or(0);
+                        pairs
+                            .iter()
+                            .map(|(key, value)| {
+                                let key = format!("{}:", key);
+                                format!("{} {}", pad(&key, width + 1), value)
+                                    .trim_end()
+                                    .to_string()
+                            })
+                            .collect::<Vec<_>>()
+                            .join("\n")
+                    }
+                    Block::Code(code) => code
+                        .lines()
+                        .map(|line| format!("    {}", line))
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                })
+                .collect::<Vec<_>>()
+                .join("\n\n")
+        }
+
+        /// Render as HTML.
+        pub fn html(&self) -> String {
+            let mut html = String::new();
+            for block in &self.blocks {
+                match block {
+                    Block::Text(text) => {
+                        let text = escape(text).replace('\n', "<br>");
+                        write!(html, "<p>{}</p>", text).unwrap();
+                    }
+                    Block::Table { header, rows } => {
+                        html.push_str("<table><thead><tr>");
+                        for cell in header {
+                            write!(html, "<th>{}</th>", escape(cell)).unwrap();
+                        }
+                        html.push_str("</tr></thead><tbody>");
+                        for row in rows {
+                            html.push_str("<tr>");
+                            for cell in row {
+                                write!(html, "<td>{}</td>", escape(cell)).unwrap();
+                            }
+                            for _ in row.len()..header.len() {
+                                html.push_str("<td></td>");
+                            }
+                            html.push_str("</tr>");
+                        }
+                        html.push_str("</tbody></table>");
+                    }
+                    Block::KeyValues(pairs) => {
+                        html.push_str("<table>");
+                        for (key, value) in pairs {
+                            let (key, value) = (escape(key), escape(value));
+                            write!(html, "<tr><th>{}</th><td>{}</td></tr>", key, value).unwrap();
+                        }
+                        html.push_str("</table>");
+                    }
+                    Block::Code(code) => {
+                        write!(html, "<pre><code>{}</code></pre>", escape(code)).unwrap();
+                    }
+                }
+            }
+            html
+        }
+
+        /// Set the rendered document as the body of the message,
+        /// adding the HTML version with the [`HTML_BODY_KEY`].
+        pub fn apply(&self, mut message: Message) -> Message {
+            message.body = self.plain();
+            message.metadata.insert(HTML_BODY_KEY.into(), self.html());
+            message
+        }
+    }
+
+    fn width(text: &str) -> usize {
+        text.chars().count()
+    }
+
+    fn pad(text: &str, width: usize) -> String {
+        format!(
+            "{}{}",
+            text,
+            " ".repeat(width.saturating_sub(self::width(text)))
+        )
+    }
+
+    fn plain_table(header: &[String], rows: &[Vec<String>]) -> String {
+        let columns = rows
+            .iter()
+            .map(|row| row.len())
+            .fold(header.len(), usize::max);
+        let cell = |row: &[String], column: usize| row.get(column).cloned().unwrap_or_default();
+        let widths = (0..columns)
+            .map(|column| {
+                std::iter::once(header)
+                    .chain(rows.iter().map(|row| row.as_slice()))
+                    .map(|row| width(&cell(row, column)))
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect::<Vec<_>>();
+
+        let line = |row: &[String]| {
+            (0..columns)
+                .map(|column| pad(&cell(row, column), widths[column]))
+                .collect::<Vec<_>>()
+                .join("  ")
+                .trim_end()
+                .to_string()
+        };
+
+        let separator = widths.iter().map(|w| "-".repeat(*w)).collect::<Vec<_>>();
+        std::iter::once(line(header))
+            .chain(std::iter::once(separator.join("  ")))
+            .chain(rows.iter().map(|row| line(row)))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn escape(text: &str) -> String {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn plain_and_html() {
+            let document = Document::default()
+                .table(["name", "size"], [vec!["a <b>", "1"], vec!["ñandú"]])
+                .code("fn main() {\n}");
+
+            assert_eq!(
+                document.plain(),
+                "name   size\n-----  ----\na <b>  1\nñandú\n\n    fn main() {\n    }"
+            );
+            assert_eq!(
+                document.html(),
+                "<table><thead><tr><th>name</th><th>size</th></tr></thead><tbody>\
+                 <tr><td>a &lt;b&gt;</td><td>1</td></tr><tr><td>ñandú</td><td></td></tr>\
+                 </tbody></table><pre><code>fn main() {\n}</code></pre>"
+            );
+        }
+
+        #[test]
+        fn short_rows() {
+            let document = Document::default().table(["a", "b", "c"], [vec!["1"], vec![]]);
+            assert_eq!(
+                document.html(),
+                "<table><thead><tr><th>a</th><th>b</th><th>c</th></tr></thead><tbody>\
+                 <tr><td>1</td><td></td><td></td></tr><tr><td></td><td></td><td></td></tr>\
+                 </tbody></table>"
+            );
+        }
+    }
 }
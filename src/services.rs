@@ -3,11 +3,83 @@
 mod echo;
 pub use echo::Echo;
 
+mod auto_reply;
+pub use auto_reply::AutoReply;
+
 mod alarm;
 pub use alarm::Alarm;
 
+#[cfg(feature = "public-ip")]
 mod public_ip;
-pub use self::public_ip::PublicIp;
+#[cfg(feature = "public-ip")]
+pub use self::public_ip::{IpProvider, PublicIp};
 
+#[cfg(feature = "process")]
 mod process;
+#[cfg(feature = "process")]
 pub use process::Process;
+
+mod inspect;
+pub use inspect::Inspect;
+
+mod admin;
+pub use admin::Admin;
+
+pub(crate) mod settings;
+pub use settings::{Settings, OUTPUT_CHANNEL_KEY};
+
+mod router;
+pub use router::Router;
+
+pub(crate) mod otp;
+
+mod protected;
+pub use protected::Protected;
+
+mod totp;
+pub use totp::Totp;
+
+mod breaker;
+pub use breaker::CircuitBreaker;
+
+mod retry;
+pub use retry::Retry;
+
+mod aggregate;
+pub use aggregate::Aggregate;
+
+mod canary;
+pub use canary::{Canary, CanaryMetrics, VariantStats};
+
+mod jobs;
+pub use jobs::{Job, JobInfo, JobRegistry, Jobs};
+
+#[cfg(feature = "process")]
+mod external;
+#[cfg(feature = "process")]
+pub use external::External;
+
+#[cfg(feature = "process")]
+mod tunnel;
+#[cfg(feature = "process")]
+pub use tunnel::Tunnel;
+
+#[cfg(feature = "process")]
+mod capture;
+#[cfg(feature = "process")]
+pub use capture::Capture;
+
+#[cfg(feature = "systemd")]
+mod systemd;
+#[cfg(feature = "systemd")]
+pub use systemd::Systemd;
+
+#[cfg(feature = "wasm")]
+mod wasm_plugin;
+#[cfg(feature = "wasm")]
+pub use wasm_plugin::WasmPlugin;
+
+#[cfg(feature = "script")]
+mod script;
+#[cfg(feature = "script")]
+pub use script::Script;
@@ -0,0 +1,80 @@
+use crate::channel::{ClosedChannel, Receiver, Sender};
+use crate::engine::EngineHandle;
+use crate::i18n;
+use crate::interface::Service;
+use crate::message::Message;
+
+use async_trait::async_trait;
+
+/// Manage the engine at runtime by messages. Supported commands:
+/// - `allow <service> <user>`: add the user to the whitelist of the service.
+/// - `disallow <service> <user>`: remove the user from the whitelist of the service.
+/// - `whitelist <service>`: list the users of the whitelist of the service.
+/// - `describe`: configuration of the engine as JSON. See [`EngineHandle::describe()`].
+///
+/// See [`EngineHandle::allow_user()`] to persist the changes.
+/// Register it with [`Engine::add_service_for()`] to only allow the administrators.
+///
+/// [`Engine::add_service_for()`]: crate::engine::Engine::add_service_for()
+pub struct Admin;
+
+#[async_trait]
+impl Service for Admin {
+    async fn run(
+        self: Box<Self>,
+        mut input: Receiver,
+        output: Sender,
+    ) -> Result<(), ClosedChannel> {
+        let engine = EngineHandle::current();
+        loop {
+            let request = input.recv().await?;
+            let response = match &engine {
+                Some(engine) => process(engine, &request).await,
+                None => {
+                    log::error!("Admin service is not running inside an engine");
+                    failure(&request, "admin-failed")
+                }
+            };
+            output.send(response).await?;
+        }
+    }
+}
+
+async fn process(engine: &EngineHandle, request: &Message) -> Message {
+    let result = match request.args_str().as_slice() {
+        ["allow", service, user] => engine.allow_user(service, user).await,
+        ["disallow", service, user] => engine.disallow_user(service, user).await,
+        ["whitelist", service] => {
+            return match engine.whitelist(service) {
+                Some(users) => Message::response(request).body(users.join("\n")),
+                None => failure(request, "admin-no-whitelist"),
+            }
+        }
+        ["describe"] => {
+            return match engine.describe().map(|d| serde_json::to_string_pretty(&d)) {
+                Some(Ok(description)) => Message::response(request).body(description),
+                _ => failure(request, "admin-failed"),
+            }
+        }
+        _ => {
+            return Message::response(request)
+                .args([i18n::text(request, "format-error")])
+                .body(i18n::text(request, "admin-expected-args"))
+        }
+    };
+
+    match result {
+        Ok(true) => Message::response(request).body(i18n::text(request, "admin-done")),
+        Ok(false) => failure(request, "admin-not-modified"),
+        Err(err) => {
+            log::error!("Whitelist could not be persisted: {}", err);
+            failure(request, "admin-failed")
+        }
+    }
+}
+
+fn failure(request: &Message, key: &str) -> Message {
+    Message::response(request)
+        .args([i18n::text(request, "error")])
+        .body(i18n::text(request, key))
+}
@@ -0,0 +1,259 @@
+use crate::channel::{self, ClosedChannel, Receiver, Sender};
+use crate::engine::EngineHandle;
+use crate::error::Error;
+use crate::i18n;
+use crate::interface::{Service, StopHook};
+use crate::message::Message;
+
+use async_trait::async_trait;
+use futures::future;
+use tokio::sync::mpsc;
+use tokio::time::{self, Instant};
+
+use std::time::Duration;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Invoke several services with the same request and reply with a combined report of
+/// their responses, one section per service in the order they were added.
+/// The services that do not reply before the timeout are reported as such.
+/// The attachments of all the responses are included in the report.
+///
+/// Requests are aggregated one after another:
+/// a new request is not dispatched until the previous report is sent.
+///
+/// # Example
+/// ```rust no_run
+/// use service_io::connectors::{DebugStdout, UserStdin};
+/// use service_io::engine::Engine;
+/// use service_io::services::{Aggregate, Echo, PublicIp};
+///
+/// use std::time::Duration;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     Engine::default()
+///         .input(UserStdin("user"))
+///         .output(DebugStdout)
+///         .add_service(
+///             "s-status",
+///             Aggregate::default()
+///                 .add("ip", PublicIp::default())
+///                 .add("echo", Echo)
+///                 .timeout(Duration::from_secs(5)),
+///         )
+///         .run()
+///         .await;
+/// }
+/// ```
+pub struct Aggregate {
+    parts: Vec<(String, Box<dyn Service + Send>)>,
+    timeout: Duration,
+}
+
+impl Default for Aggregate {
+    fn default() -> Self {
+        Aggregate {
+            parts: Vec::new(),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+impl Aggregate {
+    /// Add a service whose response is reported under `title`.
+    pub fn add(mut self, title: impl Into<String>, service: impl Service + Send + 'static) -> Self {
+        self.parts.push((title.into(), Box::new(service)));
+        self
+    }
+
+    /// Maximum time to wait for the responses of the services. By default 10 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+#[async_trait]
+impl Service for Aggregate {
+    async fn on_start(&mut self) -> Result<(), Error> {
+        for (_, service) in &mut self.parts {
+            service.on_start().await?;
+        }
+        Ok(())
+    }
+
+    fn on_stop(&mut self) -> Option<StopHook> {
+        let hooks = self
+            .parts
+            .iter_mut()
+            .filter_map(|(_, service)| service.on_stop())
+            .collect::<Vec<_>>();
+
+        match hooks.is_empty() {
+            true => None,
+            false => Some(Box::pin(async move {
+                future::join_all(hooks).await;
+            })),
+        }
+    }
+
+    async fn run(
+        self: Box<Self>,
+        mut input: Receiver,
+        output: Sender,
+    ) -> Result<(), ClosedChannel> {
+        let mut parts = self
+            .parts
+            .into_iter()
+            .map(|(title, service)| {
+                let (sender, receiver) = mpsc::channel(32);
+                let (service_output, replies) = channel::channel(32);
+                let token = input.cancellation_token();
+                let name = format!("Aggregate '{}' ({})", title, service.describe());
+                EngineHandle::spawn(
+                    name,
+                    service.run(Receiver(receiver, token, None), service_output),
+                );
+                (title, sender, replies)
+            })
+            .collect::<Vec<_>>();
+
+        loop {
+            let request = input.recv().await?;
+            for (title, sender, replies) in &mut parts {
+                // Discard the late replies of previous requests.
+                while replies.0.try_recv().is_ok() {}
+                if sender.send(request.clone()).await.is_err() {
+                    log::warn!("Drop message for finished service '{}'", title);
+                }
+            }
+
+            let deadline = Instant::now() + self.timeout;
+            let mut response = Message::response(&request);
+            let mut sections = Vec::with_capacity(parts.len());
+            for (title, _, replies) in &mut parts {
+                let body = match wait_reply(replies, &request.user, deadline).await {
+                    Ok(Some(reply)) => {
+                        response.attached_data.extend(reply.attached_data);
+                        match reply.args.is_empty() {
+                            true => reply.body,
+                            false => format!("[{}] {}", reply.args.join(" "), reply.body),
+                        }
+                    }
+                    Ok(None) => i18n::text(&request, "aggregate-no-reply"),
+                    Err(_) => i18n::text(&request, "aggregate-down"),
+                };
+                sections.push(format!("== {} ==\n{}", title, body.trim_end()));
+            }
+
+            output.send(response.body(sections.join("\n\n"))).await?;
+        }
+    }
+}
+
+/// Waits until `deadline` for the first reply to `user` that is not a progress message.
+/// Fails at once if the service is not running.
+async fn wait_reply(
+    replies: &mut Receiver,
+    user: &str,
+    deadline: Instant,
+) -> Result<Option<Message>, ClosedChannel> {
+    let reply = async {
+        loop {
+            let reply = replies.recv().await?;
+            if reply.user == user && !reply.is_progress() {
+                break Ok(reply);
+            }
+        }
+    };
+    time::timeout_at(deadline, reply).await.ok().transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::Echo;
+
+    /// Never replies.
+    struct Silent;
+
+    #[async_trait]
+    impl Service for Silent {
+        async fn run(
+            self: Box<Self>,
+            mut input: Receiver,
+            _output: Sender,
+        ) -> Result<(), ClosedChannel> {
+            loop {
+                input.recv().await?;
+            }
+        }
+    }
+
+    /// Panics with the first request.
+    struct Panicking;
+
+    #[async_trait]
+    impl Service for Panicking {
+        async fn run(
+            self: Box<Self>,
+            mut input: Receiver,
+            _output: Sender,
+        ) -> Result<(), ClosedChannel> {
+            input.recv().await?;
+            panic!("member panicked");
+        }
+    }
+
+    #[tokio::test]
+    async fn panicked_member() {
+        let aggregate = Aggregate::default()
+            .add("panicking", Panicking)
+            .add("echo", Echo)
+            .timeout(Duration::from_secs(60));
+
+        let (input, service_input) = channel::channel(4);
+        let (service_output, mut output) = channel::channel(4);
+        tokio::spawn(Box::new(aggregate).run(service_input, service_output));
+
+        let request = Message::default().user("user").body("hello");
+        input.send(request).await.unwrap();
+
+        // Reported without waiting for the timeout.
+        let response = time::timeout(Duration::from_secs(5), output.recv()).await;
+        assert_eq!(
+            response.unwrap().unwrap().body,
+            "== panicking ==\nThe service is not running\n\n== echo ==\nhello"
+        );
+    }
+
+    #[tokio::test]
+    async fn report() {
+        let aggregate = Aggregate::default()
+            .add("first", Echo)
+            .add("silent", Silent)
+            .add("second", Echo)
+            .timeout(Duration::from_millis(50));
+
+        let (input, service_input) = channel::channel(4);
+        let (service_output, mut output) = channel::channel(4);
+        tokio::spawn(Box::new(aggregate).run(service_input, service_output));
+
+        let request = Message::default()
+            .user("user")
+            .service_name("s-status")
+            .args(["arg"])
+            .body("hello")
+            .attach([("file", b"1234".to_vec())]);
+        input.send(request).await.unwrap();
+
+        let response = output.recv().await.unwrap();
+        assert_eq!(response.service_name, "s-status");
+        assert_eq!(
+            response.body,
+            "== first ==\n[arg] hello\n\n== silent ==\nNo reply in time\n\n== second ==\n[arg] hello"
+        );
+        assert_eq!(response.attached_data.len(), 1);
+    }
+}
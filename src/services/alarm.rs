@@ -1,16 +1,32 @@
 use crate::channel::{ClosedChannel, Receiver, Sender};
+use crate::i18n;
 use crate::interface::Service;
 use crate::message::Message;
+use crate::time;
 
 use async_trait::async_trait;
-use tokio::time;
 
 use std::time::Duration;
 
-/// Allow to create alarms given a name and a time in minutes.
+/// Allow to create alarms given a name and a time in minutes,
+/// or a clock time (`HH:MM` or `YYYY-MM-DD HH:MM`) in the timezone of the user
+/// (see [`time`]).
 /// Once the time is over, a response is generated.
 pub struct Alarm;
 
+/// Time to wait for the alarm written in the args.
+fn delay(request: &Message) -> Option<Duration> {
+    let when = match request.args_str().as_slice() {
+        [_, when] => match when.parse::<u64>() {
+            Ok(minutes) => return Some(Duration::from_secs(minutes * 60)),
+            Err(_) => time::parse_time(request, when)?,
+        },
+        [_, date, clock] => time::parse_time(request, &format!("{} {}", date, clock))?,
+        _ => return None,
+    };
+    (when - chrono::Utc::now()).to_std().ok()
+}
+
 #[async_trait]
 impl Service for Alarm {
     async fn run(
@@ -20,25 +36,26 @@ impl Service for Alarm {
     ) -> Result<(), ClosedChannel> {
         loop {
             let request = input.recv().await?;
-            let args = request.args.iter().map(|s| s.as_str()).collect::<Vec<_>>();
-
-            if let [name, minutes] = args.as_slice() {
-                if let Ok(minutes) = minutes.parse::<u64>() {
-                    tokio::spawn({
-                        let output = output.clone();
-                        let response = Message::response(&request).args([*name]);
-                        async move {
-                            time::sleep(Duration::from_secs(minutes * 60)).await;
-                            output.send(response).await.ok();
+            if let Some(delay) = delay(&request) {
+                tokio::spawn({
+                    let output = output.clone();
+                    let token = input.cancellation_token();
+                    let response = Message::response(&request).args([request.args[0].clone()]);
+                    async move {
+                        tokio::select! {
+                            _ = tokio::time::sleep(delay) => {
+                                output.send(response).await.ok();
+                            }
+                            _ = token.cancelled() => (),
                         }
-                    });
-                    continue;
-                }
+                    }
+                });
+                continue;
             }
 
             let response = Message::response(&request)
-                .args(["format error"])
-                .body("Expected args: <name> <minutes: POSITIVE_NUMBER>");
+                .args([i18n::text(&request, "format-error")])
+                .body(i18n::text(&request, "alarm-expected-args"));
 
             output.send(response).await?;
         }
@@ -0,0 +1,131 @@
+use crate::error::ServiceError;
+use crate::interface::{Context, SimpleService};
+use crate::message::Message;
+
+use async_trait::async_trait;
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Replies to any request with a fixed template, the bot equivalent of an out-of-office.
+/// Usually registered as the [`Engine::default_service()`] to explain the available commands
+/// to users sending unknown ones.
+///
+/// The template can contain the following placeholders:
+/// - `{user}`: the user sending the request.
+/// - `{command}`: the arguments of the request. When used as default service,
+///   the first one is the unknown service name.
+/// - `{services}`: the services the user is allowed to use, one per line.
+///
+/// [`Engine::default_service()`]: crate::engine::Engine::default_service()
+pub struct AutoReply {
+    template: String,
+    cooldown: Option<Duration>,
+    replied: Mutex<HashMap<String, Instant>>,
+}
+
+impl AutoReply {
+    pub fn new(template: impl Into<String>) -> AutoReply {
+        AutoReply {
+            template: template.into(),
+            cooldown: None,
+            replied: Mutex::default(),
+        }
+    }
+
+    /// Reply at most once to each user during `cooldown`, ignoring the other requests.
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = Some(cooldown);
+        self
+    }
+
+    fn should_reply(&self, user: &str) -> bool {
+        let cooldown = match self.cooldown {
+            Some(cooldown) => cooldown,
+            None => return true,
+        };
+
+        let now = Instant::now();
+        let mut replied = self.replied.lock().unwrap();
+        replied.retain(|_, last| now.duration_since(*last) < cooldown);
+        if replied.contains_key(user) {
+            return false;
+        }
+        replied.insert(user.into(), now);
+        true
+    }
+
+    fn services(user: &str, context: &Context) -> String {
+        let description = match context.engine().and_then(|engine| engine.describe()) {
+            Some(description) => description,
+            None => return String::new(),
+        };
+
+        description
+            .services
+            .iter()
+            .filter(|service| service.name != context.service_name())
+            .filter(|service| match &service.whitelist {
+                Some(users) => users.iter().any(|allowed| allowed == user),
+                None => true,
+            })
+            .map(|service| service.name.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[async_trait]
+impl SimpleService for AutoReply {
+    async fn handle(
+        &self,
+        request: Message,
+        context: &Context,
+    ) -> Result<Vec<Message>, ServiceError> {
+        if !self.should_reply(&request.user) {
+            return Ok(Vec::new());
+        }
+
+        let mut body = self
+            .template
+            .replace("{user}", &request.user)
+            .replace("{command}", &request.args.join(" "));
+        if body.contains("{services}") {
+            body = body.replace("{services}", &Self::services(&request.user, context));
+        }
+
+        Ok(vec![Message::response(&request).body(body)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel;
+    use crate::engine::EngineHandle;
+    use crate::interface::Service;
+
+    #[tokio::test]
+    async fn template_and_cooldown() {
+        let service =
+            AutoReply::new("Hi {user}, '{command}' is unknown").cooldown(Duration::from_secs(60));
+
+        let (input, service_input) = channel::channel(4);
+        let (service_output, mut output) = channel::channel(4);
+        let service = Box::new(service).run(service_input, service_output);
+        tokio::spawn(EngineHandle::default().scope(service));
+
+        let request = |user: &str| Message::default().user(user).args(vec!["s-foo", "bar"]);
+        input.send(request("user")).await.unwrap();
+        assert_eq!(
+            output.recv().await.unwrap().body,
+            "Hi user, 's-foo bar' is unknown"
+        );
+
+        // The second request of the same user is ignored during the cooldown.
+        input.send(request("user")).await.unwrap();
+        input.send(request("other")).await.unwrap();
+        assert_eq!(output.recv().await.unwrap().user, "other");
+    }
+}
@@ -0,0 +1,283 @@
+use crate::channel::{self, ClosedChannel, Receiver, Sender};
+use crate::engine::EngineHandle;
+use crate::error::Error;
+use crate::i18n;
+use crate::interface::{Service, StopHook};
+use crate::message::Message;
+
+use async_trait::async_trait;
+use tokio::sync::mpsc;
+use tokio::time::{self, Instant};
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Stop sending requests to the wrapped service after repeated failures,
+/// replying to the users that it is temporarily unavailable instead of leaving them waiting.
+///
+/// A request fails if the service replies with an `error` first arg,
+/// or does not reply before [`CircuitBreaker::timeout()`].
+/// After [`CircuitBreaker::max_failures()`] failures in a row, the requests are rejected
+/// for [`CircuitBreaker::cooldown()`]. Then, the next request is sent to probe the service:
+/// if it succeeds, the service is used again, otherwise it is rejected for another cooldown.
+///
+/// Services that do not reply to every request (e.g. an alarm) are seen as failing.
+///
+/// # Example
+/// ```rust no_run
+/// use service_io::connectors::{ImapClient, SmtpClient};
+/// use service_io::engine::Engine;
+/// use service_io::services::{CircuitBreaker, PublicIp};
+///
+/// use std::time::Duration;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     Engine::default()
+///         .input(ImapClient::default() /* ... */)
+///         .output(SmtpClient::default() /* ... */)
+///         .add_service(
+///             "s-public-ip",
+///             CircuitBreaker::new(PublicIp::default()).cooldown(Duration::from_secs(5 * 60)),
+///         )
+///         .run()
+///         .await;
+/// }
+/// ```
+pub struct CircuitBreaker {
+    service: Box<dyn Service + Send>,
+    max_failures: u32,
+    timeout: Duration,
+    cooldown: Duration,
+}
+
+impl CircuitBreaker {
+    /// Wraps `service`.
+    pub fn new(service: impl Service + Send + 'static) -> Self {
+        Self {
+            service: Box::new(service),
+            max_failures: 5,
+            timeout: Duration::from_secs(60),
+            cooldown: Duration::from_secs(60),
+        }
+    }
+
+    /// Failures in a row before rejecting the requests. By default, 5.
+    pub fn max_failures(mut self, failures: u32) -> Self {
+        self.max_failures = failures.max(1);
+        self
+    }
+
+    /// Time the service has to reply a request. By default, 1 minute.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Time rejecting the requests before probing the service again. By default, 1 minute.
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+}
+
+enum State {
+    Closed {
+        failures: u32,
+    },
+    Open {
+        until: Instant,
+    },
+    /// A request was sent to probe the service.
+    HalfOpen,
+}
+
+/// Decides whether the requests reach the service.
+struct Breaker {
+    state: State,
+    max_failures: u32,
+    cooldown: Duration,
+}
+
+impl Breaker {
+    fn allows(&mut self) -> bool {
+        match self.state {
+            State::Closed { .. } => true,
+            State::Open { until } if Instant::now() >= until => {
+                log::info!("Probing the service");
+                self.state = State::HalfOpen;
+                true
+            }
+            State::Open { .. } | State::HalfOpen => false,
+        }
+    }
+
+    fn record(&mut self, success: bool) {
+        self.state = match (&self.state, success) {
+            (State::Closed { .. } | State::HalfOpen, true) => State::Closed { failures: 0 },
+            (State::Closed { failures }, false) if failures + 1 < self.max_failures => {
+                State::Closed {
+                    failures: failures + 1,
+                }
+            }
+            (State::Closed { .. } | State::HalfOpen, false) => {
+                log::warn!(
+                    "Service failing, rejecting requests for {:?}",
+                    self.cooldown
+                );
+                State::Open {
+                    until: Instant::now() + self.cooldown,
+                }
+            }
+            // Late results of requests sent before opening.
+            (State::Open { until }, _) => State::Open { until: *until },
+        };
+    }
+}
+
+#[async_trait]
+impl Service for CircuitBreaker {
+    async fn on_start(&mut self) -> Result<(), Error> {
+        self.service.on_start().await
+    }
+
+    fn on_stop(&mut self) -> Option<StopHook> {
+        self.service.on_stop()
+    }
+
+    async fn run(
+        self: Box<Self>,
+        mut input: Receiver,
+        output: Sender,
+    ) -> Result<(), ClosedChannel> {
+        let CircuitBreaker {
+            service,
+            max_failures,
+            timeout,
+            cooldown,
+        } = *self;
+
+        let (sender, receiver) = mpsc::channel(32);
+        let (service_output, mut replies) = channel::channel(32);
+        let token = input.cancellation_token();
+        let name = format!("CircuitBreaker ({})", service.describe());
+        EngineHandle::spawn(
+            name,
+            service.run(Receiver(receiver, token, None), service_output),
+        );
+
+        let mut breaker = Breaker {
+            state: State::Closed { failures: 0 },
+            max_failures,
+            cooldown,
+        };
+        // Users waiting for a reply, with the deadline of their request.
+        let mut pending: VecDeque<(String, Instant)> = VecDeque::new();
+        let mut replying = true;
+        loop {
+            let deadline = pending.front().map(|(_, deadline)| *deadline);
+            tokio::select! {
+                request = input.recv() => {
+                    let request = request?;
+                    if !breaker.allows() {
+                        let response = Message::response(&request)
+                            .args([i18n::text(&request, "error")])
+                            .body(i18n::text(&request, "breaker-unavailable"));
+                        output.send(response).await?;
+                        continue;
+                    }
+                    pending.push_back((request.user.clone(), Instant::now() + timeout));
+                    if sender.send(request).await.is_err() {
+                        log::warn!("Drop message for a finished service");
+                    }
+                }
+                reply = replies.recv(), if replying => match reply {
+                    Ok(reply) => {
+                        if let Some(index) = pending.iter().position(|(user, _)| *user == reply.user) {
+                            pending.remove(index);
+                            let failed = reply.args.first() == Some(&i18n::text(&reply, "error"));
+                            breaker.record(!failed);
+                        }
+                        output.send(reply).await?;
+                    }
+                    Err(_) => replying = false,
+                },
+                _ = time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                    let (user, _) = pending.pop_front().unwrap();
+                    log::warn!("The service did not reply to '{}' in time", user);
+                    breaker.record(false);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Replies to the requests with the args they have, ignoring the "silent" ones.
+    struct Flaky;
+
+    #[async_trait]
+    impl Service for Flaky {
+        async fn run(
+            self: Box<Self>,
+            mut input: Receiver,
+            output: Sender,
+        ) -> Result<(), ClosedChannel> {
+            loop {
+                let request = input.recv().await?;
+                if request.args != ["silent"] {
+                    output
+                        .send(Message::response(&request).args(request.args))
+                        .await?;
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn open_and_close() {
+        let service = CircuitBreaker::new(Flaky)
+            .max_failures(2)
+            .timeout(Duration::from_millis(20))
+            .cooldown(Duration::from_millis(50));
+
+        let (input, service_input) = channel::channel(4);
+        let (service_output, mut output) = channel::channel(4);
+        tokio::spawn(Box::new(service).run(service_input, service_output));
+
+        let request = |arg: &str| Message::default().user("user").args([arg]);
+        input.send(request("error")).await.unwrap();
+        assert_eq!(output.recv().await.unwrap().args, ["error"]);
+        input.send(request("silent")).await.unwrap();
+        time::sleep(Duration::from_millis(30)).await;
+
+        // Open: the service is not reached.
+        input.send(request("ok")).await.unwrap();
+        let response = output.recv().await.unwrap();
+        assert_eq!(
+            response.body,
+            "Service temporarily unavailable, try again later"
+        );
+
+        // The probe fails, so it is open again.
+        time::sleep(Duration::from_millis(50)).await;
+        input.send(request("error")).await.unwrap();
+        assert_eq!(output.recv().await.unwrap().args, ["error"]);
+        input.send(request("ok")).await.unwrap();
+        let response = output.recv().await.unwrap();
+        assert_eq!(
+            response.body,
+            "Service temporarily unavailable, try again later"
+        );
+
+        // The probe succeeds, so it is closed.
+        time::sleep(Duration::from_millis(50)).await;
+        for _ in 0..2 {
+            input.send(request("ok")).await.unwrap();
+            assert_eq!(output.recv().await.unwrap().args, ["ok"]);
+        }
+    }
+}
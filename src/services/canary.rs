@@ -0,0 +1,327 @@
+use crate::channel::{self, ClosedChannel, Receiver, Sender};
+use crate::cluster;
+use crate::engine::EngineHandle;
+use crate::error::Error;
+use crate::i18n;
+use crate::interface::{Service, StopHook};
+use crate::message::Message;
+
+use async_trait::async_trait;
+use futures::future;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Statistics of a variant of a [`Canary`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VariantStats {
+    /// Requests sent to the variant.
+    pub requests: u64,
+    /// Requests replied.
+    pub replies: u64,
+    /// Requests replied with an `error` first arg.
+    pub errors: u64,
+    /// Total time taken to reply the requests.
+    pub latency: Duration,
+}
+
+impl VariantStats {
+    /// Mean time taken to reply a request, if any was replied.
+    pub fn mean_latency(&self) -> Option<Duration> {
+        let replies = u32::try_from(self.replies)
+            .ok()
+            .filter(|replies| *replies > 0)?;
+        Some(self.latency / replies)
+    }
+}
+
+/// Statistics of the variants of a [`Canary`], updated while it runs.
+#[derive(Clone, Default)]
+pub struct CanaryMetrics(Arc<Mutex<[VariantStats; 2]>>);
+
+impl CanaryMetrics {
+    pub fn stable(&self) -> VariantStats {
+        self.0.lock().unwrap()[STABLE]
+    }
+
+    pub fn candidate(&self) -> VariantStats {
+        self.0.lock().unwrap()[CANDIDATE]
+    }
+
+    fn update(&self, variant: usize, update: impl FnOnce(&mut VariantStats)) {
+        update(&mut self.0.lock().unwrap()[variant]);
+    }
+}
+
+const STABLE: usize = 0;
+const CANDIDATE: usize = 1;
+
+/// Run two implementations of a service under the same name, sending a part of the
+/// requests to the `candidate` one, so a rewritten service can be compared against the
+/// `stable` one with real traffic before replacing it.
+///
+/// The users are split by [`Canary::weight()`]: each user is always served by the same
+/// variant, so their conversations are consistent.
+/// The statistics of each variant are available through [`Canary::metrics()`].
+///
+/// # Example
+/// ```rust no_run
+/// use service_io::connectors::{ImapClient, SmtpClient};
+/// use service_io::engine::Engine;
+/// use service_io::services::{Canary, IpProvider, PublicIp};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let candidate = PublicIp::default().providers([IpProvider::Ipify]);
+///     let canary = Canary::new(PublicIp::default(), candidate).weight(10);
+///     let metrics = canary.metrics();
+///     tokio::spawn(async move {
+///         loop {
+///             tokio::time::sleep(std::time::Duration::from_secs(3600)).await;
+///             println!("{:?} vs {:?}", metrics.stable(), metrics.candidate());
+///         }
+///     });
+///
+///     Engine::default()
+///         .input(ImapClient::default() /* ... */)
+///         .output(SmtpClient::default() /* ... */)
+///         .add_service("s-public-ip", canary)
+///         .run()
+///         .await;
+/// }
+/// ```
+pub struct Canary {
+    variants: [Box<dyn Service + Send>; 2],
+    weight: u8,
+    metrics: CanaryMetrics,
+}
+
+impl Canary {
+    /// Split the requests between `stable` and `candidate`.
+    pub fn new(
+        stable: impl Service + Send + 'static,
+        candidate: impl Service + Send + 'static,
+    ) -> Self {
+        Self {
+            variants: [Box::new(stable), Box::new(candidate)],
+            weight: 10,
+            metrics: CanaryMetrics::default(),
+        }
+    }
+
+    /// Percentage of the users served by the candidate, from 0 to 100. By default, 10.
+    pub fn weight(mut self, percentage: u8) -> Self {
+        self.weight = percentage.min(100);
+        self
+    }
+
+    /// Statistics of the variants.
+    pub fn metrics(&self) -> CanaryMetrics {
+        self.metrics.clone()
+    }
+}
+
+/// Variant serving the `user`.
+fn variant(user: &str, weight: u8) -> usize {
+    match cluster::hash(user) % 100 < u64::from(weight) {
+        true => CANDIDATE,
+        false => STABLE,
+    }
+}
+
+#[async_trait]
+impl Service for Canary {
+    async fn on_start(&mut self) -> Result<(), Error> {
+        for service in &mut self.variants {
+            service.on_start().await?;
+        }
+        Ok(())
+    }
+
+    fn on_stop(&mut self) -> Option<StopHook> {
+        let hooks = self
+            .variants
+            .iter_mut()
+            .filter_map(|service| service.on_stop())
+            .collect::<Vec<_>>();
+
+        match hooks.is_empty() {
+            true => None,
+            false => Some(Box::pin(async move {
+                future::join_all(hooks).await;
+            })),
+        }
+    }
+
+    async fn run(
+        self: Box<Self>,
+        mut input: Receiver,
+        output: Sender,
+    ) -> Result<(), ClosedChannel> {
+        let Canary {
+            variants: [stable, candidate],
+            weight,
+            metrics,
+        } = *self;
+        let (stable_sender, mut stable_replies) = spawn(stable, &input);
+        let (candidate_sender, mut candidate_replies) = spawn(candidate, &input);
+        let senders = [stable_sender, candidate_sender];
+
+        // Users waiting for a reply of each variant, with the time of their request.
+        let mut pending: [VecDeque<(String, Instant)>; 2] = Default::default();
+        let mut replying = [true, true];
+        loop {
+            let (variant, reply) = tokio::select! {
+                request = input.recv() => {
+                    let request = request?;
+                    let variant = variant(&request.user, weight);
+                    metrics.update(variant, |stats| stats.requests += 1);
+                    pending[variant].push_back((request.user.clone(), Instant::now()));
+                    if senders[variant].send(request).await.is_err() {
+                        log::warn!("Drop message for a finished service");
+                    }
+                    continue;
+                }
+                reply = stable_replies.recv(), if replying[STABLE] => (STABLE, reply),
+                reply = candidate_replies.recv(), if replying[CANDIDATE] => (CANDIDATE, reply),
+            };
+
+            let Ok(reply) = reply else {
+                replying[variant] = false;
+                continue;
+            };
+            let position = pending[variant]
+                .iter()
+                .position(|(user, _)| *user == reply.user);
+            if let (Some(index), false) = (position, reply.is_progress()) {
+                let (_, since) = pending[variant].remove(index).unwrap();
+                let failed = reply.args.first() == Some(&i18n::text(&reply, "error"));
+                metrics.update(variant, |stats| {
+                    stats.replies += 1;
+                    stats.errors += u64::from(failed);
+                    stats.latency += since.elapsed();
+                });
+            }
+            output.send(reply).await?;
+        }
+    }
+}
+
+/// Runs a variant, returning its input and its replies.
+fn spawn(service: Box<dyn Service + Send>, input: &Receiver) -> (mpsc::Sender<Message>, Receiver) {
+    let (sender, receiver) = mpsc::channel(32);
+    let (service_output, replies) = channel::channel(32);
+    let token = input.cancellation_token();
+    let name = format!("Canary ({})", service.describe());
+    EngineHandle::spawn(
+        name,
+        service.run(Receiver(receiver, token, None), service_output),
+    );
+    (sender, replies)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ServiceError;
+    use crate::interface::{Context, SimpleService};
+    use crate::services::Echo;
+    use crate::state::{KeyValueStore, MemoryStore};
+
+    /// Replies to every request with an error.
+    struct Failing;
+
+    #[async_trait]
+    impl Service for Failing {
+        async fn run(
+            self: Box<Self>,
+            mut input: Receiver,
+            output: Sender,
+        ) -> Result<(), ClosedChannel> {
+            loop {
+                let request = input.recv().await?;
+                output
+                    .send(Message::response(&request).args(["error"]))
+                    .await?;
+            }
+        }
+    }
+
+    /// Records in the state the users of each variant.
+    struct Record(&'static str);
+
+    #[async_trait]
+    impl SimpleService for Record {
+        async fn handle(
+            &self,
+            request: Message,
+            context: &Context,
+        ) -> Result<Vec<Message>, ServiceError> {
+            context
+                .state()
+                .set(&request.user, self.0.into())
+                .await
+                .unwrap();
+            Ok(vec![Message::response(&request)])
+        }
+    }
+
+    #[tokio::test]
+    async fn engine_state() {
+        let store = MemoryStore::default();
+        let engine = EngineHandle::default();
+        engine.set_state_store(Arc::new(store.clone()));
+
+        let canary = Canary::new(Record("stable"), Record("candidate")).weight(50);
+        let (input, service_input) = channel::channel(4);
+        let (service_output, mut output) = channel::channel(4);
+        tokio::spawn(engine.scope(Box::new(canary).run(service_input, service_output)));
+
+        for user in ["user_0", "user_1", "user_2", "user_3"] {
+            let request = Message::default().user(user).service_name("s-canary");
+            input.send(request).await.unwrap();
+            output.recv().await.unwrap();
+
+            let expected = match variant(user, 50) {
+                STABLE => "stable",
+                _ => "candidate",
+            };
+            let key = format!("s-canary/{}", user);
+            assert_eq!(store.get(&key).await.unwrap().as_deref(), Some(expected));
+        }
+    }
+
+    #[tokio::test]
+    async fn split() {
+        let canary = Canary::new(Echo, Failing).weight(50);
+        let metrics = canary.metrics();
+
+        let (input, service_input) = channel::channel(4);
+        let (service_output, mut output) = channel::channel(4);
+        tokio::spawn(Box::new(canary).run(service_input, service_output));
+
+        let users = (0..20).map(|index| format!("user_{}", index));
+        let mut candidate_users = 0;
+        for user in users {
+            input
+                .send(Message::default().user(&user).args(["ok"]))
+                .await
+                .unwrap();
+            let response = output.recv().await.unwrap();
+            let served_by_candidate = response.args == ["error"];
+            assert_eq!(served_by_candidate, variant(&user, 50) == CANDIDATE);
+            candidate_users += u64::from(served_by_candidate);
+        }
+
+        let (stable, candidate) = (metrics.stable(), metrics.candidate());
+        assert_eq!(candidate.requests, candidate_users);
+        assert_eq!(candidate.errors, candidate_users);
+        assert_eq!(stable.requests + candidate.requests, 20);
+        assert_eq!(stable.replies, stable.requests);
+        assert_eq!(stable.errors, 0);
+        assert!(candidate_users > 0 && stable.requests > 0);
+    }
+}
@@ -0,0 +1,223 @@
+use crate::channel::{ClosedChannel, Receiver, Sender};
+use crate::i18n;
+use crate::interface::Service;
+use crate::message::Message;
+
+use async_trait::async_trait;
+use tokio::process::Command;
+use tokio::time;
+
+use std::time::Duration;
+
+/// Source of captures: a command writing the captured image to its stdout.
+struct Source {
+    name: String,
+    filename: String,
+    program: String,
+    args: Vec<String>,
+}
+
+/// Capture a screenshot or a webcam frame and reply it as an attachment.
+///
+/// The first arg is the name of the source to capture.
+/// Without args, the first source is captured.
+///
+/// Each source is a command that writes the image to its stdout.
+/// By default, the sources are:
+/// - `screen`: screenshot of the X11 display with ImageMagick (`import`).
+/// - `webcam`: frame of `/dev/video0` with `ffmpeg`.
+///
+/// Use [`Capture::new()`] to start without sources, and [`Capture::source()`] to add them.
+///
+/// # Example
+/// ```rust no_run
+/// use service_io::connectors::Email;
+/// use service_io::engine::Engine;
+/// use service_io::services::Capture;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     Engine::default()
+///         .connector(Email::gmail("service@gmail.com", "app-password"))
+///         // "s-capture garage" replies with a frame of the garage camera
+///         .add_service(
+///             "s-capture",
+///             Capture::new().source(
+///                 "garage",
+///                 "garage.jpg",
+///                 ["ffmpeg", "-loglevel", "error", "-i", "rtsp://192.168.1.20/stream",
+///                  "-frames:v", "1", "-f", "image2pipe", "-c:v", "mjpeg", "-"],
+///             ),
+///         )
+///         .run()
+///         .await;
+/// }
+/// ```
+pub struct Capture {
+    sources: Vec<Source>,
+    timeout: Duration,
+}
+
+impl Default for Capture {
+    fn default() -> Self {
+        Capture::new()
+            .source(
+                "screen",
+                "screen.png",
+                ["import", "-window", "root", "png:-"],
+            )
+            .source(
+                "webcam",
+                "webcam.jpg",
+                [
+                    "ffmpeg",
+                    "-loglevel",
+                    "error",
+                    "-f",
+                    "video4linux2",
+                    "-i",
+                    "/dev/video0",
+                    "-frames:v",
+                    "1",
+                    "-f",
+                    "image2pipe",
+                    "-c:v",
+                    "mjpeg",
+                    "-",
+                ],
+            )
+    }
+}
+
+impl Capture {
+    /// Service without sources.
+    pub fn new() -> Self {
+        Capture {
+            sources: Vec::new(),
+            timeout: Duration::from_secs(20),
+        }
+    }
+
+    /// Add the source `name`, replied as an attachment called `filename`.
+    /// The first element of the `command` is the program and the rest its args.
+    /// An existing source with the same name is replaced.
+    pub fn source<S: Into<String>>(
+        mut self,
+        name: impl Into<String>,
+        filename: impl Into<String>,
+        command: impl IntoIterator<Item = S>,
+    ) -> Self {
+        let mut command = command.into_iter().map(|s| s.into());
+        let source = Source {
+            name: name.into(),
+            filename: filename.into(),
+            program: command.next().unwrap_or_default(),
+            args: command.collect(),
+        };
+        self.sources.retain(|current| current.name != source.name);
+        self.sources.push(source);
+        self
+    }
+
+    /// Maximum time for a capture. By default, 20 seconds.
+    pub fn timeout(mut self, duration: Duration) -> Self {
+        self.timeout = duration;
+        self
+    }
+
+    async fn capture(&self, source: &Source) -> Result<Vec<u8>, String> {
+        let command = Command::new(&source.program)
+            .args(&source.args)
+            .kill_on_drop(true)
+            .output();
+
+        let output = match time::timeout(self.timeout, command).await {
+            Ok(output) => output.map_err(|err| err.to_string())?,
+            Err(_) => return Err("timeout".into()),
+        };
+
+        match output.status.success() && !output.stdout.is_empty() {
+            true => Ok(output.stdout),
+            false => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                Err(format!("{} {}", output.status, stderr.trim()))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Service for Capture {
+    async fn run(
+        self: Box<Self>,
+        mut input: Receiver,
+        output: Sender,
+    ) -> Result<(), ClosedChannel> {
+        loop {
+            let request = input.recv().await?;
+            let source = match request.args.first() {
+                Some(name) => self.sources.iter().find(|source| source.name == *name),
+                None => self.sources.first(),
+            };
+
+            let response = match source {
+                Some(source) => match self.capture(source).await {
+                    Ok(image) => Message::response(&request)
+                        .body(i18n::text_with(&request, "capture-done", [&source.name]))
+                        .attach([(source.filename.clone(), image)]),
+                    Err(err) => {
+                        log::error!("Capture of '{}' failed: {}", source.name, err);
+                        Message::response(&request)
+                            .args([i18n::text(&request, "error")])
+                            .body(i18n::text_with(
+                                &request,
+                                "capture-failed",
+                                [&source.name, &err],
+                            ))
+                    }
+                },
+                None => {
+                    let names = self.sources.iter().map(|source| source.name.as_str());
+                    Message::response(&request)
+                        .args([i18n::text(&request, "format-error")])
+                        .body(i18n::text_with(
+                            &request,
+                            "capture-expected-args",
+                            [names.collect::<Vec<_>>().join(" | ")],
+                        ))
+                }
+            };
+            output.send(response).await?;
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::channel;
+
+    #[tokio::test]
+    async fn capture() {
+        let service = Capture::new()
+            .source("fake", "fake.png", ["printf", "image"])
+            .source("broken", "broken.png", ["false"]);
+
+        let (input, service_input) = channel::channel(4);
+        let (service_output, mut output) = channel::channel(4);
+        tokio::spawn(Box::new(service).run(service_input, service_output));
+
+        let request = Message::default().user("user").service_name("s-capture");
+        input.send(request.clone()).await.unwrap();
+        let response = output.recv().await.unwrap();
+        assert_eq!(response.attached_data["fake.png"].as_ref(), b"image");
+
+        input.send(request.clone().args(["broken"])).await.unwrap();
+        let response = output.recv().await.unwrap();
+        assert_eq!(response.args, ["error"]);
+
+        input.send(request.args(["unknown"])).await.unwrap();
+        let response = output.recv().await.unwrap();
+        assert_eq!(response.body, "Expected args: <fake | broken>");
+    }
+}
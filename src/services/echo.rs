@@ -1,5 +1,6 @@
-use crate::channel::{ClosedChannel, Receiver, Sender};
-use crate::interface::Service;
+use crate::error::ServiceError;
+use crate::interface::{Context, SimpleService};
+use crate::message::Message;
 
 use async_trait::async_trait;
 
@@ -7,15 +8,12 @@ use async_trait::async_trait;
 pub struct Echo;
 
 #[async_trait]
-impl Service for Echo {
-    async fn run(
-        self: Box<Self>,
-        mut input: Receiver,
-        output: Sender,
-    ) -> Result<(), ClosedChannel> {
-        loop {
-            let message = input.recv().await?;
-            output.send(message).await?;
-        }
+impl SimpleService for Echo {
+    async fn handle(
+        &self,
+        request: Message,
+        _context: &Context,
+    ) -> Result<Vec<Message>, ServiceError> {
+        Ok(vec![request])
     }
 }
@@ -0,0 +1,297 @@
+use crate::channel::{ClosedChannel, Receiver, Sender};
+use crate::i18n;
+use crate::interface::Service;
+use crate::message::Message;
+
+use async_trait::async_trait;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time;
+
+use std::collections::VecDeque;
+use std::process::Stdio;
+use std::time::Duration;
+
+/// Time given to the process to exit once it closed its stdout before killing it.
+const EXIT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Service implemented by an external long-lived process,
+/// so it can be written in any language (Python, Node, ...).
+///
+/// Each request is written to the stdin of the process as a JSON line,
+/// and each JSON line written by the process to its stdout is sent as a response.
+/// The messages are encoded with the [`serde`] representation of [`Message`].
+/// The process must fill the user and service name of its responses
+/// (usually copying them from the request).
+///
+/// Each response is matched with the oldest in-flight request of the same user and service.
+/// The progress messages (see [`Message::progress()`]) are sent without being matched.
+/// If the process finishes or crashes, the in-flight requests are answered with an error
+/// and the process is restarted after [`External::restart_delay()`].
+///
+/// # Example
+/// ```rust no_run
+/// use service_io::connectors::{DebugStdout, UserStdin};
+/// use service_io::engine::Engine;
+/// use service_io::services::External;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     Engine::default()
+///         .input(UserStdin("user"))
+///         .output(DebugStdout)
+///         .add_service("s-python", External::new("python3").args(["service.py"]))
+///         .run()
+///         .await;
+/// }
+/// ```
+pub struct External {
+    program: String,
+    args: Vec<String>,
+    restart_delay: Duration,
+}
+
+impl External {
+    /// Create the service that will run the `program`.
+    pub fn new(program: impl Into<String>) -> Self {
+        External {
+            program: program.into(),
+            args: Vec::new(),
+            restart_delay: Duration::from_secs(1),
+        }
+    }
+
+    /// Arguments passed to the program.
+    pub fn args<S: Into<String>>(mut self, args: impl IntoIterator<Item = S>) -> Self {
+        self.args = args.into_iter().map(|s| s.into()).collect();
+        self
+    }
+
+    /// Time to wait before restarting the process once it finished. By default 1 second.
+    pub fn restart_delay(mut self, delay: Duration) -> Self {
+        self.restart_delay = delay;
+        self
+    }
+
+    fn spawn(&self) -> std::io::Result<(Child, ChildStdin, ChildStdout)> {
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        let stdin = child.stdin.take().expect("Piped stdin");
+        let stdout = child.stdout.take().expect("Piped stdout");
+        Ok((child, stdin, stdout))
+    }
+}
+
+/// Writes the requests to the stdin of the process in its own task,
+/// so its stdout is still read while a big request is being written.
+fn spawn_writer(
+    mut stdin: ChildStdin,
+) -> (
+    mpsc::UnboundedSender<String>,
+    JoinHandle<std::io::Result<()>>,
+) {
+    let (sender, mut receiver) = mpsc::unbounded_channel::<String>();
+    let task = tokio::spawn(async move {
+        while let Some(line) = receiver.recv().await {
+            stdin.write_all(line.as_bytes()).await?;
+        }
+        Ok(())
+    });
+    (sender, task)
+}
+
+#[async_trait]
+impl Service for External {
+    async fn run(
+        self: Box<Self>,
+        mut input: Receiver,
+        output: Sender,
+    ) -> Result<(), ClosedChannel> {
+        let token = input.cancellation_token();
+        loop {
+            match self.spawn() {
+                Ok((mut child, stdin, stdout)) => {
+                    log::info!("External process '{}' running", self.program);
+                    let (writer, mut writing) = spawn_writer(stdin);
+                    let mut lines = BufReader::new(stdout).lines();
+                    let mut in_flight = VecDeque::new();
+                    loop {
+                        tokio::select! {
+                            request = input.recv() => {
+                                let request = request?;
+                                let mut line = serde_json::to_string(&request).unwrap();
+                                line.push('\n');
+                                in_flight.push_back(request);
+                                // The writer only finishes with an error, handled below.
+                                writer.send(line).ok();
+                            }
+                            written = &mut writing => {
+                                if let Ok(Err(err)) = written {
+                                    log::error!("External process '{}': {}", self.program, err);
+                                }
+                                break;
+                            }
+                            line = lines.next_line() => match line {
+                                Ok(Some(line)) => match serde_json::from_str::<Message>(&line) {
+                                    Ok(response) => {
+                                        let position = in_flight.iter().position(|request| {
+                                            request.user == response.user
+                                                && request.service_name == response.service_name
+                                        });
+                                        if let (Some(position), false) =
+                                            (position, response.is_progress())
+                                        {
+                                            in_flight.remove(position);
+                                        }
+                                        output.send(response).await?
+                                    }
+                                    Err(err) => log::error!(
+                                        "External process '{}' sent an invalid message: {}",
+                                        self.program,
+                                        err
+                                    ),
+                                },
+                                Ok(None) | Err(_) => break,
+                            },
+                            _ = token.cancelled() => return Ok(()),
+                        }
+                    }
+                    // Closes the stdin of the process.
+                    writing.abort();
+
+                    for request in in_flight {
+                        let response = Message::response(&request)
+                            .args([i18n::text(&request, "error")])
+                            .body(i18n::text(&request, "external-failed"));
+                        output.send(response).await?;
+                    }
+
+                    let status = match time::timeout(EXIT_TIMEOUT, child.wait()).await {
+                        Ok(status) => status,
+                        Err(_) => {
+                            log::warn!("External process '{}' killed", self.program);
+                            match child.kill().await {
+                                Ok(()) => child.wait().await,
+                                Err(err) => Err(err),
+                            }
+                        }
+                    };
+                    match status {
+                        Ok(status) => {
+                            log::error!("External process '{}' finished ({})", self.program, status)
+                        }
+                        Err(err) => log::error!("External process '{}': {}", self.program, err),
+                    }
+                }
+                Err(err) => log::error!("Unable to run '{}': {}", self.program, err),
+            }
+
+            tokio::select! {
+                _ = time::sleep(self.restart_delay) => (),
+                _ = token.cancelled() => return Ok(()),
+            }
+            log::info!("Restarting external process '{}'", self.program);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel;
+
+    fn request(body: &str) -> Message {
+        Message::default()
+            .user("user")
+            .service_name("s-external")
+            .body(body)
+    }
+
+    #[tokio::test]
+    async fn reply() {
+        let (input, service_input) = channel::channel(4);
+        let (service_output, mut output) = channel::channel(4);
+        let service = External::new("cat");
+        tokio::spawn(Box::new(service).run(service_input, service_output));
+
+        input.send(request("hello")).await.unwrap();
+        let response = output.recv().await.unwrap();
+        assert_eq!(response.user, "user");
+        assert_eq!(response.body, "hello");
+    }
+
+    #[tokio::test]
+    async fn crash_in_flight() {
+        let (input, service_input) = channel::channel(4);
+        let (service_output, mut output) = channel::channel(4);
+        let service = External::new("sh").args(["-c", "read line; exit 1"]);
+        tokio::spawn(Box::new(service).run(service_input, service_output));
+
+        input.send(request("hello")).await.unwrap();
+        let response = output.recv().await.unwrap();
+        assert_eq!(response.user, "user");
+        assert_eq!(response.service_name, "s-external");
+        assert_eq!(response.args, ["error"]);
+        assert_eq!(
+            response.body,
+            "The external process failed processing the request"
+        );
+    }
+
+    #[tokio::test]
+    async fn big_request() {
+        let (input, service_input) = channel::channel(4);
+        let (service_output, mut output) = channel::channel(4);
+        let service = External::new("cat");
+        tokio::spawn(Box::new(service).run(service_input, service_output));
+
+        // Greater than the pipe buffers, so cat writes the response while reading the request.
+        let body = "a".repeat(1024 * 1024);
+        input.send(request(&body)).await.unwrap();
+        let response = time::timeout(Duration::from_secs(5), output.recv()).await;
+        assert_eq!(response.unwrap().unwrap().body, body);
+    }
+
+    #[tokio::test]
+    async fn crash_after_progress() {
+        let progress = Message::progress(&request("hello")).body("working");
+        let script = format!(
+            "read line; echo '{}'; exit 1",
+            serde_json::to_string(&progress).unwrap()
+        );
+
+        let (input, service_input) = channel::channel(4);
+        let (service_output, mut output) = channel::channel(4);
+        let service = External::new("sh").args(["-c", &script]);
+        tokio::spawn(Box::new(service).run(service_input, service_output));
+
+        input.send(request("hello")).await.unwrap();
+        assert!(output.recv().await.unwrap().is_progress());
+        let response = output.recv().await.unwrap();
+        assert_eq!(response.args, ["error"]);
+    }
+
+    #[tokio::test]
+    async fn closed_stdout() {
+        let (input, service_input) = channel::channel(4);
+        let (service_output, mut output) = channel::channel(4);
+        let service = External::new("sh").args(["-c", "read line; exec >&-; sleep 60"]);
+        tokio::spawn(Box::new(service).run(service_input, service_output));
+
+        input.send(request("hello")).await.unwrap();
+        let response = output.recv().await.unwrap();
+        assert_eq!(response.args, ["error"]);
+
+        // The hanging process is killed and restarted.
+        input.send(request("hello")).await.unwrap();
+        let response = output.recv().await.unwrap();
+        assert_eq!(response.args, ["error"]);
+    }
+}
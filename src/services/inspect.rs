@@ -0,0 +1,119 @@
+use crate::channel::{ClosedChannel, Receiver, Sender};
+use crate::interface::Service;
+use crate::message::Message;
+
+use async_trait::async_trait;
+
+use std::fmt::Write;
+
+/// Reply with a detailed description of the received message: args, body, attachment
+/// names and sizes, metadata, and the encoding detected for the content.
+///
+/// It is useful to check how a connector parsed a message,
+/// i.e. an unexpected argument split or an attachment with a wrong encoding.
+/// Each value is quoted to make visible the whitespaces and escaped characters.
+pub struct Inspect;
+
+#[async_trait]
+impl Service for Inspect {
+    async fn run(
+        self: Box<Self>,
+        mut input: Receiver,
+        output: Sender,
+    ) -> Result<(), ClosedChannel> {
+        loop {
+            let request = input.recv().await?;
+            let response = Message::response(&request).body(describe(&request));
+            output.send(response).await?;
+        }
+    }
+}
+
+fn describe(message: &Message) -> String {
+    let mut text = String::new();
+    writeln!(text, "user: {:?}", message.user).unwrap();
+    writeln!(text, "service: {:?}", message.service_name).unwrap();
+
+    writeln!(text, "args ({}):", message.args.len()).unwrap();
+    for (index, arg) in message.args.iter().enumerate() {
+        writeln!(text, "  {}: {:?}", index, arg).unwrap();
+    }
+
+    writeln!(text, "body: {}", content(message.body.as_bytes())).unwrap();
+    if !message.body.is_empty() {
+        writeln!(text, "  {:?}", message.body).unwrap();
+    }
+
+    let mut attachments = message.attached_data.iter().collect::<Vec<_>>();
+    attachments.sort();
+    writeln!(text, "attachments ({}):", attachments.len()).unwrap();
+    for (name, data) in attachments {
+        writeln!(text, "  {:?}: {}", name, content(data)).unwrap();
+    }
+
+    let mut metadata = message.metadata.iter().collect::<Vec<_>>();
+    metadata.sort();
+    writeln!(text, "metadata ({}):", metadata.len()).unwrap();
+    for (key, value) in metadata {
+        writeln!(text, "  {:?}: {:?}", key, value).unwrap();
+    }
+
+    text.trim_end().into()
+}
+
+/// Size and detected encoding of the content.
+fn content(data: &[u8]) -> String {
+    let encoding = match std::str::from_utf8(data) {
+        Ok(text) if text.is_ascii() => "ascii",
+        Ok(_) => "utf-8",
+        Err(_) => "binary",
+    };
+
+    let mut details = vec![format!("{} bytes", data.len())];
+    if !data.is_empty() {
+        details.push(encoding.into());
+        if encoding != "binary" {
+            if data.windows(2).any(|pair| pair == b"\r\n") {
+                details.push("CRLF line endings".into());
+            }
+            if data.starts_with("\u{feff}".as_bytes()) {
+                details.push("BOM".into());
+            }
+        }
+    }
+    details.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn description() {
+        let message = Message::default()
+            .user("user")
+            .service_name("s-inspect")
+            .args(["a", "b "])
+            .body("línea\r\n")
+            .attach([
+                ("image.png", vec![0x89, 0xff]),
+                ("notes.txt", b"ok".to_vec()),
+            ])
+            .metadata([("language", "es")]);
+
+        let expected = r#"user: "user"
+service: "s-inspect"
+args (2):
+  0: "a"
+  1: "b "
+body: 8 bytes, utf-8, CRLF line endings
+  "línea\r\n"
+attachments (2):
+  "image.png": 2 bytes, binary
+  "notes.txt": 2 bytes, ascii
+metadata (1):
+  "language": "es""#;
+
+        assert_eq!(describe(&message), expected);
+    }
+}
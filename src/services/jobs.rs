@@ -0,0 +1,274 @@
+use crate::error::ServiceError;
+use crate::i18n;
+use crate::interface::{Context, SimpleService};
+use crate::message::Message;
+
+use async_trait::async_trait;
+use tokio_util::sync::CancellationToken;
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// Information of a running job. See [`JobRegistry`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobInfo {
+    pub id: u64,
+    /// User that requested the job.
+    pub user: String,
+    /// Service running the job.
+    pub service_name: String,
+    pub description: String,
+    /// Last status set by the service, see [`Job::set_status()`].
+    pub status: String,
+    pub started: SystemTime,
+}
+
+struct Entry {
+    info: JobInfo,
+    token: CancellationToken,
+}
+
+/// Long-running requests of the services, so the users can follow and cancel them
+/// with the [`Jobs`] service.
+///
+/// The engine provides it to the services through [`Context::jobs()`].
+/// Each user can only see and cancel their own jobs.
+#[derive(Default)]
+pub struct JobRegistry {
+    next_id: AtomicU64,
+    jobs: Mutex<BTreeMap<u64, Entry>>,
+}
+
+impl JobRegistry {
+    /// Registers a job of the `user` in the service, that is running until the returned
+    /// [`Job`] is dropped.
+    pub fn start(
+        self: &Arc<Self>,
+        user: impl Into<String>,
+        service_name: impl Into<String>,
+        description: impl Into<String>,
+    ) -> Job {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let token = CancellationToken::new();
+        let info = JobInfo {
+            id,
+            user: user.into(),
+            service_name: service_name.into(),
+            description: description.into(),
+            status: String::new(),
+            started: SystemTime::now(),
+        };
+        log::info!("Job {} started: {}", id, info.description);
+        let entry = Entry {
+            info,
+            token: token.clone(),
+        };
+        self.jobs.lock().unwrap().insert(id, entry);
+        Job {
+            id,
+            token,
+            registry: self.clone(),
+        }
+    }
+
+    /// Information of the job `id` of the `user`, if it is running.
+    pub fn status(&self, user: &str, id: u64) -> Option<JobInfo> {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.get(&id)
+            .filter(|entry| entry.info.user == user)
+            .map(|entry| entry.info.clone())
+    }
+
+    /// Running jobs of the `user`, sorted by id.
+    pub fn list(&self, user: &str) -> Vec<JobInfo> {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.values()
+            .filter(|entry| entry.info.user == user)
+            .map(|entry| entry.info.clone())
+            .collect()
+    }
+
+    /// Asks the service running the job `id` of the `user` to cancel it.
+    /// See [`Job::cancelled()`].
+    ///
+    /// Returns `false` if the user has no such job running.
+    pub fn cancel(&self, user: &str, id: u64) -> bool {
+        let jobs = self.jobs.lock().unwrap();
+        match jobs.get(&id).filter(|entry| entry.info.user == user) {
+            Some(entry) => {
+                log::info!("Job {} cancelled by '{}'", id, user);
+                entry.token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Running job, registered in a [`JobRegistry`] until it is dropped.
+pub struct Job {
+    id: u64,
+    token: CancellationToken,
+    registry: Arc<JobRegistry>,
+}
+
+impl Job {
+    /// Identifier the users reference the job with.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Sets a status shown to the user (i.e. "50% done").
+    pub fn set_status(&self, status: impl Into<String>) {
+        if let Some(entry) = self.registry.jobs.lock().unwrap().get_mut(&self.id) {
+            entry.info.status = status.into();
+        }
+    }
+
+    /// Waits until the user cancels the job.
+    pub async fn cancelled(&self) {
+        self.token.cancelled().await
+    }
+
+    /// Returns `true` if the user cancelled the job.
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        self.registry.jobs.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Follow and cancel the jobs of the user (see [`JobRegistry`]). Supported commands:
+/// - `list`: running jobs of the user.
+/// - `status <id>`: status of a job.
+/// - `cancel <id>`: cancel a job.
+///
+/// # Example
+/// ```rust no_run
+/// use service_io::connectors::{ImapClient, SmtpClient};
+/// use service_io::engine::Engine;
+/// use service_io::services::{Jobs, Process};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     Engine::default()
+///         .input(ImapClient::default() /* ... */)
+///         .output(SmtpClient::default() /* ... */)
+///         .add_service("s-jobs", Jobs)
+///         .add_service_for("s-process", Process, ["admin@domain.com"])
+///         .run()
+///         .await;
+/// }
+/// ```
+pub struct Jobs;
+
+#[async_trait]
+impl SimpleService for Jobs {
+    async fn handle(
+        &self,
+        request: Message,
+        context: &Context,
+    ) -> Result<Vec<Message>, ServiceError> {
+        let jobs = context.jobs();
+        let body = match request.args_str().as_slice() {
+            ["list"] => {
+                let list = jobs.list(&request.user);
+                match list.is_empty() {
+                    true => i18n::text(&request, "jobs-empty"),
+                    false => list
+                        .iter()
+                        .map(|info| describe(&request, info))
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                }
+            }
+            ["status", id] => match id
+                .parse()
+                .ok()
+                .and_then(|id| jobs.status(&request.user, id))
+            {
+                Some(info) => describe(&request, &info),
+                None => return Err(unknown(&request, id)),
+            },
+            ["cancel", id] => match id.parse().is_ok_and(|id| jobs.cancel(&request.user, id)) {
+                true => i18n::text_with(&request, "jobs-cancelled", [*id]),
+                false => return Err(unknown(&request, id)),
+            },
+            _ => {
+                let text = i18n::text(&request, "jobs-expected-args");
+                return Err(ServiceError::Format(text));
+            }
+        };
+        Ok(vec![Message::response(&request).body(body)])
+    }
+}
+
+fn describe(request: &Message, info: &JobInfo) -> String {
+    let elapsed = info.started.elapsed().unwrap_or_default().as_secs();
+    let mut description = i18n::text_with(
+        request,
+        "jobs-status",
+        [
+            info.id.to_string(),
+            info.service_name.clone(),
+            info.description.clone(),
+            elapsed.to_string(),
+        ],
+    );
+    if !info.status.is_empty() {
+        description = format!("{}: {}", description, info.status);
+    }
+    description
+}
+
+fn unknown(request: &Message, id: &str) -> ServiceError {
+    ServiceError::Failed(i18n::text_with(request, "jobs-unknown", [id]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel;
+    use crate::engine::EngineHandle;
+    use crate::interface::Service;
+
+    #[tokio::test]
+    async fn status_and_cancel() {
+        let engine = EngineHandle::default();
+        let job = engine.jobs().start("user", "s-backup", "backup /home");
+        job.set_status("50%");
+
+        let (input, service_input) = channel::channel(4);
+        let (service_output, mut output) = channel::channel(4);
+        let service = Box::new(Jobs).run(service_input, service_output);
+        tokio::spawn(engine.clone().scope(service));
+
+        let request = |user: &str, args: &[&str]| Message::default().user(user).args(args.to_vec());
+        input.send(request("user", &["list"])).await.unwrap();
+        let body = output.recv().await.unwrap().body;
+        assert!(body.starts_with("#1 s-backup 'backup /home'"));
+        assert!(body.ends_with(": 50%"));
+
+        // Other users can not cancel it.
+        input
+            .send(request("other", &["cancel", "1"]))
+            .await
+            .unwrap();
+        assert_eq!(output.recv().await.unwrap().args, ["error"]);
+        assert!(!job.is_cancelled());
+
+        input.send(request("user", &["cancel", "1"])).await.unwrap();
+        assert_eq!(output.recv().await.unwrap().body, "Job 1 cancelled");
+        job.cancelled().await;
+
+        drop(job);
+        input.send(request("user", &["status", "1"])).await.unwrap();
+        assert_eq!(output.recv().await.unwrap().body, "Unknown job '1'");
+    }
+}
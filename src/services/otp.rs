@@ -0,0 +1,101 @@
+//! One-time passwords of RFC 6238 (TOTP), as the ones of the authenticator apps.
+
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Seconds each code is valid.
+pub(crate) const STEP: u64 = 30;
+
+/// Digits of the codes.
+pub(crate) const DIGITS: u32 = 6;
+
+/// Decodes a base32 secret (RFC 4648), the format shown by the authenticator apps.
+/// Spaces, padding and lowercase letters are accepted.
+pub(crate) fn decode_base32(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let (mut buffer, mut bits) = (0u32, 0);
+    for c in text.chars().filter(|c| !c.is_whitespace() && *c != '=') {
+        let value = match c.to_ascii_uppercase() {
+            c @ 'A'..='Z' => c as u32 - 'A' as u32,
+            c @ '2'..='7' => c as u32 - '2' as u32 + 26,
+            _ => return None,
+        };
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    (!bytes.is_empty()).then_some(bytes)
+}
+
+/// Number of the time step of `time`.
+pub(crate) fn counter(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        / STEP
+}
+
+/// Code of the `counter` time step.
+pub(crate) fn code(secret: &[u8], counter: u64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).unwrap();
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let value = u32::from_be_bytes(hash[offset..offset + 4].try_into().unwrap()) & 0x7fff_ffff;
+    format!(
+        "{:0width$}",
+        value % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    )
+}
+
+/// Compares a secret with a code in a time that does not depend on where they differ,
+/// so the secret can not be guessed measuring the time of the failed attempts.
+pub(crate) fn constant_time_eq(secret: &str, code: &str) -> bool {
+    let (secret, code) = (secret.as_bytes(), code.as_bytes());
+    secret.len() == code.len()
+        && secret
+            .iter()
+            .zip(code)
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    #[test]
+    fn rfc_6238() {
+        let secret = decode_base32("GEZDGNBVGY3TQOJQ gezdgnbvgy3tqojq").unwrap();
+        assert_eq!(secret, b"12345678901234567890");
+
+        let at = |seconds| counter(UNIX_EPOCH + Duration::from_secs(seconds));
+        assert_eq!(code(&secret, at(59)), "287082");
+        assert_eq!(code(&secret, at(1111111109)), "081804");
+        assert_eq!(code(&secret, at(2000000000)), "279037");
+    }
+
+    #[test]
+    fn constant_time() {
+        assert!(constant_time_eq("1234", "1234"));
+        assert!(!constant_time_eq("1234", "1235"));
+        assert!(!constant_time_eq("1234", "123"));
+        assert!(!constant_time_eq("1234", ""));
+    }
+
+    #[test]
+    fn invalid_base32() {
+        assert_eq!(decode_base32("ABC1"), None);
+        assert_eq!(decode_base32(""), None);
+    }
+}
@@ -1,13 +1,29 @@
 use crate::channel::{ClosedChannel, Receiver, Sender};
+use crate::engine::EngineHandle;
+use crate::i18n;
 use crate::interface::Service;
 use crate::message::Message;
+use crate::services::{Job, JobRegistry};
 
 use async_trait::async_trait;
 use tokio::process::Command;
+use tokio_util::sync::CancellationToken;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Time a process runs before the user is told its job id.
+const JOB_NOTICE_DELAY: Duration = Duration::from_secs(5);
 
 /// Allow to run any process.
 /// Each arg of the message is interpreted as a process arg, being arg0 the name of the process.
 /// The stdout of the process once finalized will be returned as a message body.
+///
+/// Each process is registered as a job (see [`Jobs`]). If it runs for more than a few seconds,
+/// a progress message tells the user its job id, so it can be stopped with `kill <job-id>`.
+/// The `kill` program itself must be run by its path (i.e. `/bin/kill`).
+///
+/// [`Jobs`]: crate::services::Jobs
 pub struct Process;
 
 #[async_trait]
@@ -17,14 +33,29 @@ impl Service for Process {
         mut input: Receiver,
         output: Sender,
     ) -> Result<(), ClosedChannel> {
+        let jobs =
+            EngineHandle::current().map_or_else(Arc::default, |engine| engine.jobs().clone());
         loop {
             let request = input.recv().await?;
-            match request.args.get(0) {
-                Some(_) => spawn_process(request, output.clone()),
-                None => {
+            match request.args_str().as_slice() {
+                ["kill", id] => {
+                    if !kill(&jobs, &request, id) {
+                        let response = Message::response(&request)
+                            .args([i18n::text(&request, "error")])
+                            .body(i18n::text_with(&request, "process-unknown-job", [*id]));
+
+                        output.send(response).await?;
+                    }
+                }
+                [_, ..] => {
+                    let job =
+                        jobs.start(&request.user, &request.service_name, request.args.join(" "));
+                    spawn_process(request, job, output.clone(), input.cancellation_token());
+                }
+                [] => {
                     let response = Message::response(&request)
-                        .args(["format error"])
-                        .body(format!("You need to specify a process to run"));
+                        .args([i18n::text(&request, "format-error")])
+                        .body(i18n::text(&request, "process-no-process"));
 
                     output.send(response).await?;
                 }
@@ -33,28 +64,112 @@ impl Service for Process {
     }
 }
 
-fn spawn_process(request: Message, output: Sender) {
+/// Cancels the job `id` if it is a process of the user.
+fn kill(jobs: &JobRegistry, request: &Message, id: &str) -> bool {
+    let Ok(id) = id.parse() else {
+        return false;
+    };
+    jobs.status(&request.user, id)
+        .is_some_and(|info| info.service_name == request.service_name)
+        && jobs.cancel(&request.user, id)
+}
+
+fn spawn_process(request: Message, job: Job, output: Sender, token: CancellationToken) {
     let mut program_args = request.args.iter();
     let arg0 = program_args.next().unwrap();
-    let child = Command::new(arg0).args(program_args).output();
+    let child = Command::new(arg0)
+        .args(program_args)
+        .kill_on_drop(true)
+        .output();
 
     tokio::spawn({
         let output = output.clone();
         async move {
             let cmd_str = request.args.join(" ");
-            if let Ok(child_output) = child.await {
+            tokio::pin!(child);
+            let notice = tokio::time::sleep(JOB_NOTICE_DELAY);
+            tokio::pin!(notice);
+            let mut noticed = false;
+            let child_output = loop {
+                tokio::select! {
+                    child_output = &mut child => break child_output,
+                    _ = &mut notice, if !noticed => {
+                        noticed = true;
+                        let id = job.id().to_string();
+                        let progress = Message::progress(&request)
+                            .body(i18n::text_with(&request, "process-running", [id, cmd_str.clone()]));
+
+                        output.send(progress).await.ok();
+                    }
+                    _ = job.cancelled() => {
+                        log::info!("Process killed by the user: {}", cmd_str);
+                        let response = Message::response(&request)
+                            .args([i18n::text(&request, "process-killed")])
+                            .body(cmd_str);
+
+                        output.send(response).await.ok();
+                        return;
+                    }
+                    _ = token.cancelled() => {
+                        log::info!("Process killed by engine shutdown: {}", cmd_str);
+                        return;
+                    }
+                }
+            };
+
+            if let Ok(child_output) = child_output {
                 let response = Message::response(&request)
-                    .args([format!("Terminated ({}): {}", child_output.status, cmd_str)])
+                    .args([i18n::text_with(
+                        &request,
+                        "process-terminated",
+                        [child_output.status.to_string(), cmd_str],
+                    )])
                     .body(std::str::from_utf8(&child_output.stdout).unwrap_or("[binary]"));
 
                 output.send(response).await.ok();
             } else {
                 let response = Message::response(&request)
-                    .args(["error"])
-                    .body(format!("Error while running: {}", cmd_str));
+                    .args([i18n::text(&request, "error")])
+                    .body(i18n::text_with(&request, "process-failed", [cmd_str]));
 
                 output.send(response).await.ok();
             }
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel;
+
+    #[tokio::test]
+    async fn kill_job() {
+        let engine = EngineHandle::default();
+        let (input, service_input) = channel::channel(4);
+        let (service_output, mut output) = channel::channel(4);
+        let service = Box::new(Process).run(service_input, service_output);
+        tokio::spawn(engine.clone().scope(service));
+
+        let request = |args: &[&str]| {
+            Message::default()
+                .user("user")
+                .service_name("s-process")
+                .args(args.to_vec())
+        };
+        input.send(request(&["sleep", "10"])).await.unwrap();
+        while engine.jobs().list("user").is_empty() {
+            tokio::task::yield_now().await;
+        }
+
+        input.send(request(&["kill", "2"])).await.unwrap();
+        let response = output.recv().await.unwrap();
+        assert_eq!(response.args, ["error"]);
+        assert_eq!(response.body, "Unknown job '2'");
+
+        input.send(request(&["kill", "1"])).await.unwrap();
+        let response = output.recv().await.unwrap();
+        assert_eq!(response.args, ["killed"]);
+        assert_eq!(response.body, "sleep 10");
+    }
+}
@@ -0,0 +1,295 @@
+use super::otp;
+use crate::channel::{ClosedChannel, Receiver, Sender};
+use crate::engine::EngineHandle;
+use crate::error::Error;
+use crate::i18n;
+use crate::interface::{Service, StopHook};
+use crate::message::Message;
+
+use async_trait::async_trait;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+/// Second factor of a user.
+enum Credential {
+    Pin(String),
+    /// Decoded secret of the authenticator app.
+    Totp(Vec<u8>),
+}
+
+/// Failed attempts and last code used by a user.
+#[derive(Default)]
+struct Attempts {
+    failures: u32,
+    locked_until: Option<Instant>,
+    last_counter: Option<u64>,
+}
+
+/// Require a PIN or a TOTP code of the user to use the wrapped service,
+/// as a second factor for dangerous services as [`Process`](super::Process).
+///
+/// The code is the first arg of the request, or the first word of the body.
+/// It is removed before the request reaches the wrapped service, so it never
+/// sees nor logs the code.
+/// Requests of users without a PIN or TOTP secret are always rejected.
+///
+/// Each TOTP code can only be used once, and the codes of the previous and next
+/// 30 seconds are accepted, to tolerate the clock drift.
+/// After [`Protected::max_failures()`] wrong codes in a row,
+/// the user is locked for [`Protected::lockout()`].
+///
+/// # Example
+/// ```rust no_run
+/// use service_io::connectors::{ImapClient, SmtpClient};
+/// use service_io::engine::Engine;
+/// use service_io::services::{Process, Protected};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     Engine::default()
+///         .input(ImapClient::default() /* ... */)
+///         .output(SmtpClient::default() /* ... */)
+///         // "s-process 123456 ls -l" runs "ls -l" if 123456 is the current code
+///         .add_service_for(
+///             "s-process",
+///             Protected::new(Process).totp("admin@domain.com", "JBSWY3DPEHPK3PXP"),
+///             ["admin@domain.com"],
+///         )
+///         .run()
+///         .await;
+/// }
+/// ```
+pub struct Protected {
+    service: Box<dyn Service + Send>,
+    guard: Guard,
+}
+
+/// Checks the codes of the requests.
+struct Guard {
+    credentials: HashMap<String, Credential>,
+    max_failures: u32,
+    lockout: Duration,
+}
+
+impl Protected {
+    /// Wraps `service`. Until credentials are added, all the requests are rejected.
+    pub fn new(service: impl Service + Send + 'static) -> Self {
+        Self {
+            service: Box::new(service),
+            guard: Guard {
+                credentials: HashMap::new(),
+                max_failures: 5,
+                lockout: Duration::from_secs(15 * 60),
+            },
+        }
+    }
+
+    /// The `user` must include the `pin` in the requests.
+    pub fn pin(mut self, user: impl Into<String>, pin: impl Into<String>) -> Self {
+        self.guard
+            .credentials
+            .insert(user.into(), Credential::Pin(pin.into()));
+        self
+    }
+
+    /// The `user` must include the current code of an authenticator app
+    /// configured with `secret`, in base32 as the apps show it.
+    ///
+    /// Panics if the `secret` is not valid base32.
+    pub fn totp(mut self, user: impl Into<String>, secret: &str) -> Self {
+        let secret = otp::decode_base32(secret).expect("The TOTP secret must be base32");
+        self.guard
+            .credentials
+            .insert(user.into(), Credential::Totp(secret));
+        self
+    }
+
+    /// Wrong codes in a row before locking the user. By default, 5.
+    pub fn max_failures(mut self, failures: u32) -> Self {
+        self.guard.max_failures = failures.max(1);
+        self
+    }
+
+    /// Time a user is locked after too many wrong codes. By default, 15 minutes.
+    pub fn lockout(mut self, duration: Duration) -> Self {
+        self.guard.lockout = duration;
+        self
+    }
+}
+
+impl Guard {
+    /// Whether the `code` is valid for the `user`, recording the attempt.
+    fn verify(&self, attempts: &mut Attempts, user: &str, code: Option<&str>) -> bool {
+        let valid = match (self.credentials.get(user), code) {
+            (Some(Credential::Pin(pin)), Some(code)) => otp::constant_time_eq(pin, code),
+            (Some(Credential::Totp(secret)), Some(code)) => {
+                let now = otp::counter(SystemTime::now());
+                let used = (now.saturating_sub(1)..=now + 1)
+                    .filter(|counter| attempts.last_counter < Some(*counter))
+                    .find(|counter| otp::constant_time_eq(&otp::code(secret, *counter), code));
+                attempts.last_counter = used.or(attempts.last_counter);
+                used.is_some()
+            }
+            _ => false,
+        };
+
+        attempts.failures = match valid {
+            true => 0,
+            false => attempts.failures + 1,
+        };
+        valid
+    }
+}
+
+/// Removes the code from the request, returning it.
+fn take_code(request: &mut Message) -> Option<String> {
+    if !request.args.is_empty() {
+        return Some(request.args.remove(0));
+    }
+
+    let body = request.body.trim_start();
+    let code = body.split_whitespace().next()?.to_string();
+    request.body = body[code.len()..].trim_start().to_string();
+    Some(code)
+}
+
+#[async_trait]
+impl Service for Protected {
+    async fn on_start(&mut self) -> Result<(), Error> {
+        self.service.on_start().await
+    }
+
+    fn on_stop(&mut self) -> Option<StopHook> {
+        self.service.on_stop()
+    }
+
+    async fn run(
+        self: Box<Self>,
+        mut input: Receiver,
+        output: Sender,
+    ) -> Result<(), ClosedChannel> {
+        let Protected { service, guard } = *self;
+
+        let (sender, receiver) = mpsc::channel(32);
+        let token = input.cancellation_token();
+        let service_output = output.clone();
+        let name = format!("Protected ({})", service.describe());
+        EngineHandle::spawn(
+            name,
+            service.run(Receiver(receiver, token, None), service_output),
+        );
+
+        let mut attempts: HashMap<String, Attempts> = HashMap::new();
+        loop {
+            let mut request = input.recv().await?;
+            let user_attempts = attempts.entry(request.user.clone()).or_default();
+
+            if let Some(until) = user_attempts.locked_until {
+                match Instant::now() < until {
+                    true => {
+                        let minutes = (until - Instant::now()).as_secs() / 60 + 1;
+                        let response = Message::response(&request)
+                            .args([i18n::text(&request, "error")])
+                            .body(i18n::text_with(&request, "protected-locked", [minutes]));
+                        output.send(response).await?;
+                        continue;
+                    }
+                    false => user_attempts.locked_until = None,
+                }
+            }
+
+            let code = take_code(&mut request);
+            if guard.verify(user_attempts, &request.user, code.as_deref()) {
+                if sender.send(request).await.is_err() {
+                    log::warn!("Drop message for a finished protected service");
+                }
+                continue;
+            }
+
+            log::warn!("Wrong code from '{}'", request.user);
+            if user_attempts.failures >= guard.max_failures {
+                log::warn!("User '{}' locked for too many wrong codes", request.user);
+                user_attempts.failures = 0;
+                user_attempts.locked_until = Some(Instant::now() + guard.lockout);
+            }
+            let response = Message::response(&request)
+                .args([i18n::text(&request, "error")])
+                .body(i18n::text(&request, "protected-wrong-code"));
+            output.send(response).await?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel;
+    use crate::services::Echo;
+
+    fn request(user: &str, args: &[&str], body: &str) -> Message {
+        Message::default()
+            .user(user)
+            .service_name("s-echo")
+            .args(args.iter().copied())
+            .body(body)
+    }
+
+    #[tokio::test]
+    async fn pin_and_totp() {
+        let secret = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+        let service = Protected::new(Echo)
+            .pin("pin_user", "4321")
+            .totp("totp_user", secret)
+            .max_failures(2);
+
+        let (input, service_input) = channel::channel(4);
+        let (service_output, mut output) = channel::channel(4);
+        tokio::spawn(Box::new(service).run(service_input, service_output));
+
+        input
+            .send(request("pin_user", &["4321", "hello"], "body"))
+            .await
+            .unwrap();
+        let response = output.recv().await.unwrap();
+        assert_eq!(response.args, ["hello"]);
+        assert_eq!(response.body, "body");
+
+        let secret = otp::decode_base32(secret).unwrap();
+        let code = otp::code(&secret, otp::counter(SystemTime::now()));
+        let body = format!("{}\nhello", code);
+        input.send(request("totp_user", &[], &body)).await.unwrap();
+        let response = output.recv().await.unwrap();
+        assert_eq!(response.body, "hello");
+
+        // The code was already used.
+        input.send(request("totp_user", &[], &body)).await.unwrap();
+        let response = output.recv().await.unwrap();
+        assert_eq!(response.body, "Wrong or missing code");
+
+        for _ in 0..2 {
+            input
+                .send(request("pin_user", &["1111"], ""))
+                .await
+                .unwrap();
+            let response = output.recv().await.unwrap();
+            assert_eq!(response.body, "Wrong or missing code");
+        }
+
+        input
+            .send(request("pin_user", &["4321"], ""))
+            .await
+            .unwrap();
+        let response = output.recv().await.unwrap();
+        assert_eq!(
+            response.body,
+            "Too many wrong codes, try again in 15 minutes"
+        );
+
+        input.send(request("unknown", &["4321"], "")).await.unwrap();
+        let response = output.recv().await.unwrap();
+        assert_eq!(response.args, ["error"]);
+    }
+}
@@ -1,12 +1,157 @@
 use crate::channel::{ClosedChannel, Receiver, Sender};
+use crate::i18n;
 use crate::interface::Service;
 use crate::message::Message;
+use crate::state::KeyValueStore;
 
 use async_trait::async_trait;
+use public_ip::{dns, http, Resolver, Version};
+use tokio::time;
+
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Key of the store with the last reported IP.
+const STORE_KEY: &str = "public-ip";
+
+/// Argument to reply only if the IP changed since the last request.
+const CHANGED_ARG: &str = "changed";
+
+/// Provider that tells the public IP of the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpProvider {
+    /// DNS query to the OpenDNS servers.
+    OpenDns,
+    /// DNS query to the Google servers.
+    Google,
+    /// HTTP request to `ipify.org`.
+    Ipify,
+    /// HTTP request to `whatismyipaddress.com`.
+    WhatIsMyIpAddress,
+}
+
+impl IpProvider {
+    fn resolver(self) -> &'static dyn Resolver<'static> {
+        match self {
+            IpProvider::OpenDns => dns::OPENDNS,
+            IpProvider::Google => dns::GOOGLE,
+            IpProvider::Ipify => http::HTTP_IPIFY_ORG,
+            IpProvider::WhatIsMyIpAddress => http::HTTP_WHATISMYIPADDRESS_COM,
+        }
+    }
+}
 
 /// Serve the public IP of the server.
 /// No args are required.
-pub struct PublicIp;
+///
+/// With the `changed` arg, it only replies if the IP changed since the last request,
+/// i.e. to be notified of the changes sending it periodically with a
+/// [`GeneratorInput`](crate::connectors::GeneratorInput).
+/// The first request only records the IP.
+/// Use [`PublicIp::store()`] to keep the last IP across restarts.
+pub struct PublicIp {
+    providers: Vec<IpProvider>,
+    ipv6: bool,
+    timeout: Duration,
+    store: Option<Arc<dyn KeyValueStore>>,
+}
+
+impl Default for PublicIp {
+    fn default() -> Self {
+        Self {
+            providers: vec![
+                IpProvider::OpenDns,
+                IpProvider::Google,
+                IpProvider::Ipify,
+                IpProvider::WhatIsMyIpAddress,
+            ],
+            ipv6: false,
+            timeout: Duration::from_secs(10),
+            store: None,
+        }
+    }
+}
+
+impl PublicIp {
+    /// Providers to ask, in order, until one of them answers.
+    /// By default, all of them: DNS providers first.
+    pub fn providers(mut self, providers: impl IntoIterator<Item = IpProvider>) -> Self {
+        self.providers = providers.into_iter().collect();
+        self
+    }
+
+    /// Report the IPv6 address along with the IPv4 one. By default, only IPv4 is reported.
+    pub fn ipv6(mut self, enabled: bool) -> Self {
+        self.ipv6 = enabled;
+        self
+    }
+
+    /// Maximum time waiting for each provider. By default, 10 seconds.
+    pub fn timeout(mut self, duration: Duration) -> Self {
+        self.timeout = duration;
+        self
+    }
+
+    /// Keep the last reported IP in `store` to detect the changes across restarts.
+    pub fn store(mut self, store: impl KeyValueStore + 'static) -> Self {
+        self.store = Some(Arc::new(store));
+        self
+    }
+
+    async fn resolve(&self, version: Version) -> Option<IpAddr> {
+        for provider in &self.providers {
+            let resolution = public_ip::addr_with(provider.resolver(), version);
+            match time::timeout(self.timeout, resolution).await {
+                Ok(Some(addr)) => return Some(addr),
+                Ok(None) => log::warn!("No IP address from {:?}", provider),
+                Err(_) => log::warn!("Timeout getting the IP address from {:?}", provider),
+            }
+        }
+        None
+    }
+
+    /// Public IP addresses as they are reported, if any of them could be resolved.
+    async fn report(&self) -> Option<String> {
+        let v4 = self.resolve(Version::V4).await;
+        if !self.ipv6 {
+            return v4.map(|addr| addr.to_string());
+        }
+
+        let v6 = self.resolve(Version::V6).await;
+        format_report(v4, v6)
+    }
+
+    async fn load_last(&self) -> Option<String> {
+        let store = self.store.as_ref()?;
+        store.get(STORE_KEY).await.unwrap_or_else(|err| {
+            log::error!("Can not load the last IP: {}", err);
+            None
+        })
+    }
+
+    async fn save_last(&self, ip: &str) {
+        if let Some(store) = &self.store {
+            if let Err(err) = store.set(STORE_KEY, ip.into()).await {
+                log::error!("Can not save the last IP: {}", err);
+            }
+        }
+    }
+}
+
+/// Report with both addresses, if any of them was resolved.
+fn format_report(v4: Option<IpAddr>, v6: Option<IpAddr>) -> Option<String> {
+    if v4.is_none() && v6.is_none() {
+        return None;
+    }
+
+    let show = |addr: Option<IpAddr>| addr.map(|addr| addr.to_string());
+    Some(format!(
+        "IPv4: {}\nIPv6: {}",
+        show(v4).unwrap_or_else(|| "-".into()),
+        show(v6).unwrap_or_else(|| "-".into()),
+    ))
+}
 
 #[async_trait]
 impl Service for PublicIp {
@@ -15,17 +160,77 @@ impl Service for PublicIp {
         mut input: Receiver,
         output: Sender,
     ) -> Result<(), ClosedChannel> {
+        let mut last = self.load_last().await;
         loop {
             let request = input.recv().await?;
-            let response = match public_ip::addr().await {
-                Some(ip_addr) => Message::response(&request).body(format!("{}", ip_addr)),
+            let only_changes = request.args.first().is_some_and(|arg| arg == CHANGED_ARG);
+
+            let response = match self.report().await {
+                Some(ip) => {
+                    let previous = last.replace(ip.clone());
+                    if previous.as_ref() != Some(&ip) {
+                        self.save_last(&ip).await;
+                    }
+
+                    let changed = previous.is_some_and(|previous| previous != ip);
+                    match (only_changes, changed) {
+                        (true, false) => continue,
+                        (true, true) => Message::response(&request)
+                            .args([i18n::text(&request, "public-ip-changed")])
+                            .body(ip),
+                        (false, _) => Message::response(&request).body(ip),
+                    }
+                }
                 None => {
-                    let msg = "Failed to get IP address";
-                    log::error!("{}", msg);
-                    Message::response(&request).args(["error"]).body(msg)
+                    log::error!("Failed to get IP address");
+                    Message::response(&request)
+                        .args([i18n::text(&request, "error")])
+                        .body(i18n::text(&request, "public-ip-failed"))
                 }
             };
             output.send(response).await?;
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel;
+
+    #[test]
+    fn report() {
+        let v4 = "203.0.113.7".parse().ok();
+        let v6 = "2001:db8::7".parse().ok();
+
+        assert_eq!(
+            format_report(v4, v6).unwrap(),
+            "IPv4: 203.0.113.7\nIPv6: 2001:db8::7"
+        );
+        assert_eq!(
+            format_report(v4, None).unwrap(),
+            "IPv4: 203.0.113.7\nIPv6: -"
+        );
+        assert_eq!(
+            format_report(None, v6).unwrap(),
+            "IPv4: -\nIPv6: 2001:db8::7"
+        );
+        assert_eq!(format_report(None, None), None);
+    }
+
+    #[tokio::test]
+    async fn no_providers() {
+        let (input, service_input) = channel::channel(4);
+        let (service_output, mut output) = channel::channel(4);
+        let service = PublicIp::default().providers([]).ipv6(true);
+        tokio::spawn(Box::new(service).run(service_input, service_output));
+
+        let request = Message::default().user("user").service_name("s-ip");
+        input.send(request).await.unwrap();
+
+        let response = output.recv().await.unwrap();
+        assert_eq!(response.user, "user");
+        assert_eq!(response.args, ["error"]);
+        assert_eq!(response.body, "Failed to get IP address");
+    }
+}
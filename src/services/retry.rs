@@ -0,0 +1,206 @@
+use crate::channel::{ClosedChannel, Receiver, Sender};
+use crate::engine::EngineHandle;
+use crate::i18n;
+use crate::interface::Service;
+use crate::message::Message;
+
+use async_trait::async_trait;
+
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Service running a fallible `operation` for each request, retrying it with an exponential
+/// backoff while it fails. Useful for operations depending on the network,
+/// where most of the failures are transient.
+///
+/// The `operation` receives the request and returns the response.
+/// Once the attempts are exhausted, the last error is replied with an `error` arg.
+/// Each request is processed in its own task, so the retries of a request
+/// do not delay the rest of them.
+///
+/// # Example
+/// ```rust no_run
+/// use service_io::connectors::{ImapClient, SmtpClient};
+/// use service_io::engine::Engine;
+/// use service_io::message::Message;
+/// use service_io::services::Retry;
+///
+/// use std::time::Duration;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     Engine::default()
+///         .input(ImapClient::default() /* ... */)
+///         .output(SmtpClient::default() /* ... */)
+///         .add_service(
+///             "s-status",
+///             Retry::new(|request: Message| async move {
+///                 let response = reqwest::get("https://status.domain.com").await?;
+///                 let body = response.error_for_status()?.text().await?;
+///                 Ok::<_, reqwest::Error>(Message::response(&request).body(body))
+///             })
+///             .attempts(5)
+///             .backoff(Duration::from_secs(2)),
+///         )
+///         .run()
+///         .await;
+/// }
+/// ```
+pub struct Retry<F> {
+    operation: Arc<F>,
+    attempts: u32,
+    backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl<F, Fut, E> Retry<F>
+where
+    F: Fn(Message) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Message, E>> + Send,
+    E: Display,
+{
+    /// Service retrying the `operation`.
+    pub fn new(operation: F) -> Self {
+        Self {
+            operation: Arc::new(operation),
+            attempts: 3,
+            backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+
+    /// Times the operation is run before giving up. By default, 3.
+    pub fn attempts(mut self, attempts: u32) -> Self {
+        self.attempts = attempts.max(1);
+        self
+    }
+
+    /// Time to wait before the first retry, doubled for each next one. By default, 1 second.
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Maximum time to wait between retries. By default, 1 minute.
+    pub fn max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+}
+
+/// Runs the `operation` until it succeeds or the attempts are exhausted.
+async fn attempt<F, Fut, E>(
+    operation: &F,
+    request: Message,
+    attempts: u32,
+    backoff: Duration,
+    max_backoff: Duration,
+) -> Message
+where
+    F: Fn(Message) -> Fut,
+    Fut: Future<Output = Result<Message, E>>,
+    E: Display,
+{
+    let mut delay = backoff;
+    let mut attempt = 1;
+    loop {
+        let err = match operation(request.clone()).await {
+            Ok(response) => return response,
+            Err(err) => err.to_string(),
+        };
+
+        if attempt >= attempts {
+            log::error!("Request failed after {} attempts: {}", attempts, err);
+            return Message::response(&request)
+                .args([i18n::text(&request, "error")])
+                .body(i18n::text_with(
+                    &request,
+                    "retry-failed",
+                    [attempts.to_string(), err],
+                ));
+        }
+
+        log::warn!(
+            "Attempt {} failed, retrying in {:?}: {}",
+            attempt,
+            delay,
+            err
+        );
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(max_backoff);
+        attempt += 1;
+    }
+}
+
+#[async_trait]
+impl<F, Fut, E> Service for Retry<F>
+where
+    F: Fn(Message) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Message, E>> + Send,
+    E: Display,
+{
+    async fn run(
+        self: Box<Self>,
+        mut input: Receiver,
+        output: Sender,
+    ) -> Result<(), ClosedChannel> {
+        loop {
+            let request = input.recv().await?;
+            let operation = self.operation.clone();
+            let (attempts, backoff, max_backoff) = (self.attempts, self.backoff, self.max_backoff);
+            let output = output.clone();
+            let token = input.cancellation_token();
+            EngineHandle::spawn("Retry", async move {
+                tokio::select! {
+                    response = attempt(&*operation, request, attempts, backoff, max_backoff) => {
+                        output.send(response).await.ok();
+                    }
+                    _ = token.cancelled() => (),
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel;
+
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[tokio::test]
+    async fn retries() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let service = Retry::new({
+            let calls = calls.clone();
+            move |request: Message| {
+                let call = calls.fetch_add(1, Ordering::Relaxed) + 1;
+                async move {
+                    match request.args[0].parse::<u32>().unwrap() <= call {
+                        true => Ok(Message::response(&request).body(call.to_string())),
+                        false => Err("unreachable"),
+                    }
+                }
+            }
+        })
+        .attempts(3)
+        .backoff(Duration::from_millis(1));
+
+        let (input, service_input) = channel::channel(4);
+        let (service_output, mut output) = channel::channel(4);
+        tokio::spawn(Box::new(service).run(service_input, service_output));
+
+        // Succeeds at the third attempt.
+        input.send(Message::default().args(["3"])).await.unwrap();
+        assert_eq!(output.recv().await.unwrap().body, "3");
+
+        calls.store(0, Ordering::Relaxed);
+        input.send(Message::default().args(["4"])).await.unwrap();
+        let response = output.recv().await.unwrap();
+        assert_eq!(response.args, ["error"]);
+        assert_eq!(response.body, "Failed after 3 attempts: unreachable");
+    }
+}
@@ -0,0 +1,167 @@
+use crate::channel::{ClosedChannel, Receiver, Sender};
+use crate::engine::EngineHandle;
+use crate::error::Error;
+use crate::i18n;
+use crate::interface::{Service, StopHook};
+use crate::message::Message;
+
+use async_trait::async_trait;
+use futures::future;
+use tokio::sync::mpsc;
+
+use std::collections::BTreeMap;
+
+/// Dispatch the messages among several services registered under the same service name.
+/// The first arg of the message is interpreted as the subcommand that selects the service.
+/// The subcommand is removed from the args before passing the message to the selected service.
+///
+/// # Example
+/// ```rust no_run
+/// use service_io::connectors::{DebugStdout, UserStdin};
+/// use service_io::engine::Engine;
+/// use service_io::services::{Echo, Process, PublicIp, Router};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     Engine::default()
+///         .input(UserStdin("user"))
+///         .output(DebugStdout)
+///         // "s-system ip" goes to PublicIp and "s-system run ls -l" goes to Process
+///         .add_service(
+///             "s-system",
+///             Router::default()
+///                 .route("ip", PublicIp::default())
+///                 .route("run", Process)
+///                 .route("echo", Echo),
+///         )
+///         .run()
+///         .await;
+/// }
+/// ```
+#[derive(Default)]
+pub struct Router {
+    routes: BTreeMap<String, Box<dyn Service + Send>>,
+}
+
+impl Router {
+    /// Register a service for a subcommand.
+    pub fn route(
+        mut self,
+        subcommand: impl Into<String>,
+        service: impl Service + Send + 'static,
+    ) -> Self {
+        self.routes.insert(subcommand.into(), Box::new(service));
+        self
+    }
+}
+
+#[async_trait]
+impl Service for Router {
+    async fn on_start(&mut self) -> Result<(), Error> {
+        for service in self.routes.values_mut() {
+            service.on_start().await?;
+        }
+        Ok(())
+    }
+
+    fn on_stop(&mut self) -> Option<StopHook> {
+        let hooks = self
+            .routes
+            .values_mut()
+            .filter_map(|service| service.on_stop())
+            .collect::<Vec<_>>();
+
+        match hooks.is_empty() {
+            true => None,
+            false => Some(Box::pin(async move {
+                future::join_all(hooks).await;
+            })),
+        }
+    }
+
+    async fn run(
+        self: Box<Self>,
+        mut input: Receiver,
+        output: Sender,
+    ) -> Result<(), ClosedChannel> {
+        let subcommands = self.routes.keys().cloned().collect::<Vec<_>>().join(", ");
+
+        let routes = self
+            .routes
+            .into_iter()
+            .map(|(subcommand, service)| {
+                let (sender, receiver) = mpsc::channel(32);
+                let output = output.clone();
+                let token = input.cancellation_token();
+                let name = format!("Route '{}' ({})", subcommand, service.describe());
+                EngineHandle::spawn(name, service.run(Receiver(receiver, token, None), output));
+                (subcommand, sender)
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        loop {
+            let mut request = input.recv().await?;
+            let route = request
+                .args
+                .first()
+                .and_then(|subcommand| routes.get(subcommand));
+
+            match route {
+                Some(sender) => {
+                    let subcommand = request.args.remove(0);
+                    if sender.send(request).await.is_err() {
+                        log::warn!("Drop message for finished subcommand '{}'", subcommand);
+                    }
+                }
+                None => {
+                    let response = Message::response(&request)
+                        .args([i18n::text(&request, "format-error")])
+                        .body(i18n::text_with(
+                            &request,
+                            "router-expected-subcommands",
+                            [&subcommands],
+                        ));
+
+                    output.send(response).await?;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel;
+    use crate::services::Echo;
+
+    fn request(args: &[&str]) -> Message {
+        Message::default()
+            .user("user")
+            .service_name("s-router")
+            .args(args.to_vec())
+    }
+
+    #[tokio::test]
+    async fn routing() {
+        let (input, service_input) = channel::channel(4);
+        let (service_output, mut output) = channel::channel(4);
+        let router = Router::default().route("echo", Echo).route("repeat", Echo);
+        tokio::spawn(Box::new(router).run(service_input, service_output));
+
+        input.send(request(&["echo", "a", "b"])).await.unwrap();
+        assert_eq!(output.recv().await.unwrap(), request(&["a", "b"]));
+
+        input.send(request(&["repeat"])).await.unwrap();
+        assert_eq!(output.recv().await.unwrap(), request(&[]));
+
+        // The subcommand must match the whole route, not a prefix of it.
+        for args in [&["ech"][..], &["echoes"], &["unknown", "echo"], &[]] {
+            input.send(request(args)).await.unwrap();
+            let response = output.recv().await.unwrap();
+            assert_eq!(response.user, "user");
+            assert_eq!(response.args, ["format error"]);
+            assert_eq!(response.body, "Expected subcommands: echo, repeat");
+        }
+    }
+}
@@ -0,0 +1,309 @@
+use crate::channel::{ClosedChannel, Receiver, Sender};
+use crate::i18n;
+use crate::interface::Service;
+use crate::message::Message;
+
+use async_trait::async_trait;
+use rhai::{Array, Dynamic, Engine, Scope, AST};
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// Default maximum number of operations of a script run.
+const DEFAULT_MAX_OPERATIONS: u64 = 10_000_000;
+
+/// Default timeout of the `http_get()` requests.
+const DEFAULT_HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Service implemented by a [Rhai](https://rhai.rs) script.
+/// The script is reloaded if the file changes, so the service can be updated without
+/// recompiling or restarting the server.
+///
+/// Only available with the `script` feature.
+///
+/// The script must define a `handle(request)` function.
+/// The request is an object map with the fields of [`Message`]
+/// (attached data encoded as base64 strings).
+/// The function can return a map with the response, an array of them, or nothing.
+/// Response fields not specified are filled with the request `user` and `service_name`.
+///
+/// The script can make HTTP requests with `http_get(url)`, that returns the response body.
+/// The proxy is read from the `ALL_PROXY`, `HTTPS_PROXY` or `HTTP_PROXY` environment variables.
+///
+/// A script run exceeding [`Script::max_operations()`] is aborted with an error response.
+///
+/// # Example
+/// A `hello.rhai` script:
+/// ```rhai
+/// fn handle(request) {
+///     #{ body: `Hello ${request.user}! Your args: ${request.args}` }
+/// }
+/// ```
+///
+/// ```rust no_run
+/// use service_io::connectors::{DebugStdout, UserStdin};
+/// use service_io::engine::Engine;
+/// use service_io::services::Script;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     Engine::default()
+///         .input(UserStdin("user"))
+///         .output(DebugStdout)
+///         .add_service("s-hello", Script::from_file("hello.rhai"))
+///         .run()
+///         .await;
+/// }
+/// ```
+pub struct Script {
+    path: PathBuf,
+    max_operations: u64,
+    http_timeout: Duration,
+}
+
+impl Script {
+    /// Load the script from a file.
+    pub fn from_file(path: impl Into<PathBuf>) -> Script {
+        Script {
+            path: path.into(),
+            max_operations: DEFAULT_MAX_OPERATIONS,
+            http_timeout: DEFAULT_HTTP_TIMEOUT,
+        }
+    }
+
+    /// Maximum number of operations of a script run.
+    /// By default 10 million.
+    pub fn max_operations(mut self, operations: u64) -> Self {
+        self.max_operations = operations;
+        self
+    }
+
+    /// Timeout of the `http_get()` requests. By default 10 seconds.
+    pub fn http_timeout(mut self, timeout: Duration) -> Self {
+        self.http_timeout = timeout;
+        self
+    }
+}
+
+#[async_trait]
+impl Service for Script {
+    async fn run(
+        self: Box<Self>,
+        mut input: Receiver,
+        output: Sender,
+    ) -> Result<(), ClosedChannel> {
+        let engine = Arc::new(script_engine(self.max_operations, self.http_timeout));
+        let path = Arc::new(self.path);
+        let mut loaded: Option<(AST, Option<SystemTime>)> = None;
+
+        loop {
+            let request = input.recv().await?;
+            let error_response = Message::response(&request)
+                .args([i18n::text(&request, "error")])
+                .body(i18n::text(&request, "script-failed"));
+
+            let engine = engine.clone();
+            let script_path = path.clone();
+            let (returned, result) =
+                tokio::task::spawn_blocking(move || match load(&engine, &script_path, loaded) {
+                    Ok((ast, modified)) => {
+                        let result = handle(&engine, &ast, &request);
+                        (Some((ast, modified)), result)
+                    }
+                    Err(err) => (None, Err(err)),
+                })
+                .await
+                .unwrap();
+
+            loaded = returned;
+            match result {
+                Ok(responses) => {
+                    for response in responses {
+                        output.send(response).await?;
+                    }
+                }
+                Err(err) => {
+                    log::error!("Script {} failed: {}", path.display(), err);
+                    output.send(error_response).await?;
+                }
+            }
+        }
+    }
+}
+
+fn load(
+    engine: &Engine,
+    path: &Path,
+    loaded: Option<(AST, Option<SystemTime>)>,
+) -> Result<(AST, Option<SystemTime>), String> {
+    let modified = std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok();
+
+    match loaded {
+        Some((ast, loaded_modified)) if loaded_modified == modified => Ok((ast, modified)),
+        _ => {
+            let ast = engine
+                .compile_file(path.to_path_buf())
+                .map_err(|err| err.to_string())?;
+            log::info!("Script loaded from {}", path.display());
+            Ok((ast, modified))
+        }
+    }
+}
+
+/// Agent of the `http_get()` requests, using the TLS backend enabled in the crate.
+fn http_agent(timeout: Duration) -> ureq::Agent {
+    let builder = ureq::AgentBuilder::new()
+        .timeout(timeout)
+        .try_proxy_from_env(true);
+
+    #[cfg(feature = "native-tls")]
+    let builder = match native_tls::TlsConnector::new() {
+        Ok(connector) => builder.tls_connector(Arc::new(connector)),
+        Err(err) => {
+            log::error!("Scripts can not make HTTPS requests: {}", err);
+            builder
+        }
+    };
+
+    builder.build()
+}
+
+fn script_engine(max_operations: u64, http_timeout: Duration) -> Engine {
+    let agent = http_agent(http_timeout);
+
+    let mut engine = Engine::new();
+    engine.set_max_operations(max_operations);
+    engine.register_fn(
+        "http_get",
+        move |url: &str| -> Result<String, Box<rhai::EvalAltResult>> {
+            agent
+                .get(url)
+                .call()
+                .map_err(|err| err.to_string())?
+                .into_string()
+                .map_err(|err| err.to_string().into())
+        },
+    );
+    engine
+}
+
+fn handle(engine: &Engine, ast: &AST, request: &Message) -> Result<Vec<Message>, String> {
+    let dynamic_request = rhai::serde::to_dynamic(request).map_err(|err| err.to_string())?;
+    let result: Dynamic = engine
+        .call_fn(&mut Scope::new(), ast, "handle", (dynamic_request,))
+        .map_err(|err| err.to_string())?;
+
+    let responses = if result.is_unit() {
+        Vec::new()
+    } else if result.is_array() {
+        result.cast::<Array>()
+    } else {
+        vec![result]
+    };
+
+    responses
+        .into_iter()
+        .map(|response| {
+            let mut response: Message =
+                rhai::serde::from_dynamic(&response).map_err(|err| err.to_string())?;
+            if response.user.is_empty() {
+                response.user = request.user.clone();
+            }
+            if response.service_name.is_empty() {
+                response.service_name = request.service_name.clone();
+            }
+            Ok(response)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel;
+
+    async fn run_script(name: &str, code: &str, script: impl FnOnce(Script) -> Script) -> Message {
+        let path = std::env::temp_dir().join(format!("service-io-{}.rhai", name));
+        std::fs::write(&path, code).unwrap();
+
+        let (input, service_input) = channel::channel(4);
+        let (service_output, mut output) = channel::channel(4);
+        let service = script(Script::from_file(path));
+        tokio::spawn(Box::new(service).run(service_input, service_output));
+
+        let request = Message::default()
+            .user("user")
+            .service_name("s-script")
+            .args(["arg0"]);
+        input.send(request).await.unwrap();
+        output.recv().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn reply() {
+        let code = "fn handle(request) { #{ body: `Hello ${request.user}!` } }";
+        let response = run_script("reply", code, |script| script).await;
+        assert_eq!(response.user, "user");
+        assert_eq!(response.service_name, "s-script");
+        assert_eq!(response.body, "Hello user!");
+    }
+
+    #[tokio::test]
+    async fn compile_error() {
+        let code = "fn handle(request) { #{ body: ";
+        let response = run_script("compile-error", code, |script| script).await;
+        assert_eq!(response.args, ["error"]);
+        assert_eq!(response.body, "The script failed processing the request");
+    }
+
+    #[tokio::test]
+    async fn max_operations() {
+        let code = "fn handle(request) { loop {} }";
+        let response =
+            run_script("max-operations", code, |script| script.max_operations(1000)).await;
+        assert_eq!(response.args, ["error"]);
+        assert_eq!(response.body, "The script failed processing the request");
+    }
+
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
+    #[test]
+    fn http_agent_tls() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!(
+            "https://127.0.0.1:{}/",
+            listener.local_addr().unwrap().port()
+        );
+        let agent = http_agent(Duration::from_secs(1));
+        let request = std::thread::spawn(move || agent.get(&url).call().is_ok());
+
+        // The first byte of a TLS ClientHello is the handshake record type.
+        let mut record_type = [0];
+        let (mut stream, _) = listener.accept().unwrap();
+        std::io::Read::read_exact(&mut stream, &mut record_type).unwrap();
+        assert_eq!(record_type[0], 0x16);
+        drop(stream);
+        assert!(!request.join().unwrap());
+    }
+
+    #[test]
+    fn http_agent_proxy() {
+        const PROXY: &str = "http://127.0.0.1:3128";
+        if std::env::var("ALL_PROXY").as_deref() == Ok(PROXY) {
+            let agent = http_agent(Duration::from_secs(1));
+            assert!(format!("{:?}", agent).contains("port: 3128"));
+            return;
+        }
+
+        // The environment is only modified in a new process, not to affect other tests.
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "services::script::tests::http_agent_proxy"])
+            .env("ALL_PROXY", PROXY)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).contains("1 passed"));
+    }
+}
@@ -0,0 +1,174 @@
+use crate::channel::{ClosedChannel, Receiver, Sender};
+use crate::i18n;
+use crate::interface::Service;
+use crate::message::Message;
+use crate::state::KeyValueStore;
+use crate::time::{self, TIMEZONE_KEY};
+
+use async_trait::async_trait;
+
+use std::io;
+use std::sync::Arc;
+
+/// Key of [`Message::metadata`] with the channel where the user prefers to receive the replies.
+/// Its meaning depends on the output connector.
+pub const OUTPUT_CHANNEL_KEY: &str = "output-channel";
+
+/// Let the users set their personal preferences. Supported commands:
+/// - `set <key> <value>`: set a preference.
+/// - `unset <key>`: remove a preference.
+/// - `show`: list the preferences of the user.
+///
+/// By default the keys are [`i18n::LANGUAGE_KEY`], [`TIMEZONE_KEY`] and [`OUTPUT_CHANNEL_KEY`].
+///
+/// The preferences are kept in a [`KeyValueStore`]. Share the store with
+/// [`Engine::user_settings()`] to add them to the [`Message::metadata`] of the incoming messages,
+/// so all the services (and the connectors for the replies) can use them.
+///
+/// # Example
+/// ```rust no_run
+/// use service_io::connectors::{DebugStdout, UserStdin};
+/// use service_io::engine::Engine;
+/// use service_io::services::{Echo, Settings};
+/// use service_io::state::FileStore;
+///
+/// use std::sync::Arc;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let store = Arc::new(FileStore::new("settings.json"));
+///
+///     Engine::default()
+///         .input(UserStdin("user"))
+///         .output(DebugStdout)
+///         // "s-settings set language es" replies in spanish from now on
+///         .user_settings(store.clone())
+///         .add_service("s-settings", Settings::new(store))
+///         .add_service("s-echo", Echo)
+///         .run()
+///         .await;
+/// }
+/// ```
+///
+/// [`Engine::user_settings()`]: crate::engine::Engine::user_settings()
+pub struct Settings {
+    store: Arc<dyn KeyValueStore>,
+    keys: Vec<String>,
+}
+
+impl Settings {
+    pub fn new(store: impl KeyValueStore + 'static) -> Self {
+        Self {
+            store: Arc::new(store),
+            keys: [i18n::LANGUAGE_KEY, TIMEZONE_KEY, OUTPUT_CHANNEL_KEY]
+                .map(String::from)
+                .into(),
+        }
+    }
+
+    /// Allow the users to set an additional preference `key`.
+    pub fn key(mut self, key: impl Into<String>) -> Self {
+        self.keys.push(key.into());
+        self
+    }
+
+    async fn process(&self, request: &Message) -> io::Result<Message> {
+        let user = &request.user;
+        let response = Message::response(request);
+        let body = match request.args_str().as_slice() {
+            ["set", TIMEZONE_KEY, value] if time::parse_timezone(value).is_none() => {
+                return Ok(response.args([i18n::text(request, "format-error")]).body(
+                    i18n::text_with(request, "settings-invalid-timezone", [value]),
+                ))
+            }
+            ["set", key, value] if self.keys.iter().any(|k| k == key) => {
+                self.store
+                    .set(&setting_key(user, key), value.to_string())
+                    .await?;
+                i18n::text(request, "settings-saved")
+            }
+            ["unset", key] if self.keys.iter().any(|k| k == key) => {
+                self.store.remove(&setting_key(user, key)).await?;
+                i18n::text(request, "settings-saved")
+            }
+            ["set", key, _] | ["unset", key] => {
+                return Ok(response.args([i18n::text(request, "format-error")]).body(
+                    i18n::text_with(
+                        request,
+                        "settings-unknown-key",
+                        [key.to_string(), self.keys.join(", ")],
+                    ),
+                ))
+            }
+            ["show"] => {
+                let settings = user_settings(&*self.store, user).await?;
+                match settings.is_empty() {
+                    true => i18n::text(request, "settings-empty"),
+                    false => settings
+                        .into_iter()
+                        .map(|(key, value)| format!("{}: {}", key, value))
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                }
+            }
+            _ => {
+                return Ok(response.args([i18n::text(request, "format-error")]).body(
+                    i18n::text_with(request, "settings-expected-args", [self.keys.join(", ")]),
+                ))
+            }
+        };
+
+        Ok(response.body(body))
+    }
+}
+
+#[async_trait]
+impl Service for Settings {
+    async fn run(
+        self: Box<Self>,
+        mut input: Receiver,
+        output: Sender,
+    ) -> Result<(), ClosedChannel> {
+        loop {
+            let request = input.recv().await?;
+            let response = match self.process(&request).await {
+                Ok(response) => response,
+                Err(err) => {
+                    log::error!("Settings of '{}' not accessible: {}", request.user, err);
+                    Message::response(&request)
+                        .args([i18n::text(&request, "error")])
+                        .body(i18n::text(&request, "settings-failed"))
+                }
+            };
+            output.send(response).await?;
+        }
+    }
+}
+
+fn setting_key(user: &str, key: &str) -> String {
+    format!("{}/{}", user, key)
+}
+
+async fn user_settings(store: &dyn KeyValueStore, user: &str) -> io::Result<Vec<(String, String)>> {
+    let prefix = setting_key(user, "");
+    let mut settings = Vec::new();
+    for key in store.keys(&prefix).await? {
+        if let Some(value) = store.get(&key).await? {
+            settings.push((key[prefix.len()..].to_string(), value));
+        }
+    }
+    Ok(settings)
+}
+
+/// Add the settings of the user to the metadata of the message,
+/// replacing the values set by the connectors or the engine.
+pub(crate) async fn apply_user_settings(
+    store: &dyn KeyValueStore,
+    mut message: Message,
+) -> Message {
+    match user_settings(store, &message.user).await {
+        Ok(settings) => message.metadata.extend(settings),
+        Err(err) => log::error!("Settings of '{}' not accessible: {}", message.user, err),
+    }
+    message
+}
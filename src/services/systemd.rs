@@ -0,0 +1,262 @@
+use crate::channel::{ClosedChannel, Receiver, Sender};
+use crate::i18n;
+use crate::interface::Service;
+use crate::message::Message;
+
+use async_trait::async_trait;
+use tokio::process::Command;
+use zbus::zvariant::OwnedObjectPath;
+use zbus::{Connection, Proxy};
+
+const DESTINATION: &str = "org.freedesktop.systemd1";
+const MANAGER_PATH: &str = "/org/freedesktop/systemd1";
+const MANAGER_INTERFACE: &str = "org.freedesktop.systemd1.Manager";
+const UNIT_INTERFACE: &str = "org.freedesktop.systemd1.Unit";
+
+/// Control systemd units through D-Bus.
+///
+/// The args are the action, `status`, `start`, `stop` or `restart`, followed by the unit.
+/// If the unit has no suffix, it is considered a `.service`.
+/// Only the units of the allowlist given to [`Systemd::new()`] can be used.
+///
+/// The reply contains the state of the unit after the action,
+/// and the recent lines of its journal as attachment (see [`Systemd::journal_lines()`]).
+///
+/// Managing units usually requires privileges: run the server as root,
+/// or allow its user with a polkit rule.
+///
+/// # Example
+/// ```rust no_run
+/// use service_io::connectors::Email;
+/// use service_io::engine::Engine;
+/// use service_io::services::Systemd;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     Engine::default()
+///         .connector(Email::gmail("service@gmail.com", "app-password"))
+///         // "s-systemd restart nginx" restarts nginx.service
+///         .add_service("s-systemd", Systemd::new(["nginx", "backup.timer"]))
+///         .run()
+///         .await;
+/// }
+/// ```
+pub struct Systemd {
+    units: Vec<String>,
+    journal_lines: usize,
+}
+
+impl Systemd {
+    /// Service that only controls the `units`.
+    pub fn new<S: Into<String>>(units: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            units: units
+                .into_iter()
+                .map(|unit| unit_name(&unit.into()))
+                .collect(),
+            journal_lines: 20,
+        }
+    }
+
+    /// Number of lines of the journal of the unit attached to the replies. By default, 20.
+    /// Reading the journal requires the `journalctl` command. Use 0 to not attach it.
+    pub fn journal_lines(mut self, lines: usize) -> Self {
+        self.journal_lines = lines;
+        self
+    }
+
+    async fn journal(&self, unit: &str) -> Option<Vec<u8>> {
+        let lines = self.journal_lines.to_string();
+        let result = Command::new("journalctl")
+            .args([
+                "--unit",
+                unit,
+                "--lines",
+                &lines,
+                "--no-pager",
+                "--output",
+                "short-iso",
+            ])
+            .output()
+            .await;
+
+        match result {
+            Ok(output) if output.status.success() => Some(output.stdout),
+            Ok(output) => {
+                log::error!("journalctl failed ({})", output.status);
+                None
+            }
+            Err(err) => {
+                log::error!("Unable to run journalctl: {}", err);
+                None
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Status,
+    Start,
+    Stop,
+    Restart,
+}
+
+impl Action {
+    fn parse(value: &str) -> Option<Action> {
+        match value {
+            "status" => Some(Action::Status),
+            "start" => Some(Action::Start),
+            "stop" => Some(Action::Stop),
+            "restart" => Some(Action::Restart),
+            _ => None,
+        }
+    }
+
+    /// Method of the systemd manager that performs the action.
+    fn method(self) -> Option<&'static str> {
+        match self {
+            Action::Status => None,
+            Action::Start => Some("StartUnit"),
+            Action::Stop => Some("StopUnit"),
+            Action::Restart => Some("RestartUnit"),
+        }
+    }
+}
+
+/// Full name of the unit, with `.service` as default suffix.
+fn unit_name(unit: &str) -> String {
+    match unit.contains('.') {
+        true => unit.into(),
+        false => format!("{}.service", unit),
+    }
+}
+
+/// Performs the action through D-Bus, returning the state of the unit after it.
+async fn control(connection: &Connection, action: Action, unit: &str) -> zbus::Result<String> {
+    let manager = Proxy::new(connection, DESTINATION, MANAGER_PATH, MANAGER_INTERFACE).await?;
+    if let Some(method) = action.method() {
+        let _job: OwnedObjectPath = manager.call(method, &(unit, "replace")).await?;
+    }
+
+    let path: OwnedObjectPath = manager.call("LoadUnit", &(unit,)).await?;
+    let unit_proxy = Proxy::new(connection, DESTINATION, path, UNIT_INTERFACE).await?;
+    let active: String = unit_proxy.get_property("ActiveState").await?;
+    let sub: String = unit_proxy.get_property("SubState").await?;
+    let description: String = unit_proxy.get_property("Description").await?;
+    Ok(format!("{}: {} ({})\n{}", unit, active, sub, description))
+}
+
+#[async_trait]
+impl Service for Systemd {
+    async fn run(
+        self: Box<Self>,
+        mut input: Receiver,
+        output: Sender,
+    ) -> Result<(), ClosedChannel> {
+        // Connected on the first request, and again after a failure.
+        let mut connection: Option<Connection> = None;
+        loop {
+            let request = input.recv().await?;
+            let (action, unit) = match request.args.as_slice() {
+                [action, unit] => match Action::parse(action) {
+                    Some(action) => (action, unit_name(unit)),
+                    None => {
+                        output.send(format_error(&request)).await?;
+                        continue;
+                    }
+                },
+                _ => {
+                    output.send(format_error(&request)).await?;
+                    continue;
+                }
+            };
+
+            if !self.units.contains(&unit) {
+                let response = Message::response(&request)
+                    .args([i18n::text(&request, "error")])
+                    .body(i18n::text_with(&request, "systemd-not-allowed", [unit]));
+                output.send(response).await?;
+                continue;
+            }
+
+            let result = match &connection {
+                Some(connection) => Ok(connection.clone()),
+                None => Connection::system().await,
+            };
+            let result = match result {
+                Ok(bus) => {
+                    let result = control(&bus, action, &unit).await;
+                    connection = result.is_ok().then_some(bus);
+                    result
+                }
+                Err(err) => Err(err),
+            };
+
+            let response = match result {
+                Ok(state) => {
+                    let response = Message::response(&request).body(state);
+                    match self.journal_lines > 0 {
+                        true => match self.journal(&unit).await {
+                            Some(journal) => response.attach([(format!("{}.log", unit), journal)]),
+                            None => response,
+                        },
+                        false => response,
+                    }
+                }
+                Err(err) => {
+                    log::error!("systemd error with '{}': {}", unit, err);
+                    Message::response(&request)
+                        .args([i18n::text(&request, "error")])
+                        .body(i18n::text_with(
+                            &request,
+                            "systemd-failed",
+                            [unit, err.to_string()],
+                        ))
+                }
+            };
+            output.send(response).await?;
+        }
+    }
+}
+
+fn format_error(request: &Message) -> Message {
+    Message::response(request)
+        .args([i18n::text(request, "format-error")])
+        .body(i18n::text(request, "systemd-expected-args"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel;
+
+    fn request<const N: usize>(args: [&str; N]) -> Message {
+        Message::default()
+            .user("user")
+            .service_name("s-systemd")
+            .args(args)
+    }
+
+    #[tokio::test]
+    async fn rejected_requests() {
+        let (input, service_input) = channel::channel(4);
+        let (service_output, mut output) = channel::channel(4);
+        let service = Systemd::new(["nginx", "backup.timer"]);
+        tokio::spawn(Box::new(service).run(service_input, service_output));
+
+        input.send(request(["reload", "nginx"])).await.unwrap();
+        let response = output.recv().await.unwrap();
+        assert_eq!(response.args, ["format error"]);
+
+        input.send(request(["restart", "sshd"])).await.unwrap();
+        let response = output.recv().await.unwrap();
+        assert_eq!(response.body, "The unit 'sshd.service' is not allowed");
+    }
+
+    #[test]
+    fn unit_names() {
+        assert_eq!(unit_name("nginx"), "nginx.service");
+        assert_eq!(unit_name("backup.timer"), "backup.timer");
+    }
+}
@@ -0,0 +1,169 @@
+use super::otp;
+use crate::channel::{ClosedChannel, Receiver, Sender};
+use crate::i18n;
+use crate::interface::Service;
+use crate::message::Message;
+use crate::state::KeyValueStore;
+
+use async_trait::async_trait;
+
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Reply with the current TOTP code of a secret kept in the server,
+/// the same code an authenticator app would show.
+///
+/// The first arg is the name of the secret. Without args, the names of the secrets are replied.
+/// The secrets are read from the [`KeyValueStore`] by their name, in base32 as the
+/// services show them when enabling the two-factor authentication.
+///
+/// Anyone using the service obtains the codes:
+/// register it with [`Engine::add_service_for()`] to only allow your users.
+///
+/// # Example
+/// ```rust no_run
+/// use service_io::connectors::{ImapClient, SmtpClient};
+/// use service_io::engine::Engine;
+/// use service_io::services::Totp;
+/// use service_io::state::{FileStore, Scoped};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     Engine::default()
+///         .input(ImapClient::default() /* ... */)
+///         .output(SmtpClient::default() /* ... */)
+///         // "s-totp github" replies with the current code of the "github" secret
+///         .add_service_for(
+///             "s-totp",
+///             Totp::new(Scoped::new(FileStore::new("state.json"), "totp")),
+///             ["me@domain.com"],
+///         )
+///         .run()
+///         .await;
+/// }
+/// ```
+///
+/// [`Engine::add_service_for()`]: crate::engine::Engine::add_service_for()
+pub struct Totp {
+    store: Arc<dyn KeyValueStore>,
+}
+
+impl Totp {
+    /// Service reading the secrets from `store`.
+    pub fn new(store: impl KeyValueStore + 'static) -> Self {
+        Self {
+            store: Arc::new(store),
+        }
+    }
+
+    async fn reply(&self, request: &Message) -> Message {
+        let name = match request.args.first() {
+            Some(name) => name,
+            None => return self.names(request).await,
+        };
+
+        let secret = match self.store.get(name).await {
+            Ok(Some(secret)) => secret,
+            Ok(None) => return error(request, "totp-unknown", name),
+            Err(err) => {
+                log::error!("Can not read the TOTP secret '{}': {}", name, err);
+                return error(request, "totp-failed", name);
+            }
+        };
+
+        match otp::decode_base32(&secret) {
+            Some(secret) => {
+                let now = SystemTime::now();
+                let elapsed = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+                let remaining = otp::STEP - elapsed % otp::STEP;
+                let code = otp::code(&secret, otp::counter(now));
+                Message::response(request).body(i18n::text_with(
+                    request,
+                    "totp-code",
+                    [code, remaining.to_string()],
+                ))
+            }
+            None => {
+                log::error!("The TOTP secret '{}' is not valid base32", name);
+                error(request, "totp-failed", name)
+            }
+        }
+    }
+
+    async fn names(&self, request: &Message) -> Message {
+        match self.store.keys("").await {
+            Ok(names) => Message::response(request)
+                .args([i18n::text(request, "format-error")])
+                .body(i18n::text_with(
+                    request,
+                    "totp-expected-args",
+                    [names.join(" | ")],
+                )),
+            Err(err) => {
+                log::error!("Can not read the TOTP secrets: {}", err);
+                error(request, "totp-failed", "")
+            }
+        }
+    }
+}
+
+fn error(request: &Message, key: &str, name: &str) -> Message {
+    Message::response(request)
+        .args([i18n::text(request, "error")])
+        .body(i18n::text_with(request, key, [name]))
+}
+
+#[async_trait]
+impl Service for Totp {
+    async fn run(
+        self: Box<Self>,
+        mut input: Receiver,
+        output: Sender,
+    ) -> Result<(), ClosedChannel> {
+        loop {
+            let request = input.recv().await?;
+            output.send(self.reply(&request).await).await?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel;
+    use crate::state::MemoryStore;
+
+    #[tokio::test]
+    async fn codes() {
+        let store = MemoryStore::default();
+        let secret = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+        store.set("github", secret.into()).await.unwrap();
+        store.set("broken", "not base32!".into()).await.unwrap();
+
+        let (input, service_input) = channel::channel(4);
+        let (service_output, mut output) = channel::channel(4);
+        tokio::spawn(Box::new(Totp::new(store)).run(service_input, service_output));
+
+        let request = Message::default().user("user").service_name("s-totp");
+        input.send(request.clone().args(["github"])).await.unwrap();
+        let response = output.recv().await.unwrap();
+        let secret = otp::decode_base32(secret).unwrap();
+        let counter = otp::counter(SystemTime::now());
+        let expected = [otp::code(&secret, counter - 1), otp::code(&secret, counter)];
+        assert!(expected
+            .iter()
+            .any(|code| response.body.starts_with(code.as_str())));
+
+        input.send(request.clone().args(["broken"])).await.unwrap();
+        let response = output.recv().await.unwrap();
+        assert_eq!(response.args, ["error"]);
+
+        input.send(request.clone().args(["gitlab"])).await.unwrap();
+        let response = output.recv().await.unwrap();
+        assert_eq!(response.body, "Unknown secret 'gitlab'");
+
+        input.send(request).await.unwrap();
+        let response = output.recv().await.unwrap();
+        assert_eq!(response.body, "Expected args: <broken | github>");
+    }
+}
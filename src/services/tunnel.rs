@@ -0,0 +1,404 @@
+use crate::channel::{ClosedChannel, Receiver, Sender};
+use crate::i18n;
+use crate::interface::Service;
+use crate::message::Message;
+
+use async_trait::async_trait;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::mpsc;
+use tokio::time::{self, Instant};
+
+use std::process::Stdio;
+use std::time::Duration;
+
+/// Service that opens a reverse tunnel on demand,
+/// to temporarily expose a local web UI to the internet.
+///
+/// The tunnel is a process (i.e. `cloudflared` or `ssh -R`) supervised by the service:
+/// if it finishes while the tunnel is open, it is restarted after [`Tunnel::restart_delay()`].
+///
+/// The first arg is the command:
+/// - `start`: opens the tunnel and replies with its public URL.
+/// - `stop`: closes the tunnel.
+/// - `status`: replies whether the tunnel is open, with its public URL.
+///
+/// The public URL is the first `https://` URL written by the process (in stdout or stderr)
+/// containing [`Tunnel::url_pattern()`], unless it is fixed with [`Tunnel::url()`].
+///
+/// # Example
+/// ```rust no_run
+/// use service_io::connectors::Email;
+/// use service_io::engine::Engine;
+/// use service_io::services::Tunnel;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     Engine::default()
+///         .connector(Email::gmail("service@gmail.com", "app-password"))
+///         // "s-tunnel start" replies with the public URL of the local port 8080
+///         .add_service("s-tunnel", Tunnel::cloudflared("http://localhost:8080"))
+///         .run()
+///         .await;
+/// }
+/// ```
+pub struct Tunnel {
+    program: String,
+    args: Vec<String>,
+    url_pattern: String,
+    url: Option<String>,
+    url_timeout: Duration,
+    restart_delay: Duration,
+}
+
+impl Tunnel {
+    /// Create the service that will run the `program` to open the tunnel.
+    pub fn new(program: impl Into<String>) -> Self {
+        Tunnel {
+            program: program.into(),
+            args: Vec::new(),
+            url_pattern: String::new(),
+            url: None,
+            url_timeout: Duration::from_secs(30),
+            restart_delay: Duration::from_secs(5),
+        }
+    }
+
+    /// Cloudflare quick tunnel exposing the `local_url` (i.e. `http://localhost:8080`).
+    /// Requires `cloudflared` installed, but no Cloudflare account.
+    pub fn cloudflared(local_url: impl Into<String>) -> Self {
+        Tunnel::new("cloudflared")
+            .args(["tunnel", "--no-autoupdate", "--url", &local_url.into()])
+            .url_pattern(".trycloudflare.com")
+    }
+
+    /// SSH remote forwarding of the `local_port` to the `remote_port` of the `host`
+    /// (as `[user@]host`). The SSH key must be already authorized in the host.
+    /// The public URL depends on the host, set it with [`Tunnel::url()`].
+    pub fn ssh(host: impl Into<String>, remote_port: u16, local_port: u16) -> Self {
+        let forwarding = format!("{}:localhost:{}", remote_port, local_port);
+        Tunnel::new("ssh").args([
+            "-N",
+            "-o",
+            "ExitOnForwardFailure=yes",
+            "-o",
+            "BatchMode=yes",
+            "-R",
+            &forwarding,
+            &host.into(),
+        ])
+    }
+
+    /// Arguments passed to the program.
+    pub fn args<S: Into<String>>(mut self, args: impl IntoIterator<Item = S>) -> Self {
+        self.args = args.into_iter().map(|s| s.into()).collect();
+        self
+    }
+
+    /// Text that the public URL written by the process must contain.
+    /// By default, the first `https://` URL is taken.
+    pub fn url_pattern(mut self, pattern: impl Into<String>) -> Self {
+        self.url_pattern = pattern.into();
+        self
+    }
+
+    /// Public URL of the tunnel, when it is known beforehand instead of written by the process.
+    pub fn url(mut self, url: impl Into<String>) -> Self {
+        self.url = Some(url.into());
+        self
+    }
+
+    /// Time waiting for the process to write the public URL. By default, 30 seconds.
+    /// After that, `start` replies without URL.
+    pub fn url_timeout(mut self, duration: Duration) -> Self {
+        self.url_timeout = duration;
+        self
+    }
+
+    /// Time to wait before restarting the process if it finishes while the tunnel is open.
+    /// By default 5 seconds.
+    pub fn restart_delay(mut self, delay: Duration) -> Self {
+        self.restart_delay = delay;
+        self
+    }
+
+    fn spawn(&self) -> std::io::Result<Process> {
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        // Both outputs are read, since the tunnel programs usually log to stderr.
+        let (sender, lines) = mpsc::unbounded_channel();
+        forward_lines(child.stdout.take().expect("Piped stdout"), sender.clone());
+        forward_lines(child.stderr.take().expect("Piped stderr"), sender);
+        Ok(Process { child, lines })
+    }
+
+    fn find_url(&self, line: &str) -> Option<String> {
+        line.split_whitespace()
+            .map(|word| word.trim_matches(|c| matches!(c, '|' | '"' | '\'' | '<' | '>')))
+            .find(|word| word.starts_with("https://") && word.contains(&self.url_pattern))
+            .map(String::from)
+    }
+}
+
+struct Process {
+    child: Child,
+    lines: mpsc::UnboundedReceiver<String>,
+}
+
+fn forward_lines(
+    output: impl AsyncRead + Unpin + Send + 'static,
+    sender: mpsc::UnboundedSender<String>,
+) {
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(output).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// State of the tunnel handled by the service loop.
+#[derive(Default)]
+struct State {
+    process: Option<Process>,
+    url: Option<String>,
+    // `start` requests waiting for the public URL.
+    pending: Vec<Message>,
+    url_deadline: Option<Instant>,
+    restart_at: Option<Instant>,
+}
+
+impl State {
+    fn is_open(&self) -> bool {
+        self.process.is_some() || self.restart_at.is_some()
+    }
+}
+
+impl Tunnel {
+    async fn handle(
+        &self,
+        request: Message,
+        state: &mut State,
+        output: &Sender,
+    ) -> Result<(), ClosedChannel> {
+        match request.args.first().map(|arg| arg.as_str()) {
+            Some("start") if state.is_open() => {
+                output.send(running_response(&request, state)).await
+            }
+            Some("start") => match self.spawn() {
+                Ok(process) => {
+                    log::info!("Tunnel '{}' started", self.program);
+                    state.process = Some(process);
+                    state.url = self.url.clone();
+                    match &state.url {
+                        Some(_) => output.send(running_response(&request, state)).await,
+                        None => {
+                            state.pending.push(request);
+                            state.url_deadline = Some(Instant::now() + self.url_timeout);
+                            Ok(())
+                        }
+                    }
+                }
+                Err(err) => {
+                    log::error!("Unable to run '{}': {}", self.program, err);
+                    let response = Message::response(&request)
+                        .args([i18n::text(&request, "error")])
+                        .body(i18n::text_with(
+                            &request,
+                            "tunnel-failed",
+                            [err.to_string()],
+                        ));
+                    output.send(response).await
+                }
+            },
+            Some("stop") => {
+                if let Some(mut process) = state.process.take() {
+                    process.child.kill().await.ok();
+                    log::info!("Tunnel '{}' stopped", self.program);
+                }
+                let pending = std::mem::take(&mut state.pending);
+                *state = State::default();
+                for request in pending.iter().chain([&request]) {
+                    let response =
+                        Message::response(request).body(i18n::text(request, "tunnel-stopped"));
+                    output.send(response).await?;
+                }
+                Ok(())
+            }
+            Some("status") => {
+                let response = match state.is_open() {
+                    true => running_response(&request, state),
+                    false => {
+                        Message::response(&request).body(i18n::text(&request, "tunnel-not-running"))
+                    }
+                };
+                output.send(response).await
+            }
+            _ => {
+                let response = Message::response(&request)
+                    .args([i18n::text(&request, "format-error")])
+                    .body(i18n::text(&request, "tunnel-expected-args"));
+                output.send(response).await
+            }
+        }
+    }
+
+    async fn process_line(
+        &self,
+        line: String,
+        state: &mut State,
+        output: &Sender,
+    ) -> Result<(), ClosedChannel> {
+        log::debug!("Tunnel '{}': {}", self.program, line);
+        if state.url.is_none() {
+            if let Some(url) = self.find_url(&line) {
+                log::info!("Tunnel '{}' open at {}", self.program, url);
+                state.url = Some(url);
+                state.url_deadline = None;
+                for request in std::mem::take(&mut state.pending) {
+                    output.send(running_response(&request, state)).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The process closed its outputs: it finished.
+    async fn process_finished(
+        &self,
+        state: &mut State,
+        output: &Sender,
+    ) -> Result<(), ClosedChannel> {
+        if let Some(mut process) = state.process.take() {
+            match process.child.wait().await {
+                Ok(status) => log::error!("Tunnel '{}' finished ({})", self.program, status),
+                Err(err) => log::error!("Tunnel '{}': {}", self.program, err),
+            }
+        }
+        state.url = None;
+        state.url_deadline = None;
+        state.restart_at = Some(Instant::now() + self.restart_delay);
+        for request in std::mem::take(&mut state.pending) {
+            let response = Message::response(&request)
+                .args([i18n::text(&request, "error")])
+                .body(i18n::text(&request, "tunnel-exited"));
+            output.send(response).await?;
+        }
+        Ok(())
+    }
+
+    fn restart(&self, state: &mut State) {
+        state.restart_at = None;
+        log::info!("Restarting tunnel '{}'", self.program);
+        match self.spawn() {
+            Ok(process) => {
+                state.process = Some(process);
+                state.url = self.url.clone();
+            }
+            Err(err) => {
+                log::error!("Unable to run '{}': {}", self.program, err);
+                state.restart_at = Some(Instant::now() + self.restart_delay);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Service for Tunnel {
+    async fn run(
+        self: Box<Self>,
+        mut input: Receiver,
+        output: Sender,
+    ) -> Result<(), ClosedChannel> {
+        let token = input.cancellation_token();
+        let mut state = State::default();
+        loop {
+            tokio::select! {
+                request = input.recv() => self.handle(request?, &mut state, &output).await?,
+                line = recv_line(&mut state.process), if state.process.is_some() => match line {
+                    Some(line) => self.process_line(line, &mut state, &output).await?,
+                    None => self.process_finished(&mut state, &output).await?,
+                },
+                _ = sleep_until(state.url_deadline), if state.url_deadline.is_some() => {
+                    state.url_deadline = None;
+                    for request in std::mem::take(&mut state.pending) {
+                        output.send(running_response(&request, &state)).await?;
+                    }
+                }
+                _ = sleep_until(state.restart_at), if state.restart_at.is_some() => {
+                    self.restart(&mut state);
+                }
+                _ = token.cancelled() => return Ok(()),
+            }
+        }
+    }
+}
+
+async fn recv_line(process: &mut Option<Process>) -> Option<String> {
+    process.as_mut()?.lines.recv().await
+}
+
+async fn sleep_until(instant: Option<Instant>) {
+    if let Some(instant) = instant {
+        time::sleep_until(instant).await;
+    }
+}
+
+fn running_response(request: &Message, state: &State) -> Message {
+    let response = Message::response(request);
+    match &state.url {
+        Some(url) => response.body(i18n::text_with(request, "tunnel-open", [url.clone()])),
+        None => response.body(i18n::text(request, "tunnel-no-url")),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::channel;
+
+    fn request(command: &str) -> Message {
+        Message::default()
+            .user("user")
+            .service_name("s-tunnel")
+            .args([command])
+    }
+
+    #[tokio::test]
+    async fn open_and_close() {
+        let tunnel = Tunnel::new("sh")
+            .args([
+                "-c",
+                "echo 'Visit it at: https://demo.example.com' >&2; sleep 10",
+            ])
+            .url_pattern("example.com");
+
+        let (input, service_input) = channel::channel(4);
+        let (service_output, mut output) = channel::channel(4);
+        tokio::spawn(Box::new(tunnel).run(service_input, service_output));
+
+        input.send(request("status")).await.unwrap();
+        let response = output.recv().await.unwrap();
+        assert_eq!(response.body, "The tunnel is not open");
+
+        input.send(request("start")).await.unwrap();
+        let response = output.recv().await.unwrap();
+        assert_eq!(response.body, "Tunnel open at https://demo.example.com");
+
+        input.send(request("status")).await.unwrap();
+        let response = output.recv().await.unwrap();
+        assert_eq!(response.body, "Tunnel open at https://demo.example.com");
+
+        input.send(request("stop")).await.unwrap();
+        let response = output.recv().await.unwrap();
+        assert_eq!(response.body, "Tunnel closed");
+    }
+}
@@ -0,0 +1,300 @@
+use crate::channel::{ClosedChannel, Receiver, Sender};
+use crate::i18n;
+use crate::interface::Service;
+use crate::message::Message;
+
+use async_trait::async_trait;
+use wasmtime::{Config, Engine, Instance, Memory, Module, Store, TypedFunc};
+
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// Service implemented by a WebAssembly module, so it can be written in any language
+/// that compiles to WebAssembly.
+/// The module is reloaded if the file changes, so the service can be updated without
+/// recompiling or restarting the server.
+///
+/// Only available with the `wasm` feature.
+///
+/// The module must export:
+/// - `memory`: the linear memory of the module.
+/// - `alloc(len: i32) -> i32`: reserves `len` bytes in the memory and returns its position.
+/// - `dealloc(ptr: i32, len: i32)`: releases the `len` bytes reserved at `ptr`.
+///   It is called for the request once handled, and for the responses once read.
+/// - `handle(ptr: i32, len: i32) -> i64`: processes the request message, encoded as JSON
+///   in the memory at `ptr` with `len` bytes. It returns the position (high 32 bits)
+///   and the length (low 32 bits) of a JSON array with the response messages.
+///
+/// The messages are encoded with the [`serde`] representation of [`Message`].
+/// Response messages without user or service name are sent to the user and service name
+/// of the request.
+///
+/// Each request is processed with a budget of [`WasmPlugin::fuel()`].
+/// A module running out of fuel (e.g. looping forever) is reloaded
+/// and the request is answered with an error.
+///
+/// # Example
+/// ```rust no_run
+/// use service_io::connectors::{DebugStdout, UserStdin};
+/// use service_io::engine::Engine;
+/// use service_io::services::WasmPlugin;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     Engine::default()
+///         .input(UserStdin("user"))
+///         .output(DebugStdout)
+///         .add_service("s-plugin", WasmPlugin::from_file("plugin.wasm"))
+///         .run()
+///         .await;
+/// }
+/// ```
+pub struct WasmPlugin {
+    path: PathBuf,
+    fuel: u64,
+}
+
+impl WasmPlugin {
+    /// Load the plugin from a `.wasm` file (or `.wat` text file).
+    pub fn from_file(path: impl Into<PathBuf>) -> WasmPlugin {
+        WasmPlugin {
+            path: path.into(),
+            fuel: 1_000_000_000,
+        }
+    }
+
+    /// Fuel units (roughly WebAssembly instructions) that the module can consume
+    /// processing a request. By default 1 billion.
+    pub fn fuel(mut self, fuel: u64) -> Self {
+        self.fuel = fuel;
+        self
+    }
+}
+
+#[async_trait]
+impl Service for WasmPlugin {
+    async fn run(
+        self: Box<Self>,
+        mut input: Receiver,
+        output: Sender,
+    ) -> Result<(), ClosedChannel> {
+        let engine = Engine::new(Config::new().consume_fuel(true)).expect("Valid config");
+        let mut plugin: Option<Plugin> = None;
+
+        loop {
+            let request = input.recv().await?;
+            let error_response = Message::response(&request)
+                .args([i18n::text(&request, "error")])
+                .body(i18n::text(&request, "plugin-failed"));
+
+            let path = self.path.clone();
+            let fuel = self.fuel;
+            let engine = engine.clone();
+            let (returned_plugin, result) = tokio::task::spawn_blocking(move || {
+                let mut plugin = match plugin {
+                    Some(plugin) if !plugin.is_outdated(&path) => plugin,
+                    _ => match Plugin::load(&engine, &path) {
+                        Ok(plugin) => {
+                            log::info!("Plugin loaded from {}", path.display());
+                            plugin
+                        }
+                        Err(err) => return (None, Err(err)),
+                    },
+                };
+                match plugin.call(&request, fuel) {
+                    Ok(responses) => (Some(plugin), Ok((request, responses))),
+                    // The instance could be left in an inconsistent state, so it is reloaded.
+                    Err(err) => (None, Err(err)),
+                }
+            })
+            .await
+            .unwrap();
+
+            plugin = returned_plugin;
+            match result {
+                Ok((request, responses)) => {
+                    for mut response in responses {
+                        if response.user.is_empty() {
+                            response.user = request.user.clone();
+                        }
+                        if response.service_name.is_empty() {
+                            response.service_name = request.service_name.clone();
+                        }
+                        output.send(response).await?;
+                    }
+                }
+                Err(err) => {
+                    log::error!("Plugin {} failed: {}", self.path.display(), err);
+                    output.send(error_response).await?;
+                }
+            }
+        }
+    }
+}
+
+struct Plugin {
+    store: Store<()>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    dealloc: TypedFunc<(i32, i32), ()>,
+    handle: TypedFunc<(i32, i32), i64>,
+    modified: Option<SystemTime>,
+}
+
+impl Plugin {
+    fn load(engine: &Engine, path: &PathBuf) -> wasmtime::Result<Plugin> {
+        let modified = std::fs::metadata(path)?.modified().ok();
+        let module = Module::from_file(engine, path)?;
+        let mut store = Store::new(engine, ());
+        let instance = Instance::new(&mut store, &module, &[])?;
+
+        Ok(Plugin {
+            memory: instance
+                .get_memory(&mut store, "memory")
+                .ok_or_else(|| wasmtime::Error::msg("'memory' export not found"))?,
+            alloc: instance.get_typed_func(&mut store, "alloc")?,
+            dealloc: instance.get_typed_func(&mut store, "dealloc")?,
+            handle: instance.get_typed_func(&mut store, "handle")?,
+            store,
+            modified,
+        })
+    }
+
+    fn is_outdated(&self, path: &PathBuf) -> bool {
+        let modified = std::fs::metadata(path).and_then(|metadata| metadata.modified());
+        match (modified, self.modified) {
+            (Ok(modified), Some(loaded)) => modified != loaded,
+            _ => false,
+        }
+    }
+
+    fn call(&mut self, request: &Message, fuel: u64) -> wasmtime::Result<Vec<Message>> {
+        self.store.set_fuel(fuel)?;
+
+        let encoded = serde_json::to_vec(request)?;
+        let len = i32::try_from(encoded.len())?;
+
+        let ptr = self.alloc.call(&mut self.store, len)?;
+        self.memory.write(&mut self.store, ptr as usize, &encoded)?;
+
+        let packed = self.handle.call(&mut self.store, (ptr, len))?;
+        self.dealloc.call(&mut self.store, (ptr, len))?;
+        let (ptr, len) = ((packed >> 32) as u32, packed as u32);
+
+        // The guest could return any range, so it is checked before reading it.
+        let encoded = (ptr as usize)
+            .checked_add(len as usize)
+            .and_then(|end| self.memory.data(&self.store).get(ptr as usize..end))
+            .ok_or_else(|| wasmtime::Error::msg("Response out of the plugin memory"))?
+            .to_vec();
+        self.dealloc
+            .call(&mut self.store, (ptr as i32, len as i32))?;
+        Ok(serde_json::from_slice(&encoded)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel;
+
+    /// Replies the request as it is: wraps the request JSON into a JSON array.
+    const ECHO_PLUGIN: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (global $next (mut i32) (i32.const 1024))
+          (func $alloc (export "alloc") (param $len i32) (result i32)
+            (local $ptr i32)
+            (local.set $ptr (global.get $next))
+            (global.set $next (i32.add (global.get $next) (local.get $len)))
+            (local.get $ptr))
+          (func (export "dealloc") (param $ptr i32) (param $len i32))
+          (func (export "handle") (param $ptr i32) (param $len i32) (result i64)
+            (local $out i32)
+            (local.set $out (call $alloc (i32.add (local.get $len) (i32.const 2))))
+            (i32.store8 (local.get $out) (i32.const 91))
+            (memory.copy (i32.add (local.get $out) (i32.const 1)) (local.get $ptr) (local.get $len))
+            (i32.store8
+              (i32.add (i32.add (local.get $out) (i32.const 1)) (local.get $len))
+              (i32.const 93))
+            (i64.or
+              (i64.shl (i64.extend_i32_u (local.get $out)) (i64.const 32))
+              (i64.extend_i32_u (i32.add (local.get $len) (i32.const 2))))))
+    "#;
+
+    /// Loops forever handling the requests.
+    const LOOP_PLUGIN: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "alloc") (param $len i32) (result i32)
+            (i32.const 1024))
+          (func (export "dealloc") (param $ptr i32) (param $len i32))
+          (func (export "handle") (param $ptr i32) (param $len i32) (result i64)
+            (loop $forever (br $forever))
+            (i64.const 0)))
+    "#;
+
+    /// Answers with a response longer than its memory.
+    const OVERFLOW_PLUGIN: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "alloc") (param $len i32) (result i32)
+            (i32.const 1024))
+          (func (export "dealloc") (param $ptr i32) (param $len i32))
+          (func (export "handle") (param $ptr i32) (param $len i32) (result i64)
+            (i64.const 0x00000400_ffffffff)))
+    "#;
+
+    fn run_plugin(
+        name: &str,
+        code: &str,
+        plugin: impl FnOnce(WasmPlugin) -> WasmPlugin,
+    ) -> (channel::Sender, channel::Receiver) {
+        let path = std::env::temp_dir().join(format!("service-io-{}-plugin.wat", name));
+        std::fs::write(&path, code).unwrap();
+
+        let (input, service_input) = channel::channel(4);
+        let (service_output, output) = channel::channel(4);
+        let service = plugin(WasmPlugin::from_file(path));
+        tokio::spawn(Box::new(service).run(service_input, service_output));
+        (input, output)
+    }
+
+    #[tokio::test]
+    async fn echo_plugin() {
+        let (input, mut output) = run_plugin("echo", ECHO_PLUGIN, |plugin| plugin);
+
+        let message = Message::default()
+            .user("user")
+            .service_name("s-plugin")
+            .args(["arg0"])
+            .attach([("file", b"1234".to_vec())]);
+
+        input.send(message.clone()).await.unwrap();
+        assert_eq!(message, output.recv().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn loop_plugin() {
+        let (input, mut output) = run_plugin("loop", LOOP_PLUGIN, |plugin| plugin.fuel(100_000));
+
+        let message = Message::default().user("user").service_name("s-plugin");
+        for _ in 0..2 {
+            input.send(message.clone()).await.unwrap();
+            let response = output.recv().await.unwrap();
+            assert_eq!(response.args, ["error"]);
+            assert_eq!(response.body, "The plugin failed processing the request");
+        }
+    }
+
+    #[tokio::test]
+    async fn overflow_plugin() {
+        let (input, mut output) = run_plugin("overflow", OVERFLOW_PLUGIN, |plugin| plugin);
+
+        let message = Message::default().user("user").service_name("s-plugin");
+        input.send(message).await.unwrap();
+        let response = output.recv().await.unwrap();
+        assert_eq!(response.args, ["error"]);
+        assert_eq!(response.body, "The plugin failed processing the request");
+    }
+}
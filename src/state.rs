@@ -0,0 +1,394 @@
+//! Persistent key-value state for the stateful services,
+//! so all of them keep their state in the same backend instead of each one in its own file.
+//!
+//! A store can be shared by several services wrapping it in an [`Arc`].
+//! Each service should use its own [`Scoped`] view of the store to not collide with the keys
+//! of other services.
+//! The values are strings, usually the JSON serialization of the service state.
+//!
+//! # Example
+//! ```rust
+//! use service_io::state::{KeyValueStore, MemoryStore, Scoped};
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let store = MemoryStore::default();
+//!     let alarms = Scoped::new(store.clone(), "alarm");
+//!
+//!     alarms.set("user@domain.com", "[\"pizza\"]".into()).await.unwrap();
+//!     assert_eq!(store.keys("").await.unwrap(), ["alarm/user@domain.com"]);
+//!     assert_eq!(alarms.keys("").await.unwrap(), ["user@domain.com"]);
+//! }
+//! ```
+
+use async_trait::async_trait;
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Storage of string values by key.
+#[async_trait]
+pub trait KeyValueStore: Send + Sync {
+    /// Value of the `key`, if any.
+    async fn get(&self, key: &str) -> io::Result<Option<String>>;
+
+    /// Stores the `value` with the `key`, replacing the previous one.
+    async fn set(&self, key: &str, value: String) -> io::Result<()>;
+
+    /// Removes the value of the `key`. Removing a non-existent key is not an error.
+    async fn remove(&self, key: &str) -> io::Result<()>;
+
+    /// Stored keys that start with `prefix`, sorted.
+    async fn keys(&self, prefix: &str) -> io::Result<Vec<String>>;
+}
+
+#[async_trait]
+impl<S: KeyValueStore + ?Sized> KeyValueStore for Arc<S> {
+    async fn get(&self, key: &str) -> io::Result<Option<String>> {
+        (**self).get(key).await
+    }
+
+    async fn set(&self, key: &str, value: String) -> io::Result<()> {
+        (**self).set(key, value).await
+    }
+
+    async fn remove(&self, key: &str) -> io::Result<()> {
+        (**self).remove(key).await
+    }
+
+    async fn keys(&self, prefix: &str) -> io::Result<Vec<String>> {
+        (**self).keys(prefix).await
+    }
+}
+
+/// In-memory [`KeyValueStore`]. The state is lost when the process finishes.
+/// The clones share the same state.
+#[derive(Clone, Default)]
+pub struct MemoryStore {
+    values: Arc<Mutex<BTreeMap<String, String>>>,
+}
+
+#[async_trait]
+impl KeyValueStore for MemoryStore {
+    async fn get(&self, key: &str) -> io::Result<Option<String>> {
+        Ok(self.values.lock().unwrap().get(key).cloned())
+    }
+
+    async fn set(&self, key: &str, value: String) -> io::Result<()> {
+        self.values.lock().unwrap().insert(key.into(), value);
+        Ok(())
+    }
+
+    async fn remove(&self, key: &str) -> io::Result<()> {
+        self.values.lock().unwrap().remove(key);
+        Ok(())
+    }
+
+    async fn keys(&self, prefix: &str) -> io::Result<Vec<String>> {
+        let values = self.values.lock().unwrap();
+        Ok(values
+            .range(prefix.to_string()..)
+            .map(|(key, _)| key)
+            .take_while(|key| key.starts_with(prefix))
+            .cloned()
+            .collect())
+    }
+}
+
+/// [`KeyValueStore`] in a JSON file, created if it does not exist.
+/// The whole file is rewritten on each change, so it fits small states,
+/// i.e. configurations modified at runtime. Use `SqliteStore` (`sqlite` feature) for bigger states.
+pub struct FileStore {
+    path: PathBuf,
+    values: tokio::sync::Mutex<Option<BTreeMap<String, String>>>,
+}
+
+impl FileStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            values: tokio::sync::Mutex::default(),
+        }
+    }
+
+    /// Values of the file, read the first time they are needed.
+    async fn values<'a>(
+        &self,
+        values: &'a mut Option<BTreeMap<String, String>>,
+    ) -> io::Result<&'a mut BTreeMap<String, String>> {
+        if values.is_none() {
+            let loaded = match tokio::fs::read(&self.path).await {
+                Ok(data) => serde_json::from_slice(&data)?,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+                Err(err) => return Err(err),
+            };
+            *values = Some(loaded);
+        }
+        Ok(values.as_mut().unwrap())
+    }
+
+    /// Writes a temporary file and renames it, so the file is never left half written.
+    async fn save(&self, values: &BTreeMap<String, String>) -> io::Result<()> {
+        let mut temporal = self.path.clone().into_os_string();
+        temporal.push(".tmp");
+        tokio::fs::write(&temporal, serde_json::to_vec_pretty(values)?).await?;
+        tokio::fs::rename(&temporal, &self.path).await
+    }
+}
+
+#[async_trait]
+impl KeyValueStore for FileStore {
+    async fn get(&self, key: &str) -> io::Result<Option<String>> {
+        let mut values = self.values.lock().await;
+        Ok(self.values(&mut values).await?.get(key).cloned())
+    }
+
+    async fn set(&self, key: &str, value: String) -> io::Result<()> {
+        let mut values = self.values.lock().await;
+        let values = self.values(&mut values).await?;
+        values.insert(key.into(), value);
+        self.save(values).await
+    }
+
+    async fn remove(&self, key: &str) -> io::Result<()> {
+        let mut values = self.values.lock().await;
+        let values = self.values(&mut values).await?;
+        if values.remove(key).is_some() {
+            self.save(values).await?;
+        }
+        Ok(())
+    }
+
+    async fn keys(&self, prefix: &str) -> io::Result<Vec<String>> {
+        let mut values = self.values.lock().await;
+        Ok(self
+            .values(&mut values)
+            .await?
+            .keys()
+            .filter(|key| key.starts_with(prefix))
+            .cloned()
+            .collect())
+    }
+}
+
+/// [`KeyValueStore`] in a table of a SQLite database file, created if it does not exist.
+/// Several stores can use the same file with different tables.
+#[cfg(feature = "sqlite")]
+#[derive(Clone)]
+pub struct SqliteStore {
+    connection: Arc<Mutex<rusqlite::Connection>>,
+    table: String,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteStore {
+    /// Opens the database at `path` using the table `"state"`.
+    pub fn open(path: impl AsRef<std::path::Path>) -> io::Result<Self> {
+        let connection = rusqlite::Connection::open(path).map_err(io::Error::other)?;
+        Self::with_connection(connection, "state")
+    }
+
+    /// Database that only lives in memory, mainly for testing.
+    pub fn memory() -> io::Result<Self> {
+        let connection = rusqlite::Connection::open_in_memory().map_err(io::Error::other)?;
+        Self::with_connection(connection, "state")
+    }
+
+    /// Uses the table `table` instead of `"state"`.
+    pub fn table(mut self, table: impl Into<String>) -> io::Result<Self> {
+        self.table = table.into();
+        self.create_table()?;
+        Ok(self)
+    }
+
+    fn with_connection(connection: rusqlite::Connection, table: &str) -> io::Result<Self> {
+        let store = Self {
+            connection: Arc::new(Mutex::new(connection)),
+            table: table.into(),
+        };
+        store.create_table()?;
+        Ok(store)
+    }
+
+    fn create_table(&self) -> io::Result<()> {
+        let statement = format!(
+            "CREATE TABLE IF NOT EXISTS \"{}\" (key TEXT PRIMARY KEY, value TEXT NOT NULL)",
+            self.table
+        );
+        let connection = self.connection.lock().unwrap();
+        connection
+            .execute(&statement, [])
+            .map(|_| ())
+            .map_err(io::Error::other)
+    }
+
+    /// Runs the blocking database access out of the async runtime.
+    async fn blocking<T: Send + 'static>(
+        &self,
+        query: impl FnOnce(&rusqlite::Connection, &str) -> rusqlite::Result<T> + Send + 'static,
+    ) -> io::Result<T> {
+        let connection = self.connection.clone();
+        let table = self.table.clone();
+        tokio::task::spawn_blocking(move || query(&connection.lock().unwrap(), &table))
+            .await
+            .map_err(io::Error::other)?
+            .map_err(io::Error::other)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl KeyValueStore for SqliteStore {
+    async fn get(&self, key: &str) -> io::Result<Option<String>> {
+        use rusqlite::OptionalExtension;
+
+        let key = key.to_string();
+        self.blocking(move |connection, table| {
+            let statement = format!("SELECT value FROM \"{}\" WHERE key = ?1", table);
+            connection
+                .query_row(&statement, [key], |row| row.get(0))
+                .optional()
+        })
+        .await
+    }
+
+    async fn set(&self, key: &str, value: String) -> io::Result<()> {
+        let key = key.to_string();
+        self.blocking(move |connection, table| {
+            let statement = format!(
+                "INSERT INTO \"{}\" (key, value) VALUES (?1, ?2) \
+                 ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+                table
+            );
+            connection.execute(&statement, [key, value]).map(|_| ())
+        })
+        .await
+    }
+
+    async fn remove(&self, key: &str) -> io::Result<()> {
+        let key = key.to_string();
+        self.blocking(move |connection, table| {
+            let statement = format!("DELETE FROM \"{}\" WHERE key = ?1", table);
+            connection.execute(&statement, [key]).map(|_| ())
+        })
+        .await
+    }
+
+    async fn keys(&self, prefix: &str) -> io::Result<Vec<String>> {
+        let prefix = prefix.to_string();
+        self.blocking(move |connection, table| {
+            let statement = format!(
+                "SELECT key FROM \"{}\" WHERE substr(key, 1, ?2) = ?1 ORDER BY key",
+                table
+            );
+            let mut statement = connection.prepare(&statement)?;
+            let length = prefix.chars().count() as i64;
+            let keys = statement
+                .query_map(rusqlite::params![prefix, length], |row| row.get(0))?
+                .collect();
+            keys
+        })
+        .await
+    }
+}
+
+/// View of a [`KeyValueStore`] where all the keys are prefixed by `"<scope>/"`,
+/// to share a store between several services.
+#[derive(Clone)]
+pub struct Scoped<S> {
+    store: S,
+    prefix: String,
+}
+
+impl<S: KeyValueStore> Scoped<S> {
+    pub fn new(store: S, scope: &str) -> Self {
+        Self {
+            store,
+            prefix: format!("{}/", scope),
+        }
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+}
+
+#[async_trait]
+impl<S: KeyValueStore> KeyValueStore for Scoped<S> {
+    async fn get(&self, key: &str) -> io::Result<Option<String>> {
+        self.store.get(&self.key(key)).await
+    }
+
+    async fn set(&self, key: &str, value: String) -> io::Result<()> {
+        self.store.set(&self.key(key), value).await
+    }
+
+    async fn remove(&self, key: &str) -> io::Result<()> {
+        self.store.remove(&self.key(key)).await
+    }
+
+    async fn keys(&self, prefix: &str) -> io::Result<Vec<String>> {
+        let keys = self.store.keys(&self.key(prefix)).await?;
+        Ok(keys
+            .into_iter()
+            .map(|key| key[self.prefix.len()..].to_string())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn check_store(store: impl KeyValueStore) {
+        store.set("a/1", "one".into()).await.unwrap();
+        store.set("a/2", "two".into()).await.unwrap();
+        store.set("b", "other".into()).await.unwrap();
+        store.set("a/1", "uno".into()).await.unwrap();
+
+        assert_eq!(store.get("a/1").await.unwrap().as_deref(), Some("uno"));
+        assert_eq!(store.get("c").await.unwrap(), None);
+        assert_eq!(store.keys("a/").await.unwrap(), ["a/1", "a/2"]);
+        assert_eq!(store.keys("").await.unwrap(), ["a/1", "a/2", "b"]);
+
+        store.remove("a/1").await.unwrap();
+        store.remove("a/1").await.unwrap();
+        assert_eq!(store.get("a/1").await.unwrap(), None);
+        assert_eq!(store.keys("a/").await.unwrap(), ["a/2"]);
+    }
+
+    #[tokio::test]
+    async fn memory_store() {
+        check_store(MemoryStore::default()).await;
+    }
+
+    #[tokio::test]
+    async fn scoped_store() {
+        let store = MemoryStore::default();
+        store.set("a", "outside".into()).await.unwrap();
+        check_store(Scoped::new(store.clone(), "scope")).await;
+        assert_eq!(store.keys("").await.unwrap(), ["a", "scope/a/2", "scope/b"]);
+    }
+
+    #[tokio::test]
+    async fn file_store() {
+        let path = std::env::temp_dir().join("service-io-test-file-store.json");
+        tokio::fs::remove_file(&path).await.ok();
+
+        check_store(FileStore::new(&path)).await;
+
+        let reopened = FileStore::new(&path);
+        assert_eq!(reopened.keys("").await.unwrap(), ["a/2", "b"]);
+        assert_eq!(reopened.get("b").await.unwrap().as_deref(), Some("other"));
+
+        tokio::fs::remove_file(path).await.unwrap();
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_store() {
+        check_store(SqliteStore::memory().unwrap()).await;
+    }
+}
@@ -0,0 +1,540 @@
+//! Storage of binary blobs shared by the subsystems that need to store files,
+//! as [`UploadOutput`], so all of them can be configured with the same backend.
+//!
+//! The blobs are identified by flat keys (i.e. `"report-2022.pdf"`).
+//! Keys containing `/` are not nested in directories.
+//! A store can be shared by several subsystems wrapping it in an [`Arc`].
+//!
+//! [`UploadOutput`]: crate::connectors::UploadOutput
+
+use crate::connectors::aws;
+#[cfg(feature = "http")]
+use crate::connectors::aws::Credentials;
+#[cfg(feature = "http")]
+use crate::connectors::proxy::{self, Proxy};
+use crate::util::IntoOption;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use percent_encoding::{percent_decode_str, utf8_percent_encode};
+#[cfg(feature = "http")]
+use reqwest::{Method, StatusCode};
+
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+#[cfg(feature = "http")]
+use std::time::{Duration, SystemTime};
+
+/// Storage of binary blobs by key.
+#[async_trait]
+pub trait Blobstore: Send + Sync {
+    /// Stores `data` with the `key`, replacing the previous blob with that key.
+    async fn put(&self, key: &str, data: Bytes) -> io::Result<()>;
+
+    /// Returns the blob of the `key`, or an [`io::ErrorKind::NotFound`] error.
+    async fn get(&self, key: &str) -> io::Result<Bytes>;
+
+    /// Removes the blob of the `key`. Removing a non-existent blob is not an error.
+    async fn delete(&self, key: &str) -> io::Result<()>;
+
+    /// Keys of the stored blobs that start with `prefix`.
+    async fn list(&self, prefix: &str) -> io::Result<Vec<String>>;
+
+    /// URL from where the users can download the blob, if the store can provide it.
+    fn url(&self, key: &str) -> Option<String>;
+}
+
+#[async_trait]
+impl<B: Blobstore + ?Sized> Blobstore for Arc<B> {
+    async fn put(&self, key: &str, data: Bytes) -> io::Result<()> {
+        (**self).put(key, data).await
+    }
+
+    async fn get(&self, key: &str) -> io::Result<Bytes> {
+        (**self).get(key).await
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        (**self).delete(key).await
+    }
+
+    async fn list(&self, prefix: &str) -> io::Result<Vec<String>> {
+        (**self).list(prefix).await
+    }
+
+    fn url(&self, key: &str) -> Option<String> {
+        (**self).url(key)
+    }
+}
+
+fn encode_key(key: &str) -> String {
+    utf8_percent_encode(key, aws::URI_ENCODE).to_string()
+}
+
+fn decode_key(encoded: &str) -> String {
+    percent_decode_str(encoded).decode_utf8_lossy().into()
+}
+
+#[cfg(feature = "http")]
+fn not_found(key: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("Blob '{}' not found", key))
+}
+
+/// [`Blobstore`] in a local directory, created if it does not exist.
+/// The keys are escaped to be valid file names.
+#[derive(Clone)]
+pub struct LocalDir {
+    path: PathBuf,
+    public_url: Option<String>,
+}
+
+impl LocalDir {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            public_url: None,
+        }
+    }
+
+    /// URL of the directory if it is served, i.e. by a HTTP file server,
+    /// to provide [`Blobstore::url()`].
+    pub fn public_url(mut self, url: impl IntoOption<String>) -> Self {
+        self.public_url = url.into_some().map(|url| url.trim_end_matches('/').into());
+        self
+    }
+
+    fn file(&self, key: &str) -> PathBuf {
+        self.path.join(encode_key(key))
+    }
+}
+
+#[async_trait]
+impl Blobstore for LocalDir {
+    async fn put(&self, key: &str, data: Bytes) -> io::Result<()> {
+        tokio::fs::create_dir_all(&self.path).await?;
+        tokio::fs::write(self.file(key), data).await
+    }
+
+    async fn get(&self, key: &str) -> io::Result<Bytes> {
+        tokio::fs::read(self.file(key)).await.map(Bytes::from)
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        match tokio::fs::remove_file(self.file(key)).await {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> io::Result<Vec<String>> {
+        let mut entries = match tokio::fs::read_dir(&self.path).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+
+        let mut keys = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let key = decode_key(&entry.file_name().to_string_lossy());
+            if key.starts_with(prefix) {
+                keys.push(key);
+            }
+        }
+        keys.sort();
+        Ok(keys)
+    }
+
+    fn url(&self, key: &str) -> Option<String> {
+        let url = self.public_url.as_ref()?;
+        Some(format!("{}/{}", url, encode_key(key)))
+    }
+}
+
+/// [`Blobstore`] in a directory of a WebDAV server.
+/// It also works with HTTP file servers that accept `PUT` and `DELETE` requests,
+/// except [`Blobstore::list()`] that requires `PROPFIND`.
+#[cfg(feature = "http")]
+#[derive(Clone)]
+pub struct WebDav {
+    url: String,
+    public_url: Option<String>,
+    credentials: Option<(String, String)>,
+    http: reqwest::Client,
+}
+
+#[cfg(feature = "http")]
+impl WebDav {
+    /// Use the directory `url`, that must exist.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into().trim_end_matches('/').into(),
+            public_url: None,
+            credentials: None,
+            http: proxy::http_client(None).build().unwrap(),
+        }
+    }
+
+    /// Reach the server through a proxy.
+    /// By default, the proxy of the environment is used, if any. See [`Proxy::from_env()`].
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.http = proxy::http_client(Some(&proxy)).build().unwrap();
+        self
+    }
+
+    /// Authenticate with HTTP basic authentication.
+    pub fn basic_auth(mut self, user: impl Into<String>, password: impl Into<String>) -> Self {
+        self.credentials = Some((user.into(), password.into()));
+        self
+    }
+
+    /// URL of the directory from where the users download the blobs,
+    /// if it is not the `url` of the server.
+    pub fn public_url(mut self, url: impl IntoOption<String>) -> Self {
+        self.public_url = url.into_some().map(|url| url.trim_end_matches('/').into());
+        self
+    }
+
+    async fn request(
+        &self,
+        method: Method,
+        url: String,
+        body: Bytes,
+    ) -> io::Result<reqwest::Response> {
+        let mut request = self.http.request(method, url).body(body);
+        if let Some((user, password)) = &self.credentials {
+            request = request.basic_auth(user, Some(password));
+        }
+        request.send().await.map_err(io::Error::other)
+    }
+}
+
+#[cfg(feature = "http")]
+#[async_trait]
+impl Blobstore for WebDav {
+    async fn put(&self, key: &str, data: Bytes) -> io::Result<()> {
+        let url = format!("{}/{}", self.url, encode_key(key));
+        let response = self.request(Method::PUT, url, data).await?;
+        response.error_for_status().map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> io::Result<Bytes> {
+        let url = format!("{}/{}", self.url, encode_key(key));
+        let response = self.request(Method::GET, url, Bytes::new()).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(not_found(key));
+        }
+        let response = response.error_for_status().map_err(io::Error::other)?;
+        response.bytes().await.map_err(io::Error::other)
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        let url = format!("{}/{}", self.url, encode_key(key));
+        let response = self.request(Method::DELETE, url, Bytes::new()).await?;
+        if response.status() != StatusCode::NOT_FOUND {
+            response.error_for_status().map_err(io::Error::other)?;
+        }
+        Ok(())
+    }
+
+    async fn list(&self, prefix: &str) -> io::Result<Vec<String>> {
+        let url = format!("{}/", self.url);
+        let method = Method::from_bytes(b"PROPFIND").unwrap();
+        let body = Bytes::from_static(b"<?xml version=\"1.0\"?><propfind xmlns=\"DAV:\"><prop><resourcetype/></prop></propfind>");
+
+        let mut request = self.http.request(method, url).header("depth", "1");
+        if let Some((user, password)) = &self.credentials {
+            request = request.basic_auth(user, Some(password));
+        }
+        let response = request.body(body).send().await.map_err(io::Error::other)?;
+        let text = response
+            .error_for_status()
+            .map_err(io::Error::other)?
+            .text()
+            .await
+            .map_err(io::Error::other)?;
+
+        let mut keys = xml_values(&text, "href")
+            .filter(|href| !href.ends_with('/'))
+            .filter_map(|href| href.rsplit('/').next().map(decode_key))
+            .filter(|key| key.starts_with(prefix))
+            .collect::<Vec<_>>();
+        keys.sort();
+        Ok(keys)
+    }
+
+    fn url(&self, key: &str) -> Option<String> {
+        let url = self.public_url.as_ref().unwrap_or(&self.url);
+        Some(format!("{}/{}", url, encode_key(key)))
+    }
+}
+
+/// Text of the XML elements named `name`, with or without namespace prefix.
+#[cfg(feature = "http")]
+fn xml_values<'a>(xml: &'a str, name: &'a str) -> impl Iterator<Item = String> + 'a {
+    xml.split('<').filter_map(move |part| {
+        let (tag, text) = part.split_once('>')?;
+        let tag = tag
+            .split_whitespace()
+            .next()
+            .filter(|tag| !tag.starts_with('/'))?;
+        let tag = tag.rsplit(':').next()?;
+        (tag == name).then(|| {
+            text.replace("&lt;", "<")
+                .replace("&gt;", ">")
+                .replace("&quot;", "\"")
+                .replace("&apos;", "'")
+                .replace("&amp;", "&")
+        })
+    })
+}
+
+/// [`Blobstore`] in a S3 bucket, or in a S3-compatible server, authenticated with IAM credentials.
+/// [`Blobstore::url()`] returns presigned URLs, so the bucket does not need to be public.
+#[cfg(feature = "http")]
+#[derive(Clone)]
+pub struct S3Bucket {
+    region: String,
+    bucket: String,
+    credentials: Credentials,
+    endpoint: Option<String>,
+    link_expiration: Duration,
+    http: reqwest::Client,
+}
+
+#[cfg(feature = "http")]
+impl S3Bucket {
+    /// Longest expiration of a presigned URL allowed by S3.
+    const MAX_LINK_EXPIRATION: Duration = Duration::from_secs(7 * 24 * 3600);
+
+    pub fn new(
+        region: impl Into<String>,
+        bucket: impl Into<String>,
+        access_key_id: impl Into<String>,
+        secret_access_key: impl Into<String>,
+    ) -> Self {
+        Self {
+            region: region.into(),
+            bucket: bucket.into(),
+            credentials: Credentials {
+                access_key_id: access_key_id.into(),
+                secret_access_key: secret_access_key.into(),
+                session_token: None,
+            },
+            endpoint: None,
+            link_expiration: Self::MAX_LINK_EXPIRATION,
+            http: proxy::http_client(None).build().unwrap(),
+        }
+    }
+
+    /// Reach the server through a proxy.
+    /// By default, the proxy of the environment is used, if any. See [`Proxy::from_env()`].
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.http = proxy::http_client(Some(&proxy)).build().unwrap();
+        self
+    }
+
+    /// Same as [`S3Bucket::new()`] but reading the credentials from the
+    /// `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN` environment variables.
+    pub fn from_env(region: impl Into<String>, bucket: impl Into<String>) -> Self {
+        let var = |name| std::env::var(name).unwrap_or_default();
+        Self::new(
+            region,
+            bucket,
+            var("AWS_ACCESS_KEY_ID"),
+            var("AWS_SECRET_ACCESS_KEY"),
+        )
+        .session_token(std::env::var("AWS_SESSION_TOKEN").ok())
+    }
+
+    /// Session token of temporary credentials.
+    pub fn session_token(mut self, value: impl IntoOption<String>) -> Self {
+        self.credentials.session_token = value.into_some();
+        self
+    }
+
+    /// Use a S3-compatible server (i.e. `http://localhost:9000`) instead of AWS.
+    /// The bucket is addressed in the path.
+    pub fn endpoint(mut self, url: impl Into<String>) -> Self {
+        self.endpoint = Some(url.into().trim_end_matches('/').into());
+        self
+    }
+
+    /// Time the URLs returned by [`Blobstore::url()`] are valid. By default and at most, 7 days.
+    pub fn link_expiration(mut self, duration: Duration) -> Self {
+        self.link_expiration = duration.min(Self::MAX_LINK_EXPIRATION);
+        self
+    }
+
+    /// Base URL and encoded path of an object.
+    fn location(&self, key: &str) -> (String, String) {
+        let key = aws::encode_path(key);
+        match &self.endpoint {
+            Some(endpoint) => (endpoint.clone(), format!("/{}/{}", self.bucket, key)),
+            None => (
+                format!("https://{}.s3.{}.amazonaws.com", self.bucket, self.region),
+                format!("/{}", key),
+            ),
+        }
+    }
+
+    /// Sends a signed request. `query` must be canonical: encoded and sorted by name.
+    async fn request(
+        &self,
+        method: Method,
+        key: &str,
+        query: &str,
+        payload: Bytes,
+    ) -> io::Result<reqwest::Response> {
+        let (base_url, path) = self.location(key);
+        let host = base_url
+            .split_once("://")
+            .map_or(&*base_url, |(_, host)| host);
+        let amz_date = aws::amz_date(SystemTime::now());
+        let payload_hash = aws::sha256_hex(&payload);
+        let mut headers = vec![
+            ("host", host),
+            ("x-amz-content-sha256", payload_hash.as_str()),
+            ("x-amz-date", amz_date.as_str()),
+        ];
+        if let Some(token) = &self.credentials.session_token {
+            headers.push(("x-amz-security-token", token));
+        }
+
+        let request = aws::Request {
+            method: method.as_str(),
+            path: &path,
+            query,
+            headers: &headers,
+            payload: &payload,
+        };
+        let authorization =
+            aws::authorization(&request, &self.credentials, &self.region, "s3", &amz_date);
+
+        let url = match query.is_empty() {
+            true => format!("{}{}", base_url, path),
+            false => format!("{}{}?{}", base_url, path, query),
+        };
+        let mut builder = self
+            .http
+            .request(method, url)
+            .header("authorization", authorization);
+        for (name, value) in headers.iter().filter(|(name, _)| *name != "host") {
+            builder = builder.header(*name, *value);
+        }
+
+        builder.body(payload).send().await.map_err(io::Error::other)
+    }
+}
+
+#[cfg(feature = "http")]
+#[async_trait]
+impl Blobstore for S3Bucket {
+    async fn put(&self, key: &str, data: Bytes) -> io::Result<()> {
+        let response = self.request(Method::PUT, key, "", data).await?;
+        response.error_for_status().map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> io::Result<Bytes> {
+        let response = self.request(Method::GET, key, "", Bytes::new()).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(not_found(key));
+        }
+        let response = response.error_for_status().map_err(io::Error::other)?;
+        response.bytes().await.map_err(io::Error::other)
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        let response = self.request(Method::DELETE, key, "", Bytes::new()).await?;
+        response.error_for_status().map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    async fn list(&self, prefix: &str) -> io::Result<Vec<String>> {
+        let mut keys = Vec::new();
+        let mut continuation: Option<String> = None;
+        loop {
+            let mut query = String::new();
+            if let Some(token) = &continuation {
+                query += &format!("continuation-token={}&", encode_key(token));
+            }
+            query += &format!("list-type=2&prefix={}", encode_key(prefix));
+
+            let response = self.request(Method::GET, "", &query, Bytes::new()).await?;
+            let text = response
+                .error_for_status()
+                .map_err(io::Error::other)?
+                .text()
+                .await
+                .map_err(io::Error::other)?;
+
+            keys.extend(xml_values(&text, "Key"));
+            continuation = xml_values(&text, "NextContinuationToken").next();
+            if continuation.is_none() {
+                break Ok(keys);
+            }
+        }
+    }
+
+    fn url(&self, key: &str) -> Option<String> {
+        let (base_url, path) = self.location(key);
+        Some(aws::presigned_url(
+            &base_url,
+            &path,
+            &self.credentials,
+            &self.region,
+            "s3",
+            &aws::amz_date(SystemTime::now()),
+            self.link_expiration.as_secs(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn local_dir() {
+        let path = std::env::temp_dir().join("service-io-test-local-dir");
+        let store = LocalDir::new(&path).public_url("http://files.domain.com/");
+
+        store.put("report/1.txt", "data 1".into()).await.unwrap();
+        store.put("report/2.txt", "data 2".into()).await.unwrap();
+        store.put("other.txt", "other".into()).await.unwrap();
+
+        assert_eq!(store.get("report/1.txt").await.unwrap(), "data 1");
+        assert_eq!(
+            store.list("report/").await.unwrap(),
+            ["report/1.txt", "report/2.txt"]
+        );
+        assert_eq!(
+            store.url("report/1.txt").unwrap(),
+            "http://files.domain.com/report%2F1.txt"
+        );
+
+        store.delete("report/1.txt").await.unwrap();
+        store.delete("report/1.txt").await.unwrap();
+        let err = store.get("report/1.txt").await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        tokio::fs::remove_dir_all(path).await.unwrap();
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn xml() {
+        let xml = "<D:multistatus xmlns:D=\"DAV:\">\
+            <D:response><D:href>/files/</D:href></D:response>\
+            <D:response><D:href>/files/a%20b.txt</D:href></D:response>\
+            <Key>x&amp;y</Key>\
+            </D:multistatus>";
+        assert_eq!(
+            xml_values(xml, "href").collect::<Vec<_>>(),
+            ["/files/", "/files/a%20b.txt"]
+        );
+        assert_eq!(xml_values(xml, "Key").collect::<Vec<_>>(), ["x&y"]);
+    }
+}
@@ -0,0 +1,413 @@
+//! In-process fake servers to test the connectors without real servers or credentials.
+//!
+//! Available with the `testing` feature.
+//!
+//! # Example
+//! ```rust
+//! use service_io::connectors::{ImapClient, SmtpClient};
+//! use service_io::engine::Engine;
+//! use service_io::message::Message;
+//! use service_io::services::Echo;
+//! use service_io::testing::{MockImapServer, MockSmtpServer};
+//!
+//! use std::time::Duration;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let imap = MockImapServer::start().await;
+//! let smtp = MockSmtpServer::start().await;
+//!
+//! tokio::spawn(
+//!     Engine::default()
+//!         .input(
+//!             ImapClient::default()
+//!                 .domain("127.0.0.1")
+//!                 .port(imap.port())
+//!                 .insecure()
+//!                 .email("service@domain.com")
+//!                 .password("1234")
+//!                 .polling_time(Duration::from_millis(10)),
+//!         )
+//!         .output(
+//!             SmtpClient::default()
+//!                 .domain("127.0.0.1")
+//!                 .port(smtp.port())
+//!                 .insecure()
+//!                 .email("service@domain.com")
+//!                 .password("1234"),
+//!         )
+//!         .add_service("s-echo", Echo)
+//!         .run(),
+//! );
+//!
+//! imap.push(Message::default().user("user@domain.com").service_name("s-echo").body("hi"));
+//! let reply = smtp.recv().await;
+//! assert_eq!(reply.user, "user@domain.com");
+//! assert_eq!(reply.body.trim(), "hi");
+//! # }
+//! ```
+
+use crate::connectors::imap::email_to_message;
+use crate::connectors::smtp::message_to_email;
+use crate::message::Message;
+
+use lettre::message::Mailbox;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Mutex as AsyncMutex};
+use tokio::task::JoinHandle;
+
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+/// Address used as recipient of the emails pushed to a [`MockImapServer`].
+const SERVICE_ADDRESS: &str = "service@service-io.test";
+
+async fn bind() -> (TcpListener, SocketAddr) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    (listener, addr)
+}
+
+/// Fake IMAP server, without TLS.
+/// The emails are pushed to the `INBOX` folder unless [`MockImapServer::push_to()`] is used.
+/// It understands the commands used by [`ImapClient`].
+///
+/// [`ImapClient`]: crate::connectors::ImapClient
+pub struct MockImapServer {
+    addr: SocketAddr,
+    mailboxes: Arc<Mutex<HashMap<String, MockInbox>>>,
+    task: JoinHandle<()>,
+}
+
+/// Emails of a [`MockImapServer`] folder with their UIDs.
+/// The UID validity is always 1.
+#[derive(Default)]
+struct MockInbox {
+    emails: VecDeque<MockEmail>,
+    next_uid: u32,
+}
+
+struct MockEmail {
+    uid: u32,
+    data: Vec<u8>,
+    deleted: bool,
+}
+
+impl MockInbox {
+    fn position(&self, uid: u32) -> Option<usize> {
+        self.emails.iter().position(|email| email.uid == uid)
+    }
+}
+
+impl MockImapServer {
+    /// Starts the server listening in a random local port.
+    pub async fn start() -> Self {
+        let (listener, addr) = bind().await;
+        let mailboxes = Arc::new(Mutex::new(HashMap::new()));
+
+        let task = tokio::spawn({
+            let mailboxes = mailboxes.clone();
+            async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    tokio::spawn(Self::session(stream, mailboxes.clone()));
+                }
+            }
+        });
+
+        Self {
+            addr,
+            mailboxes,
+            task,
+        }
+    }
+
+    pub fn port(&self) -> u16 {
+        self.addr.port()
+    }
+
+    /// Adds to the inbox an email sent by [`Message::user`], as a user would write it.
+    pub fn push(&self, message: Message) {
+        self.push_to("INBOX", message);
+    }
+
+    /// Same as [`MockImapServer::push()`] but to the folder `folder`.
+    pub fn push_to(&self, folder: &str, message: Message) {
+        let from: Mailbox = message.user.parse().expect("The user must be an email");
+        let email = message_to_email(message.user(SERVICE_ADDRESS), from)
+            .expect("The message can be represented as an email");
+        self.push_raw_to(folder, email.formatted());
+    }
+
+    /// Adds a raw RFC 822 email to the inbox.
+    pub fn push_raw(&self, email: impl Into<Vec<u8>>) {
+        self.push_raw_to("INBOX", email);
+    }
+
+    /// Adds a raw RFC 822 email to the folder `folder`.
+    pub fn push_raw_to(&self, folder: &str, email: impl Into<Vec<u8>>) {
+        let mut mailboxes = self.mailboxes.lock().unwrap();
+        let inbox = mailboxes.entry(folder.into()).or_default();
+        inbox.next_uid += 1;
+        let uid = inbox.next_uid;
+        inbox.emails.push_back(MockEmail {
+            uid,
+            data: email.into(),
+            deleted: false,
+        });
+    }
+
+    /// Removes the oldest email of the inbox, as another client of the mailbox would do.
+    pub fn pop(&self) -> Option<Vec<u8>> {
+        let mut mailboxes = self.mailboxes.lock().unwrap();
+        let inbox = mailboxes.get_mut("INBOX")?;
+        inbox.emails.pop_front().map(|email| email.data)
+    }
+
+    /// Number of emails in the inbox, not removed yet.
+    pub fn len(&self) -> usize {
+        self.len_of("INBOX")
+    }
+
+    /// Number of emails in the folder `folder`, not removed yet.
+    pub fn len_of(&self, folder: &str) -> usize {
+        let mailboxes = self.mailboxes.lock().unwrap();
+        mailboxes.get(folder).map_or(0, |inbox| inbox.emails.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    async fn session(
+        stream: TcpStream,
+        mailboxes: Arc<Mutex<HashMap<String, MockInbox>>>,
+    ) -> io::Result<()> {
+        let mut selected = String::from("INBOX");
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+
+        writer.write_all(b"* OK IMAP4rev1 mock ready\r\n").await?;
+        while let Some(line) = lines.next_line().await? {
+            let mut words = line.split_whitespace();
+            let tag = words.next().unwrap_or("*");
+            let mut command = words.next().unwrap_or_default().to_uppercase();
+            if command == "UID" {
+                command = format!("UID {}", words.next().unwrap_or_default().to_uppercase());
+            }
+
+            let mut response = Vec::new();
+            match command.as_str() {
+                "LOGIN" | "NOOP" | "CAPABILITY" => (),
+                "SELECT" | "EXAMINE" => {
+                    selected = words.next().unwrap_or_default().trim_matches('"').into();
+                    let mut mailboxes = mailboxes.lock().unwrap();
+                    let exists = mailboxes.entry(selected.clone()).or_default().emails.len();
+                    response.extend(format!("* {} EXISTS\r\n* 0 RECENT\r\n", exists).bytes());
+                    response.extend(b"* OK [UIDVALIDITY 1] UIDs valid\r\n");
+                }
+                "UID SEARCH" => {
+                    // Only the form 'UID n:*' is supported.
+                    let first: u32 = words
+                        .nth(1)
+                        .and_then(|range| range.split(':').next())
+                        .and_then(|first| first.parse().ok())
+                        .unwrap_or(1);
+                    let mut mailboxes = mailboxes.lock().unwrap();
+                    let inbox = mailboxes.entry(selected.clone()).or_default();
+                    let mut uids: Vec<u32> = inbox
+                        .emails
+                        .iter()
+                        .map(|email| email.uid)
+                        .filter(|&uid| uid >= first)
+                        .collect();
+                    // As real servers, '*' always represents the greatest UID.
+                    if let (true, Some(last)) = (uids.is_empty(), inbox.emails.back()) {
+                        uids.push(last.uid);
+                    }
+                    response.extend(b"* SEARCH");
+                    for uid in uids {
+                        response.extend(format!(" {}", uid).bytes());
+                    }
+                    response.extend(b"\r\n");
+                }
+                "UID FETCH" => {
+                    let uid = words
+                        .next()
+                        .and_then(|n| n.parse().ok())
+                        .unwrap_or_default();
+                    let item = match words.next().unwrap_or_default().contains("BODY") {
+                        true => "BODY[]",
+                        false => "RFC822",
+                    };
+                    let mut mailboxes = mailboxes.lock().unwrap();
+                    let inbox = mailboxes.entry(selected.clone()).or_default();
+                    if let Some(seq) = inbox.position(uid) {
+                        let email = &inbox.emails[seq].data;
+                        let header = format!(
+                            "* {} FETCH (UID {} {} {{{}}}\r\n",
+                            seq + 1,
+                            uid,
+                            item,
+                            email.len()
+                        );
+                        response.extend(header.bytes());
+                        response.extend(email);
+                        response.extend(b")\r\n");
+                    }
+                }
+                "UID STORE" => {
+                    let uid = words
+                        .next()
+                        .and_then(|n| n.parse().ok())
+                        .unwrap_or_default();
+                    let mut mailboxes = mailboxes.lock().unwrap();
+                    let inbox = mailboxes.entry(selected.clone()).or_default();
+                    if let Some(seq) = inbox.position(uid) {
+                        inbox.emails[seq].deleted = true;
+                        let fetch =
+                            format!("* {} FETCH (UID {} FLAGS (\\Deleted))\r\n", seq + 1, uid);
+                        response.extend(fetch.bytes());
+                    }
+                }
+                "EXPUNGE" => {
+                    let mut mailboxes = mailboxes.lock().unwrap();
+                    let inbox = mailboxes.entry(selected.clone()).or_default();
+                    while let Some(seq) = inbox.emails.iter().position(|email| email.deleted) {
+                        inbox.emails.remove(seq);
+                        response.extend(format!("* {} EXPUNGE\r\n", seq + 1).bytes());
+                    }
+                }
+                "LOGOUT" => {
+                    writer
+                        .write_all(format!("* BYE\r\n{} OK LOGOUT completed\r\n", tag).as_bytes())
+                        .await?;
+                    break;
+                }
+                _ => {
+                    let bad = format!("{} BAD Unknown command\r\n", tag);
+                    writer.write_all(bad.as_bytes()).await?;
+                    continue;
+                }
+            }
+
+            response.extend(format!("{} OK {} completed\r\n", tag, command).bytes());
+            writer.write_all(&response).await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for MockImapServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Fake SMTP server, without TLS, that accepts any credentials.
+/// The received emails are transformed back to messages.
+pub struct MockSmtpServer {
+    addr: SocketAddr,
+    received: AsyncMutex<mpsc::UnboundedReceiver<Message>>,
+    task: JoinHandle<()>,
+}
+
+impl MockSmtpServer {
+    /// Starts the server listening in a random local port.
+    pub async fn start() -> Self {
+        let (listener, addr) = bind().await;
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(Self::session(stream, sender.clone()));
+            }
+        });
+
+        Self {
+            addr,
+            received: AsyncMutex::new(receiver),
+            task,
+        }
+    }
+
+    pub fn port(&self) -> u16 {
+        self.addr.port()
+    }
+
+    /// Waits for the next received email.
+    /// The [`Message::user`] is the recipient of the email.
+    pub async fn recv(&self) -> Message {
+        let mut received = self.received.lock().await;
+        received.recv().await.expect("The server is running")
+    }
+
+    /// Returns the next received email if there is any.
+    pub async fn try_recv(&self) -> Option<Message> {
+        self.received.lock().await.try_recv().ok()
+    }
+
+    async fn session(stream: TcpStream, sender: mpsc::UnboundedSender<Message>) -> io::Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        let mut recipient = String::new();
+
+        writer.write_all(b"220 mock ESMTP ready\r\n").await?;
+        while let Some(line) = lines.next_line().await? {
+            let command = line
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .to_uppercase();
+
+            let response = match command.as_str() {
+                "EHLO" | "HELO" => "250-mock\r\n250 AUTH PLAIN LOGIN\r\n",
+                "AUTH" => "235 Authentication succeeded\r\n",
+                "RCPT" => {
+                    recipient = line
+                        .split_once(':')
+                        .map(|(_, address)| address.trim().trim_matches(['<', '>']).into())
+                        .unwrap_or_default();
+                    "250 OK\r\n"
+                }
+                "DATA" => {
+                    writer
+                        .write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n")
+                        .await?;
+                    let mut data = String::new();
+                    while let Some(line) = lines.next_line().await? {
+                        if line == "." {
+                            break;
+                        }
+                        data.push_str(line.strip_prefix('.').unwrap_or(&line));
+                        data.push_str("\r\n");
+                    }
+
+                    if let Ok(email) = mailparse::parse_mail(data.as_bytes()) {
+                        let message = email_to_message(&email).user(recipient.clone());
+                        sender.send(message).ok();
+                    }
+                    "250 OK\r\n"
+                }
+                "QUIT" => {
+                    writer.write_all(b"221 Bye\r\n").await?;
+                    break;
+                }
+                _ => "250 OK\r\n",
+            };
+
+            writer.write_all(response.as_bytes()).await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for MockSmtpServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
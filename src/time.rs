@@ -0,0 +1,130 @@
+//! Timezone aware handling of the times written by the users and shown to them,
+//! so a user writing "18:30" means 18:30 in their timezone and not in the server one.
+//!
+//! The timezone of each message is read from [`Message::metadata`] using the [`TIMEZONE_KEY`],
+//! set by the [`Settings`] service for each user or by [`Engine::timezone()`] for all of them.
+//! If there is no timezone, UTC is used.
+//!
+//! # Example
+//! ```rust
+//! use service_io::message::Message;
+//! use service_io::time::{self, TIMEZONE_KEY};
+//!
+//! let request = Message::default().metadata([(TIMEZONE_KEY, "Europe/Madrid")]);
+//! let alarm = time::parse_time(&request, "18:30").unwrap();
+//! assert!(time::format_time(&request, alarm).contains("18:30"));
+//! ```
+//!
+//! [`Settings`]: crate::services::Settings
+//! [`Engine::timezone()`]: crate::engine::Engine::timezone()
+
+use crate::message::Message;
+
+use chrono::{DateTime, Days, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+/// Key of [`Message::metadata`] with the timezone of the user, as an IANA name
+/// (i.e. `Europe/Madrid`).
+pub const TIMEZONE_KEY: &str = "timezone";
+
+/// Parse an IANA timezone name.
+pub fn parse_timezone(name: &str) -> Option<Tz> {
+    name.parse().ok()
+}
+
+/// Timezone of the message, or UTC if it is not specified or it is not valid.
+pub fn timezone(message: &Message) -> Tz {
+    match message.metadata.get(TIMEZONE_KEY) {
+        Some(name) => parse_timezone(name).unwrap_or_else(|| {
+            log::warn!("Unknown timezone '{}', using UTC", name);
+            Tz::UTC
+        }),
+        None => Tz::UTC,
+    }
+}
+
+/// Current time in the timezone of the message.
+pub fn now(message: &Message) -> DateTime<Tz> {
+    Utc::now().with_timezone(&timezone(message))
+}
+
+/// Parse a time written in the timezone of the message.
+/// Accepted formats are `HH:MM` for the next time the clock reaches that hour,
+/// and `YYYY-MM-DD HH:MM` for a specific date.
+pub fn parse_time(message: &Message, text: &str) -> Option<DateTime<Utc>> {
+    parse_time_at(timezone(message), text, Utc::now())
+}
+
+fn parse_time_at(timezone: Tz, text: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let text = text.trim();
+    if let Ok(date_time) = NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M") {
+        return to_utc(timezone, date_time);
+    }
+
+    let time = NaiveTime::parse_from_str(text, "%H:%M").ok()?;
+    let today = now.with_timezone(&timezone).date_naive();
+    let candidate = to_utc(timezone, today.and_time(time))?;
+    match candidate > now {
+        true => Some(candidate),
+        false => to_utc(
+            timezone,
+            today.checked_add_days(Days::new(1))?.and_time(time),
+        ),
+    }
+}
+
+/// Local times skipped by a DST change do not exist.
+/// The repeated ones are resolved to the earliest.
+fn to_utc(timezone: Tz, date_time: NaiveDateTime) -> Option<DateTime<Utc>> {
+    let local = timezone.from_local_datetime(&date_time).earliest()?;
+    Some(local.with_timezone(&Utc))
+}
+
+/// Format a time in the timezone of the message, to show it to the user.
+pub fn format_time(message: &Message, time: DateTime<Utc>) -> String {
+    time.with_timezone(&timezone(message))
+        .format("%Y-%m-%d %H:%M %Z")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text).unwrap().to_utc()
+    }
+
+    #[test]
+    fn clock_times() {
+        let madrid = parse_timezone("Europe/Madrid").unwrap();
+        let now = utc("2022-06-01T10:00:00Z"); // 12:00 in Madrid
+
+        let later = parse_time_at(madrid, "18:30", now).unwrap();
+        assert_eq!(later, utc("2022-06-01T16:30:00Z"));
+
+        let tomorrow = parse_time_at(madrid, "11:00", now).unwrap();
+        assert_eq!(tomorrow, utc("2022-06-02T09:00:00Z"));
+
+        let date = parse_time_at(Tz::UTC, "2022-12-24 20:00", now).unwrap();
+        assert_eq!(date, utc("2022-12-24T20:00:00Z"));
+
+        assert_eq!(parse_time_at(madrid, "25:00", now), None);
+        // Skipped by the DST change in Madrid.
+        assert_eq!(parse_time_at(madrid, "2022-03-27 02:30", now), None);
+    }
+
+    #[test]
+    fn message_timezone() {
+        let message = Message::default().metadata([(TIMEZONE_KEY, "America/New_York")]);
+        let time = utc("2022-01-01T12:00:00Z");
+        assert_eq!(format_time(&message, time), "2022-01-01 07:00 EST");
+
+        let message = Message::default().metadata([(TIMEZONE_KEY, "Mars/Olympus")]);
+        assert_eq!(timezone(&message), Tz::UTC);
+        assert_eq!(
+            format_time(&Message::default(), time),
+            "2022-01-01 12:00 UTC"
+        );
+    }
+}
@@ -0,0 +1,248 @@
+//! Registry of users and roles to control which services each user can use.
+//!
+//! Each role grants access to a set of services, and each user has any number of roles.
+//! Once the registry is set in the engine with [`Engine::users()`], a message is only
+//! delivered if one of the roles of its [`Message::user`] grants access to its service.
+//! The whitelists of [`Engine::add_service_for()`] are still checked.
+//!
+//! The roles are defined by code, while the roles of the users can be changed at runtime
+//! and persisted in a [`KeyValueStore`] (i.e. a [`FileStore`] or a SQLite store).
+//!
+//! # Example
+//! ```rust no_run
+//! use service_io::connectors::{ImapClient, SmtpClient};
+//! use service_io::engine::Engine;
+//! use service_io::services::{Alarm, Echo, Process};
+//! use service_io::state::FileStore;
+//! use service_io::users::{UserRegistry, ANY_SERVICE};
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let users = UserRegistry::default()
+//!         .role("admin", [ANY_SERVICE])
+//!         .role("family", ["s-alarm", "s-echo"])
+//!         .role("guest", ["s-echo"])
+//!         .user("me@domain.com", ["admin"])
+//!         .user("sister@domain.com", ["family"])
+//!         // Not registered users
+//!         .default_role("guest")
+//!         .store(FileStore::new("users.json"));
+//!
+//!     Engine::default()
+//!         .input(ImapClient::default() /* ... */)
+//!         .output(SmtpClient::default() /* ... */)
+//!         .users(users.clone())
+//!         .add_service("s-echo", Echo)
+//!         .add_service("s-alarm", Alarm)
+//!         .add_service("s-process", Process)
+//!         .run()
+//!         .await;
+//! }
+//! ```
+//!
+//! [`Engine::users()`]: crate::engine::Engine::users()
+//! [`Engine::add_service_for()`]: crate::engine::Engine::add_service_for()
+//! [`Message::user`]: crate::message::Message::user
+//! [`FileStore`]: crate::state::FileStore
+
+use crate::state::KeyValueStore;
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io;
+use std::sync::{Arc, RwLock};
+
+/// Service name that grants access to all the services when added to a role.
+pub const ANY_SERVICE: &str = "*";
+
+#[derive(Default)]
+struct Registry {
+    roles: HashMap<String, HashSet<String>>,
+    users: HashMap<String, BTreeSet<String>>,
+    default_role: Option<String>,
+}
+
+/// Users, roles and the services allowed for each role.
+/// The clones share the same registry, so a clone can be kept to modify it at runtime.
+#[derive(Clone, Default)]
+pub struct UserRegistry {
+    registry: Arc<RwLock<Registry>>,
+    store: Option<Arc<dyn KeyValueStore>>,
+}
+
+impl UserRegistry {
+    /// Define a role that grants access to `services`.
+    /// Use [`ANY_SERVICE`] to grant access to all of them.
+    pub fn role<S: Into<String>>(
+        self,
+        role: impl Into<String>,
+        services: impl IntoIterator<Item = S>,
+    ) -> Self {
+        let services = services.into_iter().map(|s| s.into()).collect();
+        self.registry
+            .write()
+            .unwrap()
+            .roles
+            .insert(role.into(), services);
+        self
+    }
+
+    /// Register a user with `roles`.
+    pub fn user<S: Into<String>>(
+        self,
+        user: impl Into<String>,
+        roles: impl IntoIterator<Item = S>,
+    ) -> Self {
+        let roles = roles.into_iter().map(|s| s.into()).collect();
+        self.registry
+            .write()
+            .unwrap()
+            .users
+            .insert(user.into(), roles);
+        self
+    }
+
+    /// Role of the users that are not registered.
+    /// By default, they are not allowed to use any service.
+    pub fn default_role(self, role: impl Into<String>) -> Self {
+        self.registry.write().unwrap().default_role = Some(role.into());
+        self
+    }
+
+    /// Persist the roles of the users in a `store`.
+    /// The users found in the store replace the ones registered by [`UserRegistry::user()`]
+    /// once the registry is loaded (done by the engine when it starts).
+    pub fn store(mut self, store: impl KeyValueStore + 'static) -> Self {
+        self.store = Some(Arc::new(store));
+        self
+    }
+
+    /// Returns `true` if any role of the user grants access to the service.
+    pub fn allows(&self, user: &str, service_name: &str) -> bool {
+        let registry = self.registry.read().unwrap();
+        let roles = match registry.users.get(user) {
+            Some(roles) => roles.iter().collect::<Vec<_>>(),
+            None => registry.default_role.iter().collect(),
+        };
+
+        roles
+            .into_iter()
+            .filter_map(|role| registry.roles.get(role))
+            .any(|services| services.contains(service_name) || services.contains(ANY_SERVICE))
+    }
+
+    /// Roles of a registered user, or `None` if the user is not registered.
+    pub fn roles(&self, user: &str) -> Option<Vec<String>> {
+        let registry = self.registry.read().unwrap();
+        Some(registry.users.get(user)?.iter().cloned().collect())
+    }
+
+    /// Add a role to a user, registering the user if needed.
+    /// Returns `false` if the user already had the role,
+    /// or an [`io::ErrorKind::InvalidInput`] error if the role does not exist.
+    pub async fn assign(&self, user: &str, role: &str) -> io::Result<bool> {
+        let roles = {
+            let mut registry = self.registry.write().unwrap();
+            if !registry.roles.contains_key(role) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Unknown role '{}'", role),
+                ));
+            }
+            let roles = registry.users.entry(user.into()).or_default();
+            if !roles.insert(role.into()) {
+                return Ok(false);
+            }
+            roles.clone()
+        };
+
+        self.persist(user, &roles).await?;
+        Ok(true)
+    }
+
+    /// Remove a role from a user. The user remains registered even without roles.
+    /// Returns `false` if the user did not have the role.
+    pub async fn revoke(&self, user: &str, role: &str) -> io::Result<bool> {
+        let roles = {
+            let mut registry = self.registry.write().unwrap();
+            let Some(roles) = registry.users.get_mut(user) else {
+                return Ok(false);
+            };
+            if !roles.remove(role) {
+                return Ok(false);
+            }
+            roles.clone()
+        };
+
+        self.persist(user, &roles).await?;
+        Ok(true)
+    }
+
+    async fn persist(&self, user: &str, roles: &BTreeSet<String>) -> io::Result<()> {
+        if let Some(store) = &self.store {
+            store.set(user, serde_json::to_string(roles)?).await?;
+        }
+        Ok(())
+    }
+
+    /// Read the users persisted in the store.
+    pub async fn load(&self) -> io::Result<()> {
+        let Some(store) = &self.store else {
+            return Ok(());
+        };
+
+        for user in store.keys("").await? {
+            if let Some(value) = store.get(&user).await? {
+                let roles = serde_json::from_str(&value)?;
+                self.registry.write().unwrap().users.insert(user, roles);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::MemoryStore;
+
+    fn registry() -> UserRegistry {
+        UserRegistry::default()
+            .role("admin", [ANY_SERVICE])
+            .role("family", ["s-alarm", "s-echo"])
+            .role("guest", ["s-echo"])
+            .user("admin", ["admin"])
+            .user("sister", ["family"])
+    }
+
+    #[test]
+    fn roles() {
+        let users = registry();
+        assert!(users.allows("admin", "s-process"));
+        assert!(users.allows("sister", "s-alarm"));
+        assert!(!users.allows("sister", "s-process"));
+        assert!(!users.allows("unknown", "s-echo"));
+
+        let users = users.default_role("guest");
+        assert!(users.allows("unknown", "s-echo"));
+        assert!(!users.allows("unknown", "s-alarm"));
+    }
+
+    #[tokio::test]
+    async fn runtime_changes() {
+        let store = MemoryStore::default();
+        let users = registry().store(store.clone());
+
+        assert!(users.assign("friend", "guest").await.unwrap());
+        assert!(!users.assign("friend", "guest").await.unwrap());
+        assert!(users.assign("friend", "unknown").await.is_err());
+        assert!(users.revoke("sister", "family").await.unwrap());
+        assert!(!users.allows("sister", "s-alarm"));
+        assert!(users.allows("friend", "s-echo"));
+
+        let restarted = registry().store(store);
+        restarted.load().await.unwrap();
+        assert_eq!(restarted.roles("friend").unwrap(), ["guest"]);
+        assert_eq!(restarted.roles("sister").unwrap(), Vec::<String>::new());
+        assert_eq!(restarted.roles("admin").unwrap(), ["admin"]);
+    }
+}
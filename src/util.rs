@@ -32,14 +32,22 @@ impl<T> IntoOption<T> for Option<T> {
     }
 }
 
-impl<'a> IntoOption<String> for &'a str {
+impl IntoOption<String> for &str {
     fn into_some(self) -> Option<String> {
         Some(self.into())
     }
 }
 
-impl<'a> IntoOption<String> for Option<&'a str> {
+impl IntoOption<String> for Option<&str> {
     fn into_some(self) -> Option<String> {
         self.map(|s| s.into())
     }
 }
+
+/// Name of the type without its module path nor its generic parameters,
+/// i.e. `FallbackOutput` for `service_io::connectors::FallbackOutput<A, B>`.
+pub(crate) fn type_name<T: ?Sized>() -> &'static str {
+    let name = std::any::type_name::<T>();
+    let name = name.split('<').next().unwrap_or(name);
+    name.rsplit("::").next().unwrap_or(name)
+}